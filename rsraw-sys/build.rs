@@ -1,4 +1,7 @@
-use std::{env, path::Path};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
 
 fn main() {
     let dir = env::var_os("OUT_DIR").unwrap();
@@ -95,6 +98,7 @@ fn build(out_dir: impl AsRef<Path>) {
     libraw.file("LibRaw/src/libraw_c_api.cpp");
     // libraw.file("LibRaw/src/libraw_cxx.cpp");
     libraw.file("LibRaw/src/libraw_datastream.cpp");
+    libraw.file("src/glue.cpp");
 
     libraw.warnings(false);
     libraw.extra_warnings(false);
@@ -103,11 +107,36 @@ fn build(out_dir: impl AsRef<Path>) {
     libraw.flag_if_supported("-Wno-unused-result");
     libraw.flag_if_supported("-Wno-format-overflow");
 
-    // thread safety
-    libraw.flag("-pthread");
+    let wasm = env::var("CARGO_CFG_TARGET_ARCH").is_ok_and(|arch| arch == "wasm32");
+    if wasm {
+        // LibRaw reports errors by throwing, emscripten only catches when asked to
+        if env::var("CARGO_CFG_TARGET_OS").is_ok_and(|os| os == "emscripten") {
+            libraw.flag("-fexceptions");
+            println!("cargo:rustc-link-arg=-fexceptions");
+        }
+    } else {
+        // thread safety
+        libraw.flag("-pthread");
+    }
+    if env::var_os("CARGO_FEATURE_OPENMP").is_some() {
+        if wasm {
+            panic!("the openmp feature is not supported on wasm32");
+        }
+        libraw.flag("-fopenmp");
+        if compiler.is_like_clang() {
+            println!("cargo:rustc-link-lib=omp");
+        } else {
+            println!("cargo:rustc-link-lib=gomp");
+        }
+    }
+    if env::var_os("CARGO_FEATURE_GPR").is_some() {
+        gpr(&mut libraw);
+    }
     libraw.static_flag(true);
     libraw.compile("raw");
+    build_info(&compiler);
 
+    println!("cargo:rerun-if-changed=src/glue.cpp");
     println!(
         "cargo:rustc-link-search=native={}",
         out_dir.as_ref().join("lib").display()
@@ -115,6 +144,96 @@ fn build(out_dir: impl AsRef<Path>) {
     println!("cargo:rustc-link-lib=static=raw");
 }
 
+// GoPro's VC-5 codec comes with the GPR SDK (github.com/gopro/gpr), which
+// bundles the Adobe DNG SDK revision LibRaw reads it through. RSRAW_GPR_DIR
+// is the SDK checkout, RSRAW_GPR_LIB_DIR where its cmake build put the static libraries.
+fn gpr(libraw: &mut cc::Build) {
+    let var = |name: &str| {
+        println!("cargo:rerun-if-env-changed={name}");
+        env::var_os(name)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| panic!("the gpr feature needs {name}"))
+    };
+    let (dir, lib_dir) = (var("RSRAW_GPR_DIR"), var("RSRAW_GPR_LIB_DIR"));
+    libraw.define("USE_DNGSDK", None);
+    component("DNG_SDK", &dng_sdk_version(&dir.join("source/lib/dng_sdk")));
+    libraw.define("USE_GPRSDK", None);
+    libraw.define("GPR_READING", "1");
+    libraw.define("GPR_WRITING", "0");
+    for include in [
+        "common/private",
+        "common/public",
+        "dng_sdk",
+        "gpr_sdk/private",
+        "gpr_sdk/public",
+        "vc5_common",
+        "vc5_decoder",
+        "xmp_core",
+    ] {
+        libraw.include(dir.join("source/lib").join(include));
+    }
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    for lib in [
+        "gpr_sdk",
+        "vc5_decoder",
+        "vc5_common",
+        "dng_sdk",
+        "xmp_core",
+        "expat_lib",
+        "md5_lib",
+        "common",
+    ] {
+        println!("cargo:rustc-link-lib=static={lib}");
+    }
+}
+
+// The newest DNG version the SDK handles, which is how Adobe numbers the SDK
+// releases: `dngVersion_Current = dngVersion_1_4_0_0` reads "1.4.0.0".
+fn dng_sdk_version(dir: &Path) -> String {
+    let path = dir.join("dng_tag_values.h");
+    println!("cargo:rerun-if-changed={}", path.display());
+    let header = fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("can't read {}: {err}", path.display()));
+    header
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(name, _)| name.trim_end().ends_with("dngVersion_Current"))
+        .and_then(|(_, value)| value.trim().strip_prefix("dngVersion_"))
+        .map(|version| version.trim_end_matches(';').trim().replace('_', "."))
+        .expect("dngVersion_Current in dng_tag_values.h")
+}
+
+// an optional component compiled into LibRaw, read back by the matching
+// `build_info` constant in lib.rs, which is `None` for those left out
+fn component(name: &str, version: &str) {
+    println!("cargo:rustc-env=RSRAW_SYS_{name}_VERSION={version}");
+}
+
+// read back by the `build_info` constants in lib.rs
+fn build_info(compiler: &cc::Tool) {
+    let header = fs::read_to_string("LibRaw/libraw/libraw_version.h").unwrap();
+    let part = |name: &str| {
+        header
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("#define LIBRAW_{name}_VERSION ")))
+            .map(str::trim)
+            .expect("version define")
+            .to_owned()
+    };
+    let version = [part("MAJOR"), part("MINOR"), part("PATCH")].join(".");
+    println!("cargo:rustc-env=RSRAW_SYS_LIBRAW_VERSION={version}");
+    let name = compiler.path().file_name().unwrap_or_default();
+    println!(
+        "cargo:rustc-env=RSRAW_SYS_COMPILER={}",
+        name.to_string_lossy()
+    );
+    println!(
+        "cargo:rustc-env=RSRAW_SYS_TARGET={}",
+        env::var("TARGET").unwrap()
+    );
+    println!("cargo:rerun-if-changed=LibRaw/libraw/libraw_version.h");
+}
+
 fn bindings(out_dir: impl AsRef<Path>) {
     let path = out_dir.as_ref().join("bindings.rs");
     if path.exists() {
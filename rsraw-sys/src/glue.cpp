@@ -0,0 +1,368 @@
+// Bits of the C++ API that libraw_c_api.cpp doesn't expose.
+#include <string.h>
+#include "libraw/libraw.h"
+#ifdef USE_GPRSDK
+#include "dng_host.h"
+#endif
+
+// Reads LibRaw's protected decoder state. A pointer to member named through
+// a derived class may be applied to any LibRaw object.
+struct RsrawInternals : LibRaw
+{
+  static const unpacker_data_t &unpacker(LibRaw *ip)
+  {
+    return (ip->*(&RsrawInternals::libraw_internal_data)).unpacker_data;
+  }
+
+  static void *&dng_host(LibRaw *ip)
+  {
+    return ip->*(&RsrawInternals::dnghost);
+  }
+
+  static LibRaw_abstract_datastream *&input(LibRaw *ip)
+  {
+    return (ip->*(&RsrawInternals::libraw_internal_data)).internal_data.input;
+  }
+
+  static int &input_internal(LibRaw *ip)
+  {
+    return (ip->*(&RsrawInternals::libraw_internal_data))
+        .internal_data.input_internal;
+  }
+
+  static libraw_internal_output_params_t &output_params(LibRaw *ip)
+  {
+    return (ip->*(&RsrawInternals::libraw_internal_data))
+        .internal_output_params;
+  }
+};
+
+// A datastream reading through a callback, for sources that aren't a buffer
+// or a file. Decoders mostly read a few bytes at a time and seek around the
+// headers, so reads go through a window that keeps the callback calls coarse.
+typedef long long (*rsraw_read_at_t)(void *ctx, long long offset, void *buf,
+                                     size_t len);
+
+class RsrawDatastream : public LibRaw_abstract_datastream
+{
+public:
+  RsrawDatastream(void *ctx, rsraw_read_at_t read_at, INT64 size)
+      : ctx(ctx), read_at(read_at), len(size), pos(0), start(0), filled(0)
+  {
+  }
+  int valid() { return read_at != NULL; }
+  INT64 tell() { return pos; }
+  INT64 size() { return len; }
+  int eof() { return pos >= len; }
+
+  int seek(INT64 o, int whence)
+  {
+    INT64 base = whence == SEEK_CUR ? pos : whence == SEEK_END ? len : 0;
+    if (whence != SEEK_SET && whence != SEEK_CUR && whence != SEEK_END)
+      return -1;
+    // clamped like the buffer datastream does
+    pos = base + o < 0 ? 0 : base + o > len ? len : base + o;
+    return 0;
+  }
+
+  int read(void *ptr, size_t size, size_t nmemb)
+  {
+    if (size == 0)
+      return 0;
+    unsigned char *dest = (unsigned char *)ptr;
+    size_t want = size * nmemb, done = 0;
+    while (done < want && pos < len)
+    {
+      if (pos < start || pos >= start + (INT64)filled)
+      {
+        // large reads go straight to the callback
+        if (want - done >= sizeof(window))
+        {
+          long long got = read_at(ctx, pos, dest + done, want - done);
+          if (got <= 0)
+            break;
+          done += got;
+          pos += got;
+          continue;
+        }
+        if (!fill())
+          break;
+      }
+      size_t n = filled - (size_t)(pos - start);
+      if (n > want - done)
+        n = want - done;
+      memcpy(dest + done, window + (pos - start), n);
+      done += n;
+      pos += n;
+    }
+    return int(done / size);
+  }
+
+  int get_char()
+  {
+    unsigned char c;
+    return read(&c, 1, 1) == 1 ? c : -1;
+  }
+
+  char *gets(char *s, int sz)
+  {
+    if (sz < 1 || pos >= len)
+      return NULL;
+    int i = 0;
+    while (i < sz - 1)
+    {
+      int c = get_char();
+      if (c < 0)
+        break;
+      s[i++] = (char)c;
+      if (c == '\n')
+        break;
+    }
+    s[i] = 0;
+    return s;
+  }
+
+  // the buffer datastream's way: scan what follows, then step past the token
+  int scanf_one(const char *fmt, void *val)
+  {
+    char text[64];
+    INT64 at = pos;
+    int n = read(text, 1, sizeof(text) - 1);
+    text[n] = 0;
+    int res = n > 0 ? sscanf(text, fmt, val) : 0;
+    int skip = 0;
+    if (res > 0)
+      while (skip < n - 1)
+      {
+        skip++;
+        if (text[skip] == 0 || text[skip] == ' ' || text[skip] == '\t' ||
+            text[skip] == '\n' || skip > 24)
+          break;
+      }
+    pos = at + skip;
+    return res;
+  }
+
+private:
+  bool fill()
+  {
+    long long got = read_at(ctx, pos, window, sizeof(window));
+    if (got <= 0)
+      return false;
+    start = pos;
+    filled = (size_t)got;
+    return true;
+  }
+
+  void *ctx;
+  rsraw_read_at_t read_at;
+  INT64 len, pos, start;
+  size_t filled;
+  unsigned char window[1 << 16];
+};
+
+extern "C"
+{
+  void rsraw_get_mem_image_format(libraw_data_t *lr, int *width, int *height,
+                                  int *colors, int *bps)
+  {
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    ip->get_mem_image_format(width, height, colors, bps);
+  }
+
+  int rsraw_copy_mem_image(libraw_data_t *lr, void *scan0, int stride,
+                           int bgr)
+  {
+    if (!lr)
+      return EINVAL;
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    try
+    {
+      return ip->copy_mem_image(scan0, stride, bgr);
+    }
+    catch (...)
+    {
+      return LIBRAW_UNSPECIFIED_ERROR;
+    }
+  }
+
+  int rsraw_error_count(libraw_data_t *lr)
+  {
+    if (!lr)
+      return 0;
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    return ip->error_count();
+  }
+
+  // where the raw image data starts and how long it is, 0 if unknown
+  void rsraw_data_segment(libraw_data_t *lr, long long *offset,
+                          unsigned *size)
+  {
+    const unpacker_data_t &unpacker =
+        RsrawInternals::unpacker((LibRaw *)lr->parent_class);
+    *offset = unpacker.data_offset;
+    *size = unpacker.data_size;
+  }
+
+  // the Fuji width, shrink and such raw2image_start and
+  // adjust_sizes_info_only change in place
+  libraw_internal_output_params_t *rsraw_output_params(libraw_data_t *lr)
+  {
+    return &RsrawInternals::output_params((LibRaw *)lr->parent_class);
+  }
+
+  // DNG: where the raw IFD's OpcodeList2 starts, other formats reuse the field
+  long long rsraw_meta_offset(libraw_data_t *lr)
+  {
+    return RsrawInternals::unpacker((LibRaw *)lr->parent_class).meta_offset;
+  }
+
+  // CR3 track i from its moov box: 1 raw, 2 jpeg, 3 CTMD timed metadata and
+  // 0 past the last one. Offset and size are those of the selected frame.
+  unsigned rsraw_crx_track(libraw_data_t *lr, int i, long long *offset,
+                           unsigned *size)
+  {
+    const unpacker_data_t &unpacker =
+        RsrawInternals::unpacker((LibRaw *)lr->parent_class);
+    if (i < 0 || i > unpacker.crx_track_count || i >= LIBRAW_CRXTRACKS_MAXCOUNT)
+      return 0;
+    const crx_data_header_t &track = unpacker.crx_header[i];
+    *offset = track.MediaOffset;
+    *size = track.MediaSize;
+    return track.MediaType;
+  }
+
+  // reads up to len bytes at offset of the opened file, -1 without one.
+  // Decoders seek before every read, so moving the stream between calls is fine.
+  int rsraw_read_at(libraw_data_t *lr, long long offset, void *buf,
+                    unsigned len)
+  {
+    LibRaw_abstract_datastream *input =
+        RsrawInternals::input((LibRaw *)lr->parent_class);
+    if (!input || input->seek(offset, SEEK_SET) != 0)
+      return -1;
+    return input->read(buf, 1, len);
+  }
+
+  // LibRaw doesn't own the stream, free it after libraw_close
+  void *rsraw_datastream_new(void *ctx, rsraw_read_at_t read_at,
+                             long long size)
+  {
+    try
+    {
+      return new RsrawDatastream(ctx, read_at, size);
+    }
+    catch (...)
+    {
+      return NULL;
+    }
+  }
+
+  void rsraw_datastream_free(void *stream)
+  {
+    delete static_cast<RsrawDatastream *>(stream);
+  }
+
+  int rsraw_open_datastream(libraw_data_t *lr, void *stream)
+  {
+    if (!lr)
+      return EINVAL;
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    return ip->open_datastream(static_cast<RsrawDatastream *>(stream));
+  }
+
+  // Identifies the opened file again, e.g. after a change of shot_select.
+  // open_datastream recycles first, which would free a stream LibRaw made
+  // for a buffer, so ownership is dropped for the reopen and taken back.
+  // identify reads the byte order before it seeks, the stream has to be
+  // back at the start like a fresh one.
+  int rsraw_reopen(libraw_data_t *lr)
+  {
+    if (!lr)
+      return EINVAL;
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    LibRaw_abstract_datastream *input = RsrawInternals::input(ip);
+    if (!input)
+      return LIBRAW_OUT_OF_ORDER_CALL;
+    int owned = RsrawInternals::input_internal(ip);
+    RsrawInternals::input_internal(ip) = 0;
+    input->seek(0, SEEK_SET);
+    int ret = ip->open_datastream(input);
+    if (RsrawInternals::input(ip) == input)
+      RsrawInternals::input_internal(ip) = owned;
+    else if (owned)
+      delete input;
+    return ret;
+  }
+
+  // Points the opened image at another buffer holding the same file, e.g.
+  // one padded past a cut, at the same position. The old stream is freed if
+  // LibRaw made it, the new one on recycle like open_buffer's.
+  int rsraw_swap_buffer(libraw_data_t *lr, const void *buf, size_t len)
+  {
+    if (!lr)
+      return EINVAL;
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    LibRaw_abstract_datastream *&input = RsrawInternals::input(ip);
+    if (!input)
+      return LIBRAW_OUT_OF_ORDER_CALL;
+    LibRaw_buffer_datastream *stream;
+    try
+    {
+      stream = new LibRaw_buffer_datastream(buf, len);
+    }
+    catch (const std::bad_alloc &)
+    {
+      return LIBRAW_UNSUFFICIENT_MEMORY;
+    }
+    if (!stream->valid())
+    {
+      delete stream;
+      return LIBRAW_IO_ERROR;
+    }
+    stream->seek(input->tell(), SEEK_SET);
+    if (RsrawInternals::input_internal(ip))
+      delete input;
+    input = stream;
+    RsrawInternals::input_internal(ip) = 1;
+    return LIBRAW_SUCCESS;
+  }
+
+  // GPR files only decode through the DNG SDK, which needs a host object per
+  // decoder. Call before opening, returns 0 when built without the gpr feature.
+  int rsraw_gpr_attach(libraw_data_t *lr)
+  {
+#ifdef USE_GPRSDK
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    try
+    {
+      ip->set_dng_host(new dng_host());
+    }
+    catch (...)
+    {
+      return 0;
+    }
+    // only VC-5 goes through the SDK, other DNGs keep LibRaw's own decoders
+    lr->rawparams.use_dngsdk = 0;
+    return 1;
+#else
+    (void)lr;
+    return 0;
+#endif
+  }
+
+  // frees the host again, before libraw_close
+  void rsraw_gpr_detach(libraw_data_t *lr)
+  {
+#ifdef USE_GPRSDK
+    LibRaw *ip = (LibRaw *)lr->parent_class;
+    // the negative and image recycle() frees were made by the host
+    ip->recycle();
+    void *&host = RsrawInternals::dng_host(ip);
+    delete static_cast<dng_host *>(host);
+    host = NULL;
+#else
+    (void)lr;
+#endif
+  }
+}
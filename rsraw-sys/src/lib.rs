@@ -12,3 +12,86 @@ mod c_api {
 }
 
 pub use c_api::*;
+
+// src/glue.cpp
+extern "C" {
+    pub fn rsraw_get_mem_image_format(
+        lr: *mut libraw_data_t,
+        width: *mut libc::c_int,
+        height: *mut libc::c_int,
+        colors: *mut libc::c_int,
+        bps: *mut libc::c_int,
+    );
+    pub fn rsraw_copy_mem_image(
+        lr: *mut libraw_data_t,
+        scan0: *mut libc::c_void,
+        stride: libc::c_int,
+        bgr: libc::c_int,
+    ) -> libc::c_int;
+    pub fn rsraw_error_count(lr: *mut libraw_data_t) -> libc::c_int;
+    pub fn rsraw_data_segment(
+        lr: *mut libraw_data_t,
+        offset: *mut libc::c_longlong,
+        size: *mut libc::c_uint,
+    );
+    pub fn rsraw_meta_offset(lr: *mut libraw_data_t) -> libc::c_longlong;
+    pub fn rsraw_output_params(lr: *mut libraw_data_t) -> *mut libraw_internal_output_params_t;
+    pub fn rsraw_read_at(
+        lr: *mut libraw_data_t,
+        offset: libc::c_longlong,
+        buf: *mut libc::c_void,
+        len: libc::c_uint,
+    ) -> libc::c_int;
+    pub fn rsraw_crx_track(
+        lr: *mut libraw_data_t,
+        i: libc::c_int,
+        offset: *mut libc::c_longlong,
+        size: *mut libc::c_uint,
+    ) -> libc::c_uint;
+    pub fn rsraw_datastream_new(
+        ctx: *mut libc::c_void,
+        read_at: Option<
+            unsafe extern "C" fn(
+                ctx: *mut libc::c_void,
+                offset: libc::c_longlong,
+                buf: *mut libc::c_void,
+                len: libc::size_t,
+            ) -> libc::c_longlong,
+        >,
+        size: libc::c_longlong,
+    ) -> *mut libc::c_void;
+    pub fn rsraw_datastream_free(stream: *mut libc::c_void);
+    pub fn rsraw_open_datastream(
+        lr: *mut libraw_data_t,
+        stream: *mut libc::c_void,
+    ) -> libc::c_int;
+    pub fn rsraw_reopen(lr: *mut libraw_data_t) -> libc::c_int;
+    pub fn rsraw_swap_buffer(
+        lr: *mut libraw_data_t,
+        buf: *const libc::c_void,
+        len: libc::size_t,
+    ) -> libc::c_int;
+    pub fn rsraw_gpr_attach(lr: *mut libraw_data_t) -> libc::c_int;
+    pub fn rsraw_gpr_detach(lr: *mut libraw_data_t);
+}
+
+// What build.rs compiled into the static library. The optional components
+// carry the version build.rs found for them and are `None` when it left them
+// out. zlib, libjpeg and rawspeed aren't vendored, the DNG SDK only comes in
+// with the GPR SDK.
+pub mod build_info {
+    pub const LIBRAW_VERSION: &str = env!("RSRAW_SYS_LIBRAW_VERSION");
+    pub const COMPILER: &str = env!("RSRAW_SYS_COMPILER");
+    pub const TARGET: &str = env!("RSRAW_SYS_TARGET");
+    pub const OPENMP: bool = cfg!(feature = "openmp");
+    pub const ZLIB: Option<&str> = option_env!("RSRAW_SYS_ZLIB_VERSION");
+    pub const JPEG: Option<&str> = option_env!("RSRAW_SYS_JPEG_VERSION");
+    pub const RAWSPEED: Option<&str> = option_env!("RSRAW_SYS_RAWSPEED_VERSION");
+    pub const DNG_SDK: Option<&str> = option_env!("RSRAW_SYS_DNG_SDK_VERSION");
+}
+
+#[cfg(feature = "openmp")]
+extern "C" {
+    pub fn omp_set_num_threads(num_threads: libc::c_int);
+    pub fn omp_get_max_threads() -> libc::c_int;
+}
@@ -0,0 +1,67 @@
+/* C interface of rsraw, built with the `capi` feature. */
+#ifndef RSRAW_H
+#define RSRAW_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RsrawImage RsrawImage;
+
+typedef struct {
+  int bits; /* 8 or 16 */
+  bool half_size;
+  bool use_camera_wb;
+  bool use_auto_wb;
+  bool no_auto_bright;
+} RsrawProcessParams;
+
+/* interleaved, rows tightly packed, 16 bit samples in native byte order */
+typedef struct {
+  uint32_t width;
+  uint32_t height;
+  uint16_t colors;
+  uint16_t bits;
+  uint8_t *data;
+  size_t len;
+} RsrawBitmap;
+
+/* Functions returning int give 0 on success or a LibRaw error code. None of
+ * them unwind into the caller: an internal panic returns
+ * LIBRAW_UNSPECIFIED_ERROR, or NULL where a pointer is returned. */
+
+/* data must point to len readable bytes and out to writable storage. Copies
+ * the buffer, it can be freed once this returns. */
+int rsraw_open(const uint8_t *data, size_t len, RsrawImage **out);
+/* image must come from rsraw_open and is not to be used afterwards, NULL is
+ * ignored */
+void rsraw_close(RsrawImage *image);
+
+/* image must be a live handle from rsraw_open. The metadata as a JSON
+ * object, NULL on failure, free with rsraw_string_free. */
+char *rsraw_info_json(const RsrawImage *image);
+/* s must come from this library and is not to be used afterwards, NULL is
+ * ignored */
+void rsraw_string_free(char *s);
+
+/* image must be a live handle from rsraw_open and out writable. Unpacks if
+ * needed and develops the image into out, free it with rsraw_bitmap_free.
+ * params may be NULL for 8 bit output with LibRaw's defaults. */
+int rsraw_process(RsrawImage *image, const RsrawProcessParams *params,
+                  RsrawBitmap *out);
+/* bitmap must have been filled by rsraw_process, NULL is ignored. Its data
+ * pointer is cleared, so a second free is harmless. */
+void rsraw_bitmap_free(RsrawBitmap *bitmap);
+
+/* LibRaw's description of an error code, a static string */
+const char *rsraw_strerror(int code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RSRAW_H */
@@ -0,0 +1,93 @@
+use crate::{version, Metrics, ProcessParams, RawImage};
+
+// What produced an output, to store next to it for reproducible and
+// forensic work: the library versions, the camera and decoder, the settings
+// LibRaw last processed with and everything that went wrong on the way.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProcessingLog {
+    pub rsraw_version: String,
+    pub libraw_version: String,
+    pub make: String,
+    pub model: String,
+    // no demosaic ran, see `RawImage::is_linear_raw`
+    pub linear_raw: bool,
+    // the frame of a file with several, see `RawImage::select_shot`
+    pub shot: u32,
+    // as LibRaw holds them after the last process, which may have adjusted
+    // some, `None` when the image hasn't been processed
+    pub params: Option<ProcessParams>,
+    // the part of the visible area the last process developed (left, top,
+    // width, height), the params' crop, a region's or `set_auto_crop`'s.
+    // `None` for all of it.
+    pub cropbox: Option<[u32; 4]>,
+    pub bits: Option<u32>,
+    // names as in `Warnings::names`
+    pub warnings: Vec<String>,
+    pub data_errors: u32,
+    // timings, memory and the decoder's name
+    pub metrics: Metrics,
+}
+
+impl RawImage {
+    // A snapshot, take it after the last call that went into the output.
+    // Every `ProcessedImage` carries the one taken as it was made, see
+    // `ProcessedImage::log`.
+    pub fn processing_log(&self) -> ProcessingLog {
+        let processed = self.metrics().process.is_some();
+        let params = &self.as_ref().params;
+        ProcessingLog {
+            rsraw_version: env!("CARGO_PKG_VERSION").into(),
+            libraw_version: version().into(),
+            make: self.make().into_owned(),
+            model: self.model().into_owned(),
+            linear_raw: self.is_linear_raw(),
+            shot: self.as_ref().rawparams.shot_select,
+            params: processed.then(|| ProcessParams::from_libraw(params, &self.as_ref().rawparams)),
+            cropbox: self.cropbox.filter(|_| processed),
+            bits: processed.then_some(params.output_bps as _),
+            warnings: self.warnings().names().map(str::to_owned).collect(),
+            data_errors: self.data_errors().count,
+            metrics: self.metrics().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, BIT_DEPTH_16};
+
+    #[test]
+    fn test_processing_log() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let log = raw_image.processing_log();
+        assert_eq!((log.params, log.bits), (None, None));
+        assert_eq!((log.model.as_str(), log.linear_raw), ("Z 8", false));
+        assert!(!log.libraw_version.is_empty() && !log.metrics.decoder.is_empty());
+
+        let params = ProcessParams {
+            use_camera_wb: true,
+            quality: Some(0),
+            gamma: Some([1.0, 1.0]),
+            ..Default::default()
+        };
+        let image = raw_image
+            .process_with::<BIT_DEPTH_16>(&params)
+            .expect("processed");
+        let log = raw_image.processing_log();
+        assert_eq!(log.params, Some(params.clone()));
+        assert_eq!((log.bits, log.shot, log.cropbox), (Some(16), 0, None));
+        assert!(log.metrics.unpack.is_some() && log.metrics.process.is_some());
+        assert_eq!(image.log(), &log);
+
+        // the image keeps its own as the raw image moves on
+        let other = ProcessParams::new().half_size(true);
+        let half = raw_image
+            .process_with::<BIT_DEPTH_16>(&other)
+            .expect("processed");
+        assert_eq!(half.log().params, Some(other));
+        assert_eq!(image.log().params, Some(params));
+    }
+}
@@ -0,0 +1,290 @@
+use avif_serialize::{constants, Aviffy};
+use rav1e::prelude::{
+    ChromaSampling, ColorDescription, ColorPrimaries, Config, Context, EncoderConfig,
+    EncoderStatus, FrameType, MatrixCoefficients, Pixel, PixelRange, TransferCharacteristics,
+};
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    ImageLayout, ProcessedImage,
+};
+
+// SDR output is tagged as what LibRaw writes by default, sRGB primaries and
+// the BT.709 curve. HDR output is re-encoded to BT.2020 with a PQ or HLG
+// curve, which needs at least 10 bits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AvifOptions {
+    // 0 to 100
+    pub quality: u8,
+    // rav1e's speed preset, 0 (slowest, smallest) to 10
+    pub speed: u8,
+    // 8, 10 or 12
+    pub depth: u8,
+    pub hdr: Option<AvifHdr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AvifHdr {
+    pub transfer: HdrTransfer,
+    // The brightness the developed image's white ends up at, in cd/m². 203 is
+    // reference white (ITU-R BT.2408), anything brighter moves highlights a
+    // darker develop kept below clipping up into the display's headroom.
+    pub white_nits: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HdrTransfer {
+    Pq,
+    Hlg,
+}
+
+impl Default for AvifOptions {
+    fn default() -> Self {
+        Self {
+            quality: 80,
+            speed: 6,
+            depth: 8,
+            hdr: None,
+        }
+    }
+}
+
+impl Default for AvifHdr {
+    fn default() -> Self {
+        Self {
+            transfer: HdrTransfer::Pq,
+            white_nits: 203.0,
+        }
+    }
+}
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // An AVIF file, 4:4:4 and full range. Only RGB images can be encoded,
+    // anything else is `Error::NotImplemented` like depths other than 8, 10
+    // and 12 or HDR at 8 bits.
+    pub fn to_avif(&self, options: &AvifOptions) -> Result<Vec<u8>> {
+        let layout = ImageLayout {
+            width: self.width(),
+            height: self.height(),
+            colors: self.colors(),
+            bits: D as _,
+        };
+        encode(&layout, self.bytes(), options)
+    }
+}
+
+pub(crate) fn encode(layout: &ImageLayout, data: &[u8], options: &AvifOptions) -> Result<Vec<u8>> {
+    let supported = match options.hdr {
+        Some(_) => matches!(options.depth, 10 | 12),
+        None => matches!(options.depth, 8 | 10 | 12),
+    };
+    if !supported || layout.colors != 3 || data.len() < layout.len() {
+        return Err(Error::NotImplemented);
+    }
+    let color = color(options.hdr);
+    let av1 = match options.depth {
+        8 => encode_av1::<u8>(layout, data, options, color)?,
+        _ => encode_av1::<u16>(layout, data, options, color)?,
+    };
+    let mut avif = Aviffy::new();
+    avif.set_full_color_range(true)
+        .set_color_primaries(match color.color_primaries {
+            ColorPrimaries::BT2020 => constants::ColorPrimaries::Bt2020,
+            _ => constants::ColorPrimaries::Bt709,
+        })
+        .set_transfer_characteristics(match color.transfer_characteristics {
+            TransferCharacteristics::SMPTE2084 => constants::TransferCharacteristics::Smpte2084,
+            TransferCharacteristics::HLG => constants::TransferCharacteristics::Hlg,
+            _ => constants::TransferCharacteristics::Bt709,
+        })
+        .set_matrix_coefficients(match color.matrix_coefficients {
+            MatrixCoefficients::BT2020NCL => constants::MatrixCoefficients::Bt2020Ncl,
+            _ => constants::MatrixCoefficients::Bt709,
+        });
+    Ok(avif.to_vec(&av1, None, layout.width, layout.height, options.depth))
+}
+
+fn color(hdr: Option<AvifHdr>) -> ColorDescription {
+    match hdr {
+        None => ColorDescription {
+            color_primaries: ColorPrimaries::BT709,
+            transfer_characteristics: TransferCharacteristics::BT709,
+            matrix_coefficients: MatrixCoefficients::BT709,
+        },
+        Some(hdr) => ColorDescription {
+            color_primaries: ColorPrimaries::BT2020,
+            transfer_characteristics: match hdr.transfer {
+                HdrTransfer::Pq => TransferCharacteristics::SMPTE2084,
+                HdrTransfer::Hlg => TransferCharacteristics::HLG,
+            },
+            matrix_coefficients: MatrixCoefficients::BT2020NCL,
+        },
+    }
+}
+
+fn encode_av1<P: Pixel>(
+    layout: &ImageLayout,
+    data: &[u8],
+    options: &AvifOptions,
+    color: ColorDescription,
+) -> Result<Vec<u8>> {
+    let (width, height) = (layout.width as usize, layout.height as usize);
+    let quantizer = (255 - options.quality.min(100) as usize * 255 / 100).max(1);
+    let config = EncoderConfig {
+        width,
+        height,
+        bit_depth: options.depth as _,
+        chroma_sampling: ChromaSampling::Cs444,
+        pixel_range: PixelRange::Full,
+        color_description: Some(color),
+        still_picture: true,
+        quantizer,
+        min_quantizer: quantizer as _,
+        ..EncoderConfig::with_speed_preset(options.speed.min(10))
+    };
+    let mut ctx: Context<P> = Config::new()
+        .with_encoder_config(config)
+        .new_context()
+        .map_err(|_| Error::NotImplemented)?;
+
+    let max = ((1u32 << options.depth) - 1) as f32;
+    let (kr, kb) = match color.matrix_coefficients {
+        MatrixCoefficients::BT2020NCL => (0.2627, 0.0593),
+        _ => (0.2126, 0.0722),
+    };
+    let mut frame = ctx.new_frame();
+    let [y, u, v] = &mut frame.planes;
+    let mut y = y.mut_slice(Default::default());
+    let mut u = u.mut_slice(Default::default());
+    let mut v = v.mut_slice(Default::default());
+    let rows = y
+        .rows_iter_mut()
+        .zip(u.rows_iter_mut())
+        .zip(v.rows_iter_mut());
+    for (row, ((y, u), v)) in rows.take(height).enumerate() {
+        for x in 0..width {
+            let i = (row * width + x) * 3;
+            let [r, g, b] = [i, i + 1, i + 2].map(|i| sample(data, i, layout.bits));
+            let [r, g, b] = match options.hdr {
+                Some(hdr) => to_hdr([r, g, b], hdr),
+                None => [r, g, b],
+            };
+            let luma = kr * r + (1.0 - kr - kb) * g + kb * b;
+            let cb = (b - luma) / (2.0 * (1.0 - kb)) + 0.5;
+            let cr = (r - luma) / (2.0 * (1.0 - kr)) + 0.5;
+            let quantize = |v: f32| P::cast_from((v.clamp(0.0, 1.0) * max).round() as u16);
+            y[x] = quantize(luma);
+            u[x] = quantize(cb);
+            v[x] = quantize(cr);
+        }
+    }
+    ctx.send_frame(frame).map_err(|_| Error::Unspecified)?;
+    ctx.flush();
+
+    let mut out = Vec::new();
+    loop {
+        match ctx.receive_packet() {
+            Ok(packet) if packet.frame_type == FrameType::KEY => out.extend(packet.data),
+            Ok(_) | Err(EncoderStatus::Encoded) => {}
+            Err(EncoderStatus::LimitReached | EncoderStatus::NeedMoreData) => break,
+            Err(_) => return Err(Error::Unspecified),
+        }
+    }
+    Ok(out)
+}
+
+// normalized to 0..1, 16 bit samples are in native byte order
+fn sample(data: &[u8], i: usize, bits: u16) -> f32 {
+    match bits {
+        8 => data[i] as f32 / 255.0,
+        _ => u16::from_ne_bytes([data[i * 2], data[i * 2 + 1]]) as f32 / 65535.0,
+    }
+}
+
+// LibRaw's BT.709 coded sRGB to PQ or HLG coded BT.2020
+fn to_hdr(rgb: [f32; 3], hdr: AvifHdr) -> [f32; 3] {
+    const BT709_TO_BT2020: [[f32; 3]; 3] = [
+        [0.6274, 0.3293, 0.0433],
+        [0.0691, 0.9195, 0.0114],
+        [0.0164, 0.0880, 0.8956],
+    ];
+    let linear = rgb.map(|v| match v {
+        v if v < 0.081 => v / 4.5,
+        v => ((v + 0.099) / 1.099).powf(1.0 / 0.45),
+    });
+    BT709_TO_BT2020.map(|m| {
+        let v = m[0] * linear[0] + m[1] * linear[1] + m[2] * linear[2];
+        match hdr.transfer {
+            HdrTransfer::Pq => pq(v * hdr.white_nits / 10000.0),
+            // scene light relative to the 1000 cd/m² nominal peak, through
+            // the inverse of the 1.2 system gamma
+            HdrTransfer::Hlg => hlg((v * hdr.white_nits / 1000.0).max(0.0).powf(1.0 / 1.2)),
+        }
+    })
+}
+
+// SMPTE ST 2084, `v` as a fraction of 10000 cd/m²
+fn pq(v: f32) -> f32 {
+    const M1: f32 = 0.159_301_76;
+    const M2: f32 = 78.843_75;
+    const C1: f32 = 0.835_937_5;
+    const C2: f32 = 18.851_563;
+    const C3: f32 = 18.687_5;
+    let p = v.clamp(0.0, 1.0).powf(M1);
+    ((C1 + C2 * p) / (1.0 + C3 * p)).powf(M2)
+}
+
+// ITU-R BT.2100 HLG OETF
+fn hlg(v: f32) -> f32 {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 0.284_668_92;
+    const C: f32 = 0.559_910_7;
+    let v = v.clamp(0.0, 1.0);
+    match v {
+        v if v <= 1.0 / 12.0 => (3.0 * v).sqrt(),
+        v => A * (12.0 * v - B).ln() + C,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_avif() {
+        let layout = ImageLayout {
+            width: 64,
+            height: 48,
+            colors: 3,
+            bits: 16,
+        };
+        let data: Vec<u8> = (0..layout.len() / 2)
+            .flat_map(|i| ((i * 37 % 65536) as u16).to_ne_bytes())
+            .collect();
+        let options = AvifOptions {
+            speed: 10,
+            ..Default::default()
+        };
+        for depth in [8, 10, 12] {
+            let avif = encode(&layout, &data, &AvifOptions { depth, ..options }).expect("avif");
+            assert_eq!(&avif[4..12], b"ftypavif");
+            assert!(avif.windows(4).any(|w| w == b"mdat"));
+        }
+        let hdr = AvifOptions {
+            depth: 10,
+            hdr: Some(AvifHdr::default()),
+            ..options
+        };
+        assert!(encode(&layout, &data, &hdr).is_ok());
+        assert!(matches!(
+            encode(&layout, &data, &AvifOptions { depth: 8, ..hdr }),
+            Err(Error::NotImplemented)
+        ));
+
+        // reference white lands where BT.2408 puts it
+        assert!((pq(203.0 / 10000.0) - 0.58).abs() < 0.01);
+        assert!((hlg((203.0f32 / 1000.0).powf(1.0 / 1.2)) - 0.75).abs() < 0.01);
+        assert_eq!(to_hdr([0.0; 3], AvifHdr::default())[0], pq(0.0));
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Condvar, Mutex,
+    },
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    FullRawInfo, ProcessParams, ProcessedImage, RawImage, ThumbInfo,
+};
+
+#[cfg(feature = "arrow")]
+mod arrow;
+
+#[cfg(feature = "arrow")]
+pub use arrow::to_arrow;
+#[cfg(feature = "parquet")]
+pub use arrow::write_parquet;
+
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "ari", "arw", "bay", "cap", "cr2", "cr3", "crw", "dcr", "dcs", "dng", "drf", "eip",
+    "erf", "fff", "gpr", "iiq", "k25", "kdc", "mdc", "mef", "mos", "mrw", "nef", "nrw", "orf",
+    "pef", "ptx", "pxn", "qtk", "raf", "raw", "rw2", "rwl", "rwz", "sr2", "srf", "srw", "sti",
+    "x3f",
+];
+
+#[derive(Debug)]
+pub struct BatchItem<T> {
+    pub path: PathBuf,
+    pub result: Result<T>,
+}
+
+// results are yielded in completion order, not in the order of the input paths
+pub struct BatchResults<T> {
+    results: Receiver<BatchItem<T>>,
+    _pool: rayon::ThreadPool,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexOptions {
+    pub concurrency: usize,
+    pub recursive: bool,
+    // `None` tries every file, otherwise matched case-insensitively
+    pub extensions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedRaw {
+    pub info: FullRawInfo,
+    pub best_thumb: Option<ThumbInfo>,
+}
+
+impl Default for IndexOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 0,
+            recursive: true,
+            extensions: Some(RAW_EXTENSIONS.iter().map(|ext| ext.to_string()).collect()),
+        }
+    }
+}
+
+impl<T> Iterator for BatchResults<T> {
+    type Item = BatchItem<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.results.recv().ok()
+    }
+}
+
+// concurrency of 0 uses one worker per logical core
+pub fn process<const D: BitDepth, P>(
+    paths: impl IntoIterator<Item = P>,
+    params: &ProcessParams,
+    concurrency: usize,
+) -> Result<BatchResults<ProcessedImage<D>>>
+where
+    P: AsRef<Path>,
+    ProcessedImage<D>: Send,
+{
+    process_budgeted(paths, params, concurrency, u64::MAX)
+}
+
+// like `process`, but workers wait until the estimated decode memory of all
+// in-flight files fits into `memory_budget` bytes. A file larger than the whole
+// budget still runs, just never alongside another one.
+pub fn process_budgeted<const D: BitDepth, P>(
+    paths: impl IntoIterator<Item = P>,
+    params: &ProcessParams,
+    concurrency: usize,
+    memory_budget: u64,
+) -> Result<BatchResults<ProcessedImage<D>>>
+where
+    P: AsRef<Path>,
+    ProcessedImage<D>: Send,
+{
+    let budget = Arc::new(MemoryBudget::new(memory_budget));
+    process_in(paths, params, concurrency, budget)
+}
+
+fn process_in<const D: BitDepth, P>(
+    paths: impl IntoIterator<Item = P>,
+    params: &ProcessParams,
+    concurrency: usize,
+    budget: Arc<MemoryBudget>,
+) -> Result<BatchResults<ProcessedImage<D>>>
+where
+    P: AsRef<Path>,
+    ProcessedImage<D>: Send,
+{
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|p| p.as_ref().to_path_buf())
+        .collect();
+    let params = params.clone();
+    let pool = thread_pool(concurrency)?;
+    let (tx, rx) = mpsc::channel();
+    pool.spawn(move || {
+        paths.into_par_iter().for_each_with(tx, |tx, path| {
+            let result = process_file(&path, &params, &budget);
+            let _ = tx.send(BatchItem { path, result });
+        });
+    });
+    Ok(BatchResults {
+        results: rx,
+        _pool: pool,
+    })
+}
+
+pub fn index(dir: impl AsRef<Path>, options: &IndexOptions) -> Result<BatchResults<IndexedRaw>> {
+    let walk = Walk::new(dir.as_ref().to_path_buf(), options.recursive);
+    let extensions = options.extensions.clone();
+    let pool = thread_pool(options.concurrency)?;
+    let (tx, rx) = mpsc::channel();
+    pool.spawn(move || {
+        walk.filter(|entry| match (entry, &extensions) {
+            (Ok(path), Some(extensions)) => has_extension(path, extensions),
+            _ => true,
+        })
+        .par_bridge()
+        .for_each_with(tx, |tx, entry| {
+            let item = match entry {
+                Ok(path) => BatchItem {
+                    result: index_file(&path),
+                    path,
+                },
+                Err((path, err)) => BatchItem {
+                    path,
+                    result: Err(err.into()),
+                },
+            };
+            let _ = tx.send(item);
+        });
+    });
+    Ok(BatchResults {
+        results: rx,
+        _pool: pool,
+    })
+}
+
+// open only reads the headers, which is all the index needs
+fn index_file(path: &Path) -> Result<IndexedRaw> {
+    let raw_image = RawImage::open_file(path)?;
+    Ok(IndexedRaw {
+        info: raw_image.full_info(),
+        best_thumb: raw_image.best_thumb_info(),
+    })
+}
+
+pub(crate) fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+}
+
+// peak working set of unpack + process: the raw mosaic, LibRaw's 4 channel
+// 16 bit working image and the output bitmap. The file isn't counted, open
+// and unpack read it as they go.
+pub fn estimate_process_memory(
+    raw_image: &RawImage,
+    params: &ProcessParams,
+    bit_depth: BitDepth,
+) -> u64 {
+    let sizes = &raw_image.as_ref().sizes;
+    let raw = sizes.raw_width as u64 * sizes.raw_height as u64 * 2;
+    let (mut width, mut height) = (sizes.width as u64, sizes.height as u64);
+    if params.half_size {
+        width /= 2;
+        height /= 2;
+    }
+    let image = width * height * 4 * 2;
+    let output = width * height * 3 * (bit_depth as u64 / 8);
+    raw + image + output
+}
+
+pub(crate) fn process_file<const D: BitDepth>(
+    path: &Path,
+    params: &ProcessParams,
+    budget: &MemoryBudget,
+) -> Result<ProcessedImage<D>> {
+    // open only reads the headers, the decode waits until the budget has
+    // room for it
+    let mut raw_image = RawImage::open_file(path)?;
+    let estimate = estimate_process_memory(&raw_image, params, D);
+    let _reservation = budget.acquire(estimate);
+    raw_image.unpack()?;
+    Ok(raw_image.process_with::<D>(params)?)
+}
+
+pub(crate) struct MemoryBudget {
+    limit: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+    // the most that was ever reserved at once
+    #[cfg(test)]
+    peak: std::sync::atomic::AtomicU64,
+}
+
+struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+            #[cfg(test)]
+            peak: Default::default(),
+        }
+    }
+
+    fn acquire(&self, bytes: u64) -> Reservation<'_> {
+        let mut used = self.used.lock().unwrap_or_else(|e| e.into_inner());
+        while *used > 0 && used.saturating_add(bytes) > self.limit {
+            used = self.released.wait(used).unwrap_or_else(|e| e.into_inner());
+        }
+        *used += bytes;
+        #[cfg(test)]
+        self.peak
+            .fetch_max(*used, std::sync::atomic::Ordering::Relaxed);
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut used = self.budget.used.lock().unwrap_or_else(|e| e.into_inner());
+        *used -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+pub(crate) fn thread_pool(concurrency: usize) -> Result<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(concurrency)
+        .thread_name(|i| format!("rsraw-batch-{i}"))
+        .build()
+        .map_err(|_| Error::Unspecified)
+}
+
+pub(crate) struct Walk {
+    dirs: Vec<PathBuf>,
+    entries: Option<(PathBuf, fs::ReadDir)>,
+    recursive: bool,
+}
+
+impl Walk {
+    pub(crate) fn new(root: PathBuf, recursive: bool) -> Self {
+        Self {
+            dirs: vec![root],
+            entries: None,
+            recursive,
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = std::result::Result<PathBuf, (PathBuf, io::Error)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((dir, entries)) = &mut self.entries {
+                match entries.next() {
+                    Some(Ok(entry)) => {
+                        let path = entry.path();
+                        match entry.file_type() {
+                            Ok(ft) if ft.is_dir() => {
+                                if self.recursive {
+                                    self.dirs.push(path);
+                                }
+                            }
+                            Ok(_) => return Some(Ok(path)),
+                            Err(err) => return Some(Err((path, err))),
+                        }
+                    }
+                    Some(Err(err)) => return Some(Err((dir.clone(), err))),
+                    None => self.entries = None,
+                }
+                continue;
+            }
+            let dir = self.dirs.pop()?;
+            match fs::read_dir(&dir) {
+                Ok(entries) => self.entries = Some((dir, entries)),
+                Err(err) => return Some(Err((dir, err))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    #[test]
+    fn test_batch_process() {
+        let assets = get_test_assets_path();
+        let paths = [
+            assets.join("test-z8.NEF"),
+            assets.join("test-a7rm4.ARW"),
+            assets.join("missing.NEF"),
+        ];
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        let mut items: Vec<_> = process::<BIT_DEPTH_8, _>(&paths, &params, 2)
+            .expect("pool")
+            .collect();
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(items.len(), 3);
+        assert!(matches!(items[0].result, Err(Error::Fs(_))));
+        let image = items[1].result.as_ref().expect("processed a7rm4");
+        assert_eq!((image.width(), image.height()), (4784, 3188));
+        let image = items[2].result.as_ref().expect("processed z8");
+        assert_eq!((image.width(), image.height()), (4140, 2760));
+    }
+
+    #[test]
+    fn test_batch_process_budgeted() {
+        let assets = get_test_assets_path();
+        let paths = [assets.join("test-z8.NEF"), assets.join("test-a7rm4.ARW")];
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        // a budget smaller than any single file serializes the batch
+        let budget = Arc::new(MemoryBudget::new(1));
+        let items: Vec<_> = process_in::<BIT_DEPTH_8, _>(&paths, &params, 2, budget.clone())
+            .expect("pool")
+            .collect();
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| item.result.is_ok()));
+        let largest = paths
+            .iter()
+            .map(|path| {
+                let raw_image = RawImage::open_file(path).expect("opened");
+                estimate_process_memory(&raw_image, &params, BIT_DEPTH_8)
+            })
+            .max()
+            .unwrap();
+        let peak = budget.peak.load(std::sync::atomic::Ordering::Relaxed);
+        assert!(peak > 0 && peak <= largest, "{peak} of {largest}");
+    }
+
+    #[test]
+    fn test_index() {
+        let mut items: Vec<_> = index(get_test_assets_path(), &Default::default())
+            .expect("pool")
+            .collect();
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(items.len(), 2);
+        let indexed = items[1].result.as_ref().expect("indexed z8");
+        assert_eq!(indexed.info.model, "Z 8");
+        let thumb = indexed.best_thumb.expect("z8 has previews");
+        assert_eq!(thumb.format, crate::ThumbFormat::Jpeg);
+        assert_eq!((thumb.width, thumb.height), (8256, 5504));
+    }
+}
@@ -0,0 +1,190 @@
+// Shoot metadata as columns for analytics tools: one row per file with the
+// `FullRawInfo` fields flattened, GPS in decimal degrees. Files that fail to
+// open keep their row, with only `path` and `error` set.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use arrow_array::{
+    ArrayRef, Float32Array, Float64Array, Int32Array, Int64Array, RecordBatch, StringArray,
+    UInt16Array, UInt32Array,
+};
+use arrow_schema::{Field, Schema};
+use rayon::prelude::*;
+
+use super::thread_pool;
+use crate::{err::Result, FullRawInfo, RawImage};
+
+// Opens every file in parallel, header only, rows come out in the order of
+// `paths`
+pub fn to_arrow<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) -> Result<RecordBatch> {
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|p| p.as_ref().to_path_buf())
+        .collect();
+    let pool = thread_pool(0)?;
+    let rows: Vec<Result<FullRawInfo>> = pool.install(|| {
+        paths
+            .par_iter()
+            .map(|path| Ok(RawImage::open_file(path)?.full_info()))
+            .collect()
+    });
+    record_batch(&paths, &rows)
+}
+
+fn record_batch(paths: &[PathBuf], rows: &[Result<FullRawInfo>]) -> Result<RecordBatch> {
+    let infos: Vec<Option<&FullRawInfo>> = rows.iter().map(|r| r.as_ref().ok()).collect();
+    let strings = |f: fn(&FullRawInfo) -> &str| -> ArrayRef {
+        Arc::new(StringArray::from_iter(infos.iter().map(|i| i.map(f))))
+    };
+    let u32s = |f: fn(&FullRawInfo) -> u32| -> ArrayRef {
+        Arc::new(UInt32Array::from_iter(infos.iter().map(|i| i.map(f))))
+    };
+    let f32s = |f: fn(&FullRawInfo) -> f32| -> ArrayRef {
+        Arc::new(Float32Array::from_iter(infos.iter().map(|i| i.map(f))))
+    };
+    let position = |f: fn((f64, f64)) -> f64| -> ArrayRef {
+        Arc::new(Float64Array::from_iter(
+            infos
+                .iter()
+                .map(|i| i.and_then(|i| i.gps.decimal_degrees()).map(f)),
+        ))
+    };
+
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "path",
+            Arc::new(StringArray::from_iter_values(
+                paths.iter().map(|p| p.to_string_lossy()),
+            )),
+        ),
+        (
+            "error",
+            Arc::new(StringArray::from_iter(
+                rows.iter()
+                    .map(|r| r.as_ref().err().map(|err| err.to_string())),
+            )),
+        ),
+        ("width", u32s(|i| i.width)),
+        ("height", u32s(|i| i.height)),
+        (
+            "colors",
+            Arc::new(Int32Array::from_iter(
+                infos.iter().map(|i| i.map(|i| i.colors)),
+            )),
+        ),
+        ("iso_speed", u32s(|i| i.iso_speed)),
+        ("shutter", f32s(|i| i.shutter)),
+        ("aperture", f32s(|i| i.aperture)),
+        ("focal_len", f32s(|i| i.focal_len)),
+        (
+            "timestamp",
+            Arc::new(Int64Array::from_iter(
+                infos.iter().map(|i| i.map(|i| i.timestamp())),
+            )),
+        ),
+        ("latitude", position(|(lat, _)| lat)),
+        ("longitude", position(|(_, lon)| lon)),
+        (
+            "altitude",
+            Arc::new(Float32Array::from_iter(infos.iter().map(|i| {
+                i.filter(|i| i.gps.has_location())
+                    .map(|i| match i.gps.altref {
+                        1 => -i.gps.altitude,
+                        _ => i.gps.altitude,
+                    })
+            }))),
+        ),
+        ("make", strings(|i| &i.make)),
+        ("model", strings(|i| &i.model)),
+        ("normalized_make", strings(|i| &i.normalized_make)),
+        ("normalized_model", strings(|i| &i.normalized_model)),
+        ("software", strings(|i| &i.software)),
+        ("artist", strings(|i| &i.artist)),
+        ("desc", strings(|i| &i.desc)),
+        ("lens_make", strings(|i| &i.lens_info.lens_make)),
+        ("lens_name", strings(|i| &i.lens_info.lens_name)),
+        ("lens_serial", strings(|i| &i.lens_info.lens_serial)),
+        ("min_focal", f32s(|i| i.lens_info.min_focal)),
+        ("max_focal", f32s(|i| i.lens_info.max_focal)),
+        (
+            "focal_length_in_35mm_format",
+            Arc::new(UInt16Array::from_iter(
+                infos
+                    .iter()
+                    .map(|i| i.map(|i| i.lens_info.focal_length_in_35mm_format)),
+            )),
+        ),
+        ("raw_count", u32s(|i| i.raw_count)),
+        ("dng_version", u32s(|i| i.dng_version)),
+    ];
+    let schema = Schema::new(
+        columns
+            .iter()
+            .map(|(name, array)| Field::new(*name, array.data_type().clone(), *name != "path"))
+            .collect::<Vec<_>>(),
+    );
+    RecordBatch::try_new(
+        Arc::new(schema),
+        columns.into_iter().map(|(_, array)| array).collect(),
+    )
+    .map_err(|err| io::Error::other(err).into())
+}
+
+// `batch` as a single row group Parquet file
+#[cfg(feature = "parquet")]
+pub fn write_parquet<W: std::io::Write + Send>(batch: &RecordBatch, writer: W) -> Result<()> {
+    let mut writer = parquet::arrow::ArrowWriter::try_new(writer, batch.schema(), None)
+        .map_err(io::Error::other)?;
+    writer.write(batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+    use arrow_array::Array;
+
+    #[test]
+    fn test_to_arrow() {
+        let assets = get_test_assets_path();
+        let paths = [
+            assets.join("test-z8.NEF"),
+            assets.join("missing.NEF"),
+            assets.join("test-a7rm4.ARW"),
+        ];
+        let batch = to_arrow(&paths).expect("batch");
+        assert_eq!(batch.num_rows(), 3);
+        let column = |name| batch.column_by_name(name).expect(name);
+        let models = column("model")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert_eq!(models.value(0), "Z 8");
+        assert!(models.is_null(1));
+        assert!(!models.is_null(2));
+        let errors = column("error")
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        assert!(errors.is_null(0));
+        assert!(
+            errors.value(1).starts_with("fs error: "),
+            "{}",
+            errors.value(1)
+        );
+        assert_eq!(column("path").null_count(), 0);
+
+        #[cfg(feature = "parquet")]
+        {
+            let mut file = Vec::new();
+            write_parquet(&batch, &mut file).expect("parquet");
+            assert_eq!(&file[..4], b"PAR1");
+        }
+    }
+}
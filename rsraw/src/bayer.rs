@@ -0,0 +1,229 @@
+// Sensor dumps without a container, from machine vision and scientific
+// cameras: LibRaw is told the geometry and layout instead of reading them.
+
+use rsraw_sys as sys;
+
+use crate::{
+    err::{ContextError, Error, ErrorStage},
+    Borders, RawImage,
+};
+
+// the color of the top left 2x2 block, row by row
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BayerPattern {
+    #[default]
+    Rggb,
+    Bggr,
+    Grbg,
+    Gbrg,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BayerOptions {
+    pub pattern: BayerPattern,
+    // masked or unused pixels along the edges, left out of the image
+    pub margins: Borders,
+    pub black_level: u32,
+    // the top of the range, the bit depth's maximum by default
+    pub white_level: Option<u32>,
+    // 16 bit samples only
+    pub big_endian: bool,
+    // Of 16 bit samples this many are used, the rest zero; low bits unless
+    // `msb_aligned`. 0 uses the whole sample.
+    pub significant_bits: u8,
+    pub msb_aligned: bool,
+    // Low bits the camera never sets, LibRaw puts the white level at the
+    // last value they allow instead of the top of the range. Below the
+    // sample's used bits, `white_level` overrides it.
+    pub unused_bits: u8,
+    // 10 bit data only, Android's RAW10 packing instead of MIPI's
+    pub android_tight: bool,
+    // pixels reading 0 are dead and interpolated over
+    pub zero_is_bad: bool,
+}
+
+impl RawImage {
+    // Opens `width` x `height` pixels of bare CFA data. The bit depth follows
+    // from the buffer's length: 8, 10 (MIPI RAW10 or Android RAW10), 12
+    // (packed) or 16 bits per pixel, rows without padding,
+    // `Error::FileUnsupported` for any other length. The image keeps the
+    // buffer as with `open_owned`. Make and model read "BayerDump" and the
+    // size, and without a color matrix processing stays in camera colors.
+    pub fn open_bayer(
+        buf: impl AsRef<[u8]> + Send + 'static,
+        width: u32,
+        height: u32,
+        options: BayerOptions,
+    ) -> std::result::Result<Self, ContextError> {
+        // nothing is open yet to tell the camera
+        let invalid = |err| ContextError::new(ErrorStage::Open, err);
+        let (Ok(raw_width), Ok(raw_height)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(invalid(Error::TooBig));
+        };
+        let margins = options.margins;
+        let (Some(horizontal), Some(vertical)) = (
+            margins.left.checked_add(margins.right),
+            margins.top.checked_add(margins.bottom),
+        ) else {
+            return Err(invalid(Error::BadCrop));
+        };
+        if horizontal >= width || vertical >= height {
+            return Err(invalid(Error::BadCrop));
+        }
+        let pixels = width as u64 * height as u64;
+        let len = buf.as_ref().len() as u64;
+        let bits = len * 8 / pixels;
+        if ![8, 10, 12, 16].contains(&bits) || !(len * 8).is_multiple_of(pixels) {
+            return Err(invalid(Error::FileUnsupported));
+        }
+
+        let pattern = match options.pattern {
+            BayerPattern::Rggb => sys::LibRaw_openbayer_patterns_LIBRAW_OPENBAYER_RGGB,
+            BayerPattern::Bggr => sys::LibRaw_openbayer_patterns_LIBRAW_OPENBAYER_BGGR,
+            BayerPattern::Grbg => sys::LibRaw_openbayer_patterns_LIBRAW_OPENBAYER_GRBG,
+            BayerPattern::Gbrg => sys::LibRaw_openbayer_patterns_LIBRAW_OPENBAYER_GBRG,
+        };
+        // load flags as open_bayer reads them: bit 0 is the byte order or the
+        // RAW10 flavor, bits 1 to 3 a right shift, the rest bits to drop
+        let unused = match options.significant_bits {
+            0 => 0,
+            n => 16u32.saturating_sub(n as u32),
+        };
+        let flags = (options.big_endian || options.android_tight) as u32
+            | match options.msb_aligned {
+                true => (unused & 7) << 1,
+                false => unused << 4,
+            };
+        let used = match bits {
+            16 => 16 - unused,
+            bits => bits as u32,
+        };
+        if options.unused_bits as u32 >= used {
+            return Err(invalid(Error::FileUnsupported));
+        }
+        let procflags = (options.zero_is_bad as u8) << 1;
+        let mut image = Self::keep(buf, |buf| {
+            Self::open_source(len, None, &Default::default(), |raw_data| unsafe {
+                sys::libraw_open_bayer(
+                    raw_data,
+                    buf.as_ptr() as *mut _,
+                    buf.len() as _,
+                    raw_width,
+                    raw_height,
+                    margins.left as _,
+                    margins.top as _,
+                    margins.right as _,
+                    margins.bottom as _,
+                    procflags,
+                    pattern as _,
+                    options.unused_bits as _,
+                    flags,
+                    options.black_level,
+                )
+            })
+        })
+        .map_err(ContextError::from)?;
+        if let Some(white) = options.white_level {
+            unsafe { image.with_raw_mut(|data| data.color.maximum = white) };
+        }
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BIT_DEPTH_8;
+
+    #[test]
+    fn test_open_bayer() {
+        const SIZE: u32 = 64;
+        let samples: Vec<u16> = (0..SIZE * SIZE).map(|i| (i % 4000) as u16).collect();
+        let le: Vec<u8> = samples.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let options = BayerOptions {
+            pattern: BayerPattern::Grbg,
+            margins: Borders {
+                left: 2,
+                ..Default::default()
+            },
+            black_level: 64,
+            white_level: Some(4095),
+            significant_bits: 12,
+            ..Default::default()
+        };
+        let mut raw_image = RawImage::open_bayer(le.clone(), SIZE, SIZE, options).expect("opened");
+        assert_eq!(raw_image.make(), "BayerDump");
+        assert_eq!((raw_image.width(), raw_image.height()), (SIZE - 2, SIZE));
+        assert_eq!(raw_image.as_ref().color.maximum, 4095);
+        raw_image.unpack().expect("unpacked");
+        assert_eq!(raw_image.raw_image(), &samples[..]);
+        assert_eq!(raw_image.as_ref().rawdata.color.black, 64);
+        let image = raw_image.process::<BIT_DEPTH_8>().expect("processed");
+        assert_eq!((image.width(), image.height()), (SIZE - 2, SIZE));
+        // nothing embedded, the error still names the camera
+        let err = raw_image.largest_jpeg_preview().expect_err("no preview");
+        assert_eq!(err.stage, ErrorStage::Thumb(0));
+        assert_eq!(err.make.as_deref(), Some("BayerDump"));
+
+        // the same samples big endian and at the top of the 16 bits
+        let be: Vec<u8> = samples
+            .iter()
+            .flat_map(|v| (v << 4).to_be_bytes())
+            .collect();
+        let options = BayerOptions {
+            big_endian: true,
+            msb_aligned: true,
+            ..options
+        };
+        let mut raw_image = RawImage::open_bayer(be.clone(), SIZE, SIZE, options).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        assert_eq!(raw_image.raw_image(), &samples[..]);
+
+        let packed = vec![0u8; (SIZE * SIZE * 12 / 8) as usize];
+        let raw_image = RawImage::open_bayer(packed, SIZE, SIZE, Default::default());
+        assert!(raw_image.is_ok());
+        assert!(matches!(
+            RawImage::open_bayer(le[..100].to_vec(), SIZE, SIZE, Default::default())
+                .map_err(Error::from),
+            Err(Error::FileUnsupported)
+        ));
+        assert!(matches!(
+            RawImage::open_bayer(le.clone(), 1 << 16, 1, Default::default()).map_err(Error::from),
+            Err(Error::TooBig)
+        ));
+        let margins = Borders {
+            left: u32::MAX,
+            right: 2,
+            ..Default::default()
+        };
+        assert!(matches!(
+            RawImage::open_bayer(le, SIZE, SIZE, BayerOptions { margins, ..options })
+                .map_err(Error::from),
+            Err(Error::BadCrop)
+        ));
+
+        // the two low bits unused, the white level follows without being given
+        let options = BayerOptions {
+            white_level: None,
+            unused_bits: 2,
+            ..options
+        };
+        let raw_image = RawImage::open_bayer(be.clone(), SIZE, SIZE, options).expect("opened");
+        assert_eq!(raw_image.as_ref().color.maximum, 4096 - 4);
+        assert!(matches!(
+            RawImage::open_bayer(
+                be,
+                SIZE,
+                SIZE,
+                BayerOptions {
+                    unused_bits: 12,
+                    ..options
+                }
+            )
+            .map_err(Error::from),
+            Err(Error::FileUnsupported)
+        ));
+    }
+}
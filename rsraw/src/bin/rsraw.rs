@@ -0,0 +1,200 @@
+// Command line front end for the library, built with `--features cli`.
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use rsraw::{batch, ProcessParams, ProcessedImage, RawImage, BIT_DEPTH_16, BIT_DEPTH_8};
+
+const USAGE: &str = "usage:
+  rsraw info <file>...
+  rsraw thumb <file> <out.jpg>
+  rsraw convert [options] <file> <out.ppm>
+  rsraw batch [options] [--jobs N] <out-dir> <file>...
+
+convert and batch write binary PPM, options:
+  --16          16 bit output instead of 8
+  --half        half size, skips demosaicing
+  --camera-wb   use the white balance recorded by the camera
+  --auto-wb     estimate the white balance from the image
+  --no-bright   keep LibRaw from brightening the image
+  --deterministic  no auto adjustments and one thread, for reproducible output";
+
+#[derive(Default)]
+struct Options {
+    params: ProcessParams,
+    sixteen: bool,
+    jobs: usize,
+    args: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let Some(command) = args.next() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+    let options = match parse(args) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = match (command.as_str(), options.args.as_slice()) {
+        ("info", files) if !files.is_empty() => info(files),
+        ("thumb", [file, out]) => thumb(file, out),
+        ("convert", [file, out]) => convert(&options, file, out),
+        ("batch", [out_dir, files @ ..]) if !files.is_empty() => {
+            batch_convert(&options, out_dir, files)
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("rsraw: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--16" => options.sixteen = true,
+            "--half" => options.params.half_size = true,
+            "--camera-wb" => options.params.use_camera_wb = true,
+            "--auto-wb" => options.params.use_auto_wb = true,
+            "--no-bright" => options.params.no_auto_bright = true,
+            "--deterministic" => options.params.deterministic = true,
+            "--jobs" => {
+                let jobs = args.next().ok_or("--jobs needs a value")?;
+                options.jobs = jobs.parse().map_err(|_| format!("bad --jobs {jobs}"))?;
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            _ => options.args.push(arg),
+        }
+    }
+    Ok(options)
+}
+
+fn open(path: &str) -> rsraw::Result<RawImage> {
+    Ok(RawImage::open_owned(fs::read(path)?)?)
+}
+
+fn info(files: &[String]) -> rsraw::Result<ExitCode> {
+    let mut infos = Vec::new();
+    for file in files {
+        let raw_image = open(file)?;
+        infos.push(serde_json::json!({
+            "path": file,
+            "info": raw_image.full_info(),
+            "thumbs": raw_image.thumb_infos(),
+            "decoder": raw_image.decoder_info().name,
+        }));
+    }
+    let out = serde_json::to_string_pretty(&infos).map_err(io::Error::from)?;
+    writeln!(io::stdout().lock(), "{out}")?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn thumb(file: &str, out: &str) -> rsraw::Result<ExitCode> {
+    let mut raw_image = open(file)?;
+    let thumb = raw_image.largest_jpeg_preview()?;
+    fs::write(out, &thumb.data)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn convert(options: &Options, file: &str, out: &str) -> rsraw::Result<ExitCode> {
+    let mut raw_image = open(file)?;
+    if options.sixteen {
+        let image = raw_image.process_with::<BIT_DEPTH_16>(&options.params)?;
+        write_ppm16(&image, out.as_ref())?;
+    } else {
+        let image = raw_image.process_with::<BIT_DEPTH_8>(&options.params)?;
+        write_ppm8(&image, out.as_ref())?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// keeps going past files that fail, the exit code says whether any did
+fn batch_convert(options: &Options, out_dir: &str, files: &[String]) -> rsraw::Result<ExitCode> {
+    fs::create_dir_all(out_dir)?;
+    let out = |path: &Path| {
+        let name = path.file_stem().unwrap_or_default();
+        Path::new(out_dir).join(name).with_extension("ppm")
+    };
+    let mut failed = 0;
+    let mut report = |path: PathBuf, result: rsraw::Result<()>| match result {
+        Ok(()) => println!("{}", out(&path).display()),
+        Err(err) => {
+            failed += 1;
+            eprintln!("{}: {err}", path.display());
+        }
+    };
+    if options.sixteen {
+        for item in batch::process::<BIT_DEPTH_16, _>(files, &options.params, options.jobs)? {
+            let result = item
+                .result
+                .and_then(|image| write_ppm16(&image, &out(&item.path)));
+            report(item.path, result);
+        }
+    } else {
+        for item in batch::process::<BIT_DEPTH_8, _>(files, &options.params, options.jobs)? {
+            let result = item
+                .result
+                .and_then(|image| write_ppm8(&image, &out(&item.path)));
+            report(item.path, result);
+        }
+    }
+    if failed > 0 {
+        eprintln!("{failed} of {} files failed", files.len());
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn ppm_header(
+    out: &mut impl Write,
+    colors: u16,
+    width: u32,
+    height: u32,
+    max: u16,
+) -> io::Result<()> {
+    let magic = if colors == 1 { "P5" } else { "P6" };
+    write!(out, "{magic}\n{width} {height}\n{max}\n")
+}
+
+fn write_ppm8(image: &ProcessedImage<BIT_DEPTH_8>, path: &Path) -> rsraw::Result<()> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    ppm_header(&mut out, image.colors(), image.width(), image.height(), 255)?;
+    out.write_all(image)?;
+    out.flush()?;
+    Ok(())
+}
+
+// 16 bit PPM samples are big endian
+fn write_ppm16(image: &ProcessedImage<BIT_DEPTH_16>, path: &Path) -> rsraw::Result<()> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    ppm_header(
+        &mut out,
+        image.colors(),
+        image.width(),
+        image.height(),
+        u16::MAX,
+    )?;
+    for v in image.iter() {
+        out.write_all(&v.to_be_bytes())?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,188 @@
+// Some bodies leak a few rows or columns of masked, near black pixels into
+// the area LibRaw calls visible. Found by comparing each edge line with a
+// line well inside the frame, so a dark scene isn't mistaken for a border.
+
+use crate::{
+    err::{Error, Result},
+    RawImage,
+};
+
+const FILTERS_XTRANS: u32 = 9;
+// no body leaks more than a handful of lines
+const MAX_BORDER: usize = 16;
+// the line compared against sits this far in
+const REFERENCE: usize = 2 * MAX_BORDER;
+// an edge line darker than this share of the reference one is masked
+const DARK: f64 = 0.25;
+
+// Lines taken off each edge of the visible area, rounded up to whole CFA
+// periods so the pattern's phase doesn't change
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Borders {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl Borders {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    // what's left of a `width` by `height` area as (left, top, width, height)
+    pub(crate) fn inner(&self, width: u32, height: u32) -> [u32; 4] {
+        [
+            self.left,
+            self.top,
+            width.saturating_sub(self.left + self.right),
+            height.saturating_sub(self.top + self.bottom),
+        ]
+    }
+}
+
+impl RawImage {
+    // With `enabled`, every unpack looks for dark borders and `process` and
+    // `channel_stats` leave out what it found, `dark_borders` tells what.
+    // Takes effect at once on an image that is already unpacked.
+    pub fn set_auto_crop(&mut self, enabled: bool) -> Result<()> {
+        self.auto_crop = enabled;
+        self.dark_borders = None;
+        if enabled && self.is_unpacked() {
+            self.dark_borders = Some(self.detect_dark_borders()?);
+        }
+        Ok(())
+    }
+
+    // what auto crop takes off, `None` while it's off or before unpack
+    pub fn dark_borders(&self) -> Option<Borders> {
+        self.dark_borders
+    }
+
+    // The dark lines along each edge of the unpacked mosaic, whether or not
+    // auto crop is on. Linear raws have none. `Error::OutOfOrderCall`
+    // before `unpack`.
+    pub fn detect_dark_borders(&self) -> Result<Borders> {
+        let visible = match self.visible_mosaic() {
+            Err(Error::NotImplemented) => return Ok(Borders::default()),
+            visible => visible?,
+        };
+        let (width, height) = (visible.width, visible.height);
+        if width <= 2 * REFERENCE || height <= 2 * REFERENCE {
+            return Ok(Borders::default());
+        }
+        let value = |row: usize, col: usize| {
+            visible.row(row)[col] as f64 - visible.black.at(row, col) as f64
+        };
+
+        // mean level above black of every row, and of the columns near the edges
+        let rows: Vec<f64> = (0..height)
+            .map(|row| (0..width).map(|col| value(row, col)).sum::<f64>() / width as f64)
+            .collect();
+        let edge_cols: Vec<usize> = (0..=REFERENCE)
+            .chain(width - 1 - REFERENCE..width)
+            .collect();
+        let mut cols = vec![0.0; width];
+        for row in 0..height {
+            for &col in &edge_cols {
+                cols[col] += value(row, col) / height as f64;
+            }
+        }
+
+        // consecutive dark lines from the edge inwards
+        let dark = |line: &dyn Fn(usize) -> f64| {
+            let reference = line(REFERENCE);
+            if reference <= 0.0 {
+                return 0;
+            }
+            (0..MAX_BORDER)
+                .take_while(|&i| line(i) < reference * DARK)
+                .count()
+        };
+        let (row_period, col_period) = match self.filters() {
+            FILTERS_XTRANS => (6, 6),
+            0 => (1, 1),
+            // a Bayer layout repeats every 2 rows, a few others every 8
+            f if f.rotate_right(8) == f => (2, 2),
+            _ => (8, 2),
+        };
+        let round = |n: usize, period: usize| n.div_ceil(period) * period;
+        Ok(Borders {
+            top: round(dark(&|i| rows[i]), row_period) as u32,
+            bottom: round(dark(&|i| rows[height - 1 - i]), row_period) as u32,
+            left: round(dark(&|i| cols[i]), col_period) as u32,
+            right: round(dark(&|i| cols[width - 1 - i]), col_period) as u32,
+        })
+    }
+
+    pub(crate) fn detect_borders_after_unpack(&mut self) -> Result<()> {
+        if self.auto_crop {
+            self.dark_borders = Some(self.detect_dark_borders()?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    #[test]
+    fn test_dark_borders() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        raw_image.set_auto_crop(true).expect("not unpacked yet");
+        assert_eq!(raw_image.dark_borders(), None);
+        raw_image.unpack().expect("unpacked");
+        assert_eq!(raw_image.dark_borders(), Some(Borders::default()));
+        let (width, height) = (raw_image.width(), raw_image.height());
+
+        // blacken the first 5 visible rows and the last 3 columns
+        unsafe {
+            raw_image.with_raw_mut(|data| {
+                let sizes = data.sizes;
+                let pitch = sizes.raw_width as usize;
+                let (top, left) = (sizes.top_margin as usize, sizes.left_margin as usize);
+                let black = data.color.black as u16;
+                for row in 0..height as usize {
+                    for col in 0..width as usize {
+                        if row < 5 || col >= width as usize - 3 {
+                            *data.rawdata.raw_image.add((top + row) * pitch + left + col) = black;
+                        }
+                    }
+                }
+            })
+        };
+        let borders = raw_image.detect_dark_borders().expect("detected");
+        assert_eq!(
+            borders,
+            Borders {
+                top: 6,
+                right: 4,
+                ..Default::default()
+            }
+        );
+        assert_eq!(raw_image.dark_borders(), Some(Borders::default()));
+        raw_image.set_auto_crop(true).expect("detected");
+        assert_eq!(raw_image.dark_borders(), Some(borders));
+
+        let stats = raw_image.channel_stats().expect("stats");
+        let pixels = (width as u64 - 4) * (height as u64 - 6);
+        assert_eq!(stats.iter().map(|s| s.count).sum::<u64>(), pixels);
+        let image = raw_image.process::<BIT_DEPTH_8>().expect("processed");
+        assert_eq!((image.width(), image.height()), (width - 4, height - 6));
+        // LibRaw's cropbox is back to all of it, the log has the one used
+        assert_eq!(
+            raw_image.as_ref().params.cropbox,
+            [0, 0, u32::MAX, u32::MAX]
+        );
+        assert_eq!(image.log().cropbox, Some(borders.inner(width, height)));
+
+        raw_image.set_auto_crop(false).expect("off");
+        let image = raw_image.process::<BIT_DEPTH_8>().expect("processed");
+        assert_eq!((image.width(), image.height()), (width, height));
+        assert_eq!(image.log().cropbox, None);
+    }
+}
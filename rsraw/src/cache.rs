@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::{err::Result, FullRawInfo, RawImage, ThumbInfo};
+
+const FORMAT_VERSION: u32 = 2;
+// raw containers keep their headers up front and often a trailer at the
+// end, hashing both catches edits without reading whole files
+const HASHED_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct CacheKey {
+    pub size: u64,
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CachedMetadata {
+    pub info: FullRawInfo,
+    pub thumbs: Vec<ThumbInfo>,
+}
+
+// Persistent `FullRawInfo` and thumbnail list store, keyed by content rather
+// than path so renamed or moved files still hit. Call `save` to write it back.
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    path: Option<PathBuf>,
+    entries: HashMap<CacheKey, CachedMetadata>,
+    dirty: bool,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Vec<(CacheKey, CachedMetadata)>,
+}
+
+impl CacheKey {
+    pub fn of(data: &[u8]) -> Self {
+        let size = data.len() as u64;
+        let head = &data[..data.len().min(HASHED_BYTES as usize)];
+        let tail = &data[data.len().saturating_sub(HASHED_BYTES as usize)..];
+        Self {
+            size,
+            hash: fnv1a(&[&size.to_le_bytes(), head, tail]),
+        }
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let mut head = vec![0; size.min(HASHED_BYTES) as usize];
+        file.read_exact(&mut head)?;
+        let mut tail = vec![0; size.min(HASHED_BYTES) as usize];
+        file.seek(SeekFrom::Start(size - tail.len() as u64))?;
+        file.read_exact(&mut tail)?;
+        Ok(Self {
+            size,
+            hash: fnv1a(&[&size.to_le_bytes(), &head, &tail]),
+        })
+    }
+}
+
+impl CachedMetadata {
+    pub fn new(raw_image: &RawImage) -> Self {
+        Self {
+            info: raw_image.full_info(),
+            thumbs: raw_image.thumb_infos(),
+        }
+    }
+}
+
+impl MetadataCache {
+    pub fn in_memory() -> Self {
+        Default::default()
+    }
+
+    // a missing, unreadable or outdated cache file starts out empty
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<CacheFile>(&data).ok())
+            .filter(|file| file.version == FORMAT_VERSION)
+            .map(|file| file.entries.into_iter().collect())
+            .unwrap_or_default();
+        Self {
+            path: Some(path),
+            entries,
+            dirty: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<&CachedMetadata> {
+        self.entries.get(key)
+    }
+
+    pub fn insert(&mut self, key: CacheKey, metadata: CachedMetadata) {
+        self.entries.insert(key, metadata);
+        self.dirty = true;
+    }
+
+    // only opens the file with LibRaw if its content isn't cached yet
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<CachedMetadata> {
+        let path = path.as_ref();
+        let key = CacheKey::from_file(path)?;
+        if let Some(metadata) = self.entries.get(&key) {
+            return Ok(metadata.clone());
+        }
+        let metadata = CachedMetadata::new(&RawImage::open_file(path)?);
+        self.insert(key, metadata.clone());
+        Ok(metadata)
+    }
+
+    // writes through a temporary file so a crash never leaves a torn cache
+    pub fn save(&mut self) -> Result<()> {
+        let Some(path) = self.path.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        let file = CacheFile {
+            version: FORMAT_VERSION,
+            entries: self
+                .entries
+                .iter()
+                .map(|(key, metadata)| (*key, metadata.clone()))
+                .collect(),
+        };
+        let data = serde_json::to_vec(&file).map_err(io::Error::from)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+// stable across builds and platforms, unlike std's `DefaultHasher`
+fn fnv1a(chunks: &[&[u8]]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in chunks.iter().flat_map(|chunk| chunk.iter()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_metadata_cache() {
+        let file = get_test_assets_path().join("test-z8.NEF");
+        let key = CacheKey::from_file(&file).unwrap();
+        assert_eq!(key, CacheKey::of(&fs::read(&file).unwrap()));
+
+        let path = std::env::temp_dir().join(format!("rsraw-cache-{}.json", std::process::id()));
+        let mut cache = MetadataCache::open(&path);
+        assert!(cache.is_empty());
+        let metadata = cache.load(&file).expect("loaded");
+        assert_eq!(metadata.info.model, "Z 8");
+        cache.save().expect("saved");
+
+        let cache = MetadataCache::open(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(cache.get(&key), Some(&metadata));
+    }
+}
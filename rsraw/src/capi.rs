@@ -0,0 +1,246 @@
+// A small C interface over the safe pipeline, for applications that want
+// rsraw's defaults without binding LibRaw themselves. Declared in
+// include/rsraw.h; build the library with
+// `cargo rustc -p rsraw --release --features capi --crate-type cdylib`.
+//
+// Functions returning int give 0 on success or a LibRaw error code, see
+// `rsraw_strerror`. Only the returned pointers are owned by the caller and
+// each has its own free function. What the pointer arguments must be is
+// documented with the declarations in the header. A panic is caught before
+// it reaches the caller and turns into LIBRAW_UNSPECIFIED_ERROR or NULL.
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    ffi::{c_char, c_int, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use rsraw_sys as sys;
+
+use crate::{
+    err::{Error, Result},
+    ProcessParams, RawImage, BIT_DEPTH_16, BIT_DEPTH_8,
+};
+
+// an open file, LibRaw reads from the copy of the buffer the image keeps
+pub struct RsrawImage {
+    image: RawImage,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RsrawProcessParams {
+    // 8 or 16
+    pub bits: c_int,
+    pub half_size: bool,
+    pub use_camera_wb: bool,
+    pub use_auto_wb: bool,
+    pub no_auto_bright: bool,
+}
+
+// interleaved, rows tightly packed, 16 bit samples in native byte order
+#[repr(C)]
+#[derive(Debug)]
+pub struct RsrawBitmap {
+    pub width: u32,
+    pub height: u32,
+    pub colors: u16,
+    pub bits: u16,
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rsraw_open(
+    data: *const u8,
+    len: usize,
+    out: *mut *mut RsrawImage,
+) -> c_int {
+    guarded(error_code(Error::Unspecified), || {
+        if data.is_null() || out.is_null() {
+            return error_code(Error::Unspecified);
+        }
+        let owned: Box<[u8]> = slice::from_raw_parts(data, len).into();
+        match RawImage::open_owned(owned) {
+            Ok(image) => {
+                *out = Box::into_raw(Box::new(RsrawImage { image }));
+                0
+            }
+            Err(err) => error_code(err),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rsraw_close(image: *mut RsrawImage) {
+    guarded((), || {
+        if !image.is_null() {
+            drop(Box::from_raw(image));
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rsraw_info_json(image: *const RsrawImage) -> *mut c_char {
+    guarded(ptr::null_mut(), || {
+        let Some(image) = image.as_ref() else {
+            return ptr::null_mut();
+        };
+        serde_json::to_string(&image.image.full_info())
+            .ok()
+            .and_then(|json| CString::new(json).ok())
+            .map_or(ptr::null_mut(), CString::into_raw)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rsraw_string_free(s: *mut c_char) {
+    guarded((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+    })
+}
+
+// unpacks if needed and develops the image into `out`
+#[no_mangle]
+pub unsafe extern "C" fn rsraw_process(
+    image: *mut RsrawImage,
+    params: *const RsrawProcessParams,
+    out: *mut RsrawBitmap,
+) -> c_int {
+    guarded(error_code(Error::Unspecified), || {
+        let (Some(image), Some(out)) = (image.as_mut(), out.as_mut()) else {
+            return error_code(Error::Unspecified);
+        };
+        let (bits, params) = match params.as_ref() {
+            Some(p) => (
+                p.bits,
+                ProcessParams {
+                    half_size: p.half_size,
+                    use_camera_wb: p.use_camera_wb,
+                    use_auto_wb: p.use_auto_wb,
+                    no_auto_bright: p.no_auto_bright,
+                    ..Default::default()
+                },
+            ),
+            None => (8, ProcessParams::default()),
+        };
+        match process(&mut image.image, bits, &params) {
+            Ok(bitmap) => {
+                *out = bitmap;
+                0
+            }
+            Err(err) => error_code(err),
+        }
+    })
+}
+
+// the data pointer is cleared so a second free is harmless
+#[no_mangle]
+pub unsafe extern "C" fn rsraw_bitmap_free(bitmap: *mut RsrawBitmap) {
+    guarded((), || {
+        let Some(bitmap) = bitmap.as_mut() else {
+            return;
+        };
+        if !bitmap.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                bitmap.data,
+                bitmap.len,
+            )));
+            bitmap.data = ptr::null_mut();
+            bitmap.len = 0;
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn rsraw_strerror(code: c_int) -> *const c_char {
+    guarded(ptr::null(), || unsafe { sys::libraw_strerror(code) })
+}
+
+// a panic mustn't unwind into the caller, it gets `fallback` instead
+fn guarded<T>(fallback: T, run: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(run)).unwrap_or(fallback)
+}
+
+fn process(image: &mut RawImage, bits: c_int, params: &ProcessParams) -> Result<RsrawBitmap> {
+    let mut buf = Vec::new();
+    let layout = match bits {
+        8 => image.process_into_with::<BIT_DEPTH_8>(params, &mut buf)?,
+        16 => image.process_into_with::<BIT_DEPTH_16>(params, &mut buf)?,
+        _ => return Err(Error::NotImplemented),
+    };
+    let data = Box::into_raw(buf.into_boxed_slice());
+    Ok(RsrawBitmap {
+        width: layout.width,
+        height: layout.height,
+        colors: layout.colors,
+        bits: layout.bits,
+        len: data.len(),
+        data: data as *mut u8,
+    })
+}
+
+// errors without a LibRaw code are reading the file, closest to an io error
+fn error_code(err: impl Into<Error>) -> c_int {
+    err.into()
+        .code()
+        .unwrap_or(sys::LibRaw_errors_LIBRAW_IO_ERROR as _)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_capi() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        unsafe {
+            let mut image = ptr::null_mut();
+            assert_eq!(rsraw_open(data.as_ptr(), data.len(), &mut image), 0);
+            drop(data);
+
+            let json = rsraw_info_json(image);
+            assert!(CStr::from_ptr(json)
+                .to_str()
+                .unwrap()
+                .contains(r#""model":"Z 8""#));
+            rsraw_string_free(json);
+
+            let params = RsrawProcessParams {
+                bits: 16,
+                half_size: true,
+                use_camera_wb: true,
+                use_auto_wb: false,
+                no_auto_bright: false,
+            };
+            let mut bitmap = std::mem::zeroed::<RsrawBitmap>();
+            assert_eq!(rsraw_process(image, &params, &mut bitmap), 0);
+            assert_eq!((bitmap.width, bitmap.height, bitmap.bits), (4140, 2760, 16));
+            assert_eq!(bitmap.len, 4140 * 2760 * 3 * 2);
+            rsraw_bitmap_free(&mut bitmap);
+            assert!(bitmap.data.is_null());
+            rsraw_close(image);
+
+            let mut image = ptr::null_mut();
+            let junk = [0u8; 4096];
+            let code = rsraw_open(junk.as_ptr(), junk.len(), &mut image);
+            assert_eq!(code, sys::LibRaw_errors_LIBRAW_FILE_UNSUPPORTED);
+            assert!(!CStr::from_ptr(rsraw_strerror(code)).to_bytes().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_capi_panic() {
+        let code = guarded(error_code(Error::Unspecified), || -> c_int {
+            panic!("decoder bug")
+        });
+        assert_eq!(code, sys::LibRaw_errors_LIBRAW_UNSPECIFIED_ERROR);
+        assert!(guarded(ptr::null_mut::<c_char>(), || panic!("decoder bug")).is_null());
+    }
+}
@@ -0,0 +1,270 @@
+// How far two developed images of the same size are apart, for regression
+// tests of processing changes: PSNR over the samples as stored and CIEDE2000
+// color differences. Both are meant for images processed the same way
+// except for what's being tested.
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    OutputColor, ProcessedImage,
+};
+
+// linear RGB to XYZ, all of them relative to D65 as LibRaw adapts them
+const SRGB: [[f64; 3]; 3] = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175_0],
+    [0.019_333_9, 0.119_192_0, 0.950_304_1],
+];
+const ADOBE: [[f64; 3]; 3] = [
+    [0.576_730_9, 0.185_554_0, 0.188_185_2],
+    [0.297_376_9, 0.627_349_1, 0.075_274_1],
+    [0.027_034_3, 0.070_687_2, 0.991_108_5],
+];
+const DCI_P3: [[f64; 3]; 3] = [
+    [0.486_570_9, 0.265_667_7, 0.198_217_3],
+    [0.228_974_6, 0.691_738_5, 0.079_286_9],
+    [0.0, 0.045_113_4, 1.043_944_4],
+];
+const REC2020: [[f64; 3]; 3] = [
+    [0.636_958_0, 0.144_616_9, 0.168_881_0],
+    [0.262_700_2, 0.677_998_1, 0.059_301_7],
+    [0.0, 0.028_072_7, 1.060_985_1],
+];
+const XYZ: [[f64; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+const D65: [f64; 3] = [0.950_47, 1.0, 1.088_83];
+
+// CIEDE2000 differences over all pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeltaEStats {
+    pub mean: f64,
+    // the 95th percentile, what most pixels stay under
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // In dB, `f64::INFINITY` for identical images. `Error::NotImplemented`
+    // unless both have the same size and number of colors.
+    pub fn psnr(&self, other: &Self) -> Result<f64> {
+        self.check_comparable(other)?;
+        let width = D as usize / 8;
+        let a = self.bytes().chunks_exact(width).map(sample);
+        let b = other.bytes().chunks_exact(width).map(sample);
+        let sum: f64 = a.zip(b).map(|(a, b)| (a - b) * (a - b)).sum();
+        let mse = sum / (self.bytes().len() / width).max(1) as f64;
+        if mse == 0.0 {
+            return Ok(f64::INFINITY);
+        }
+        let peak = ((1u32 << D) - 1) as f64;
+        Ok(10.0 * (peak * peak / mse).log10())
+    }
+
+    // Of RGB images in the same color space and with the same curve, LibRaw's
+    // default BT.709 one or linear as their logs tell. `Error::NotImplemented`
+    // for other sizes, colors, curves or spaces than sRGB, Adobe RGB,
+    // DCI-P3, Rec. 2020 and XYZ.
+    pub fn delta_e_stats(&self, other: &Self) -> Result<DeltaEStats> {
+        self.check_comparable(other)?;
+        let gamma = |image: &Self| image.log().params.as_ref().and_then(|p| p.gamma);
+        let curve: fn(f64) -> f64 = match (gamma(self), gamma(other)) {
+            (a, b) if a != b => return Err(Error::NotImplemented),
+            (None, _) => bt709_to_linear,
+            (Some([1.0, 1.0]), _) => |v| v,
+            _ => return Err(Error::NotImplemented),
+        };
+        let matrix = match (self.output_color(), self.colors()) {
+            (color, _) if color != other.output_color() => return Err(Error::NotImplemented),
+            (_, colors) if colors != 3 => return Err(Error::NotImplemented),
+            (OutputColor::Srgb, _) => SRGB,
+            (OutputColor::Adobe, _) => ADOBE,
+            (OutputColor::DciP3, _) => DCI_P3,
+            (OutputColor::Rec2020, _) => REC2020,
+            (OutputColor::Xyz, _) => XYZ,
+            _ => return Err(Error::NotImplemented),
+        };
+        let peak = ((1u32 << D) - 1) as f64;
+        let pixel = 3 * (D as usize / 8);
+        let lab = |rgb: &[u8]| {
+            let mut samples = rgb.chunks_exact(pixel / 3).map(|v| sample(v) / peak);
+            let rgb = [(); 3].map(|_| curve(samples.next().unwrap_or_default()));
+            to_lab(&matrix, rgb)
+        };
+        let mut deltas: Vec<f32> = self
+            .bytes()
+            .chunks_exact(pixel)
+            .zip(other.bytes().chunks_exact(pixel))
+            .map(|(a, b)| ciede2000(lab(a), lab(b)) as f32)
+            .collect();
+        if deltas.is_empty() {
+            return Ok(DeltaEStats {
+                mean: 0.0,
+                p95: 0.0,
+                max: 0.0,
+            });
+        }
+        let mean = deltas.iter().map(|&d| d as f64).sum::<f64>() / deltas.len() as f64;
+        let max = deltas.iter().fold(0.0f32, |max, &d| max.max(d)) as f64;
+        let rank = ((deltas.len() - 1) as f64 * 0.95).round() as usize;
+        let (_, p95, _) = deltas.select_nth_unstable_by(rank, f32::total_cmp);
+        Ok(DeltaEStats {
+            mean,
+            p95: *p95 as f64,
+            max,
+        })
+    }
+
+    fn check_comparable(&self, other: &Self) -> Result<()> {
+        let shape = |image: &Self| (image.width(), image.height(), image.colors());
+        match shape(self) == shape(other) {
+            true => Ok(()),
+            false => Err(Error::NotImplemented),
+        }
+    }
+}
+
+// one 8 or 16 bit sample, the latter in native byte order
+fn sample(bytes: &[u8]) -> f64 {
+    match *bytes {
+        [v] => v as f64,
+        [lo, hi, ..] => u16::from_ne_bytes([lo, hi]) as f64,
+        [] => 0.0,
+    }
+}
+
+// the inverse of LibRaw's gamma curve at its defaults, power 0.45 and toe
+// slope 4.5
+fn bt709_to_linear(v: f64) -> f64 {
+    match v < 0.081 {
+        true => v / 4.5,
+        false => ((v + 0.099) / 1.099).powf(1.0 / 0.45),
+    }
+}
+
+// of linear RGB
+fn to_lab(matrix: &[[f64; 3]; 3], linear: [f64; 3]) -> [f64; 3] {
+    let xyz = matrix.map(|row| row.iter().zip(&linear).map(|(m, v)| m * v).sum::<f64>());
+    let f = |t: f64| match t > (6.0f64 / 29.0).powi(3) {
+        true => t.cbrt(),
+        false => t / (3.0 * (6.0f64 / 29.0).powi(2)) + 4.0 / 29.0,
+    };
+    let [x, y, z] = [0, 1, 2].map(|i| f(xyz[i] / D65[i]));
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+// Sharma, Wu and Dalal's formulation, angles in degrees
+fn ciede2000([l1, a1, b1]: [f64; 3], [l2, a2, b2]: [f64; 3]) -> f64 {
+    let pow7 = |v: f64| v.powi(7);
+    let c_mean = ((a1.hypot(b1) + a2.hypot(b2)) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (c_mean / (c_mean + pow7(25.0))).sqrt());
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f64, b: f64| match a == 0.0 && b == 0.0 {
+        true => 0.0,
+        false => b.atan2(a).to_degrees().rem_euclid(360.0),
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = match (c1 * c2 == 0.0, h2 - h1) {
+        (true, _) => 0.0,
+        (false, d) if d.abs() <= 180.0 => d,
+        (false, d) if d > 180.0 => d - 360.0,
+        (false, d) => d + 360.0,
+    };
+    let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = match (c1 * c2 == 0.0, (h1 - h2).abs() <= 180.0) {
+        (true, _) => h1 + h2,
+        (false, true) => (h1 + h2) / 2.0,
+        (false, false) if h1 + h2 < 360.0 => (h1 + h2 + 360.0) / 2.0,
+        (false, false) => (h1 + h2 - 360.0) / 2.0,
+    };
+    let cos = |deg: f64| deg.to_radians().cos();
+    let t =
+        1.0 - 0.17 * cos(h_mean - 30.0) + 0.24 * cos(2.0 * h_mean) + 0.32 * cos(3.0 * h_mean + 6.0)
+            - 0.20 * cos(4.0 * h_mean - 63.0);
+    let rotation = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let rc = 2.0 * (pow7(c_mean) / (pow7(c_mean) + pow7(25.0))).sqrt();
+    let sl = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let sc = 1.0 + 0.045 * c_mean;
+    let sh = 1.0 + 0.015 * c_mean * t;
+    let rt = -(2.0 * rotation).to_radians().sin() * rc;
+    let (l, c, h) = (dl / sl, dc / sc, dh / sh);
+    (l * l + c * c + h * h + rt * c * h).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, ProcessParams, RawImage, BIT_DEPTH_8};
+
+    #[test]
+    fn test_ciede2000() {
+        // pairs from Sharma, Wu and Dalal's test data
+        for (a, b, expected) in [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([2.0776, 0.0795, -1.135], [0.9033, -0.0636, -0.5514], 0.9082),
+        ] {
+            assert!((ciede2000(a, b) - expected).abs() < 1e-4, "{a:?} {b:?}");
+        }
+        let white = to_lab(&SRGB, [1.0, 1.0, 1.0]);
+        assert!((white[0] - 100.0).abs() < 0.01 && white[1].abs() < 0.01);
+    }
+
+    #[test]
+    fn test_compare() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        let a = raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        let mut b = raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        assert_eq!(a.psnr(&b).unwrap(), f64::INFINITY);
+        assert_eq!(a.delta_e_stats(&b).unwrap().max, 0.0);
+
+        // every sample one off is an MSE of 1
+        for v in b.iter_mut() {
+            *v = match *v {
+                255 => 254,
+                v => v + 1,
+            };
+        }
+        let psnr = a.psnr(&b).unwrap();
+        assert!((psnr - 20.0 * 255f64.log10()).abs() < 1e-9);
+        let stats = a.delta_e_stats(&b).unwrap();
+        assert!(stats.mean > 0.0 && stats.mean <= stats.p95 && stats.p95 <= stats.max);
+        assert!(stats.max < 3.0);
+
+        b.crop(0, 0, b.width() - 2, b.height());
+        assert!(matches!(a.psnr(&b), Err(Error::NotImplemented)));
+
+        // linear output compares, other curves and mixed ones don't
+        let linear = raw_image
+            .process_with::<BIT_DEPTH_8>(&params.clone().gamma(1.0, 1.0))
+            .expect("processed");
+        assert_eq!(linear.delta_e_stats(&linear).unwrap().max, 0.0);
+        assert!(matches!(
+            a.delta_e_stats(&linear),
+            Err(Error::NotImplemented)
+        ));
+        let srgb = raw_image
+            .process_with::<BIT_DEPTH_8>(&params.gamma(1.0 / 2.4, 12.92))
+            .expect("processed");
+        assert!(matches!(
+            srgb.delta_e_stats(&srgb),
+            Err(Error::NotImplemented)
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+// Post-decode buffer conversions. With the `simd` feature the hot loops use
+// explicit SSE2/SSSE3 intrinsics on x86_64, everything else uses the scalar paths.
+
+const DITHER_4X4: [[u16; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+fn dither(x: usize, y: usize) -> u16 {
+    DITHER_4X4[y % 4][x % 4] * 16 + 8
+}
+
+pub fn normalize_u16(src: &[u16], white: u16, dst: &mut [f32]) {
+    assert_eq!(src.len(), dst.len());
+    let scale = 1.0 / white.max(1) as f32;
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    let done = unsafe { x86::normalize_u16(src, scale, dst) };
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    let done = 0;
+    scalar::normalize_u16(&src[done..], scale, &mut dst[done..]);
+}
+
+pub fn to_planar<T: Copy>(src: &[T], channels: usize, dst: &mut [T]) {
+    assert_eq!(src.len(), dst.len());
+    assert_eq!(src.len().checked_rem(channels), Some(0));
+    scalar::to_planar(src, channels, 0, dst);
+}
+
+pub fn to_planar_u16(src: &[u16], channels: usize, dst: &mut [u16]) {
+    assert_eq!(src.len(), dst.len());
+    assert_eq!(src.len().checked_rem(channels), Some(0));
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    let done = if channels == 3 && is_x86_feature_detected!("ssse3") {
+        unsafe { x86::to_planar_rgb_u16(src, dst) }
+    } else {
+        0
+    };
+    #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+    let done = 0;
+    scalar::to_planar(src, channels, done, dst);
+}
+
+// 16 -> 8 bit with a 4x4 ordered dither, so smooth gradients don't band
+pub fn dither_to_u8(src: &[u16], width: usize, channels: usize, dst: &mut [u8]) {
+    assert_eq!(src.len(), dst.len());
+    let stride = width * channels;
+    assert_eq!(src.len().checked_rem(stride), Some(0));
+    let mut pattern = vec![0u16; stride * 4];
+    for (i, d) in pattern.iter_mut().enumerate() {
+        *d = dither((i % stride) / channels, i / stride);
+    }
+    for (y, (src, dst)) in src.chunks(stride).zip(dst.chunks_mut(stride)).enumerate() {
+        let pattern = &pattern[(y % 4) * stride..][..stride];
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        let done = unsafe { x86::add_shift_u16(src, pattern, dst) };
+        #[cfg(not(all(feature = "simd", target_arch = "x86_64")))]
+        let done = 0;
+        scalar::add_shift_u16(&src[done..], &pattern[done..], &mut dst[done..]);
+    }
+}
+
+// box filter, each output pixel averages the source pixels it covers
+pub fn downscale_rgb8(
+    src: &[u8],
+    width: usize,
+    height: usize,
+    dst_width: usize,
+    dst_height: usize,
+) -> Vec<u8> {
+    assert_eq!(src.len(), width * height * 3);
+    assert!(dst_width <= width && dst_height <= height);
+    let mut dst = vec![0; dst_width * dst_height * 3];
+    for y in 0..dst_height {
+        let (y0, y1) = (y * height / dst_height, (y + 1) * height / dst_height);
+        for x in 0..dst_width {
+            let (x0, x1) = (x * width / dst_width, (x + 1) * width / dst_width);
+            let mut sum = [0u32; 3];
+            for row in src[y0 * width * 3..y1 * width * 3].chunks(width * 3) {
+                for px in row[x0 * 3..x1 * 3].chunks(3) {
+                    for c in 0..3 {
+                        sum[c] += px[c] as u32;
+                    }
+                }
+            }
+            let n = ((y1 - y0) * (x1 - x0)) as u32;
+            for c in 0..3 {
+                dst[(y * dst_width + x) * 3 + c] = ((sum[c] + n / 2) / n) as u8;
+            }
+        }
+    }
+    dst
+}
+
+mod scalar {
+    pub fn normalize_u16(src: &[u16], scale: f32, dst: &mut [f32]) {
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d = *s as f32 * scale;
+        }
+    }
+
+    // `from` is the first pixel not yet converted by a vectorized prefix
+    pub fn to_planar<T: Copy>(src: &[T], channels: usize, from: usize, dst: &mut [T]) {
+        let pixels = src.len() / channels;
+        for i in from / channels..pixels {
+            for c in 0..channels {
+                dst[c * pixels + i] = src[i * channels + c];
+            }
+        }
+    }
+
+    pub fn add_shift_u16(src: &[u16], pattern: &[u16], dst: &mut [u8]) {
+        for ((d, s), p) in dst.iter_mut().zip(src).zip(pattern) {
+            *d = (s.saturating_add(*p) >> 8) as u8;
+        }
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    // each function returns how many leading elements it converted,
+    // the caller finishes the tail with the scalar path
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn normalize_u16(src: &[u16], scale: f32, dst: &mut [f32]) -> usize {
+        let n = src.len() / 8 * 8;
+        let scale = _mm_set1_ps(scale);
+        let zero = _mm_setzero_si128();
+        for i in (0..n).step_by(8) {
+            let v = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let lo = _mm_cvtepi32_ps(_mm_unpacklo_epi16(v, zero));
+            let hi = _mm_cvtepi32_ps(_mm_unpackhi_epi16(v, zero));
+            _mm_storeu_ps(dst.as_mut_ptr().add(i), _mm_mul_ps(lo, scale));
+            _mm_storeu_ps(dst.as_mut_ptr().add(i + 4), _mm_mul_ps(hi, scale));
+        }
+        n
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub unsafe fn add_shift_u16(src: &[u16], pattern: &[u16], dst: &mut [u8]) -> usize {
+        let n = src.len() / 16 * 16;
+        for i in (0..n).step_by(16) {
+            let a = _mm_loadu_si128(src.as_ptr().add(i) as *const __m128i);
+            let b = _mm_loadu_si128(src.as_ptr().add(i + 8) as *const __m128i);
+            let pa = _mm_loadu_si128(pattern.as_ptr().add(i) as *const __m128i);
+            let pb = _mm_loadu_si128(pattern.as_ptr().add(i + 8) as *const __m128i);
+            let a = _mm_srli_epi16(_mm_adds_epu16(a, pa), 8);
+            let b = _mm_srli_epi16(_mm_adds_epu16(b, pb), 8);
+            _mm_storeu_si128(
+                dst.as_mut_ptr().add(i) as *mut __m128i,
+                _mm_packus_epi16(a, b),
+            );
+        }
+        n
+    }
+
+    // byte shuffle picking the given u16 lanes of a source vector, -1 clears the lane
+    const fn lanes(src: [i8; 8]) -> [i8; 16] {
+        let mut mask = [-1i8; 16];
+        let mut i = 0;
+        while i < 8 {
+            if src[i] >= 0 {
+                mask[i * 2] = src[i] * 2;
+                mask[i * 2 + 1] = src[i] * 2 + 1;
+            }
+            i += 1;
+        }
+        mask
+    }
+
+    const X: i8 = -1;
+    // 8 rgb pixels span three vectors: a = r0g0b0r1g1b1r2g2, b = b2r3g3b3r4g4b4r5, c = g5b5r6g6b6r7g7b7
+    const MASKS: [[[i8; 16]; 3]; 3] = [
+        [
+            lanes([0, 3, 6, X, X, X, X, X]),
+            lanes([X, X, X, 1, 4, 7, X, X]),
+            lanes([X, X, X, X, X, X, 2, 5]),
+        ],
+        [
+            lanes([1, 4, 7, X, X, X, X, X]),
+            lanes([X, X, X, 2, 5, X, X, X]),
+            lanes([X, X, X, X, X, 0, 3, 6]),
+        ],
+        [
+            lanes([2, 5, X, X, X, X, X, X]),
+            lanes([X, X, 0, 3, 6, X, X, X]),
+            lanes([X, X, X, X, X, 1, 4, 7]),
+        ],
+    ];
+
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn to_planar_rgb_u16(src: &[u16], dst: &mut [u16]) -> usize {
+        let pixels = src.len() / 3;
+        let n = pixels / 8 * 8;
+        for p in (0..n).step_by(8) {
+            let ptr = src.as_ptr().add(p * 3) as *const __m128i;
+            let v = [
+                _mm_loadu_si128(ptr),
+                _mm_loadu_si128(ptr.add(1)),
+                _mm_loadu_si128(ptr.add(2)),
+            ];
+            for (c, masks) in MASKS.iter().enumerate() {
+                let mut plane = _mm_setzero_si128();
+                for (v, mask) in v.iter().zip(masks) {
+                    let mask = _mm_loadu_si128(mask.as_ptr() as *const __m128i);
+                    plane = _mm_or_si128(plane, _mm_shuffle_epi8(*v, mask));
+                }
+                _mm_storeu_si128(dst.as_mut_ptr().add(c * pixels + p) as *mut __m128i, plane);
+            }
+        }
+        n * 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(len: usize) -> Vec<u16> {
+        (0..len as u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 16) as u16)
+            .collect()
+    }
+
+    #[test]
+    fn test_conversions_match_scalar() {
+        let (width, channels) = (37, 3);
+        let src = samples(width * channels * 5);
+
+        let mut normalized = vec![0.0; src.len()];
+        normalize_u16(&src, u16::MAX, &mut normalized);
+        let mut expected = vec![0.0; src.len()];
+        scalar::normalize_u16(&src, 1.0 / u16::MAX as f32, &mut expected);
+        assert_eq!(normalized, expected);
+
+        let mut planar = vec![0; src.len()];
+        to_planar_u16(&src, channels, &mut planar);
+        let mut expected = vec![0; src.len()];
+        to_planar(&src, channels, &mut expected);
+        assert_eq!(planar, expected);
+        assert_eq!(planar[width * 5], src[1]);
+
+        let mut dithered = vec![0; src.len()];
+        dither_to_u8(&src, width, channels, &mut dithered);
+        for (i, (d, s)) in dithered.iter().zip(&src).enumerate() {
+            let p = dither((i % (width * channels)) / channels, i / (width * channels));
+            assert_eq!(*d, (s.saturating_add(p) >> 8) as u8);
+        }
+    }
+
+    // the vectorized prefixes themselves, the test above goes through
+    // whichever path the build picked
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    #[test]
+    fn test_simd_matches_scalar() {
+        let src = samples(3 * 8 * 5 + 7);
+        let pattern = samples(src.len())
+            .iter()
+            .map(|v| v >> 8)
+            .collect::<Vec<_>>();
+
+        let scale = 1.0 / 4095.0;
+        let (mut simd, mut expected) = (vec![0.0; src.len()], vec![0.0; src.len()]);
+        let done = unsafe { x86::normalize_u16(&src, scale, &mut simd) };
+        assert_eq!(done, src.len() / 8 * 8);
+        scalar::normalize_u16(&src, scale, &mut expected);
+        assert_eq!(simd[..done], expected[..done]);
+
+        let (mut simd, mut expected) = (vec![0; src.len()], vec![0; src.len()]);
+        let done = unsafe { x86::add_shift_u16(&src, &pattern, &mut simd) };
+        assert_eq!(done, src.len() / 16 * 16);
+        scalar::add_shift_u16(&src, &pattern, &mut expected);
+        assert_eq!(simd[..done], expected[..done]);
+
+        if is_x86_feature_detected!("ssse3") {
+            let src = &src[..src.len() / 3 * 3];
+            let (mut simd, mut expected) = (vec![0; src.len()], vec![0; src.len()]);
+            let done = unsafe { x86::to_planar_rgb_u16(src, &mut simd) };
+            assert_eq!(done, src.len() / 24 * 24);
+            scalar::to_planar(src, 3, 0, &mut expected);
+            let pixels = src.len() / 3;
+            for c in 0..3 {
+                let plane = c * pixels..c * pixels + done / 3;
+                assert_eq!(simd[plane.clone()], expected[plane]);
+            }
+        }
+    }
+}
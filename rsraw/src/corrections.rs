@@ -0,0 +1,171 @@
+use crate::{
+    ifd::FileTiff,
+    opcodes::{OPCODE_GAIN_MAP, OPCODE_WARP_RECTILINEAR},
+    RawImage,
+};
+
+const OPCODE_WARP_FISHEYE: u32 = 2;
+const OPCODE_FIX_VIGNETTE_RADIAL: u32 = 3;
+
+// Sony's lens correction parameters in the ARW raw IFD: a count, then that
+// many int16 values, all zero when the lens didn't report any
+const TAG_SONY_VIGNETTING: u16 = 0x7032;
+const TAG_SONY_CHROMATIC_ABERRATION: u16 = 0x7035;
+const TAG_SONY_DISTORTION: u16 = 0x7037;
+const TIFF_SSHORT: u16 = 8;
+
+// Where correction data comes from, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CorrectionSource {
+    // a DNG opcode rsraw applies, see `warp_rectilinear` and `apply_gain_maps`
+    DngOpcode,
+    // a DNG opcode for raw converters that implement it
+    OtherDngOpcode,
+    // the vendor's coefficients, meant for its own software
+    Makernote,
+}
+
+// What a UI can offer to correct, `None` where the file carries nothing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Corrections {
+    pub distortion: Option<CorrectionSource>,
+    pub vignetting: Option<CorrectionSource>,
+    // lateral, i.e. a distortion model per color plane
+    pub chromatic_aberration: Option<CorrectionSource>,
+}
+
+impl Corrections {
+    pub fn any(&self) -> bool {
+        *self != Self::default()
+    }
+
+    // can be applied without a lens database
+    pub fn any_applicable(&self) -> bool {
+        [self.distortion, self.vignetting, self.chromatic_aberration]
+            .contains(&Some(CorrectionSource::DngOpcode))
+    }
+}
+
+fn best(a: &mut Option<CorrectionSource>, b: CorrectionSource) {
+    *a = Some(a.map_or(b, |a| a.min(b)));
+}
+
+impl RawImage {
+    // The DNG opcodes of both lists and Sony's makernote parameters. Other
+    // vendors' makernotes only record whether the camera corrected its own
+    // JPEG, which is no use for the raw and isn't reported.
+    pub fn corrections_available(&self) -> Corrections {
+        let mut corrections = Corrections::default();
+        for op in self.opcode_list2().iter().chain(&self.opcode_list3()) {
+            match op.id {
+                OPCODE_WARP_RECTILINEAR => {
+                    best(&mut corrections.distortion, CorrectionSource::DngOpcode)
+                }
+                OPCODE_WARP_FISHEYE => best(
+                    &mut corrections.distortion,
+                    CorrectionSource::OtherDngOpcode,
+                ),
+                OPCODE_FIX_VIGNETTE_RADIAL => best(
+                    &mut corrections.vignetting,
+                    CorrectionSource::OtherDngOpcode,
+                ),
+                OPCODE_GAIN_MAP => best(&mut corrections.vignetting, CorrectionSource::DngOpcode),
+                _ => {}
+            }
+        }
+        if self
+            .warp_rectilinear()
+            .is_some_and(|warp| warp.coefficients.len() > 1)
+        {
+            best(
+                &mut corrections.chromatic_aberration,
+                CorrectionSource::DngOpcode,
+            );
+        }
+
+        if let Some(tiff) = FileTiff::new(self) {
+            for ifd in tiff.ifds() {
+                for (tag, found) in [
+                    (TAG_SONY_DISTORTION, &mut corrections.distortion),
+                    (TAG_SONY_VIGNETTING, &mut corrections.vignetting),
+                    (
+                        TAG_SONY_CHROMATIC_ABERRATION,
+                        &mut corrections.chromatic_aberration,
+                    ),
+                ] {
+                    let Some(entry) = ifd.get(tag).filter(|e| e.kind == TIFF_SSHORT) else {
+                        continue;
+                    };
+                    let params = tiff.data(entry).unwrap_or_default();
+                    if params.iter().skip(2).any(|&b| b != 0) {
+                        best(found, CorrectionSource::Makernote);
+                    }
+                }
+            }
+        }
+        corrections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::{get_test_assets_path, linear_dng, linear_dng_with};
+
+    #[test]
+    fn test_corrections_available() {
+        let data = std::fs::read(get_test_assets_path().join("test-a7rm4.ARW")).unwrap();
+        let raw_image = RawImage::open_owned(data).expect("opened");
+        let corrections = raw_image.corrections_available();
+        assert_eq!(
+            corrections,
+            Corrections {
+                distortion: Some(CorrectionSource::Makernote),
+                vignetting: Some(CorrectionSource::Makernote),
+                chromatic_aberration: Some(CorrectionSource::Makernote),
+            }
+        );
+        assert!(corrections.any() && !corrections.any_applicable());
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(!raw_image.corrections_available().any());
+        let data = linear_dng();
+        let raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(!raw_image.corrections_available().any());
+
+        // OpcodeList3 with a warp per plane and a radial vignette
+        let opcode = |id: u32, params: Vec<u8>| {
+            let header = [id, 1, 0, params.len() as u32].map(u32::to_be_bytes);
+            [header.concat(), params].concat()
+        };
+        let mut warp = 3u32.to_be_bytes().to_vec();
+        for v in [[1.0, 0.0, 0.0, 0.0, 0.0, 0.0]; 3]
+            .concat()
+            .into_iter()
+            .chain([0.5, 0.5])
+        {
+            warp.extend_from_slice(&f64::to_be_bytes(v));
+        }
+        let list = [
+            2u32.to_be_bytes().to_vec(),
+            opcode(OPCODE_WARP_RECTILINEAR, warp),
+            opcode(OPCODE_FIX_VIGNETTE_RADIAL, vec![0; 56]),
+        ]
+        .concat();
+        let data = linear_dng_with(vec![(0xc74e, 7, list.len() as u32, list)]);
+        let raw_image = RawImage::open_owned(data).expect("opened");
+        let corrections = raw_image.corrections_available();
+        assert_eq!(
+            corrections,
+            Corrections {
+                distortion: Some(CorrectionSource::DngOpcode),
+                vignetting: Some(CorrectionSource::OtherDngOpcode),
+                chromatic_aberration: Some(CorrectionSource::DngOpcode),
+            }
+        );
+        assert!(corrections.any_applicable());
+    }
+}
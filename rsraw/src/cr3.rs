@@ -0,0 +1,264 @@
+use crate::{ifd::FileTiff, RawImage};
+
+// CR3 track media types, as LibRaw numbers them
+const TRACK_CTMD: u32 = 3;
+
+const RECORD_TIME: u16 = 1;
+const RECORD_FOCAL: u16 = 4;
+const RECORD_EXPOSURE: u16 = 5;
+// exif and maker note blocks, LibRaw parses 7 and 8 as maker notes too
+const RECORD_EXIF: [u16; 3] = [7, 8, 9];
+
+const TAG_MAKER_NOTE: u32 = 0x927c;
+// Canon's LevelInfo maker note tag, int32s
+const TAG_LEVEL_INFO: u16 = 0x4059;
+const TIFF_SLONG: u16 = 9;
+
+// a CTMD record is its 4 byte size, a 2 byte type and 6 unspecified bytes
+const RECORD_HEADER: usize = 12;
+// CTMD holds a few dozen records per frame
+const MAX_RECORDS: usize = 1024;
+
+// Canon timed metadata, the CR3 track recording per frame what the camera
+// knew when it took the shot. LibRaw folds some of it into its makernote
+// fields and drops the rest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ctmd {
+    pub records: Vec<CtmdRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CtmdRecord {
+    pub kind: u16,
+    // everything after the record header
+    pub data: Vec<u8>,
+}
+
+// camera wall clock time, like LibRaw's timestamp but to the hundredth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CtmdTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub hundredths: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CtmdExposure {
+    pub aperture: f32,
+    pub shutter: f32,
+    pub iso_speed: u32,
+}
+
+// the camera's electronic level, in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelInfo {
+    // positive is clockwise
+    pub roll: f32,
+    // positive is up
+    pub pitch: f32,
+}
+
+impl Ctmd {
+    // the records of a CTMD sample, stops at the first malformed one
+    pub fn parse(buf: &[u8]) -> Self {
+        let mut records = Vec::new();
+        let mut rest = buf;
+        while records.len() < MAX_RECORDS {
+            let Some(size) = le_u32(rest, 0).map(|size| size as usize) else {
+                break;
+            };
+            let (Some(kind), Some(record)) = (le_u16(rest, 4), rest.get(..size)) else {
+                break;
+            };
+            let Some(data) = record.get(RECORD_HEADER..) else {
+                break;
+            };
+            records.push(CtmdRecord {
+                kind,
+                data: data.to_vec(),
+            });
+            rest = &rest[size..];
+        }
+        Self { records }
+    }
+
+    fn record(&self, kind: u16) -> Option<&[u8]> {
+        self.records
+            .iter()
+            .find(|r| r.kind == kind)
+            .map(|r| r.data.as_slice())
+    }
+
+    pub fn time(&self) -> Option<CtmdTime> {
+        let data = self.record(RECORD_TIME)?;
+        let b = data.get(4..10)?;
+        Some(CtmdTime {
+            year: le_u16(data, 2)?,
+            month: b[0],
+            day: b[1],
+            hour: b[2],
+            minute: b[3],
+            second: b[4],
+            hundredths: b[5],
+        })
+    }
+
+    // in mm
+    pub fn focal_length(&self) -> Option<f32> {
+        rational(self.record(RECORD_FOCAL)?, 0)
+    }
+
+    pub fn exposure(&self) -> Option<CtmdExposure> {
+        let data = self.record(RECORD_EXPOSURE)?;
+        Some(CtmdExposure {
+            aperture: rational(data, 0)?,
+            shutter: rational(data, 4)?,
+            iso_speed: le_u32(data, 8)?,
+        })
+    }
+
+    // from the LevelInfo tag of the embedded maker notes, when the body records one
+    pub fn level(&self) -> Option<LevelInfo> {
+        self.records
+            .iter()
+            .filter(|r| RECORD_EXIF.contains(&r.kind))
+            .find_map(|r| level_info(&r.data))
+    }
+}
+
+impl RawImage {
+    // `None` for anything but a CR3 with a CTMD track
+    pub fn ctmd(&self) -> Option<Ctmd> {
+        let (_, offset, size) = self
+            .crx_tracks()
+            .into_iter()
+            .find(|(kind, _, _)| *kind == TRACK_CTMD)?;
+        Some(Ctmd::parse(&self.read_at(offset, size)?))
+    }
+}
+
+// exif records are a list of (size, tag) boxes, the maker note one holds a TIFF
+fn level_info(data: &[u8]) -> Option<LevelInfo> {
+    let mut pos = 0;
+    while let (Some(len), Some(tag)) = (le_u32(data, pos), le_u32(data, pos + 4)) {
+        let len = len as usize;
+        if len < 8 {
+            return None;
+        }
+        if tag == TAG_MAKER_NOTE {
+            let tiff = data.get(pos + 8..pos.checked_add(len)?)?;
+            if let Some(level) = maker_note_level(tiff) {
+                return Some(level);
+            }
+        }
+        pos += len;
+    }
+    None
+}
+
+fn maker_note_level(buf: &[u8]) -> Option<LevelInfo> {
+    let le = match buf.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let tiff = FileTiff::with_order(buf, le);
+    let (ifd, _) = tiff.ifd(tiff.u32_at(4)? as u64)?;
+    let entry = ifd
+        .entries
+        .iter()
+        .find(|e| e.tag == TAG_LEVEL_INFO && e.kind == TIFF_SLONG && e.count >= 6)?;
+    let angle = |i: usize| {
+        // tenths of a degree, 0 to 3600 for a full turn
+        let v = tiff.u32_at(entry.value as u64 + i as u64 * 4)? as i32;
+        Some(if v > 1800 { v - 3600 } else { v } as f32 / 10.0)
+    };
+    Some(LevelInfo {
+        roll: -angle(4)?,
+        pitch: angle(5)?,
+    })
+}
+
+fn le_u16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(buf.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn le_u32(buf: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+// two u16, numerator first
+fn rational(buf: &[u8], pos: usize) -> Option<f32> {
+    let (num, den) = (le_u16(buf, pos)?, le_u16(buf, pos + 2)?);
+    (den != 0).then(|| num as f32 / den as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    fn record(kind: u16, data: &[u8]) -> Vec<u8> {
+        let mut buf = ((data.len() + RECORD_HEADER) as u32).to_le_bytes().to_vec();
+        buf.extend_from_slice(&kind.to_le_bytes());
+        buf.extend_from_slice(&[0; 6]);
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    // a maker note box with a one entry IFD: LevelInfo, 7 int32s
+    fn maker_note(roll: i32, pitch: i32) -> Vec<u8> {
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend_from_slice(&8u32.to_le_bytes());
+        tiff.extend_from_slice(&1u16.to_le_bytes());
+        tiff.extend_from_slice(&TAG_LEVEL_INFO.to_le_bytes());
+        tiff.extend_from_slice(&TIFF_SLONG.to_le_bytes());
+        tiff.extend_from_slice(&7u32.to_le_bytes());
+        tiff.extend_from_slice(&26u32.to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        for v in [28, 0, 0, 0, roll, pitch, 0] {
+            tiff.extend_from_slice(&v.to_le_bytes());
+        }
+        let mut buf = ((tiff.len() + 8) as u32).to_le_bytes().to_vec();
+        buf.extend_from_slice(&TAG_MAKER_NOTE.to_le_bytes());
+        buf.extend_from_slice(&tiff);
+        buf
+    }
+
+    #[test]
+    fn test_ctmd() {
+        let mut buf = record(RECORD_TIME, &[0, 0, 0xe8, 0x07, 11, 4, 20, 11, 38, 42]);
+        buf.extend(record(RECORD_FOCAL, &[105, 0, 1, 0]));
+        buf.extend(record(
+            RECORD_EXPOSURE,
+            &[28, 0, 10, 0, 1, 0, 250, 0, 100, 0, 0, 0],
+        ));
+        buf.extend(record(8, &maker_note(3585, 25)));
+        let ctmd = Ctmd::parse(&buf);
+        assert_eq!(ctmd.records.len(), 4);
+        let time = ctmd.time().expect("time");
+        assert_eq!((time.year, time.month, time.day), (2024, 11, 4));
+        assert_eq!((time.second, time.hundredths), (38, 42));
+        assert_eq!(ctmd.focal_length(), Some(105.0));
+        let exposure = ctmd.exposure().expect("exposure");
+        assert_eq!((exposure.aperture, exposure.shutter), (2.8, 1.0 / 250.0));
+        assert_eq!(exposure.iso_speed, 100);
+        assert_eq!(
+            ctmd.level(),
+            Some(LevelInfo {
+                roll: 1.5,
+                pitch: 2.5
+            })
+        );
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        assert!(RawImage::open_owned(data).unwrap().ctmd().is_none());
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    ffi::{c_char, c_int, c_void},
+    sync::Mutex,
+};
+
+use rsraw_sys as sys;
+
+// Corruption LibRaw ran into while decoding. LibRaw counts every damaged read
+// but only passes the first one of a file to its callback, so `count` comes
+// from the decoder while the offset belongs to that first report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DataErrors {
+    pub count: u32,
+    pub first_offset: Option<u64>,
+    // the file ended before the decoder got all the data it expected
+    pub eof: bool,
+}
+
+// How much of a truncated frame `RawImage::open_partial` could decode, the
+// rest of the rows are filled with zeros or decoder noise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PartialDecode {
+    pub rows: u32,
+    pub rows_recovered: u32,
+}
+
+impl DataErrors {
+    pub fn is_empty(&self) -> bool {
+        self.count == 0 && !self.eof
+    }
+}
+
+// Replaces LibRaw's default handler, which prints every report to stderr.
+// Boxed and owned by the `RawImage` like the progress state.
+#[derive(Default)]
+pub(crate) struct DataErrorLog {
+    first: Mutex<DataErrors>,
+}
+
+impl DataErrorLog {
+    pub(crate) unsafe fn install(self: &mut Box<Self>, raw_data: *mut sys::libraw_data_t) {
+        let data = self.as_mut() as *mut Self as *mut c_void;
+        sys::libraw_set_dataerror_handler(raw_data, Some(on_data_error), data);
+    }
+
+    // for the next file opened with the same LibRaw context
+    pub(crate) fn reset(&self) {
+        *self.first.lock().unwrap_or_else(|e| e.into_inner()) = DataErrors::default();
+    }
+
+    pub(crate) fn get(&self, raw_data: *mut sys::libraw_data_t) -> DataErrors {
+        let mut errors = *self.first.lock().unwrap_or_else(|e| e.into_inner());
+        errors.count = unsafe { sys::rsraw_error_count(raw_data) }.max(0) as _;
+        errors
+    }
+}
+
+unsafe extern "C" fn on_data_error(data: *mut c_void, _file: *const c_char, offset: c_int) {
+    let log = &*(data as *const DataErrorLog);
+    let mut first = log.first.lock().unwrap_or_else(|e| e.into_inner());
+    match u64::try_from(offset) {
+        Ok(offset) => {
+            first.first_offset.get_or_insert(offset);
+        }
+        Err(_) => first.eof = true,
+    }
+}
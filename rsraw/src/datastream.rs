@@ -0,0 +1,365 @@
+// Decoding from anything readable and seekable, an object store, an archive
+// member or a cache, without holding the whole file in memory. LibRaw reads
+// it through a small C++ datastream that calls back into the source.
+
+use std::{
+    ffi::{c_longlong, c_void},
+    io::{self, Read, Seek, SeekFrom, Write},
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    panic::{self, AssertUnwindSafe},
+    ptr::NonNull,
+    slice,
+};
+
+use rsraw_sys as sys;
+
+use crate::{
+    err::{ContextError, Error, ErrorStage},
+    raw::BitDepth,
+    DecoderPreference, ProcessParams, ProcessedImage, RawImage, ThumbInfo, ThumbnailImage,
+};
+
+pub trait RawDataSource: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send + ?Sized> RawDataSource for T {}
+
+// what `Source` reads from, `open_reader`'s readers needn't be Send
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+// the C++ stream and the source it reads, freed after LibRaw is closed. The
+// source is only reached through the pointer LibRaw calls back with.
+pub(crate) struct Datastream {
+    stream: *mut c_void,
+    source: NonNull<Source>,
+}
+
+struct Source {
+    inner: Box<dyn ReadSeek>,
+    // the first failure, LibRaw only sees a short read
+    error: Option<io::Error>,
+}
+
+impl Source {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.seek(SeekFrom::Start(offset))?;
+        let mut done = 0;
+        while done < buf.len() {
+            match self.inner.read(&mut buf[done..]) {
+                Ok(0) => break,
+                Ok(n) => done += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(done)
+    }
+}
+
+unsafe extern "C" fn read_at(
+    ctx: *mut c_void,
+    offset: c_longlong,
+    buf: *mut c_void,
+    len: usize,
+) -> c_longlong {
+    let source = &mut *(ctx as *mut Source);
+    let buf = slice::from_raw_parts_mut(buf as *mut u8, len);
+    // a panic mustn't unwind through LibRaw
+    let result = panic::catch_unwind(AssertUnwindSafe(|| source.read_at(offset as u64, buf)))
+        .unwrap_or_else(|_| Err(io::Error::other("raw data source panicked")));
+    match result {
+        Ok(n) => n as _,
+        Err(err) => {
+            source.error.get_or_insert(err);
+            -1
+        }
+    }
+}
+
+impl Datastream {
+    // the first read failure since the last call, LibRaw isn't reading then
+    pub(crate) fn take_error(&mut self) -> Option<io::Error> {
+        unsafe { (*self.source.as_ptr()).error.take() }
+    }
+}
+
+// only ever used by the one image that owns it, like the image itself
+unsafe impl Send for Datastream {}
+
+impl Drop for Datastream {
+    fn drop(&mut self) {
+        unsafe {
+            sys::rsraw_datastream_free(self.stream);
+            drop(Box::from_raw(self.source.as_ptr()));
+        }
+    }
+}
+
+impl RawImage {
+    // Like `open` for a source read on demand: open reads the headers, unpack
+    // the raw data and the thumbnail calls their previews, all in chunks of
+    // up to 64 KiB at whatever offsets LibRaw asks for. The source is kept
+    // until the image is dropped and must not change meanwhile. A failure
+    // reading it is returned as `Error::Fs`, by open as well as by unpack and
+    // the thumbnail calls.
+    pub fn open_stream(
+        mut source: impl RawDataSource + 'static,
+    ) -> std::result::Result<Self, ContextError> {
+        let len = source
+            .seek(SeekFrom::End(0))
+            .map_err(|err| ContextError::new(ErrorStage::Open, err.into()))?;
+        Self::open_datastream(len, Box::new(source))
+    }
+
+    // Like `open_stream` for a reader that borrows, e.g. an archive member
+    // tied to its archive, or isn't Send. It's read on demand the same way,
+    // nothing is buffered up front. The image lives in a `ReaderImage` that
+    // can't outlive the reader: it derefs to the `RawImage` for everything
+    // that reads and forwards the calls that decode.
+    pub fn open_reader<'r, R: Read + Seek + 'r>(
+        mut reader: R,
+    ) -> std::result::Result<ReaderImage<'r>, ContextError> {
+        let len = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|err| ContextError::new(ErrorStage::Open, err.into()))?;
+        let inner: Box<dyn ReadSeek + 'r> = Box::new(reader);
+        // the reader is only reached through the image, which `ReaderImage`
+        // never hands out by value or `&mut`, and both go before `'r` ends
+        let inner: Box<dyn ReadSeek> = unsafe { mem::transmute(inner) };
+        let image = Self::open_datastream(len, inner)?;
+        Ok(ReaderImage {
+            image,
+            reader: PhantomData,
+        })
+    }
+
+    fn open_datastream(
+        len: u64,
+        inner: Box<dyn ReadSeek>,
+    ) -> std::result::Result<Self, ContextError> {
+        let source = Box::into_raw(Box::new(Source { inner, error: None }));
+        let source = unsafe { NonNull::new_unchecked(source) };
+        let stream = unsafe {
+            sys::rsraw_datastream_new(source.as_ptr() as *mut c_void, Some(read_at), len as _)
+        };
+        if stream.is_null() {
+            unsafe { drop(Box::from_raw(source.as_ptr())) };
+            return Err(ContextError::new(
+                ErrorStage::Open,
+                Error::UnsufficientMemory,
+            ));
+        }
+        let mut datastream = Datastream { stream, source };
+        let result = Self::open_source(len, None, &Default::default(), |raw_data| unsafe {
+            sys::rsraw_open_datastream(raw_data, stream)
+        });
+        match result {
+            Ok(mut image) => {
+                image.datastream = Some(datastream);
+                Ok(image)
+            }
+            Err(diagnosis) => {
+                let mut err = ContextError::from(diagnosis);
+                // the source's own error says more than LibRaw's short read
+                if let Some(source) = datastream.take_error() {
+                    err.error = source.into();
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+// A `RawImage` reading from a borrowed reader, see `RawImage::open_reader`.
+// Neither Send nor Sync, the reader may be neither.
+pub struct ReaderImage<'r> {
+    image: RawImage,
+    reader: PhantomData<(&'r mut (), *mut ())>,
+}
+
+impl ReaderImage<'_> {
+    pub fn unpack(&mut self) -> std::result::Result<(), ContextError> {
+        self.image.unpack()
+    }
+
+    pub fn unpack_with(
+        &mut self,
+        decoder: DecoderPreference,
+    ) -> std::result::Result<(), ContextError> {
+        self.image.unpack_with(decoder)
+    }
+
+    pub fn process<const D: BitDepth>(
+        &mut self,
+    ) -> std::result::Result<ProcessedImage<D>, ContextError> {
+        self.image.process::<D>()
+    }
+
+    pub fn process_with<const D: BitDepth>(
+        &mut self,
+        params: &ProcessParams,
+    ) -> std::result::Result<ProcessedImage<D>, ContextError> {
+        self.image.process_with::<D>(params)
+    }
+
+    pub fn extract_thumbs(&mut self) -> std::result::Result<Vec<ThumbnailImage>, ContextError> {
+        self.image.extract_thumbs()
+    }
+
+    pub fn extract_thumb(
+        &mut self,
+        index: i32,
+    ) -> std::result::Result<ThumbnailImage, ContextError> {
+        self.image.extract_thumb(index)
+    }
+
+    pub fn extract_thumb_to(
+        &mut self,
+        index: i32,
+        out: impl Write,
+    ) -> std::result::Result<ThumbInfo, ContextError> {
+        self.image.extract_thumb_to(index, out)
+    }
+
+    pub fn extract_best_thumb(&mut self) -> std::result::Result<ThumbnailImage, ContextError> {
+        self.image.extract_best_thumb()
+    }
+
+    pub fn set_memory_limit_mb(&mut self, limit: u32) {
+        self.image.set_memory_limit_mb(limit)
+    }
+}
+
+impl Deref for ReaderImage<'_> {
+    type Target = RawImage;
+
+    fn deref(&self) -> &Self::Target {
+        &self.image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+    use std::{
+        io::Cursor,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    // reads fine up to `fail_at`, then errors
+    struct Flaky {
+        inner: Cursor<Vec<u8>>,
+        fail_at: u64,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.inner.position() >= self.fail_at {
+                return Err(io::Error::other("connection reset"));
+            }
+            let n = buf
+                .len()
+                .min((self.fail_at - self.inner.position()) as usize);
+            self.inner.read(&mut buf[..n])
+        }
+    }
+
+    impl Seek for Flaky {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    // fails every read once `broken` is set, a connection lost after open
+    struct Breakable {
+        inner: Cursor<Vec<u8>>,
+        broken: Arc<AtomicBool>,
+    }
+
+    impl Read for Breakable {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match self.broken.load(Ordering::Relaxed) {
+                true => Err(io::Error::other("connection reset")),
+                false => self.inner.read(buf),
+            }
+        }
+    }
+
+    impl Seek for Breakable {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_open_stream() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut expected = RawImage::open_owned(data.clone()).expect("opened");
+        let mut raw_image = RawImage::open_stream(Cursor::new(data.clone())).expect("opened");
+        assert_eq!(raw_image.model(), "Z 8");
+        assert_eq!(raw_image.thumb_infos(), expected.thumb_infos());
+        raw_image.unpack().expect("unpacked");
+        expected.unpack().expect("unpacked");
+        assert!(raw_image.raw_image() == expected.raw_image());
+
+        let flaky = Flaky {
+            inner: Cursor::new(data.clone()),
+            fail_at: 4096,
+        };
+        assert!(matches!(
+            RawImage::open_stream(flaky),
+            Err(ContextError {
+                stage: ErrorStage::Open,
+                error: Error::Fs(_),
+                ..
+            })
+        ));
+
+        let broken = Arc::new(AtomicBool::new(false));
+        let mut raw_image = RawImage::open_stream(Breakable {
+            inner: Cursor::new(data),
+            broken: broken.clone(),
+        })
+        .expect("opened");
+        broken.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            raw_image.extract_thumb(0).map_err(Error::from),
+            Err(Error::Fs(_))
+        ));
+        assert!(matches!(
+            raw_image.unpack().map_err(Error::from),
+            Err(Error::Fs(_))
+        ));
+    }
+
+    #[test]
+    fn test_open_reader() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut expected = RawImage::open_owned(data.clone()).expect("opened");
+        expected.unpack().expect("unpacked");
+
+        // borrowed and not Send, like a member of an archive being read
+        let shared = std::rc::Rc::new(data);
+        let mut raw_image = RawImage::open_reader(Cursor::new(&shared[..])).expect("opened");
+        assert_eq!(raw_image.model(), "Z 8");
+        assert_eq!(raw_image.thumb_infos(), expected.thumb_infos());
+        raw_image.unpack().expect("unpacked");
+        assert!(raw_image.raw_image() == expected.raw_image());
+
+        let flaky = Flaky {
+            inner: Cursor::new(shared.to_vec()),
+            fail_at: 4096,
+        };
+        assert!(matches!(
+            RawImage::open_reader(flaky).map_err(Error::from),
+            Err(Error::Fs(_))
+        ));
+    }
+}
@@ -0,0 +1,172 @@
+use rsraw_sys as sys;
+
+use crate::{
+    err::{Error, Result},
+    version::capabilities,
+    Warnings,
+};
+
+// Which LibRaw load_raw routine handles the file, known right after open.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DecoderInfo {
+    // the unpack function, e.g. "nikon_load_raw" or "sony_arw2_load_raw"
+    pub name: String,
+    // LibRaw_decoder_flags
+    pub flags: u32,
+}
+
+impl DecoderInfo {
+    fn has(&self, flag: sys::LibRaw_decoder_flags) -> bool {
+        self.flags & flag as u32 != 0
+    }
+
+    // the decoder applies a tone curve while loading
+    pub fn has_curve(&self) -> bool {
+        self.has(sys::LibRaw_decoder_flags_LIBRAW_DECODER_HASCURVE)
+    }
+
+    // one value per pixel without a CFA layout, as in linear DNGs
+    pub fn is_flat(&self) -> bool {
+        self.has(sys::LibRaw_decoder_flags_LIBRAW_DECODER_FLATDATA)
+    }
+
+    pub fn tries_rawspeed(&self) -> bool {
+        self.has(sys::LibRaw_decoder_flags_LIBRAW_DECODER_TRYRAWSPEED)
+            || self.has(sys::LibRaw_decoder_flags_LIBRAW_DECODER_TRYRAWSPEED3)
+    }
+
+    pub fn is_unsupported(&self) -> bool {
+        self.has(sys::LibRaw_decoder_flags_LIBRAW_DECODER_UNSUPPORTED_FORMAT)
+    }
+}
+
+// Which decoder `RawImage::unpack_with` should use. rawspeed is faster but
+// now and then wrong on edge-case files, forcing one or the other lets the
+// two be compared. Forcing a decoder the build doesn't have, see
+// `capabilities`, is `Error::NotImplemented`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecoderPreference {
+    // rawspeed, then the DNG SDK for the DNGs LibRaw hands it by default,
+    // then LibRaw's own
+    #[default]
+    Auto,
+    ForceRawspeed,
+    ForceNative,
+    ForceDngSdk,
+}
+
+// what decoded the raw data, see `Metrics::decoded_by`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Decoder {
+    Rawspeed,
+    DngSdk,
+    // LibRaw's own load_raw routine, named by `DecoderInfo`
+    Native,
+}
+
+impl DecoderPreference {
+    pub(crate) fn check(&self) -> Result<()> {
+        let caps = capabilities();
+        let available = match self {
+            Self::ForceRawspeed => caps.rawspeed || caps.rawspeed3,
+            Self::ForceDngSdk => caps.dng_sdk,
+            Self::Auto | Self::ForceNative => true,
+        };
+        match available {
+            true => Ok(()),
+            false => Err(Error::NotImplemented),
+        }
+    }
+
+    pub(crate) fn apply(&self, params: &mut sys::libraw_raw_unpack_params_t) {
+        let (rawspeed, dng_sdk) = match self {
+            Self::Auto => (1, sys::LibRaw_dng_processing_LIBRAW_DNG_DEFAULT),
+            Self::ForceRawspeed => (1, sys::LibRaw_dng_processing_LIBRAW_DNG_NONE),
+            Self::ForceNative => (0, sys::LibRaw_dng_processing_LIBRAW_DNG_NONE),
+            Self::ForceDngSdk => (0, sys::LibRaw_dng_processing_LIBRAW_DNG_ALL),
+        };
+        params.use_rawspeed = rawspeed;
+        params.use_dngsdk = dng_sdk as _;
+    }
+
+    // whether `decoder` is what was asked for, a forced decoder may still
+    // reject the file and leave it to LibRaw's own
+    pub(crate) fn allows(&self, decoder: Decoder) -> bool {
+        match self {
+            Self::Auto => true,
+            Self::ForceRawspeed => decoder == Decoder::Rawspeed,
+            Self::ForceNative => decoder == Decoder::Native,
+            Self::ForceDngSdk => decoder == Decoder::DngSdk,
+        }
+    }
+}
+
+impl Decoder {
+    // from the warnings an unpack raised
+    pub(crate) fn from_warnings(warnings: Warnings) -> Self {
+        if warnings.contains(Warnings::RAWSPEED_PROCESSED)
+            || warnings.contains(Warnings::RAWSPEED3_PROCESSED)
+        {
+            Self::Rawspeed
+        } else if warnings.contains(Warnings::DNGSDK_PROCESSED) {
+            Self::DngSdk
+        } else {
+            Self::Native
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, RawImage};
+
+    #[test]
+    fn test_decoder_info() {
+        let data = std::fs::read(get_test_assets_path().join("test-a7rm4.ARW")).unwrap();
+        let raw_image = RawImage::open_owned(data).expect("opened");
+        let info = raw_image.decoder_info();
+        assert_eq!(info.name, raw_image.metrics().decoder);
+        assert!(!info.is_flat());
+    }
+
+    #[test]
+    fn test_unpack_with() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        assert_eq!(raw_image.metrics().decoded_by, None);
+        raw_image
+            .unpack_with(DecoderPreference::ForceNative)
+            .expect("unpacked");
+        assert_eq!(raw_image.metrics().decoded_by, Some(Decoder::Native));
+        assert_eq!(raw_image.as_ref().rawparams.use_rawspeed, 0);
+        let mut auto = RawImage::open_owned(data).expect("opened");
+        auto.unpack().expect("unpacked");
+        assert_eq!(auto.as_ref().rawparams.use_rawspeed, 1);
+        assert!(auto.raw_image() == raw_image.raw_image());
+
+        // neither is part of the bundled build
+        let caps = crate::capabilities();
+        for (decoder, built) in [
+            (
+                DecoderPreference::ForceRawspeed,
+                caps.rawspeed || caps.rawspeed3,
+            ),
+            (DecoderPreference::ForceDngSdk, caps.dng_sdk),
+        ] {
+            if !built {
+                assert!(matches!(
+                    raw_image.unpack_with(decoder).map_err(Error::from),
+                    Err(Error::NotImplemented)
+                ));
+            }
+        }
+
+        let warnings = Warnings::from_bits(Warnings::RAWSPEED3_PROCESSED.bits());
+        assert_eq!(Decoder::from_warnings(warnings), Decoder::Rawspeed);
+        assert!(!DecoderPreference::ForceDngSdk.allows(Decoder::Native));
+        assert!(DecoderPreference::Auto.allows(Decoder::DngSdk));
+    }
+}
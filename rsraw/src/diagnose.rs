@@ -0,0 +1,125 @@
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{ContextError, Error, RawImage};
+
+// Why `RawImage::open_diagnosed` rejected a buffer, in terms an ingest UI can
+// act on. The camera fields are what identify read before giving up, `error`
+// says the same with the offset LibRaw stopped at.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OpenFailure {
+    // no camera could be identified, e.g. a JPEG or a plain TIFF
+    NotRaw,
+    // the file ends before the metadata LibRaw needs
+    Truncated,
+    // identify named the camera but found nothing it can decode. A fragment
+    // that still holds the complete header also ends up here.
+    UnsupportedCamera { make: String, model: String },
+    // the camera is known but its raw data is encrypted or uses a licensed
+    // codec LibRaw can't decode, such as Nikon's High Efficiency NEFs
+    Locked { make: String, model: String },
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenDiagnosis {
+    pub failure: OpenFailure,
+    // boxed, the camera names would make every `open` result this big
+    pub error: Box<ContextError>,
+}
+
+impl OpenDiagnosis {
+    pub(crate) fn new(image: &RawImage, len: u64, error: ContextError) -> Self {
+        let (make, model) = (image.make().into_owned(), image.model().into_owned());
+        // identify may parse the header of a cut off file and only then notice
+        // the image data is missing, the embedded previews give that away
+        let cut_off = image
+            .thumb_infos()
+            .iter()
+            .any(|thumb| thumb.offset as u64 + thumb.length as u64 > len);
+        let failure = match error.error {
+            _ if image.data_errors().eof || cut_off => OpenFailure::Truncated,
+            Error::Io => OpenFailure::Truncated,
+            Error::FileUnsupported | Error::NotImplemented if make.is_empty() => {
+                OpenFailure::NotRaw
+            }
+            Error::FileUnsupported | Error::NotImplemented
+                if image.decoder_info().is_unsupported() =>
+            {
+                OpenFailure::Locked { make, model }
+            }
+            Error::FileUnsupported | Error::NotImplemented => {
+                OpenFailure::UnsupportedCamera { make, model }
+            }
+            _ => OpenFailure::Other,
+        };
+        Self {
+            failure,
+            error: Box::new(error),
+        }
+    }
+}
+
+impl Display for OpenDiagnosis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.failure {
+            OpenFailure::NotRaw => write!(f, "not a raw file"),
+            OpenFailure::Truncated => write!(f, "the file is truncated"),
+            OpenFailure::UnsupportedCamera { make, model } => {
+                write!(f, "unsupported camera: {make} {model}")
+            }
+            OpenFailure::Locked { make, model } => {
+                write!(
+                    f,
+                    "{make} {model} raw data is encrypted or uses an unsupported codec"
+                )
+            }
+            OpenFailure::Other => write!(f, "failed to open the file"),
+        }
+    }
+}
+
+impl StdError for OpenDiagnosis {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<OpenDiagnosis> for ContextError {
+    fn from(diagnosis: OpenDiagnosis) -> Self {
+        *diagnosis.error
+    }
+}
+
+impl From<OpenDiagnosis> for Error {
+    fn from(diagnosis: OpenDiagnosis) -> Self {
+        diagnosis.error.error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_open_diagnosis() {
+        let not_raw: Vec<u8> = (0..4096u32).map(|i| (i * 31) as u8).collect();
+        let diagnosis = RawImage::open_owned_diagnosed(not_raw)
+            .err()
+            .expect("not raw");
+        assert_eq!(diagnosis.failure, OpenFailure::NotRaw);
+        assert_eq!(diagnosis.to_string(), "not a raw file");
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let diagnosis = RawImage::open_owned_diagnosed(data[..1000].to_vec())
+            .err()
+            .expect("truncated");
+        assert_eq!(diagnosis.failure, OpenFailure::Truncated);
+        assert_eq!(diagnosis.error.stage, crate::ErrorStage::Open);
+        assert!(matches!(Error::from(diagnosis), Error::Io));
+        assert!(RawImage::open_owned_diagnosed(data).is_ok());
+    }
+}
@@ -1,6 +1,8 @@
 use std::{
     error::Error as StdError,
     fmt::{self, Display, Formatter},
+    io,
+    sync::Arc,
 };
 
 use rsraw_sys as sys;
@@ -26,9 +28,34 @@ pub enum Error {
     BadCrop,
     TooBig,
     MempoolOverflow,
+    Fs(Arc<io::Error>),
+    // the file ends before its image data and may still be being written,
+    // see `tether::open`
+    Incomplete,
     Unknown(i32),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorStage {
+    Open,
+    Unpack,
+    Thumb(i32),
+    Process,
+}
+
+// An `Error` plus where it happened, returned by the opens, unpack, the
+// thumbnail extraction and process. Camera fields are `None` when identify
+// didn't get that far.
+#[derive(Debug, Clone)]
+pub struct ContextError {
+    pub stage: ErrorStage,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    // where LibRaw first ran into damaged or missing data, for io and data errors
+    pub offset: Option<u64>,
+    pub error: Error,
+}
+
 impl From<sys::LibRaw_errors> for Error {
     fn from(code: sys::LibRaw_errors) -> Self {
         match code {
@@ -67,6 +94,61 @@ impl Error {
         }
     }
 
+    pub fn code(&self) -> Option<i32> {
+        let code = match self {
+            Error::Success => sys::LibRaw_errors_LIBRAW_SUCCESS,
+            Error::Unspecified => sys::LibRaw_errors_LIBRAW_UNSPECIFIED_ERROR,
+            Error::FileUnsupported => sys::LibRaw_errors_LIBRAW_FILE_UNSUPPORTED,
+            Error::RequestForNonexistentImage => {
+                sys::LibRaw_errors_LIBRAW_REQUEST_FOR_NONEXISTENT_IMAGE
+            }
+            Error::OutOfOrderCall => sys::LibRaw_errors_LIBRAW_OUT_OF_ORDER_CALL,
+            Error::NoThumbnail => sys::LibRaw_errors_LIBRAW_NO_THUMBNAIL,
+            Error::UnsupportedThumbnail => sys::LibRaw_errors_LIBRAW_UNSUPPORTED_THUMBNAIL,
+            Error::InputClosed => sys::LibRaw_errors_LIBRAW_INPUT_CLOSED,
+            Error::NotImplemented => sys::LibRaw_errors_LIBRAW_NOT_IMPLEMENTED,
+            Error::RequestForNonexistentThumbnail => {
+                sys::LibRaw_errors_LIBRAW_REQUEST_FOR_NONEXISTENT_THUMBNAIL
+            }
+            Error::UnsufficientMemory => sys::LibRaw_errors_LIBRAW_UNSUFFICIENT_MEMORY,
+            Error::Data => sys::LibRaw_errors_LIBRAW_DATA_ERROR,
+            Error::Io => sys::LibRaw_errors_LIBRAW_IO_ERROR,
+            Error::CancelledByCallback => sys::LibRaw_errors_LIBRAW_CANCELLED_BY_CALLBACK,
+            Error::BadCrop => sys::LibRaw_errors_LIBRAW_BAD_CROP,
+            Error::TooBig => sys::LibRaw_errors_LIBRAW_TOO_BIG,
+            Error::MempoolOverflow => sys::LibRaw_errors_LIBRAW_MEMPOOL_OVERFLOW,
+            Error::Unknown(code) => return Some(*code),
+            Error::Fs(_) | Error::Incomplete => return None,
+        };
+        Some(code as _)
+    }
+
+    // LibRaw's description of the error code
+    pub fn message(&self) -> &'static str {
+        let Some(code) = self.code() else {
+            return match self {
+                Error::Incomplete => "File is still being written",
+                _ => "Filesystem error",
+            };
+        };
+        let msg = unsafe { std::ffi::CStr::from_ptr(sys::libraw_strerror(code)) };
+        msg.to_str().unwrap_or_default()
+    }
+
+    // per LIBRAW_FATAL_ERROR: the handle is unusable afterwards and has to be
+    // reopened, non-fatal errors only fail the call that returned them
+    pub fn is_fatal(&self) -> bool {
+        self.code().is_some_and(|code| code < -100000)
+    }
+
+    // allocation failures inside LibRaw and requests beyond the memory limit
+    pub fn is_out_of_memory(&self) -> bool {
+        matches!(
+            self,
+            Error::UnsufficientMemory | Error::TooBig | Error::MempoolOverflow
+        )
+    }
+
     pub fn repr(&self) -> &'static str {
         match self {
             Error::Success => "Success",
@@ -86,6 +168,8 @@ impl Error {
             Error::BadCrop => "BadCrop",
             Error::TooBig => "TooBig",
             Error::MempoolOverflow => "MempoolOverflow",
+            Error::Fs(_) => "FsError",
+            Error::Incomplete => "Incomplete",
             Error::Unknown(_) => "Unknown",
         }
     }
@@ -93,8 +177,101 @@ impl Error {
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "libraw error: {}", self.repr())
+        match self {
+            Error::Fs(err) => write!(f, "fs error: {}", err),
+            Error::Incomplete => write!(f, "the file is incomplete, retry once it is written"),
+            _ => write!(f, "libraw error: {}: {}", self.repr(), self.message()),
+        }
     }
 }
 
-impl StdError for Error {}
+impl ContextError {
+    pub fn new(stage: ErrorStage, error: Error) -> Self {
+        Self {
+            stage,
+            make: None,
+            model: None,
+            offset: None,
+            error,
+        }
+    }
+
+    // the LibRaw code of the underlying error, see `Error::code`
+    pub fn code(&self) -> Option<i32> {
+        self.error.code()
+    }
+}
+
+impl Display for ErrorStage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorStage::Open => write!(f, "open"),
+            ErrorStage::Unpack => write!(f, "unpack"),
+            ErrorStage::Thumb(index) => write!(f, "thumbnail {index}"),
+            ErrorStage::Process => write!(f, "process"),
+        }
+    }
+}
+
+impl Display for ContextError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed", self.stage)?;
+        match (&self.make, &self.model) {
+            (Some(make), Some(model)) => write!(f, " for {make} {model}")?,
+            (Some(camera), None) | (None, Some(camera)) => write!(f, " for {camera}")?,
+            (None, None) => {}
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " at byte {offset}")?;
+        }
+        Ok(())
+    }
+}
+
+impl StdError for ContextError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<ContextError> for Error {
+    fn from(err: ContextError) -> Self {
+        err.error
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Fs(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Fs(Arc::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let err = Error::from(sys::LibRaw_errors_LIBRAW_IO_ERROR);
+        assert!(matches!(err, Error::Io));
+        assert_eq!(err.code(), Some(sys::LibRaw_errors_LIBRAW_IO_ERROR as _));
+        assert!(err.is_fatal());
+        assert_eq!(err.to_string(), "libraw error: IoError: Input/output error");
+
+        assert!(!Error::FileUnsupported.is_fatal());
+        assert_eq!(
+            Error::FileUnsupported.message(),
+            "Unsupported file format or not RAW file"
+        );
+        assert_eq!(Error::Unknown(-42).code(), Some(-42));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    time::Duration,
+};
+
+use crate::{err::ErrorStage, progress::ProgressStage, Error, Warnings};
+
+// What a decode is doing, pushed as it happens so a GUI can repaint from its
+// event loop instead of polling the image. Every `Started` is followed by a
+// `Finished` of the same stage, a process that has to unpack first nests the
+// unpack inside it.
+#[derive(Debug, Clone)]
+pub enum DecodeEvent {
+    Started(ErrorStage),
+    Finished {
+        stage: ErrorStage,
+        elapsed: Duration,
+        // `None` when the stage succeeded
+        error: Option<Error>,
+    },
+    // LibRaw's coarse steps, as given to `on_progress`
+    Progress {
+        stage: ProgressStage,
+        iteration: i32,
+        expected: i32,
+    },
+    // warnings the stage that's about to finish raised, sent before its `Finished`
+    Warning(Warnings),
+}
+
+// Sending half handed to `RawImage::open_with_events` or `set_decode_events`.
+// Cheap to clone, one receiver can watch several images. A dropped receiver
+// doesn't fail the decode, events are discarded from then on.
+#[derive(Debug, Clone)]
+pub struct DecodeEvents {
+    sender: Sender<DecodeEvent>,
+}
+
+impl DecodeEvents {
+    pub fn new(sender: Sender<DecodeEvent>) -> Self {
+        Self { sender }
+    }
+
+    pub fn channel() -> (Self, Receiver<DecodeEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self::new(sender), receiver)
+    }
+
+    pub(crate) fn send(&self, event: DecodeEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, RawImage, BIT_DEPTH_8};
+
+    #[test]
+    fn test_decode_events() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let (events, receiver) = DecodeEvents::channel();
+        let mut raw_image = RawImage::open_owned_with_events(data.clone(), events).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        let _ = raw_image
+            .process_with::<BIT_DEPTH_8>(&Default::default())
+            .expect("processed");
+
+        let stages: Vec<_> = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                DecodeEvent::Started(stage) => Some((stage, true)),
+                DecodeEvent::Finished { stage, error, .. } => {
+                    assert!(error.is_none(), "{stage}: {error:?}");
+                    Some((stage, false))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            stages,
+            [
+                (ErrorStage::Open, true),
+                (ErrorStage::Open, false),
+                (ErrorStage::Unpack, true),
+                (ErrorStage::Unpack, false),
+                (ErrorStage::Process, true),
+                (ErrorStage::Process, false),
+            ]
+        );
+
+        // unpacks without being asked, reported inside the process
+        let (events, receiver) = DecodeEvents::channel();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        raw_image.set_decode_events(Some(events));
+        let _ = raw_image.process::<BIT_DEPTH_8>().expect("processed");
+        let events: Vec<_> = receiver.try_iter().collect();
+        assert!(matches!(
+            events[..2],
+            [
+                DecodeEvent::Started(ErrorStage::Process),
+                DecodeEvent::Started(ErrorStage::Unpack)
+            ]
+        ));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, DecodeEvent::Progress { .. })));
+        drop(receiver);
+        raw_image.process::<BIT_DEPTH_8>().expect("receiver gone");
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{btree_map, BTreeMap};
+
+#[cfg(feature = "exif")]
+use exif::{Field, In, Tag, Value};
+
+#[cfg(feature = "exif")]
+use crate::{
+    err::{Error, Result},
+    RawMetadata,
+};
+
+// Tags read by kamadak-exif straight from the file, for what LibRaw parses
+// but doesn't expose: sub-second times, UTC offsets, comments, serials.
+// Without the exif feature nothing reads them and they stay empty.
+// Keyed by the EXIF tag name, values rendered like exiftool prints them.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExifTags {
+    tags: BTreeMap<String, String>,
+}
+
+impl ExifTags {
+    // only TIFF based raws (NEF, ARW, DNG, ORF, PEF...) carry a plain EXIF
+    // block, CR3 and RAF fail with `FileUnsupported`
+    #[cfg(feature = "exif")]
+    pub fn read(buf: &[u8]) -> Result<Self> {
+        let exif = exif::Reader::new()
+            .read_raw(buf.to_vec())
+            .map_err(|err| match err {
+                exif::Error::Io(err) => Error::from(err),
+                _ => Error::FileUnsupported,
+            })?;
+        let mut tags = BTreeMap::new();
+        // thumbnail IFDs repeat the primary's tags with less interesting values,
+        // tags kamadak-exif has no name for are vendor blobs, like the maker note
+        let fields = exif.fields().filter(|f| {
+            f.ifd_num == In::PRIMARY && f.tag != Tag::MakerNote && f.tag.description().is_some()
+        });
+        for field in fields {
+            if let Some(value) = render(field, &exif) {
+                tags.entry(field.tag.to_string()).or_insert(value);
+            }
+        }
+        Ok(Self { tags })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.tags.get(name).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.tags.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    pub fn user_comment(&self) -> Option<&str> {
+        self.get("UserComment")
+    }
+
+    pub fn sub_sec_time_original(&self) -> Option<&str> {
+        self.get("SubSecTimeOriginal")
+    }
+
+    // e.g. "+02:00", the zone `datetime()` can't know about
+    pub fn offset_time_original(&self) -> Option<&str> {
+        self.get("OffsetTimeOriginal")
+    }
+
+    pub fn body_serial_number(&self) -> Option<&str> {
+        self.get("BodySerialNumber")
+    }
+
+    pub fn lens_serial_number(&self) -> Option<&str> {
+        self.get("LensSerialNumber")
+    }
+}
+
+impl<'a> IntoIterator for &'a ExifTags {
+    type Item = (&'a String, &'a String);
+    type IntoIter = btree_map::Iter<'a, String, String>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tags.iter()
+    }
+}
+
+#[cfg(feature = "exif")]
+impl RawMetadata {
+    // `buf` has to be the buffer the image was opened from
+    pub fn merge_exif(&mut self, buf: &[u8]) -> Result<()> {
+        self.exif = ExifTags::read(buf)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "exif")]
+fn render(field: &Field, exif: &exif::Exif) -> Option<String> {
+    let value = match &field.value {
+        Value::Ascii(parts) => parts
+            .iter()
+            .filter(|part| !part.is_empty())
+            .map(|part| String::from_utf8_lossy(part))
+            .collect::<Vec<_>>()
+            .join(", "),
+        // 8 byte charset id, then the comment, usually padded with spaces or nuls
+        Value::Undefined(bytes, _) if field.tag == Tag::UserComment => {
+            match bytes.split_at_checked(8) {
+                Some((b"UNICODE\0", text)) => {
+                    let units: Vec<u16> = text
+                        .chunks_exact(2)
+                        .map(|c| match exif.little_endian() {
+                            true => u16::from_le_bytes([c[0], c[1]]),
+                            false => u16::from_be_bytes([c[0], c[1]]),
+                        })
+                        .collect();
+                    String::from_utf16_lossy(&units)
+                }
+                Some((_, text)) => String::from_utf8_lossy(text).into_owned(),
+                None => return None,
+            }
+        }
+        _ => field.display_value().with_unit(exif).to_string(),
+    };
+    let value = value.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(all(test, feature = "exif"))]
+mod tests {
+    use crate::{raw::tests::get_test_assets_path, RawImage};
+
+    #[test]
+    fn test_exif_tags() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        let mut metadata = raw_image.metadata();
+        assert!(metadata.exif.is_empty());
+        metadata.merge_exif(&data).expect("z8 has exif");
+        let exif = &metadata.exif;
+        assert_eq!(exif.get("Model"), Some("NIKON Z 8"));
+        assert_eq!(exif.get("LensModel"), Some("NIKKOR Z MC 105mm f/2.8 VR S"));
+        assert_eq!(exif.sub_sec_time_original(), Some("66"));
+        assert_eq!(exif.offset_time_original(), Some("+08:00"));
+        assert_eq!(exif.lens_serial_number(), Some("20044280"));
+        assert!(exif.get("MakerNote").is_none());
+    }
+}
@@ -0,0 +1,654 @@
+// Files for publishing, with the capture metadata carried over from the raw:
+// camera and exposure in IFD0 and the EXIF IFD, the capture time with its UTC
+// offset when the file has one and the position in a GPS IFD. Pipelines that
+// mustn't leak where a picture was taken set `strip_location`.
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    FullRawInfo, GpsInfo, ProcessedImage, RawMetadata,
+};
+
+#[cfg(feature = "contact-sheet")]
+mod contact_sheet;
+#[cfg(feature = "fits")]
+mod fits;
+#[cfg(feature = "npy")]
+mod npy;
+mod pyramid;
+
+#[cfg(feature = "contact-sheet")]
+pub use contact_sheet::{contact_sheet, SheetFormat, SheetLayout};
+#[cfg(feature = "fits")]
+pub use fits::FitsKeywords;
+pub use pyramid::{pyramid, Pyramid, PyramidOptions, Tile};
+
+const TAG_IMAGE_WIDTH: u16 = 0x100;
+const TAG_IMAGE_LENGTH: u16 = 0x101;
+const TAG_BITS_PER_SAMPLE: u16 = 0x102;
+const TAG_COMPRESSION: u16 = 0x103;
+const TAG_PHOTOMETRIC: u16 = 0x106;
+const TAG_MAKE: u16 = 0x10f;
+const TAG_MODEL: u16 = 0x110;
+const TAG_STRIP_OFFSETS: u16 = 0x111;
+const TAG_SAMPLES_PER_PIXEL: u16 = 0x115;
+const TAG_ROWS_PER_STRIP: u16 = 0x116;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x117;
+const TAG_PLANAR_CONFIG: u16 = 0x11c;
+const TAG_DATETIME: u16 = 0x132;
+const TAG_ARTIST: u16 = 0x13b;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+
+const TAG_EXPOSURE_TIME: u16 = 0x829a;
+const TAG_F_NUMBER: u16 = 0x829d;
+const TAG_ISO: u16 = 0x8827;
+const TAG_EXIF_VERSION: u16 = 0x9000;
+const TAG_DATETIME_ORIGINAL: u16 = 0x9003;
+const TAG_DATETIME_DIGITIZED: u16 = 0x9004;
+const TAG_OFFSET_TIME: u16 = 0x9010;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_OFFSET_TIME_DIGITIZED: u16 = 0x9012;
+const TAG_FOCAL_LENGTH: u16 = 0x920a;
+
+const TAG_GPS_VERSION: u16 = 0;
+const TAG_GPS_LATITUDE_REF: u16 = 1;
+const TAG_GPS_LATITUDE: u16 = 2;
+const TAG_GPS_LONGITUDE_REF: u16 = 3;
+const TAG_GPS_LONGITUDE: u16 = 4;
+const TAG_GPS_ALTITUDE_REF: u16 = 5;
+const TAG_GPS_ALTITUDE: u16 = 6;
+const TAG_GPS_TIMESTAMP: u16 = 7;
+
+// APP1 segments are limited to 64 KB, marker length included
+const MAX_APP1_LEN: usize = 0xffff - 2;
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExportMetadata {
+    pub make: String,
+    pub model: String,
+    pub artist: String,
+    pub iso_speed: u32,
+    // seconds
+    pub shutter: f32,
+    pub aperture: f32,
+    pub focal_len: f32,
+    // the camera's wall clock as EXIF writes it, "YYYY:MM:DD HH:MM:SS"
+    pub datetime: Option<String>,
+    // its UTC offset, "+HH:MM". Only `From<&RawMetadata>` with the exif
+    // feature reads it from the file, otherwise it's `None` unless set.
+    pub offset: Option<String>,
+    // `None` or a `GpsInfo` without a position writes no GPS IFD
+    pub gps: Option<GpsInfo>,
+    // leave the GPS IFD out even with a position
+    pub strip_location: bool,
+}
+
+impl ExportMetadata {
+    // The capture time needs the chrono feature to get the wall clock back
+    // from LibRaw's timestamp. `FullRawInfo` knows nothing of the UTC offset,
+    // `From<&RawMetadata>` takes it from `merge_exif`'s tags, which need the
+    // exif feature.
+    pub fn new(info: &FullRawInfo) -> Self {
+        #[cfg(feature = "chrono")]
+        let datetime = info
+            .datetime
+            .filter(|_| info.timestamp() > 0)
+            .map(|dt| dt.format("%Y:%m:%d %H:%M:%S").to_string());
+        #[cfg(not(feature = "chrono"))]
+        let datetime = None;
+        Self {
+            make: info.make.clone(),
+            model: info.model.clone(),
+            artist: info.artist.clone(),
+            iso_speed: info.iso_speed,
+            shutter: info.shutter,
+            aperture: info.aperture,
+            focal_len: info.focal_len,
+            datetime,
+            offset: None,
+            gps: Some(info.gps).filter(GpsInfo::has_location),
+            strip_location: false,
+        }
+    }
+
+    // The EXIF block on its own, a TIFF structure in native byte order. This
+    // is what JPEG's APP1 segment, WebP's and PNG's `eXIf` chunks carry.
+    pub fn exif(&self) -> Vec<u8> {
+        let mut out = tiff_header();
+        let ifd0 = self.write_ifds(&mut out, Vec::new());
+        patch_u32(&mut out, 4, ifd0);
+        out
+    }
+
+    // Writes the EXIF and GPS IFDs, then IFD0 with `entries` and the tags
+    // pointing to them. Returns where IFD0 starts.
+    fn write_ifds(&self, out: &mut Vec<u8>, mut entries: Vec<Entry>) -> u32 {
+        let ascii = |tag, value: &str| (!value.is_empty()).then(|| Entry::ascii(tag, value));
+        entries.extend(ascii(TAG_MAKE, &self.make));
+        entries.extend(ascii(TAG_MODEL, &self.model));
+        entries.extend(ascii(TAG_ARTIST, &self.artist));
+
+        let mut exif = vec![Entry::new(
+            TAG_EXIF_VERSION,
+            Value::Undefined(b"0231".to_vec()),
+        )];
+        if self.iso_speed > 0 {
+            let iso = self.iso_speed.min(u16::MAX as _) as u16;
+            exif.push(Entry::new(TAG_ISO, Value::Short(vec![iso])));
+        }
+        if self.shutter > 0.0 {
+            let time = exposure_time(self.shutter);
+            exif.push(Entry::new(TAG_EXPOSURE_TIME, Value::Rational(vec![time])));
+        }
+        for (tag, value) in [
+            (TAG_F_NUMBER, self.aperture),
+            (TAG_FOCAL_LENGTH, self.focal_len),
+        ] {
+            if value > 0.0 {
+                exif.push(Entry::new(tag, Value::Rational(vec![rational(value)])));
+            }
+        }
+        if let Some(datetime) = &self.datetime {
+            entries.push(Entry::ascii(TAG_DATETIME, datetime));
+            exif.push(Entry::ascii(TAG_DATETIME_ORIGINAL, datetime));
+            exif.push(Entry::ascii(TAG_DATETIME_DIGITIZED, datetime));
+            if let Some(offset) = &self.offset {
+                for tag in [
+                    TAG_OFFSET_TIME,
+                    TAG_OFFSET_TIME_ORIGINAL,
+                    TAG_OFFSET_TIME_DIGITIZED,
+                ] {
+                    exif.push(Entry::ascii(tag, offset));
+                }
+            }
+        }
+        let exif = write_ifd(out, exif);
+        entries.push(Entry::new(TAG_EXIF_IFD, Value::Long(vec![exif])));
+
+        if let Some(gps) = self.location() {
+            let gps = write_ifd(out, gps_entries(gps));
+            entries.push(Entry::new(TAG_GPS_IFD, Value::Long(vec![gps])));
+        }
+        write_ifd(out, entries)
+    }
+
+    // `None` when stripped or unknown
+    pub fn location(&self) -> Option<&GpsInfo> {
+        self.gps
+            .as_ref()
+            .filter(|gps| !self.strip_location && gps.has_location())
+    }
+}
+
+impl From<&RawMetadata> for ExportMetadata {
+    fn from(metadata: &RawMetadata) -> Self {
+        Self {
+            offset: metadata.exif.offset_time_original().map(str::to_string),
+            ..Self::new(&metadata.info)
+        }
+    }
+}
+
+// The formats `ProcessedImage::encode_to_vec` writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Tiff,
+    #[cfg(feature = "png")]
+    Png,
+    #[cfg(feature = "jpeg")]
+    Jpeg(crate::JpegOptions),
+    #[cfg(feature = "webp")]
+    Webp(crate::WebpOptions),
+}
+
+impl Encoding {
+    // for a Content-Type header
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Tiff => "image/tiff",
+            #[cfg(feature = "png")]
+            Self::Png => "image/png",
+            #[cfg(feature = "jpeg")]
+            Self::Jpeg(_) => "image/jpeg",
+            #[cfg(feature = "webp")]
+            Self::Webp(_) => "image/webp",
+        }
+    }
+}
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // The image encoded in memory, e.g. for an HTTP response body, with
+    // `metadata` in the TIFF's IFDs, the PNG's `eXIf` chunk or the JPEG's
+    // APP1 segment; WebP is written without. JPEG and WebP are 8 bits and
+    // RGB only, see their `to_` functions.
+    pub fn encode_to_vec(&self, encoding: &Encoding, metadata: &ExportMetadata) -> Result<Vec<u8>> {
+        match encoding {
+            Encoding::Tiff => self.to_tiff(metadata),
+            #[cfg(feature = "png")]
+            Encoding::Png => self.to_png(metadata),
+            #[cfg(feature = "jpeg")]
+            Encoding::Jpeg(options) => with_exif(&self.to_jpeg(options)?, metadata),
+            #[cfg(feature = "webp")]
+            Encoding::Webp(options) => {
+                if self.colors() != 3 {
+                    return Err(Error::NotImplemented);
+                }
+                crate::webp::encode(self.width(), self.height(), &self.to_u8(), options)
+            }
+        }
+    }
+
+    // An uncompressed baseline TIFF at the image's bit depth with `metadata`
+    // in its EXIF and GPS IFDs. RGB and grayscale only, anything else is
+    // `Error::NotImplemented`.
+    pub fn to_tiff(&self, metadata: &ExportMetadata) -> Result<Vec<u8>> {
+        let colors = self.colors();
+        if !matches!(colors, 1 | 3) {
+            return Err(Error::NotImplemented);
+        }
+        let data = self.bytes();
+        let len = u32::try_from(data.len()).map_err(|_| Error::TooBig)?;
+        let mut out = tiff_header();
+        out.extend_from_slice(data);
+        let entries = vec![
+            Entry::new(TAG_IMAGE_WIDTH, Value::Long(vec![self.width()])),
+            Entry::new(TAG_IMAGE_LENGTH, Value::Long(vec![self.height()])),
+            Entry::new(TAG_BITS_PER_SAMPLE, Value::Short(vec![D as _; colors as _])),
+            Entry::new(TAG_COMPRESSION, Value::Short(vec![1])),
+            // RGB or BlackIsZero
+            Entry::new(
+                TAG_PHOTOMETRIC,
+                Value::Short(vec![if colors == 3 { 2 } else { 1 }]),
+            ),
+            Entry::new(TAG_STRIP_OFFSETS, Value::Long(vec![8])),
+            Entry::new(TAG_SAMPLES_PER_PIXEL, Value::Short(vec![colors])),
+            Entry::new(TAG_ROWS_PER_STRIP, Value::Long(vec![self.height()])),
+            Entry::new(TAG_STRIP_BYTE_COUNTS, Value::Long(vec![len])),
+            Entry::new(TAG_PLANAR_CONFIG, Value::Short(vec![1])),
+        ];
+        let ifd0 = metadata.write_ifds(&mut out, entries);
+        if out.len() > u32::MAX as usize {
+            return Err(Error::TooBig);
+        }
+        patch_u32(&mut out, 4, ifd0);
+        Ok(out)
+    }
+}
+
+// `jpeg` with its EXIF segments replaced by one with `metadata`, right after
+// the JFIF header if it has one. `Error::FileUnsupported` for anything that
+// isn't a JPEG, `Error::Data` if it ends before the image data.
+pub fn with_exif(jpeg: &[u8], metadata: &ExportMetadata) -> Result<Vec<u8>> {
+    if jpeg.get(..2) != Some(&[0xff, 0xd8]) {
+        return Err(Error::FileUnsupported);
+    }
+    let exif = metadata.exif();
+    if EXIF_HEADER.len() + exif.len() > MAX_APP1_LEN {
+        return Err(Error::TooBig);
+    }
+    let mut app1 = vec![0xff, 0xe1];
+    app1.extend_from_slice(&((2 + EXIF_HEADER.len() + exif.len()) as u16).to_be_bytes());
+    app1.extend_from_slice(EXIF_HEADER);
+    app1.extend_from_slice(&exif);
+
+    let mut out = jpeg[..2].to_vec();
+    let mut pos = 2;
+    let mut inserted = false;
+    loop {
+        let marker = match jpeg.get(pos..pos + 2) {
+            Some(&[0xff, marker]) => marker,
+            _ => return Err(Error::Data),
+        };
+        // everything from the start of scan on is copied as is
+        if marker == 0xda {
+            break;
+        }
+        let len = jpeg
+            .get(pos + 2..pos + 4)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .filter(|&len| len >= 2 && pos + 2 + len <= jpeg.len())
+            .ok_or(Error::Data)?;
+        let segment = &jpeg[pos..pos + 2 + len];
+        if !inserted && marker != 0xe0 {
+            out.extend_from_slice(&app1);
+            inserted = true;
+        }
+        if !(marker == 0xe1 && segment[4..].starts_with(EXIF_HEADER)) {
+            out.extend_from_slice(segment);
+        }
+        pos += 2 + len;
+    }
+    if !inserted {
+        out.extend_from_slice(&app1);
+    }
+    out.extend_from_slice(&jpeg[pos..]);
+    Ok(out)
+}
+
+fn gps_entries(gps: &GpsInfo) -> Vec<Entry> {
+    let dms = |v: [f32; 3]| Value::Rational(v.map(rational).to_vec());
+    let mut entries = vec![
+        Entry::new(TAG_GPS_VERSION, Value::Byte(vec![2, 3, 0, 0])),
+        Entry::new(TAG_GPS_LATITUDE, dms(gps.latitude)),
+        Entry::new(TAG_GPS_LONGITUDE, dms(gps.longitude)),
+        Entry::new(TAG_GPS_ALTITUDE_REF, Value::Byte(vec![gps.altref])),
+        Entry::new(
+            TAG_GPS_ALTITUDE,
+            Value::Rational(vec![rational(gps.altitude)]),
+        ),
+    ];
+    for (tag, reference) in [
+        (TAG_GPS_LATITUDE_REF, gps.latref),
+        (TAG_GPS_LONGITUDE_REF, gps.longref),
+    ] {
+        if reference.is_ascii_alphabetic() {
+            entries.push(Entry::ascii(tag, &reference.to_string()));
+        }
+    }
+    if gps.gpstimestamp != [0.0; 3] {
+        entries.push(Entry::new(TAG_GPS_TIMESTAMP, dms(gps.gpstimestamp)));
+    }
+    entries
+}
+
+// shutter speeds as 1/x where they are one, like cameras write them
+fn exposure_time(seconds: f32) -> (u32, u32) {
+    let inverse = 1.0 / seconds;
+    match seconds < 1.0 && (inverse - inverse.round()).abs() < 0.01 {
+        true => (1, inverse.round() as u32),
+        false => rational(seconds),
+    }
+}
+
+fn rational(v: f32) -> (u32, u32) {
+    let v = v.max(0.0);
+    if v.fract() == 0.0 {
+        (v.min(u32::MAX as f32) as u32, 1)
+    } else {
+        ((v * 10000.0).min(u32::MAX as f32).round() as u32, 10000)
+    }
+}
+
+enum Value {
+    Byte(Vec<u8>),
+    // nul terminated
+    Ascii(Vec<u8>),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    Undefined(Vec<u8>),
+}
+
+struct Entry {
+    tag: u16,
+    value: Value,
+}
+
+impl Entry {
+    fn new(tag: u16, value: Value) -> Self {
+        Self { tag, value }
+    }
+
+    fn ascii(tag: u16, value: &str) -> Self {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.push(0);
+        Self::new(tag, Value::Ascii(bytes))
+    }
+
+    // TIFF type, count and the value's bytes
+    fn encode(&self) -> (u16, u32, Vec<u8>) {
+        match &self.value {
+            Value::Byte(v) => (1, v.len() as _, v.clone()),
+            Value::Ascii(v) => (2, v.len() as _, v.clone()),
+            Value::Short(v) => (
+                3,
+                v.len() as _,
+                v.iter().flat_map(|v| v.to_ne_bytes()).collect(),
+            ),
+            Value::Long(v) => (
+                4,
+                v.len() as _,
+                v.iter().flat_map(|v| v.to_ne_bytes()).collect(),
+            ),
+            Value::Rational(v) => (
+                5,
+                v.len() as _,
+                v.iter()
+                    .flat_map(|(n, d)| [n.to_ne_bytes(), d.to_ne_bytes()])
+                    .flatten()
+                    .collect(),
+            ),
+            Value::Undefined(v) => (7, v.len() as _, v.clone()),
+        }
+    }
+}
+
+// byte order mark, magic and a zero IFD0 offset to patch
+fn tiff_header() -> Vec<u8> {
+    let mut out = match cfg!(target_endian = "little") {
+        true => b"II".to_vec(),
+        false => b"MM".to_vec(),
+    };
+    out.extend_from_slice(&42u16.to_ne_bytes());
+    out.extend_from_slice(&0u32.to_ne_bytes());
+    out
+}
+
+fn patch_u32(out: &mut [u8], pos: usize, value: u32) {
+    out[pos..pos + 4].copy_from_slice(&value.to_ne_bytes());
+}
+
+// Appends an IFD with its longer values right behind it, word aligned.
+// Returns its offset.
+fn write_ifd(out: &mut Vec<u8>, mut entries: Vec<Entry>) -> u32 {
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    entries.sort_by_key(|e| e.tag);
+    let start = out.len();
+    let mut data_pos = start + 2 + entries.len() * 12 + 4;
+    let mut data = Vec::new();
+    out.extend_from_slice(&(entries.len() as u16).to_ne_bytes());
+    for entry in &entries {
+        let (kind, count, mut bytes) = entry.encode();
+        out.extend_from_slice(&entry.tag.to_ne_bytes());
+        out.extend_from_slice(&kind.to_ne_bytes());
+        out.extend_from_slice(&count.to_ne_bytes());
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            out.extend_from_slice(&bytes);
+        } else {
+            out.extend_from_slice(&(data_pos as u32).to_ne_bytes());
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            data_pos += bytes.len();
+            data.extend_from_slice(&bytes);
+        }
+    }
+    out.extend_from_slice(&0u32.to_ne_bytes());
+    out.extend_from_slice(&data);
+    start as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        format::Tiff,
+        ifd::FileTiff,
+        raw::tests::{get_test_assets_path, linear_dng},
+        ProcessParams, RawImage, BIT_DEPTH_16,
+    };
+
+    fn metadata() -> ExportMetadata {
+        ExportMetadata {
+            make: "Nikon".into(),
+            model: "Z 8".into(),
+            shutter: 1.0 / 250.0,
+            aperture: 5.6,
+            datetime: Some("2024:05:01 12:30:00".into()),
+            offset: Some("+02:00".into()),
+            gps: Some(GpsInfo {
+                latitude: [48.0, 8.0, 12.5],
+                longitude: [11.0, 34.0, 0.0],
+                latref: 'N',
+                longref: 'E',
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    // the entries of IFD0 and the IFD it points to with `tag`
+    fn ifd(exif: &[u8], tag: u16) -> Option<Vec<(u16, u32)>> {
+        let tiff = FileTiff::new(exif)?;
+        let (ifd0, _) = tiff.ifd(tiff.u32_at(4)? as u64)?;
+        let (ifd, _) = tiff.ifd(ifd0.get(tag)?.value as u64)?;
+        Some(ifd.entries.iter().map(|e| (e.tag, e.value)).collect())
+    }
+
+    #[test]
+    fn test_exif() {
+        let exif = metadata().exif();
+        let tiff = Tiff {
+            buf: &exif,
+            le: exif[0] == b'I',
+        };
+        let gps = ifd(&exif, TAG_GPS_IFD).expect("gps ifd");
+        let (_, latitude) = gps.iter().find(|e| e.0 == TAG_GPS_LATITUDE).unwrap();
+        let seconds = *latitude as usize + 16;
+        assert_eq!(tiff.u32_at(seconds), Some(125000));
+        assert_eq!(tiff.u32_at(seconds + 4), Some(10000));
+        assert!(gps.contains(&(TAG_GPS_LATITUDE_REF, u32::from_ne_bytes(*b"N\0\0\0"))));
+
+        let exif_ifd = ifd(&exif, TAG_EXIF_IFD).expect("exif ifd");
+        let (_, offset) = exif_ifd
+            .iter()
+            .find(|e| e.0 == TAG_OFFSET_TIME_ORIGINAL)
+            .unwrap();
+        assert_eq!(&exif[*offset as usize..*offset as usize + 7], b"+02:00\0");
+        let (_, shutter) = exif_ifd.iter().find(|e| e.0 == TAG_EXPOSURE_TIME).unwrap();
+        assert_eq!(tiff.u32_at(*shutter as usize + 4), Some(250));
+
+        let private = ExportMetadata {
+            strip_location: true,
+            ..metadata()
+        };
+        assert!(ifd(&private.exif(), TAG_GPS_IFD).is_none());
+        assert!(ifd(&private.exif(), TAG_EXIF_IFD).is_some());
+    }
+
+    #[test]
+    fn test_with_exif() {
+        // SOI, JFIF, an old EXIF segment, then the scan
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0, 4, 1, 2];
+        jpeg.extend_from_slice(&[0xff, 0xe1, 0, 8]);
+        jpeg.extend_from_slice(EXIF_HEADER);
+        jpeg.extend_from_slice(&[0xff, 0xda, 0, 2, 9, 9, 0xff, 0xd9]);
+        let out = with_exif(&jpeg, &metadata()).expect("jpeg");
+        assert_eq!(out[..8], jpeg[..8]);
+        assert_eq!(&out[8..10], [0xff, 0xe1]);
+        assert_eq!(&out[12..18], EXIF_HEADER);
+        assert_eq!(&out[18..], [&metadata().exif()[..], &jpeg[18..]].concat());
+        assert!(matches!(
+            with_exif(b"RIFF", &metadata()),
+            Err(Error::FileUnsupported)
+        ));
+        assert!(matches!(
+            with_exif(&jpeg[..10], &metadata()),
+            Err(Error::Data)
+        ));
+    }
+
+    #[test]
+    fn test_to_tiff() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        let metadata = ExportMetadata::from(&raw_image.metadata());
+        assert_eq!(metadata.model, "Z 8");
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        let image = raw_image
+            .process_with::<BIT_DEPTH_16>(&params)
+            .expect("processed");
+        let tiff = image.to_tiff(&metadata).expect("tiff");
+        assert_eq!(&tiff[8..8 + image.data_size()], image.bytes());
+        let reader = FileTiff::new(&tiff[..]).expect("tiff");
+        let (ifd0, _) = reader.ifd(reader.u32_at(4).unwrap() as u64).unwrap();
+        let width = ifd0.get(TAG_IMAGE_WIDTH).unwrap();
+        assert_eq!(width.value, image.width());
+        assert!(ifd(&tiff, TAG_EXIF_IFD).is_some());
+    }
+
+    #[test]
+    fn test_encode_to_vec() {
+        let data = linear_dng();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let image = raw_image.process::<BIT_DEPTH_16>().expect("processed");
+        let tiff = image
+            .encode_to_vec(&Encoding::Tiff, &metadata())
+            .expect("tiff");
+        assert_eq!(tiff, image.to_tiff(&metadata()).unwrap());
+        assert_eq!(Encoding::Tiff.mime_type(), "image/tiff");
+
+        #[cfg(feature = "png")]
+        {
+            let png = image
+                .encode_to_vec(&Encoding::Png, &metadata())
+                .expect("png");
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+            assert_eq!(&png[12..16], b"IHDR");
+            // 16 bits, RGB
+            assert_eq!(png[24..26], [16, 2]);
+            let exif = metadata().exif();
+            assert_eq!(&png[37..41], b"eXIf");
+            assert_eq!(&png[41..41 + exif.len()], exif);
+            let idat = 41 + exif.len() + 4;
+            let len = u32::from_be_bytes(png[idat..idat + 4].try_into().unwrap()) as usize;
+            assert_eq!(&png[idat + 4..idat + 8], b"IDAT");
+            let filtered =
+                miniz_oxide::inflate::decompress_to_vec_zlib(&png[idat + 8..idat + 8 + len])
+                    .expect("inflated");
+            // undo the Up filter and compare with the samples
+            let stride = image.width() as usize * 6;
+            let mut rows: Vec<u8> = Vec::new();
+            for (i, row) in filtered.chunks_exact(stride + 1).enumerate() {
+                assert_eq!(row[0], 2);
+                for (j, &v) in row[1..].iter().enumerate() {
+                    let above = match i {
+                        0 => 0,
+                        _ => rows[(i - 1) * stride + j],
+                    };
+                    rows.push(v.wrapping_add(above));
+                }
+            }
+            let samples: Vec<u16> = rows
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .collect();
+            assert_eq!(samples, &image[..]);
+            assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+        }
+
+        #[cfg(feature = "jpeg")]
+        {
+            let options = crate::JpegOptions {
+                max_edge: 32,
+                ..Default::default()
+            };
+            let jpeg = image
+                .encode_to_vec(&Encoding::Jpeg(options), &metadata())
+                .expect("jpeg");
+            assert_eq!(&jpeg[..2], [0xff, 0xd8]);
+            assert!(jpeg.windows(EXIF_HEADER.len()).any(|w| w == EXIF_HEADER));
+            let (width, height, _) = crate::preview::decode_jpeg(&jpeg, 0).expect("decodes");
+            assert_eq!((width, height), (32, 32));
+        }
+    }
+}
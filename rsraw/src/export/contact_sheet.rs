@@ -0,0 +1,345 @@
+// Contact sheets: the previews of a set of files on one page, in a grid with
+// the file name and exposure under each, as a JPEG or as a one page PDF
+// wrapping that JPEG. Previews are what `quick_preview` finds, files that
+// don't open get an empty cell with their name so nothing goes missing
+// silently. Captions use an 8x8 bitmap font, ASCII only.
+
+use std::path::{Path, PathBuf};
+
+use font8x8::legacy::BASIC_LEGACY;
+use rayon::prelude::*;
+
+use crate::{
+    batch::thread_pool,
+    err::{Error, Result},
+    jpeg, FullRawInfo, JpegOptions, Preview, RawImage,
+};
+
+const BACKGROUND: [u8; 3] = [255; 3];
+const EMPTY_CELL: [u8; 3] = [224; 3];
+const TEXT: [u8; 3] = [32; 3];
+// caption lines per cell
+const LINES: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SheetFormat {
+    Jpeg,
+    Pdf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SheetLayout {
+    pub columns: u32,
+    // the longer edge of every preview, pixels
+    pub cell_size: u32,
+    // around the sheet and between cells
+    pub spacing: u32,
+    pub captions: bool,
+    pub format: SheetFormat,
+    // JPEG quality, 1 to 100
+    pub quality: u8,
+    // the PDF's page size follows from the pixels at this resolution
+    pub dpi: u32,
+}
+
+impl Default for SheetLayout {
+    fn default() -> Self {
+        Self {
+            columns: 4,
+            cell_size: 320,
+            spacing: 24,
+            captions: true,
+            format: SheetFormat::Jpeg,
+            quality: 90,
+            dpi: 150,
+        }
+    }
+}
+
+struct Cell {
+    name: String,
+    info: Option<FullRawInfo>,
+    preview: Option<Preview>,
+}
+
+// Files are decoded in parallel and laid out in the order given.
+// `Error::RequestForNonexistentImage` without any files.
+pub fn contact_sheet<P: AsRef<Path>>(
+    files: impl IntoIterator<Item = P>,
+    layout: &SheetLayout,
+) -> Result<Vec<u8>> {
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .map(|p| p.as_ref().to_path_buf())
+        .collect();
+    if files.is_empty() {
+        return Err(Error::RequestForNonexistentImage);
+    }
+    let pool = thread_pool(0)?;
+    let cells: Vec<Cell> = pool.install(|| {
+        files
+            .par_iter()
+            .map(|path| cell(path, layout.cell_size))
+            .collect()
+    });
+    let sheet = Sheet::draw(&cells, layout);
+    let jpeg = sheet.to_jpeg(layout.quality)?;
+    Ok(match layout.format {
+        SheetFormat::Jpeg => jpeg,
+        SheetFormat::Pdf => pdf(&jpeg, sheet.width, sheet.height, layout.dpi),
+    })
+}
+
+fn cell(path: &Path, size: u32) -> Cell {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let Ok(mut raw_image) = RawImage::open_file(path) else {
+        return Cell {
+            name,
+            info: None,
+            preview: None,
+        };
+    };
+    Cell {
+        name,
+        info: Some(raw_image.full_info()),
+        preview: raw_image.quick_preview(size).ok(),
+    }
+}
+
+// "1/250s f/2.8 ISO 100 50mm", whatever of it is known
+fn exposure(info: &FullRawInfo) -> String {
+    // one decimal at most, none for whole numbers
+    let short = |v: f32| (v * 10.0).round() / 10.0;
+    let mut parts = Vec::new();
+    match info.shutter {
+        s if s > 0.0 && s < 1.0 => parts.push(format!("1/{}s", (1.0 / s).round())),
+        s if s >= 1.0 => parts.push(format!("{}s", short(s))),
+        _ => {}
+    }
+    if info.aperture > 0.0 {
+        parts.push(format!("f/{}", short(info.aperture)));
+    }
+    if info.iso_speed > 0 {
+        parts.push(format!("ISO {}", info.iso_speed));
+    }
+    if info.focal_len > 0.0 {
+        parts.push(format!("{}mm", info.focal_len.round()));
+    }
+    parts.join(" ")
+}
+
+struct Sheet {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+impl Sheet {
+    fn draw(cells: &[Cell], layout: &SheetLayout) -> Self {
+        let columns = layout.columns.max(1).min(cells.len() as u32);
+        let rows = (cells.len() as u32).div_ceil(columns);
+        let size = layout.cell_size.max(1);
+        let scale = (size / 160).max(1);
+        let line = 10 * scale;
+        let caption = match layout.captions {
+            true => line * LINES + layout.spacing / 2,
+            false => 0,
+        };
+        let pitch_x = size + layout.spacing;
+        let pitch_y = size + caption + layout.spacing;
+        let mut sheet = Self {
+            width: columns * pitch_x + layout.spacing,
+            height: rows * pitch_y + layout.spacing,
+            rgb: Vec::new(),
+        };
+        sheet.rgb = BACKGROUND.repeat((sheet.width * sheet.height) as usize);
+
+        for (i, cell) in cells.iter().enumerate() {
+            let x = layout.spacing + (i as u32 % columns) * pitch_x;
+            let y = layout.spacing + (i as u32 / columns) * pitch_y;
+            match &cell.preview {
+                // centered in the square
+                Some(p) => sheet.blit(p, x + (size - p.width) / 2, y + (size - p.height) / 2),
+                None => sheet.fill(x, y, size, size, EMPTY_CELL),
+            }
+            if !layout.captions {
+                continue;
+            }
+            let chars = (size / (8 * scale)) as usize;
+            let details = match &cell.info {
+                Some(info) => exposure(info),
+                None => "unreadable".to_string(),
+            };
+            let y = y + size + layout.spacing / 4;
+            sheet.text(&cell.name, x, y, scale, chars);
+            sheet.text(&details, x, y + line, scale, chars);
+        }
+        sheet
+    }
+
+    fn blit(&mut self, preview: &Preview, x: u32, y: u32) {
+        let row = preview.width as usize * 3;
+        for (i, src) in preview.data.chunks_exact(row).enumerate() {
+            let start = ((y as usize + i) * self.width as usize + x as usize) * 3;
+            self.rgb[start..start + row].copy_from_slice(src);
+        }
+    }
+
+    fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, color: [u8; 3]) {
+        for row in y..y + height {
+            let start = (row as usize * self.width as usize + x as usize) * 3;
+            for px in self.rgb[start..start + width as usize * 3].chunks_exact_mut(3) {
+                px.copy_from_slice(&color);
+            }
+        }
+    }
+
+    // at most `chars` characters, the end of longer ones replaced by "..."
+    fn text(&mut self, text: &str, x: u32, y: u32, scale: u32, chars: usize) {
+        let mut text: Vec<char> = text.chars().collect();
+        if text.len() > chars {
+            text.truncate(chars.saturating_sub(3));
+            text.extend("...".chars());
+        }
+        for (i, c) in text.into_iter().enumerate() {
+            let c = if c.is_ascii() { c } else { '?' };
+            let glyph = BASIC_LEGACY[c as usize];
+            let left = x + i as u32 * 8 * scale;
+            for (gy, bits) in glyph.iter().enumerate() {
+                for gx in 0..8 {
+                    // the lowest bit is the leftmost pixel
+                    if bits & (1 << gx) != 0 {
+                        let (px, py) = (left + gx * scale, y + gy as u32 * scale);
+                        self.fill(px, py, scale, scale, TEXT);
+                    }
+                }
+            }
+        }
+    }
+
+    fn to_jpeg(&self, quality: u8) -> Result<Vec<u8>> {
+        let options = JpegOptions {
+            quality,
+            ..Default::default()
+        };
+        jpeg::encode(&self.rgb, self.width, self.height, &options)
+    }
+}
+
+// One page showing `jpeg` edge to edge, the JPEG embedded as is
+fn pdf(jpeg: &[u8], width: u32, height: u32, dpi: u32) -> Vec<u8> {
+    let points = |px: u32| px as f64 * 72.0 / dpi.max(1) as f64;
+    let (w, h) = (points(width), points(height));
+    let content = format!("q {w:.2} 0 0 {h:.2} 0 0 cm /Im0 Do Q");
+    let objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {w:.2} {h:.2}] \
+             /Resources << /XObject << /Im0 4 0 R >> >> /Contents 5 0 R >>"
+        )
+        .into_bytes(),
+        [
+            format!(
+                "<< /Type /XObject /Subtype /Image /Width {width} /Height {height} \
+                 /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode \
+                 /Length {} >>\nstream\n",
+                jpeg.len()
+            )
+            .as_bytes(),
+            jpeg,
+            b"\nendstream",
+        ]
+        .concat(),
+        format!(
+            "<< /Length {} >>\nstream\n{content}\nendstream",
+            content.len()
+        )
+        .into_bytes(),
+    ];
+
+    let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        out.extend_from_slice(object);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+    let xref = out.len();
+    out.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+    );
+    for offset in offsets {
+        out.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{preview::decode_jpeg, raw::tests::get_test_assets_path};
+
+    #[test]
+    fn test_contact_sheet() {
+        let assets = get_test_assets_path();
+        let files = [
+            assets.join("test-z8.NEF"),
+            assets.join("test-a7rm4.ARW"),
+            assets.join("missing.NEF"),
+        ];
+        let layout = SheetLayout {
+            columns: 2,
+            cell_size: 160,
+            spacing: 16,
+            ..Default::default()
+        };
+        let jpeg = contact_sheet(&files, &layout).expect("sheet");
+        let (width, height, rgb) = decode_jpeg(&jpeg, 0).expect("jpeg");
+        // two columns, two rows of a 160 px square, two caption lines and spacing
+        assert_eq!(
+            (width, height),
+            (16 + 2 * 176, 16 + 2 * (160 + 20 + 8 + 16))
+        );
+        // the missing file's empty cell
+        let at = |x: u32, y: u32| rgb[((y * width + x) * 3) as usize];
+        assert!(at(16 + 80, 16 + 204 + 80).abs_diff(EMPTY_CELL[0]) < 8);
+        assert!(at(4, 4) > 240);
+
+        let pdf = contact_sheet(
+            &files[..1],
+            &SheetLayout {
+                format: SheetFormat::Pdf,
+                ..layout
+            },
+        )
+        .expect("pdf");
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert!(matches!(
+            contact_sheet(Vec::<PathBuf>::new(), &layout),
+            Err(Error::RequestForNonexistentImage)
+        ));
+    }
+
+    #[test]
+    fn test_exposure() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut info = RawImage::open_owned(data).expect("opened").full_info();
+        (info.shutter, info.aperture, info.iso_speed, info.focal_len) = (0.004, 2.8, 100, 50.0);
+        assert_eq!(exposure(&info), "1/250s f/2.8 ISO 100 50mm");
+        (info.shutter, info.aperture, info.iso_speed, info.focal_len) = (30.0, 8.0, 0, 0.0);
+        assert_eq!(exposure(&info), "30s f/8");
+    }
+}
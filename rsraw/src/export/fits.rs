@@ -0,0 +1,337 @@
+// FITS for astrophotography stacking (Siril, PixInsight): the black
+// subtracted mosaic with its Bayer pattern, or a linear develop as three
+// planes. 16 bit samples are stored as FITS' signed integers with BZERO 32768.
+// Rows are written top down and say so in ROWORDER, as Siril and N.I.N.A. do,
+// so BAYERPAT reads from the top left corner.
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    FullRawInfo, ProcessedImage, RawImage,
+};
+
+const BLOCK: usize = 2880;
+const CARD: usize = 80;
+// LibRaw's value for temperatures the makernotes don't have
+const NO_TEMPERATURE: f32 = -1000.0;
+
+// The capture keywords, written when known
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FitsKeywords {
+    // INSTRUME, make and model
+    pub instrument: String,
+    // EXPTIME, seconds
+    pub exposure: f32,
+    // ISOSPEED
+    pub iso_speed: u32,
+    // CCD-TEMP, °C
+    pub temperature: Option<f32>,
+    // DATE-OBS, UTC as "YYYY-MM-DDTHH:MM:SS"
+    pub date_obs: Option<String>,
+    // FOCALLEN, mm
+    pub focal_len: f32,
+    // FOCRATIO
+    pub aperture: f32,
+}
+
+impl FitsKeywords {
+    // Without a temperature, `FullRawInfo` doesn't carry one. The capture time
+    // needs the chrono feature.
+    pub fn new(info: &FullRawInfo) -> Self {
+        #[cfg(feature = "chrono")]
+        let date_obs = info
+            .datetime
+            .filter(|_| info.timestamp() > 0)
+            .map(|dt| dt.naive_utc().format("%Y-%m-%dT%H:%M:%S").to_string());
+        #[cfg(not(feature = "chrono"))]
+        let date_obs = None;
+        Self {
+            instrument: format!("{} {}", info.make, info.model).trim().to_string(),
+            exposure: info.shutter,
+            iso_speed: info.iso_speed,
+            temperature: None,
+            date_obs,
+            focal_len: info.focal_len,
+            aperture: info.aperture,
+        }
+    }
+}
+
+impl RawImage {
+    // The sensor's temperature from the makernotes, the camera body's or the
+    // EXIF ambient temperature when that's all there is. °C.
+    pub fn sensor_temperature(&self) -> Option<f32> {
+        let common = &self.as_ref().makernotes.common;
+        [
+            common.SensorTemperature,
+            common.CameraTemperature,
+            common.exifAmbientTemperature,
+        ]
+        .into_iter()
+        .find(|&t| t > NO_TEMPERATURE)
+    }
+
+    pub fn fits_keywords(&self) -> FitsKeywords {
+        FitsKeywords {
+            temperature: self.sensor_temperature(),
+            ..FitsKeywords::new(&self.full_info())
+        }
+    }
+
+    // The visible area of the unpacked mosaic with the black level taken off,
+    // as a 16 bit FITS with `fits_keywords`. `Error::OutOfOrderCall` before
+    // `unpack`, `Error::NotImplemented` for files without a mosaic.
+    pub fn mosaic_to_fits(&self) -> Result<Vec<u8>> {
+        let visible = self.visible_mosaic()?;
+        let (width, height) = (visible.width, visible.height);
+
+        let mut header = Header::new(16, &[width, height]);
+        header.keywords(&self.fits_keywords());
+        if let Some(pattern) = bayer_pattern(self.filters(), &self.channel_description()) {
+            header.string("BAYERPAT", &pattern, "CFA from the top left");
+            header.int("XBAYROFF", 0, "");
+            header.int("YBAYROFF", 0, "");
+        }
+        let white = visible
+            .maximum
+            .saturating_sub(self.as_ref().rawdata.color.black);
+        header.int("DATAMAX", white as i64, "saturation, black subtracted");
+        let mut out = header.finish();
+        out.reserve(width * height * 2 + BLOCK);
+        for row in 0..height {
+            for (col, &v) in visible.row(row).iter().enumerate() {
+                let v = (v as u32)
+                    .saturating_sub(visible.black.at(row, col))
+                    .min(u16::MAX as _);
+                out.extend_from_slice(&(v as u16 ^ 0x8000).to_be_bytes());
+            }
+        }
+        pad(&mut out, 0);
+        Ok(out)
+    }
+}
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // The bitmap as a FITS cube, one plane per color. Meant for linear
+    // develops, `gamma: (1.0, 1.0)` and `no_auto_bright`.
+    pub fn to_fits(&self, keywords: &FitsKeywords) -> Result<Vec<u8>> {
+        let channels = self.colors() as usize;
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let axes = match channels {
+            1 => vec![width, height],
+            _ => vec![width, height, channels],
+        };
+        let mut header = Header::new(self.bits() as _, &axes);
+        header.keywords(keywords);
+        let mut out = header.finish();
+        let bytes = self.bytes();
+        let plane = width * height;
+        out.reserve(bytes.len() + BLOCK);
+        for channel in 0..channels {
+            match self.bits() {
+                16 => {
+                    for i in 0..plane {
+                        let pos = (i * channels + channel) * 2;
+                        let v = u16::from_ne_bytes([bytes[pos], bytes[pos + 1]]);
+                        out.extend_from_slice(&(v ^ 0x8000).to_be_bytes());
+                    }
+                }
+                8 => out.extend((0..plane).map(|i| bytes[i * channels + channel])),
+                _ => return Err(Error::NotImplemented),
+            }
+        }
+        pad(&mut out, 0);
+        Ok(out)
+    }
+}
+
+// "RGGB" and the like for a 2x2 Bayer layout, `None` for X-Trans, Leaf or
+// linear data
+fn bayer_pattern(filters: u32, cdesc: &str) -> Option<String> {
+    if filters < 1000 {
+        return None;
+    }
+    let cdesc: Vec<char> = cdesc.chars().collect();
+    (0..2)
+        .flat_map(|row| (0..2).map(move |col| (row, col)))
+        .map(|(row, col)| {
+            let color = (filters >> ((((row << 1) & 14) | (col & 1)) << 1)) & 3;
+            cdesc.get(color as usize).copied()
+        })
+        .collect()
+}
+
+fn pad(out: &mut Vec<u8>, fill: u8) {
+    out.resize(out.len().div_ceil(BLOCK) * BLOCK, fill);
+}
+
+// 80 column cards, fixed format
+struct Header(Vec<u8>);
+
+impl Header {
+    fn new(bitpix: i64, axes: &[usize]) -> Self {
+        let mut header = Self(Vec::new());
+        header.raw("SIMPLE", "T", "");
+        header.int("BITPIX", bitpix, "");
+        header.int("NAXIS", axes.len() as _, "");
+        for (i, &n) in axes.iter().enumerate() {
+            header.int(&format!("NAXIS{}", i + 1), n as _, "");
+        }
+        if bitpix == 16 {
+            header.raw("BZERO", "32768", "unsigned 16 bit samples");
+            header.raw("BSCALE", "1", "");
+        }
+        header.string("ROWORDER", "TOP-DOWN", "");
+        header
+    }
+
+    fn keywords(&mut self, keywords: &FitsKeywords) {
+        if !keywords.instrument.is_empty() {
+            self.string("INSTRUME", &keywords.instrument, "");
+        }
+        if let Some(date) = &keywords.date_obs {
+            self.string("DATE-OBS", date, "UTC");
+        }
+        if keywords.exposure > 0.0 {
+            self.float("EXPTIME", keywords.exposure, "seconds");
+        }
+        if keywords.iso_speed > 0 {
+            self.int("ISOSPEED", keywords.iso_speed as _, "");
+        }
+        if let Some(t) = keywords.temperature {
+            self.float("CCD-TEMP", t, "degrees C");
+        }
+        if keywords.focal_len > 0.0 {
+            self.float("FOCALLEN", keywords.focal_len, "mm");
+        }
+        if keywords.aperture > 0.0 {
+            self.float("FOCRATIO", keywords.aperture, "");
+        }
+    }
+
+    // the value right aligned to column 30
+    fn raw(&mut self, key: &str, value: &str, comment: &str) {
+        self.card(key, &format!("{value:>20}"), comment);
+    }
+
+    fn int(&mut self, key: &str, value: i64, comment: &str) {
+        self.raw(key, &value.to_string(), comment);
+    }
+
+    // Display, unlike Debug, never goes to an exponent
+    fn float(&mut self, key: &str, value: f32, comment: &str) {
+        let mut value = value.to_string();
+        if !value.contains('.') {
+            value.push_str(".0");
+        }
+        self.raw(key, &value, comment);
+    }
+
+    // quoted, at least 8 characters between the quotes
+    fn string(&mut self, key: &str, value: &str, comment: &str) {
+        let value: String = value
+            .chars()
+            .filter(|c| c.is_ascii() && !c.is_ascii_control())
+            .collect::<String>()
+            .replace('\'', "''");
+        self.card(key, &format!("'{value:<8}'"), comment);
+    }
+
+    fn card(&mut self, key: &str, value: &str, comment: &str) {
+        let mut card = format!("{key:<8}= {value}");
+        if !comment.is_empty() {
+            card.push_str(" / ");
+            card.push_str(comment);
+        }
+        let mut card = card.into_bytes();
+        card.resize(CARD, b' ');
+        self.0.extend_from_slice(&card);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let mut end = b"END".to_vec();
+        end.resize(CARD, b' ');
+        self.0.extend_from_slice(&end);
+        pad(&mut self.0, b' ');
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    // the value of a card, without quotes and comment
+    fn value<'a>(fits: &'a [u8], key: &str) -> Option<&'a str> {
+        fits.chunks(CARD)
+            .take_while(|card| !card.starts_with(b"END "))
+            .map(|card| std::str::from_utf8(card).unwrap())
+            .find(|card| card[..8].trim_end() == key)
+            .map(|card| {
+                card[10..]
+                    .split(" /")
+                    .next()
+                    .unwrap()
+                    .trim()
+                    .trim_matches('\'')
+            })
+            .map(str::trim_end)
+    }
+
+    fn data_start(fits: &[u8]) -> usize {
+        let end = fits
+            .chunks(CARD)
+            .position(|c| c.starts_with(b"END "))
+            .unwrap();
+        ((end + 1) * CARD).div_ceil(BLOCK) * BLOCK
+    }
+
+    #[test]
+    fn test_header() {
+        let mut header = Header::new(16, &[3, 2]);
+        header.string("INSTRUME", "it's", "");
+        header.float("EXPTIME", 30.0, "");
+        let fits = header.finish();
+        assert_eq!(fits.len(), BLOCK);
+        assert_eq!(&fits[..30], b"SIMPLE  =                    T");
+        assert_eq!(&fits[CARD..CARD + 30], b"BITPIX  =                   16");
+        assert_eq!(value(&fits, "INSTRUME"), Some("it''s"));
+        assert_eq!(value(&fits, "BZERO"), Some("32768"));
+        assert_eq!(value(&fits, "EXPTIME"), Some("30.0"));
+        assert_eq!(bayer_pattern(0x94949494, "RGBG").as_deref(), Some("RGGB"));
+        assert_eq!(bayer_pattern(9, "RGBG"), None);
+    }
+
+    #[test]
+    fn test_to_fits() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(matches!(
+            raw_image.mosaic_to_fits(),
+            Err(Error::OutOfOrderCall)
+        ));
+        raw_image.unpack().expect("unpacked");
+        let fits = raw_image.mosaic_to_fits().expect("fits");
+        let (width, height) = (raw_image.width() as usize, raw_image.height() as usize);
+        assert_eq!(fits.len() % BLOCK, 0);
+        assert_eq!(value(&fits, "NAXIS1"), Some(width.to_string().as_str()));
+        assert_eq!(value(&fits, "NAXIS2"), Some(height.to_string().as_str()));
+        assert_eq!(value(&fits, "INSTRUME"), Some("Nikon Z 8"));
+        assert!(value(&fits, "BAYERPAT").is_some());
+        assert!(value(&fits, "EXPTIME").is_some());
+        assert!(fits.len() - data_start(&fits) >= width * height * 2);
+
+        let image = raw_image
+            .process_with::<BIT_DEPTH_8>(&Default::default())
+            .expect("processed");
+        let fits = image.to_fits(&raw_image.fits_keywords()).expect("fits");
+        assert_eq!(value(&fits, "BITPIX"), Some("8"));
+        assert_eq!(value(&fits, "NAXIS3"), Some("3"));
+        assert_eq!(value(&fits, "BZERO"), None);
+        // the first plane is red
+        let start = data_start(&fits);
+        assert_eq!(fits.len() - start, image.len().div_ceil(BLOCK) * BLOCK);
+        assert_eq!(fits[start + 1], image[3]);
+    }
+}
@@ -0,0 +1,120 @@
+// NumPy `.npy` files of normalized float32 data for Python research code:
+// the mosaic as (height, width) the way `tensor` normalizes it, processed
+// images as (height, width, channels) scaled to 0..1 by their bit depth.
+// `numpy.load` reads them as is.
+
+use crate::{err::Result, raw::BitDepth, ProcessedImage, RawImage};
+
+const MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+// header and padding end on this, so the data is aligned for memory mapping
+const ALIGN: usize = 64;
+
+impl RawImage {
+    // `Error::OutOfOrderCall` before `unpack`, `Error::NotImplemented` for
+    // files without a mosaic
+    pub fn mosaic_to_npy(&self) -> Result<Vec<u8>> {
+        let (samples, [_, height, width]) = self.normalized_mosaic()?;
+        let mut out = header(&[height, width]);
+        out.reserve(samples.len() * 4);
+        for v in samples {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        Ok(out)
+    }
+}
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // gray images are (height, width)
+    pub fn to_npy(&self) -> Vec<u8> {
+        let (width, height) = (self.width() as usize, self.height() as usize);
+        let mut out = match self.colors() {
+            1 => header(&[height, width]),
+            colors => header(&[height, width, colors as usize]),
+        };
+        let bytes = self.bytes();
+        match self.bits() {
+            16 => {
+                out.reserve(bytes.len() * 2);
+                for b in bytes.chunks_exact(2) {
+                    let v = u16::from_ne_bytes([b[0], b[1]]) as f32 / u16::MAX as f32;
+                    out.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+            _ => {
+                out.reserve(bytes.len() * 4);
+                for &b in bytes {
+                    out.extend_from_slice(&(b as f32 / u8::MAX as f32).to_le_bytes());
+                }
+            }
+        }
+        out
+    }
+}
+
+// version 1.0: magic, a little endian u16 length and the array's description
+// as a Python dict literal, padded with spaces and closed by a newline
+fn header(shape: &[usize]) -> Vec<u8> {
+    let dims: Vec<String> = shape.iter().map(usize::to_string).collect();
+    // a one element tuple needs its trailing comma
+    let shape = match dims.len() {
+        1 => format!("({},)", dims[0]),
+        _ => format!("({})", dims.join(", ")),
+    };
+    let mut dict = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {shape}, }}");
+    let len = MAGIC.len() + 2 + dict.len() + 1;
+    dict.extend(std::iter::repeat_n(' ', len.next_multiple_of(ALIGN) - len));
+    dict.push('\n');
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    out.extend_from_slice(dict.as_bytes());
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, Error, BIT_DEPTH_8};
+
+    fn dict(npy: &[u8]) -> &str {
+        let len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        std::str::from_utf8(&npy[10..10 + len]).unwrap()
+    }
+
+    #[test]
+    fn test_header() {
+        let npy = header(&[3]);
+        assert_eq!(npy.len() % ALIGN, 0);
+        assert!(npy.starts_with(MAGIC));
+        assert!(dict(&npy).contains("'shape': (3,)"));
+        assert!(dict(&npy).ends_with('\n'));
+    }
+
+    #[test]
+    fn test_to_npy() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(matches!(
+            raw_image.mosaic_to_npy(),
+            Err(Error::OutOfOrderCall)
+        ));
+        let image = raw_image
+            .process_with::<BIT_DEPTH_8>(&Default::default())
+            .expect("processed");
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let npy = image.to_npy();
+        let start = 10 + dict(&npy).len();
+        assert!(dict(&npy).contains(&format!("'shape': ({height}, {width}, 3)")));
+        assert_eq!(npy.len() - start, width * height * 3 * 4);
+        let second = f32::from_le_bytes(npy[start + 4..start + 8].try_into().unwrap());
+        assert_eq!(second, image[1] as f32 / 255.0);
+
+        let npy = raw_image.mosaic_to_npy().expect("npy");
+        let (width, height) = (raw_image.width(), raw_image.height());
+        assert!(dict(&npy).contains(&format!("'shape': ({height}, {width})")));
+        assert_eq!(
+            npy.len() - 10 - dict(&npy).len(),
+            (width * height) as usize * 4
+        );
+    }
+}
@@ -0,0 +1,345 @@
+// Deep zoom pyramids for web viewers: every level half the size of the one
+// above, from the developed image down to a single pixel, cut into tiles.
+// Levels are numbered the Deep Zoom way, 0 is the 1x1 level and the last one
+// is full size. `Pyramid::dzi` writes the descriptor for OpenSeadragon and
+// friends, `Pyramid::to_tiff` a tiled pyramidal TIFF for IIIF image servers.
+
+use super::{
+    tiff_header, write_ifd, Entry, ExportMetadata, Value, TAG_BITS_PER_SAMPLE, TAG_COMPRESSION,
+    TAG_IMAGE_LENGTH, TAG_IMAGE_WIDTH, TAG_PHOTOMETRIC, TAG_PLANAR_CONFIG, TAG_SAMPLES_PER_PIXEL,
+};
+use crate::{
+    convert,
+    err::{Error, Result},
+    raw::BitDepth,
+    ProcessedImage,
+};
+
+const TAG_NEW_SUBFILE_TYPE: u16 = 0xfe;
+const TAG_TILE_WIDTH: u16 = 0x142;
+const TAG_TILE_LENGTH: u16 = 0x143;
+const TAG_TILE_OFFSETS: u16 = 0x144;
+const TAG_TILE_BYTE_COUNTS: u16 = 0x145;
+// NewSubfileType of the levels below full size
+const SUBFILE_REDUCED: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PyramidOptions {
+    pub tile_size: u32,
+    // pixels repeated from the neighbouring tiles on every inner edge, Deep
+    // Zoom only, TIFF tiles never overlap
+    pub overlap: u32,
+}
+
+impl Default for PyramidOptions {
+    fn default() -> Self {
+        Self {
+            tile_size: 254,
+            overlap: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pyramid {
+    options: PyramidOptions,
+    // 8 bit RGB, smallest first
+    levels: Vec<Level>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Level {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+// One tile of a level, stored by Deep Zoom viewers as `{level}/{col}_{row}`
+// next to the descriptor's `_files` directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub level: u32,
+    pub col: u32,
+    pub row: u32,
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Vec<u8>,
+}
+
+// Every level of `image`, 16 bit images dithered down to 8 bits. RGB only,
+// `Error::NotImplemented` for other outputs and a zero tile size.
+pub fn pyramid<const D: BitDepth>(
+    image: &ProcessedImage<D>,
+    options: &PyramidOptions,
+) -> Result<Pyramid> {
+    if image.colors() != 3 || options.tile_size == 0 {
+        return Err(Error::NotImplemented);
+    }
+    let (width, height) = (image.width(), image.height());
+    Ok(Pyramid {
+        options: *options,
+        levels: levels(width, height, image.to_u8().into_owned()),
+    })
+}
+
+// halved until a single pixel is left, smallest first
+fn levels(width: u32, height: u32, rgb: Vec<u8>) -> Vec<Level> {
+    let mut levels = vec![Level { width, height, rgb }];
+    while let Some(last) = levels.last().filter(|l| l.width > 1 || l.height > 1) {
+        let (w, h) = (last.width.div_ceil(2), last.height.div_ceil(2));
+        let rgb =
+            convert::downscale_rgb8(&last.rgb, last.width as _, last.height as _, w as _, h as _);
+        levels.push(Level {
+            width: w,
+            height: h,
+            rgb,
+        });
+    }
+    levels.reverse();
+    levels
+}
+
+impl Pyramid {
+    pub fn options(&self) -> &PyramidOptions {
+        &self.options
+    }
+
+    pub fn width(&self) -> u32 {
+        self.levels.last().map_or(0, |l| l.width)
+    }
+
+    pub fn height(&self) -> u32 {
+        self.levels.last().map_or(0, |l| l.height)
+    }
+
+    pub fn level_count(&self) -> u32 {
+        self.levels.len() as _
+    }
+
+    pub fn level_size(&self, level: u32) -> Option<(u32, u32)> {
+        let level = self.levels.get(level as usize)?;
+        Some((level.width, level.height))
+    }
+
+    // columns and rows of tiles
+    pub fn grid(&self, level: u32) -> Option<(u32, u32)> {
+        let (width, height) = self.level_size(level)?;
+        let size = self.options.tile_size;
+        Some((width.div_ceil(size), height.div_ceil(size)))
+    }
+
+    // `None` outside the level's grid
+    pub fn tile(&self, level: u32, col: u32, row: u32) -> Option<Tile> {
+        let (cols, rows) = self.grid(level)?;
+        if col >= cols || row >= rows {
+            return None;
+        }
+        let image = &self.levels[level as usize];
+        let span = |i: u32, len: u32| {
+            let PyramidOptions { tile_size, overlap } = self.options;
+            let start = (i * tile_size).saturating_sub(if i > 0 { overlap } else { 0 });
+            let end = ((i + 1) * tile_size + overlap).min(len);
+            (start, end - start)
+        };
+        let (x, width) = span(col, image.width);
+        let (y, height) = span(row, image.height);
+        let stride = image.width as usize * 3;
+        let mut rgb = Vec::with_capacity(width as usize * height as usize * 3);
+        for line in image.rgb.chunks(stride).skip(y as _).take(height as _) {
+            rgb.extend_from_slice(&line[x as usize * 3..(x + width) as usize * 3]);
+        }
+        Some(Tile {
+            level,
+            col,
+            row,
+            width,
+            height,
+            rgb,
+        })
+    }
+
+    // all tiles, level by level and row by row
+    pub fn tiles(&self) -> impl Iterator<Item = Tile> + '_ {
+        (0..self.level_count()).flat_map(move |level| {
+            let (cols, rows) = self.grid(level).unwrap_or_default();
+            (0..rows)
+                .flat_map(move |row| (0..cols).filter_map(move |col| self.tile(level, col, row)))
+        })
+    }
+
+    // The `.dzi` descriptor, `format` being the extension the tiles are
+    // saved with, like "jpg" or "webp"
+    pub fn dzi(&self, format: &str) -> String {
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                "\n",
+                r#"<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" Format="{}" Overlap="{}" TileSize="{}">"#,
+                "\n",
+                r#"  <Size Width="{}" Height="{}"/>"#,
+                "\n</Image>\n"
+            ),
+            format,
+            self.options.overlap,
+            self.options.tile_size,
+            self.width(),
+            self.height()
+        )
+    }
+
+    // An uncompressed tiled TIFF with the full size image first and the
+    // reduced levels chained behind it, down to the first that fits into a
+    // tile. `metadata` goes into the full size IFD. TIFF wants tiles in
+    // multiples of 16, other sizes are `Error::NotImplemented`.
+    pub fn to_tiff(&self, metadata: &ExportMetadata) -> Result<Vec<u8>> {
+        let size = self.options.tile_size;
+        if !size.is_multiple_of(16) {
+            return Err(Error::NotImplemented);
+        }
+        let mut out = tiff_header();
+        // where the previous IFD keeps the offset of the next one
+        let mut link = 4;
+        for (i, level) in self.levels.iter().rev().enumerate() {
+            let (cols, rows) = (level.width.div_ceil(size), level.height.div_ceil(size));
+            let (mut offsets, mut counts) = (Vec::new(), Vec::new());
+            for row in 0..rows {
+                for col in 0..cols {
+                    offsets.push(out.len() as u32);
+                    counts.push(size * size * 3);
+                    write_tile(&mut out, level, col * size, row * size, size);
+                    if out.len() > u32::MAX as usize {
+                        return Err(Error::TooBig);
+                    }
+                }
+            }
+            let mut entries = vec![
+                Entry::new(TAG_IMAGE_WIDTH, Value::Long(vec![level.width])),
+                Entry::new(TAG_IMAGE_LENGTH, Value::Long(vec![level.height])),
+                Entry::new(TAG_BITS_PER_SAMPLE, Value::Short(vec![8; 3])),
+                Entry::new(TAG_COMPRESSION, Value::Short(vec![1])),
+                Entry::new(TAG_PHOTOMETRIC, Value::Short(vec![2])),
+                Entry::new(TAG_SAMPLES_PER_PIXEL, Value::Short(vec![3])),
+                Entry::new(TAG_PLANAR_CONFIG, Value::Short(vec![1])),
+                Entry::new(TAG_TILE_WIDTH, Value::Long(vec![size])),
+                Entry::new(TAG_TILE_LENGTH, Value::Long(vec![size])),
+                Entry::new(TAG_TILE_OFFSETS, Value::Long(offsets)),
+                Entry::new(TAG_TILE_BYTE_COUNTS, Value::Long(counts)),
+            ];
+            let ifd = match i {
+                0 => metadata.write_ifds(&mut out, entries),
+                _ => {
+                    entries.push(Entry::new(
+                        TAG_NEW_SUBFILE_TYPE,
+                        Value::Long(vec![SUBFILE_REDUCED]),
+                    ));
+                    write_ifd(&mut out, entries)
+                }
+            };
+            if out.len() > u32::MAX as usize {
+                return Err(Error::TooBig);
+            }
+            out[link..link + 4].copy_from_slice(&ifd.to_ne_bytes());
+            let count = u16::from_ne_bytes([out[ifd as usize], out[ifd as usize + 1]]);
+            link = ifd as usize + 2 + count as usize * 12;
+            if level.width <= size && level.height <= size {
+                break;
+            }
+        }
+        Ok(out)
+    }
+}
+
+// a full `size` square at `x`, `y`, zero padded past the level's edges
+fn write_tile(out: &mut Vec<u8>, level: &Level, x: u32, y: u32, size: u32) {
+    let stride = level.width as usize * 3;
+    let width = size.min(level.width - x) as usize * 3;
+    for row in y..y + size {
+        let start = out.len();
+        if row < level.height {
+            let line = &level.rgb[row as usize * stride + x as usize * 3..][..width];
+            out.extend_from_slice(line);
+        }
+        out.resize(start + size as usize * 3, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ifd::FileTiff;
+
+    fn gradient(width: u32, height: u32) -> Pyramid {
+        let rgb = (0..width * height * 3).map(|i| (i % 256) as u8).collect();
+        Pyramid {
+            options: PyramidOptions {
+                tile_size: 64,
+                overlap: 1,
+            },
+            levels: levels(width, height, rgb),
+        }
+    }
+
+    #[test]
+    fn test_dzi_tiles() {
+        let pyramid = gradient(300, 130);
+        // ceil(log2(300)) + 1
+        assert_eq!(pyramid.level_count(), 10);
+        assert_eq!(pyramid.level_size(0), Some((1, 1)));
+        assert_eq!(pyramid.level_size(8), Some((150, 65)));
+        assert_eq!(pyramid.grid(9), Some((5, 3)));
+
+        let first = pyramid.tile(9, 0, 0).unwrap();
+        assert_eq!((first.width, first.height), (65, 65));
+        let inner = pyramid.tile(9, 1, 1).unwrap();
+        assert_eq!((inner.width, inner.height), (66, 66));
+        // the overlap repeats the neighbour's last column
+        assert_eq!(
+            inner.rgb[..3],
+            pyramid.levels[9].rgb[(63 * 300 + 63) * 3..][..3]
+        );
+        let last = pyramid.tile(9, 4, 2).unwrap();
+        assert_eq!((last.width, last.height), (45, 3));
+        assert!(pyramid.tile(9, 5, 0).is_none());
+        assert_eq!(
+            pyramid.tiles().count(),
+            (0..10)
+                .map(|l| pyramid.grid(l).map_or(0, |(c, r)| c * r))
+                .sum::<u32>() as usize
+        );
+        assert!(pyramid
+            .dzi("jpg")
+            .contains(r#"Format="jpg" Overlap="1" TileSize="64""#));
+    }
+
+    #[test]
+    fn test_pyramid_tiff() {
+        let pyramid = gradient(300, 130);
+        let tiff = pyramid.to_tiff(&ExportMetadata::default()).expect("tiff");
+        let reader = FileTiff::new(&tiff[..]).expect("tiff");
+        // down to 38x17, the first level inside a single tile
+        let mut sizes = Vec::new();
+        let mut next = reader.u32_at(4);
+        while let Some(pos) = next.filter(|&pos| pos != 0) {
+            let (ifd, following) = reader.ifd(pos as u64).unwrap();
+            let get = |tag| ifd.get(tag).map(|e| e.value);
+            sizes.push((get(TAG_IMAGE_WIDTH).unwrap(), get(TAG_NEW_SUBFILE_TYPE)));
+            next = following;
+        }
+        assert_eq!(
+            sizes,
+            [(300, None), (150, Some(1)), (75, Some(1)), (38, Some(1))]
+        );
+
+        let odd = Pyramid {
+            options: PyramidOptions {
+                tile_size: 100,
+                overlap: 0,
+            },
+            ..pyramid
+        };
+        assert!(matches!(
+            odd.to_tiff(&ExportMetadata::default()),
+            Err(Error::NotImplemented)
+        ));
+    }
+}
@@ -0,0 +1,210 @@
+use chrono::{Local, NaiveDateTime, TimeZone};
+use rawler::{
+    decoders::{RawDecodeParams, RawMetadata},
+    formats::tiff::Rational,
+    rawimage::{RawImageData, RawPhotometricInterpretation},
+    rawsource::RawSource,
+};
+
+use crate::{
+    err::{Error, Result},
+    source::{MetadataAccess, MosaicAccess},
+    FocusType, FullRawInfo, GpsInfo, LensInfo,
+};
+
+// A file decoded by rawler. Unlike LibRaw it decodes the pixels while
+// opening, so the mosaic is available right away.
+pub struct FallbackImage {
+    image: rawler::RawImage,
+    metadata: RawMetadata,
+}
+
+impl FallbackImage {
+    pub fn open(buf: &[u8]) -> Result<Self> {
+        let source = RawSource::new_from_slice(buf);
+        let params = RawDecodeParams::default();
+        let decoder = rawler::get_decoder(&source).map_err(|_| Error::FileUnsupported)?;
+        let image = decoder
+            .raw_image(&source, &params, false)
+            .map_err(|_| Error::Data)?;
+        let metadata = decoder
+            .raw_metadata(&source, &params)
+            .map_err(|_| Error::Data)?;
+        Ok(Self { image, metadata })
+    }
+
+    pub fn rawler_image(&self) -> &rawler::RawImage {
+        &self.image
+    }
+}
+
+fn ratio(value: Option<Rational>) -> f32 {
+    match value {
+        Some(Rational { n, d }) if d != 0 => n as f32 / d as f32,
+        _ => 0.0,
+    }
+}
+
+fn dms(value: Option<[Rational; 3]>) -> [f32; 3] {
+    value.map_or([0.0; 3], |v| v.map(|r| ratio(Some(r))))
+}
+
+// 'N', 'S', 'E' or 'W' as LibRaw has them, '\0' when missing
+fn reference(value: &Option<String>) -> char {
+    value
+        .as_deref()
+        .and_then(|r| r.chars().next())
+        .unwrap_or_default()
+}
+
+impl MetadataAccess for FallbackImage {
+    fn full_info(&self) -> FullRawInfo {
+        let exif = &self.metadata.exif;
+        let (width, height) = match &self.image.crop_area {
+            Some(crop) => (crop.d.w, crop.d.h),
+            None => (self.image.width, self.image.height),
+        };
+        let gps = exif
+            .gps
+            .as_ref()
+            .map_or_else(GpsInfo::default, |gps| GpsInfo {
+                latitude: dms(gps.gps_latitude),
+                longitude: dms(gps.gps_longitude),
+                gpstimestamp: dms(gps.gps_timestamp),
+                altitude: ratio(gps.gps_altitude),
+                latref: reference(&gps.gps_latitude_ref),
+                longref: reference(&gps.gps_longitude_ref),
+                altref: gps.gps_altitude_ref.unwrap_or(0),
+            });
+        let [min_focal, max_focal, ap_min, ap_max] = exif
+            .lens_spec
+            .map_or([0.0; 4], |spec| spec.map(|r| ratio(Some(r))));
+        let lens = self.metadata.lens.as_ref();
+        let datetime = exif
+            .date_time_original
+            .as_deref()
+            .and_then(|dt| NaiveDateTime::parse_from_str(dt, "%Y:%m:%d %H:%M:%S").ok())
+            .and_then(|dt| Local.from_local_datetime(&dt).single());
+        FullRawInfo {
+            width: width as _,
+            height: height as _,
+            colors: if self.image.cpp == 1 {
+                3
+            } else {
+                self.image.cpp as _
+            },
+            iso_speed: exif
+                .iso_speed_ratings
+                .map(u32::from)
+                .or(exif.iso_speed)
+                .unwrap_or_default(),
+            shutter: ratio(exif.exposure_time),
+            aperture: ratio(exif.fnumber),
+            focal_len: ratio(exif.focal_length),
+            datetime,
+            gps,
+            artist: exif.artist.clone().unwrap_or_default(),
+            desc: Default::default(),
+            make: self.image.make.clone(),
+            model: self.image.model.clone(),
+            normalized_make: self.image.clean_make.clone(),
+            normalized_model: self.image.clean_model.clone(),
+            software: Default::default(),
+            raw_count: 1,
+            dng_version: 0,
+            lens_info: LensInfo {
+                min_focal,
+                max_focal,
+                max_aperture_at_min_focal: ap_min,
+                max_aperture_at_max_focal: ap_max,
+                lens_make: exif.lens_make.clone().unwrap_or_default(),
+                lens_name: exif.lens_model.clone().unwrap_or_default(),
+                lens_serial: exif.lens_serial_number.clone().unwrap_or_default(),
+                internal_lens_serial: Default::default(),
+                focal_length_in_35mm_format: 0,
+                mounts: lens.map(|l| l.mount.clone()).unwrap_or_default(),
+                focus_type: if min_focal == 0.0 {
+                    FocusType::Unknown
+                } else if min_focal == max_focal {
+                    FocusType::Prime
+                } else if ap_min == ap_max {
+                    FocusType::ZoomConstAp
+                } else {
+                    FocusType::ZoomVarAp
+                },
+                feture_pre: Default::default(),
+                feture_suf: Default::default(),
+            },
+        }
+    }
+}
+
+impl MosaicAccess for FallbackImage {
+    fn mosaic(&self) -> Option<&[u16]> {
+        match &self.image.data {
+            RawImageData::Integer(data) => Some(data),
+            RawImageData::Float(_) => None,
+        }
+    }
+
+    fn mosaic_width(&self) -> u32 {
+        self.image.width as _
+    }
+
+    fn mosaic_height(&self) -> u32 {
+        self.image.height as _
+    }
+
+    fn filters(&self) -> u32 {
+        let RawPhotometricInterpretation::Cfa(config) = &self.image.photometric else {
+            return 0;
+        };
+        // LibRaw's pattern starts at the visible area, and the green sharing
+        // a row with blue is numbered 3
+        let cfa = match &self.image.crop_area {
+            Some(crop) => config.cfa.shift(crop.p.x, crop.p.y),
+            None => config.cfa.clone(),
+        };
+        match (cfa.width, cfa.height) {
+            (6, 6) => 9,
+            (2, 2) | (2, 4) | (2, 8) | (1, 2) => {
+                let mut filters = 0;
+                for row in 0..8 {
+                    let blue_row = (0..cfa.width).any(|col| cfa.color_at(row, col) == 2);
+                    for col in 0..2 {
+                        let color = match cfa.color_at(row, col) as u32 & 3 {
+                            1 if blue_row => 3,
+                            color => color,
+                        };
+                        filters |= color << ((((row << 1) & 14) | (col & 1)) << 1);
+                    }
+                }
+                filters
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, RawImage};
+
+    #[test]
+    fn test_fallback_matches_libraw() {
+        let data = std::fs::read(get_test_assets_path().join("test-a7rm4.ARW")).unwrap();
+        let image = FallbackImage::open(&data).expect("decoded by rawler");
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        raw_image.unpack().expect("unpacked");
+
+        let (info, expected) = (image.full_info(), raw_image.full_info());
+        assert_eq!(info.model, expected.model);
+        assert_eq!(info.iso_speed, expected.iso_speed);
+        assert_eq!(
+            (image.mosaic_width(), image.mosaic_height()),
+            (raw_image.mosaic_width(), raw_image.mosaic_height())
+        );
+        assert_eq!(image.filters(), raw_image.filters());
+    }
+}
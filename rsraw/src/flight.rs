@@ -0,0 +1,136 @@
+use std::borrow::Cow;
+
+use crate::RawImage;
+
+// What a drone recorded about its flight when it took the shot, from the
+// XMP packet DJI (drone-dji namespace) and Autel embed in their DNGs.
+// Angles are in degrees, altitudes in meters, speeds in m/s.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlightInfo {
+    // above the takeoff point
+    pub relative_altitude: Option<f32>,
+    // above sea level, by the drone's barometer and GPS rather than the exif GPS block's
+    pub absolute_altitude: Option<f32>,
+    pub gimbal_pitch: Option<f32>,
+    pub gimbal_yaw: Option<f32>,
+    pub gimbal_roll: Option<f32>,
+    pub flight_pitch: Option<f32>,
+    pub flight_yaw: Option<f32>,
+    pub flight_roll: Option<f32>,
+    // along the drone's x (north), y (east) and z (down) axes
+    pub speed: Option<[f32; 3]>,
+}
+
+impl FlightInfo {
+    // `None` when the packet has none of the fields
+    pub fn parse(xmp: &str) -> Option<Self> {
+        let field = |name| xmp_value(xmp, name);
+        let speed = match (
+            field("FlightXSpeed"),
+            field("FlightYSpeed"),
+            field("FlightZSpeed"),
+        ) {
+            (Some(x), Some(y), Some(z)) => Some([x, y, z]),
+            _ => None,
+        };
+        let info = Self {
+            relative_altitude: field("RelativeAltitude"),
+            absolute_altitude: field("AbsoluteAltitude"),
+            gimbal_pitch: field("GimbalPitchDegree"),
+            gimbal_yaw: field("GimbalYawDegree"),
+            gimbal_roll: field("GimbalRollDegree"),
+            flight_pitch: field("FlightPitchDegree"),
+            flight_yaw: field("FlightYawDegree"),
+            flight_roll: field("FlightRollDegree"),
+            speed,
+        };
+        (info != Self::default()).then_some(info)
+    }
+}
+
+impl RawImage {
+    // the XMP packet LibRaw found, empty without one
+    pub fn xmp(&self) -> Cow<'_, str> {
+        let idata = &self.as_ref().idata;
+        if idata.xmpdata.is_null() {
+            return Cow::Borrowed("");
+        }
+        let data = unsafe {
+            std::slice::from_raw_parts(idata.xmpdata as *const u8, idata.xmplen as usize)
+        };
+        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        String::from_utf8_lossy(&data[..end])
+    }
+
+    // `None` for anything but drone footage
+    pub fn flight(&self) -> Option<FlightInfo> {
+        FlightInfo::parse(&self.xmp())
+    }
+}
+
+// The value of a property written either as attribute `ns:name="value"` or as
+// element `<ns:name>value</ns:name>`. Vendors pick their own namespace
+// prefix, so the name is matched on its own.
+fn xmp_value(xmp: &str, name: &str) -> Option<f32> {
+    let mut rest = xmp;
+    while let Some(pos) = rest.find(name) {
+        let (before, after) = rest.split_at(pos);
+        rest = &after[name.len()..];
+        if !before.ends_with(':') {
+            continue;
+        }
+        let value = if let Some(quoted) = rest.strip_prefix("=\"") {
+            quoted.split('"').next()
+        } else if let Some(text) = rest.strip_prefix('>') {
+            text.split('<').next()
+        } else {
+            continue;
+        };
+        if let Some(value) = value.and_then(|v| v.trim().parse().ok()) {
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_flight_info() {
+        let dji = r#"<rdf:Description rdf:about="DJI Meta Data"
+            drone-dji:AbsoluteAltitude="+412.35"
+            drone-dji:RelativeAltitude="+120.10"
+            drone-dji:GimbalRollDegree="+0.00"
+            drone-dji:GimbalYawDegree="-35.20"
+            drone-dji:GimbalPitchDegree="-90.00"
+            drone-dji:FlightRollDegree="+1.30"
+            drone-dji:FlightYawDegree="-34.90"
+            drone-dji:FlightPitchDegree="+2.10"
+            drone-dji:FlightXSpeed="+4.2"
+            drone-dji:FlightYSpeed="-0.5"
+            drone-dji:FlightZSpeed="+0.0"/>"#;
+        let info = FlightInfo::parse(dji).expect("flight info");
+        assert_eq!(info.relative_altitude, Some(120.1));
+        assert_eq!(info.absolute_altitude, Some(412.35));
+        assert_eq!(info.gimbal_pitch, Some(-90.0));
+        assert_eq!(info.gimbal_yaw, Some(-35.2));
+        assert_eq!(info.flight_pitch, Some(2.1));
+        assert_eq!(info.speed, Some([4.2, -0.5, 0.0]));
+
+        let autel = "<drone:GimbalPitchDegree>-45.5</drone:GimbalPitchDegree>\
+            <drone:RelativeAltitude> 80 </drone:RelativeAltitude>";
+        let info = FlightInfo::parse(autel).expect("flight info");
+        assert_eq!(info.gimbal_pitch, Some(-45.5));
+        assert_eq!(info.relative_altitude, Some(80.0));
+        assert_eq!(info.speed, None);
+        assert!(FlightInfo::parse(r#"<x:RelativeAltitudeRef>1</x:RelativeAltitudeRef>"#).is_none());
+        assert!(FlightInfo::parse("").is_none());
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        assert!(RawImage::open_owned(data).unwrap().flight().is_none());
+    }
+}
@@ -0,0 +1,292 @@
+// The container a buffer's magic bytes and TIFF tags point to, without
+// asking LibRaw. Useful to tell why a file won't open.
+
+use crate::{
+    err::{Error, Result},
+    ifd::{FileIfd, FileTiff},
+};
+
+const TAG_IMAGE_WIDTH: u16 = 0x100;
+const TAG_IMAGE_LENGTH: u16 = 0x101;
+// Panasonic's sensor size, in IFD0 of RW2s
+const TAG_RW2_SENSOR_WIDTH: u16 = 0x2;
+const TAG_RW2_SENSOR_HEIGHT: u16 = 0x3;
+const TAG_COMPRESSION: u16 = 0x103;
+const TAG_PHOTOMETRIC: u16 = 0x106;
+const TAG_MAKE: u16 = 0x10f;
+const TAG_DNG_VERSION: u16 = 0xc612;
+// VC-5, registered by GoPro for GPR
+const COMPRESSION_VC5: u32 = 9;
+// what image editors and scanners write, anything else next to a Make is a
+// vendor's raw compression: old style JPEG for CR2, 32767 for ARW, 34713
+// for NEF, 65000 for Kodak...
+const PLAIN_COMPRESSIONS: &[u32] = &[1, 2, 3, 4, 5, 7, 8, 32773, 32946, 34887, 50000];
+const PHOTOMETRIC_CFA: u32 = 32803;
+const PHOTOMETRIC_LINEAR_RAW: u32 = 34892;
+// IFDs only raws have: Sony's SR2Private, the Kodak IFD, Leaf's, DNG's
+// private data and CR2's slices
+const VENDOR_TAGS: &[u16] = &[0x7200, 0x8290, 0x8606, 0xc634, 0xc640];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RawFormat {
+    // TIFF based raws other than DNG: NEF, ARW, CR2, PEF, 3FR... Plain
+    // TIFFs without a sign of raw data are `Unknown`.
+    Tiff,
+    Dng,
+    // GoPro's DNGs with VC-5 compressed raw data, needs the gpr feature
+    Gpr,
+    Cr3,
+    Crw,
+    Raf,
+    Orf,
+    Rw2,
+    // none of the above, LibRaw may still know it
+    Unknown,
+}
+
+impl RawFormat {
+    pub fn detect(buf: &[u8]) -> Self {
+        match buf {
+            [b'I', b'I', 0x1a, 0, 0, 0, b'H', b'E', b'A', b'P', b'C', b'C', b'D', b'R', ..] => {
+                Self::Crw
+            }
+            [_, _, _, _, b'f', b't', b'y', b'p', b'c', b'r', b'x', b' ', ..] => Self::Cr3,
+            [b'F', b'U', b'J', b'I', b'F', b'I', b'L', b'M', ..] => Self::Raf,
+            [b'I', b'I', b'R', b'O' | b'S', ..] | [b'M', b'M', b'O', b'R', ..] => Self::Orf,
+            [b'I', b'I', b'U', 0, ..] => Self::Rw2,
+            [b'I', b'I', b'*', 0, ..] => tiff_format(buf),
+            [b'M', b'M', 0, b'*', ..] => tiff_format(buf),
+            _ => Self::Unknown,
+        }
+    }
+
+    // whether this build decodes the format, `Unknown` is up to LibRaw
+    pub fn is_supported(self) -> bool {
+        self != Self::Gpr || cfg!(feature = "gpr")
+    }
+}
+
+// What `probe` found: the container and, where the headers give it without
+// decoding anything, the size of the raw image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawProbe {
+    pub format: RawFormat,
+    // width and height as stored, before cropping to the visible area.
+    // `None` for CR3, CRW and RAF, whose sizes take parsing their boxes or
+    // blocks, and for CR2, whose raw IFD leaves them to its lossless JPEG.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+// Whether `buf` is a raw this build can decode, from its magic bytes and
+// TIFF tags only, for importers sorting files before opening any.
+// `Error::FileUnsupported` for what isn't, including the formats LibRaw
+// would only recognize by parsing the whole file. A probe that passes is no
+// promise `RawImage::open` will.
+pub fn probe(buf: &[u8]) -> Result<RawProbe> {
+    let format = RawFormat::detect(buf);
+    if format == RawFormat::Unknown || !format.is_supported() {
+        return Err(Error::FileUnsupported);
+    }
+    let dimensions = match format {
+        RawFormat::Tiff if buf.get(8..10) == Some(b"CR") => None,
+        RawFormat::Tiff | RawFormat::Dng | RawFormat::Gpr | RawFormat::Orf => tiff_dimensions(buf),
+        RawFormat::Rw2 => rw2_dimensions(buf),
+        _ => None,
+    };
+    Ok(RawProbe { format, dimensions })
+}
+
+// DNG and GPR are told apart from other TIFFs by their tags, a GPR's raw
+// IFD sits in a SubIFD like that of most DNGs. Other TIFFs need a CFA or
+// linear raw image, a vendor's IFD or its compression next to a Make.
+fn tiff_format(buf: &[u8]) -> RawFormat {
+    let (mut dng, mut vc5, mut raw, mut make) = (false, false, false, false);
+    let mut compressions = Vec::new();
+    for entry in tiff_ifds(buf).iter().flat_map(|ifd| &ifd.entries) {
+        match (entry.tag, entry.value) {
+            (TAG_DNG_VERSION, _) => dng = true,
+            (TAG_COMPRESSION, COMPRESSION_VC5) => vc5 = true,
+            (TAG_MAKE, _) => make = true,
+            (TAG_PHOTOMETRIC, PHOTOMETRIC_CFA | PHOTOMETRIC_LINEAR_RAW) => raw = true,
+            (tag, _) if VENDOR_TAGS.contains(&tag) => raw = true,
+            _ => {}
+        }
+        if entry.tag == TAG_COMPRESSION {
+            compressions.push(entry.value);
+        }
+    }
+    raw |= make && compressions.iter().any(|c| !PLAIN_COMPRESSIONS.contains(c));
+    match (dng, vc5, raw) {
+        (true, true, _) => RawFormat::Gpr,
+        (true, false, _) => RawFormat::Dng,
+        (false, _, true) => RawFormat::Tiff,
+        _ => RawFormat::Unknown,
+    }
+}
+
+// the little endian variants and big endian TIFFs start alike
+fn tiff(buf: &[u8]) -> FileTiff<'_, [u8]> {
+    FileTiff::with_order(buf, buf.starts_with(b"II"))
+}
+
+fn tiff_ifds(buf: &[u8]) -> Vec<FileIfd> {
+    tiff(buf).ifds()
+}
+
+// the largest full resolution image, previews and thumbnails are reduced
+// ones by their subfile type
+fn tiff_dimensions(buf: &[u8]) -> Option<(u32, u32)> {
+    tiff_ifds(buf)
+        .iter()
+        .filter(|ifd| ifd.subfile_type() & 1 == 0)
+        .filter_map(|ifd| {
+            let value = |tag| ifd.get(tag).map(|e| e.value);
+            Some((value(TAG_IMAGE_WIDTH)?, value(TAG_IMAGE_LENGTH)?))
+        })
+        .max_by_key(|&(width, height)| width as u64 * height as u64)
+}
+
+fn rw2_dimensions(buf: &[u8]) -> Option<(u32, u32)> {
+    let tiff = tiff(buf);
+    let (ifd, _) = tiff.ifd(tiff.u32_at(4)? as u64)?;
+    let value = |tag| ifd.get(tag).map(|e| e.value);
+    Some((value(TAG_RW2_SENSOR_WIDTH)?, value(TAG_RW2_SENSOR_HEIGHT)?))
+}
+
+// a TIFF structure in memory, offsets are relative to `buf`
+pub(crate) struct Tiff<'a> {
+    pub buf: &'a [u8],
+    pub le: bool,
+}
+
+impl Tiff<'_> {
+    pub fn u16_at(&self, pos: usize) -> Option<u16> {
+        let b: [u8; 2] = self.buf.get(pos..pos.checked_add(2)?)?.try_into().ok()?;
+        Some(match self.le {
+            true => u16::from_le_bytes(b),
+            false => u16::from_be_bytes(b),
+        })
+    }
+
+    pub fn u32_at(&self, pos: usize) -> Option<u32> {
+        let b: [u8; 4] = self.buf.get(pos..pos.checked_add(4)?)?.try_into().ok()?;
+        Some(match self.le {
+            true => u32::from_le_bytes(b),
+            false => u32::from_be_bytes(b),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ifd::TAG_SUB_IFDS, raw::tests::get_test_assets_path};
+
+    // a little endian IFD0 with DNGVersion pointing to one SubIFD
+    fn dng(compression: u16) -> Vec<u8> {
+        let mut buf = b"II*\0".to_vec();
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        let entry = |buf: &mut Vec<u8>, tag: u16, kind: u16, value: u32| {
+            buf.extend_from_slice(&tag.to_le_bytes());
+            buf.extend_from_slice(&kind.to_le_bytes());
+            buf.extend_from_slice(&1u32.to_le_bytes());
+            buf.extend_from_slice(&value.to_le_bytes());
+        };
+        buf.extend_from_slice(&2u16.to_le_bytes());
+        entry(&mut buf, TAG_SUB_IFDS, 4, 38);
+        entry(&mut buf, TAG_DNG_VERSION, 1, 0x0401);
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf.extend_from_slice(&1u16.to_le_bytes());
+        entry(&mut buf, TAG_COMPRESSION, 3, compression as u32);
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(RawFormat::detect(&dng(9)), RawFormat::Gpr);
+        assert_eq!(RawFormat::detect(&dng(7)), RawFormat::Dng);
+        // cut off before the SubIFD's next pointer, its entries still count
+        let cut = dng(9);
+        assert_eq!(RawFormat::detect(&cut[..cut.len() - 4]), RawFormat::Gpr);
+        assert_eq!(RawFormat::Gpr.is_supported(), cfg!(feature = "gpr"));
+        assert_eq!(RawFormat::detect(b"nope"), RawFormat::Unknown);
+        for name in ["test-z8.NEF", "test-a7rm4.ARW"] {
+            let data = std::fs::read(get_test_assets_path().join(name)).unwrap();
+            assert_eq!(RawFormat::detect(&data), RawFormat::Tiff);
+        }
+    }
+
+    // IFD0 of a 16 by 16 RGB TIFF, optionally with a Make
+    fn plain_tiff(make: bool, compression: u16) -> Vec<u8> {
+        let mut entries = vec![
+            (TAG_IMAGE_WIDTH, 16),
+            (TAG_IMAGE_LENGTH, 16),
+            (TAG_COMPRESSION, compression as u32),
+            (TAG_PHOTOMETRIC, 2),
+        ];
+        if make {
+            // "Cam" and its nul, inline
+            entries.push((TAG_MAKE, u32::from_le_bytes(*b"Cam\0")));
+        }
+        let mut buf = b"II*\0".to_vec();
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        buf.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (tag, value) in entries {
+            let (kind, count) = match tag {
+                TAG_MAKE => (2u16, 4u32),
+                _ => (4, 1),
+            };
+            buf.extend_from_slice(&tag.to_le_bytes());
+            buf.extend_from_slice(&kind.to_le_bytes());
+            buf.extend_from_slice(&count.to_le_bytes());
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        buf.extend_from_slice(&0u32.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_plain_tiff() {
+        for (make, compression) in [(false, 1), (true, 1), (true, 5), (false, 34713)] {
+            let tiff = plain_tiff(make, compression);
+            assert_eq!(RawFormat::detect(&tiff), RawFormat::Unknown);
+            assert!(matches!(probe(&tiff), Err(Error::FileUnsupported)));
+        }
+        // a NEF's compression next to the camera's name
+        let found = probe(&plain_tiff(true, 34713)).expect("probed");
+        assert_eq!(
+            found,
+            RawProbe {
+                format: RawFormat::Tiff,
+                dimensions: Some((16, 16))
+            }
+        );
+
+        // a CR2 header, IFD0 is then the full size JPEG rather than the raw
+        let mut cr2 = plain_tiff(true, 6);
+        cr2[4] = 16;
+        cr2.splice(8..8, *b"CR\x02\0\0\0\0\0");
+        let found = probe(&cr2).expect("probed");
+        assert_eq!((found.format, found.dimensions), (RawFormat::Tiff, None));
+    }
+
+    #[test]
+    fn test_probe() {
+        let found = probe(&dng(7)).expect("probed");
+        assert_eq!((found.format, found.dimensions), (RawFormat::Dng, None));
+        assert!(matches!(probe(b"nope"), Err(Error::FileUnsupported)));
+        assert!(matches!(probe(&dng(9)), Err(Error::FileUnsupported)) != cfg!(feature = "gpr"));
+        for name in ["test-z8.NEF", "test-a7rm4.ARW"] {
+            let data = std::fs::read(get_test_assets_path().join(name)).unwrap();
+            let raw_image = crate::RawImage::open_owned(data.clone()).expect("opened");
+            let sizes = &raw_image.as_ref().sizes;
+            assert_eq!(
+                probe(&data).expect("probed").dimensions,
+                Some((sizes.raw_width as u32, sizes.raw_height as u32))
+            );
+        }
+    }
+}
@@ -0,0 +1,354 @@
+// Files holding more than one raw frame of a single capture: Fuji's SuperCCD
+// SR and EXR DR modes read out a second, less sensitive set of photosites,
+// some DNGs carry a second gain or exposure, pixel shift bodies store one
+// frame per sensor position. LibRaw opens the first unless told otherwise,
+// so without asking the rest is silently dropped.
+
+use rsraw_sys as sys;
+
+use crate::{
+    err::{ContextError, Error, ErrorStage, Result},
+    ifd::{FileIfd, FileTiff},
+    raw::VisibleMosaic,
+    RawImage,
+};
+
+// bright frame values past this share of its range are treated as clipped
+const CLIP: f32 = 0.95;
+
+const TAG_PHOTOMETRIC: u16 = 0x106;
+const TAG_SAMPLES_PER_PIXEL: u16 = 0x115;
+const PHOTOMETRIC_CFA: u32 = 32803;
+const PHOTOMETRIC_LINEAR_RAW: u32 = 34892;
+// what sets a DNG's frames apart when they're two exposures or gains
+const TAG_EXPOSURE_TIME: u16 = 0x829a;
+const TAG_ISO_SPEED: u16 = 0x8827;
+const TAG_BASELINE_EXPOSURE: u16 = 0xc62a;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameLayout {
+    Single,
+    // two readouts of the same scene at different sensitivities, for
+    // `merge_exposures`
+    DualExposure,
+    // frames as counted by LibRaw the file doesn't say more about, e.g.
+    // pixel shift, dual pixel or a burst
+    Unknown(u32),
+}
+
+impl RawImage {
+    // `DualExposure` for RAFs with a second raw, SuperCCD SR's and EXR DR's
+    // less sensitive photosites, and for DNGs whose two raw IFDs differ in
+    // exposure time, ISO or baseline exposure or that interleave two
+    // samples per pixel, Fuji's SuperCCD converted
+    pub fn frame_layout(&self) -> FrameLayout {
+        match self.raw_count() {
+            0 | 1 => FrameLayout::Single,
+            2 if self.dng_version() == 0 && self.make() == "Fujifilm" => FrameLayout::DualExposure,
+            2 if self.dng_version() != 0 && self.dng_dual_exposure() => FrameLayout::DualExposure,
+            n => FrameLayout::Unknown(n),
+        }
+    }
+
+    fn dng_dual_exposure(&self) -> bool {
+        let Some(tiff) = FileTiff::new(self) else {
+            return false;
+        };
+        let raws: Vec<FileIfd> = tiff
+            .ifds()
+            .into_iter()
+            .filter(|ifd| {
+                ifd.subfile_type() == 0
+                    && ifd.get(TAG_PHOTOMETRIC).is_some_and(|e| {
+                        matches!(e.value, PHOTOMETRIC_CFA | PHOTOMETRIC_LINEAR_RAW)
+                    })
+            })
+            .collect();
+        let value = |ifd: &FileIfd, tag| {
+            let entry = ifd.get(tag)?;
+            tiff.f64s(entry)
+                .or_else(|| Some(tiff.u32s(entry)?.into_iter().map(f64::from).collect()))
+        };
+        match &raws[..] {
+            [raw] => raw.get(TAG_SAMPLES_PER_PIXEL).is_some_and(|e| e.value == 2),
+            [a, b] => [TAG_EXPOSURE_TIME, TAG_ISO_SPEED, TAG_BASELINE_EXPOSURE]
+                .into_iter()
+                .any(|tag| matches!((value(a, tag), value(b, tag)), (Some(a), Some(b)) if a != b)),
+            _ => false,
+        }
+    }
+
+    // Like `open`, for the frame at `index` of a file `frame_layout` says has
+    // several. `Error::RequestForNonexistentImage` past the last one.
+    pub fn open_frame(
+        buf: impl AsRef<[u8]> + Send + 'static,
+        index: u32,
+    ) -> std::result::Result<Self, ContextError> {
+        let mut image = Self::open_owned(buf)?;
+        if let Err(err) = image.select_shot(index) {
+            return Err(image.context(ErrorStage::Open, err));
+        }
+        Ok(image)
+    }
+
+    // Switches the image to the frame at `index`, reading the file's headers
+    // again for it, so what was unpacked or set since opening, e.g. with
+    // `set_camera_matrix`, is gone. Nothing happens if it's the current
+    // frame already. `Error::RequestForNonexistentImage` past the last one,
+    // the image is left as it was then.
+    pub fn select_shot(&mut self, index: u32) -> Result<()> {
+        if index >= self.raw_count().max(1) {
+            return Err(Error::RequestForNonexistentImage);
+        }
+        if index == self.as_ref().rawparams.shot_select {
+            return Ok(());
+        }
+        let result = unsafe {
+            self.with_raw_mut(|data| {
+                data.rawparams.shot_select = index;
+                Error::check(sys::rsraw_reopen(data))
+            })
+        };
+        self.dark_borders = None;
+        self.metrics.decoder = self.decoder_info().name;
+        result
+    }
+}
+
+// A dual exposure's two unpacked frames as one mosaic of the visible area,
+// row by row. Units are those of the brighter frame with its black level
+// off, 1.0 being its white level; where it clips the darker frame takes
+// over, scaled by the ratio between the two measured where neither clips,
+// so highlights go past 1.0. The order of the frames doesn't matter.
+pub fn merge_exposures(a: &RawImage, b: &RawImage) -> Result<Vec<f32>> {
+    let (a, b) = (a.visible_mosaic()?, b.visible_mosaic()?);
+    if (a.width, a.height) != (b.width, b.height) {
+        return Err(Error::NotImplemented);
+    }
+    let (bright, dark) = match mean(&a) >= mean(&b) {
+        true => (a, b),
+        false => (b, a),
+    };
+
+    let (mut sum_bright, mut sum_dark) = (0.0f64, 0.0f64);
+    for (vb, vd) in bright.normalized().zip(dark.normalized()) {
+        // the darkest values are mostly noise
+        if vb < CLIP && vd < CLIP && vd > 0.01 {
+            sum_bright += vb as f64;
+            sum_dark += vd as f64;
+        }
+    }
+    let ratio = match sum_dark > 0.0 {
+        true => (sum_bright / sum_dark) as f32,
+        false => 1.0,
+    };
+    Ok(bright
+        .normalized()
+        .zip(dark.normalized())
+        .map(|(vb, vd)| match vb >= CLIP {
+            true => vd * ratio,
+            false => vb,
+        })
+        .collect())
+}
+
+fn mean(frame: &VisibleMosaic) -> f64 {
+    let n = (frame.width * frame.height).max(1) as f64;
+    frame.normalized().map(|v| v as f64).sum::<f64>() / n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, ProcessParams, BIT_DEPTH_8};
+
+    #[test]
+    fn test_frames() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_frame(data.clone(), 0).expect("opened");
+        assert_eq!(raw_image.frame_layout(), FrameLayout::Single);
+        assert!(matches!(
+            RawImage::open_frame(data, 1).map_err(Error::from),
+            Err(Error::RequestForNonexistentImage)
+        ));
+        assert!(matches!(
+            merge_exposures(&raw_image, &raw_image),
+            Err(Error::OutOfOrderCall)
+        ));
+
+        // a frame merged with itself is just that frame, normalized
+        raw_image.unpack().expect("unpacked");
+        let merged = merge_exposures(&raw_image, &raw_image).expect("merged");
+        let frame = raw_image.visible_mosaic().unwrap();
+        assert_eq!(merged.len(), frame.width * frame.height);
+        for (m, v) in merged.iter().zip(frame.normalized()).step_by(997) {
+            assert!((m - v).abs() < 1e-6);
+        }
+    }
+
+    const SIZE: usize = 64;
+    const WHITE: u16 = 4095;
+    // RGGB, a different black for each position of the CFA
+    const BLACK: [u16; 4] = [64, 128, 192, 256];
+
+    // a DNG with two 64 by 64 frames of the same scene, RGGB, the second
+    // exposed for `exposures.1` seconds and given a quarter of the light of
+    // the first, which clips in its right half
+    fn dual_dng(exposures: (u32, u32)) -> Vec<u8> {
+        let signal = |row: usize, col: usize| (col * 100 + row * 4) as u16;
+        let mut file = b"II*\0\x08\0\0\0".to_vec();
+        for (frame, exposure) in [(0, exposures.0), (1, exposures.1)] {
+            let mut pixels = Vec::new();
+            for row in 0..SIZE {
+                for col in 0..SIZE {
+                    let black = BLACK[(row % 2) * 2 + col % 2];
+                    let v = match frame {
+                        0 => (black + signal(row, col)).min(WHITE),
+                        _ => black + signal(row, col) / 4,
+                    };
+                    pixels.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+            let short = |v: &[u16]| v.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+            let long = |v: u32| v.to_le_bytes().to_vec();
+            let ratio = |v: &[(i32, i32)]| {
+                v.iter()
+                    .flat_map(|(n, d)| [n.to_le_bytes(), d.to_le_bytes()].concat())
+                    .collect::<Vec<_>>()
+            };
+            // (tag, type, count, value bytes), the strip offset filled in below
+            let entries: Vec<(u16, u16, u32, Vec<u8>)> = vec![
+                (254, 4, 1, long(0)),
+                (256, 4, 1, long(SIZE as u32)),
+                (257, 4, 1, long(SIZE as u32)),
+                (258, 3, 1, short(&[16])),
+                (259, 3, 1, short(&[1])),
+                (262, 3, 1, short(&[32803])),
+                (271, 2, 5, b"Test\0".to_vec()),
+                (272, 2, 5, b"Dual\0".to_vec()),
+                (273, 4, 1, Vec::new()),
+                (277, 3, 1, short(&[1])),
+                (278, 4, 1, long(SIZE as u32)),
+                (279, 4, 1, long(pixels.len() as u32)),
+                (284, 3, 1, short(&[1])),
+                (33421, 3, 2, short(&[2, 2])),
+                (33422, 1, 4, vec![0, 1, 1, 2]),
+                (33434, 5, 1, ratio(&[(1, exposure as i32)])),
+                (50706, 1, 4, vec![1, 4, 0, 0]),
+                (50708, 2, 10, b"Test Dual\0".to_vec()),
+                (50713, 3, 2, short(&[2, 2])),
+                (50714, 3, 4, short(&BLACK)),
+                (50717, 3, 1, short(&[WHITE])),
+                (
+                    50721,
+                    10,
+                    9,
+                    ratio(&[
+                        (1, 1),
+                        (0, 1),
+                        (0, 1),
+                        (0, 1),
+                        (1, 1),
+                        (0, 1),
+                        (0, 1),
+                        (0, 1),
+                        (1, 1),
+                    ]),
+                ),
+                (50728, 5, 3, ratio(&[(1, 1), (1, 1), (1, 1)])),
+            ];
+            let start = file.len();
+            let extra_at = start + 2 + entries.len() * 12 + 4;
+            let extra_len: usize = entries
+                .iter()
+                .filter(|e| e.3.len() > 4)
+                .map(|e| e.3.len())
+                .sum();
+            let pixels_at = (extra_at + extra_len) as u32;
+            let next = match frame {
+                0 => pixels_at + pixels.len() as u32,
+                _ => 0,
+            };
+            let mut extra = Vec::new();
+            file.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            for (tag, kind, count, mut value) in entries {
+                if tag == 273 {
+                    value = long(pixels_at);
+                }
+                file.extend_from_slice(&tag.to_le_bytes());
+                file.extend_from_slice(&kind.to_le_bytes());
+                file.extend_from_slice(&count.to_le_bytes());
+                if value.len() > 4 {
+                    file.extend_from_slice(&((extra_at + extra.len()) as u32).to_le_bytes());
+                    extra.extend(value);
+                } else {
+                    value.resize(4, 0);
+                    file.extend(value);
+                }
+            }
+            file.extend_from_slice(&next.to_le_bytes());
+            file.extend(extra);
+            file.extend(pixels);
+        }
+        file
+    }
+
+    #[test]
+    fn test_dual_exposure() {
+        let same = dual_dng((100, 100));
+        let raw_image = RawImage::open_owned(same).expect("opened");
+        assert_eq!(raw_image.frame_layout(), FrameLayout::Unknown(2));
+
+        let data = dual_dng((100, 400));
+        let mut bright = RawImage::open_frame(data.clone(), 0).expect("opened");
+        assert_eq!(bright.frame_layout(), FrameLayout::DualExposure);
+        let mut dark = RawImage::open_frame(data, 1).expect("opened");
+        bright.unpack().expect("unpacked");
+        dark.unpack().expect("unpacked");
+        assert_ne!(bright.raw_image(), dark.raw_image());
+
+        // the clipped half comes from the dark frame, both in the bright
+        // one's units with each position's own black level off
+        let merged = merge_exposures(&dark, &bright).expect("merged");
+        assert_eq!(merged.len(), SIZE * SIZE);
+        for (i, &m) in merged.iter().enumerate() {
+            let (row, col) = (i / SIZE, i % SIZE);
+            let black = BLACK[(row % 2) * 2 + col % 2] as f32;
+            let expected = (col * 100 + row * 4) as f32 / (WHITE as f32 - black);
+            assert!((m - expected).abs() < 1e-3, "{row} {col}: {m} {expected}");
+        }
+        assert!(merged[SIZE - 1] > 1.0);
+    }
+
+    #[test]
+    fn test_select_shot() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        raw_image.select_shot(0).expect("selected");
+        assert!(raw_image.is_unpacked());
+        assert!(matches!(
+            raw_image.select_shot(1),
+            Err(Error::RequestForNonexistentImage)
+        ));
+
+        // the one frame there is read again, from a buffer and from a stream
+        let stream = RawImage::open_stream(std::io::Cursor::new(data.clone())).expect("opened");
+        for mut raw_image in [raw_image, stream] {
+            unsafe { raw_image.with_raw_mut(|data| data.rawparams.shot_select = 1) };
+            raw_image.select_shot(0).expect("selected");
+            assert!(!raw_image.is_unpacked());
+            assert_eq!(raw_image.model(), "Z 8");
+            let params = ProcessParams {
+                half_size: true,
+                shot_select: Some(0),
+                ..Default::default()
+            };
+            let image = raw_image
+                .process_with::<BIT_DEPTH_8>(&params)
+                .expect("processed");
+            assert_eq!((image.width(), image.height()), (4140, 2760));
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use rsraw_sys as sys;
+
+use crate::{
+    err::{Error, Result},
+    opcodes::{Reader, OPCODE_GAIN_MAP},
+    RawImage,
+};
+
+// A DNG GainMap opcode from OpcodeList2: lens shading gains phones and drones
+// record for their sensor, applied to the linear mosaic before demosaic.
+// Rows of `gains` are `points_h` wide, each point holds `map_planes` gains.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GainMap {
+    pub top: u32,
+    pub left: u32,
+    pub bottom: u32,
+    pub right: u32,
+    pub plane: u32,
+    pub planes: u32,
+    pub row_pitch: u32,
+    pub col_pitch: u32,
+    pub points_v: u32,
+    pub points_h: u32,
+    // spacing and origin of the map points, relative to the image size
+    pub spacing_v: f64,
+    pub spacing_h: f64,
+    pub origin_v: f64,
+    pub origin_h: f64,
+    pub map_planes: u32,
+    pub gains: Vec<f32>,
+}
+
+impl GainMap {
+    // bilinear between the map points around a position given relative to
+    // the image size, positions outside the map take the nearest edge
+    pub fn gain(&self, v: f64, h: f64, map_plane: u32) -> f32 {
+        let plane = map_plane.min(self.map_planes - 1) as usize;
+        let (rows, cols) = (self.points_v as usize, self.points_h as usize);
+        let (row, fv) = map_index(v, self.origin_v, self.spacing_v, rows);
+        let (col, fh) = map_index(h, self.origin_h, self.spacing_h, cols);
+        let at = |r: usize, c: usize| {
+            let (r, c) = (r.min(rows - 1), c.min(cols - 1));
+            self.gains[(r * cols + c) * self.map_planes as usize + plane] as f64
+        };
+        let top = at(row, col) + (at(row, col + 1) - at(row, col)) * fh;
+        let bottom = at(row + 1, col) + (at(row + 1, col + 1) - at(row + 1, col)) * fh;
+        (top + (bottom - top) * fv) as f32
+    }
+
+    fn parse(params: &[u8]) -> Option<Self> {
+        let mut r = Reader(params);
+        let mut map = Self {
+            top: r.u32()?,
+            left: r.u32()?,
+            bottom: r.u32()?,
+            right: r.u32()?,
+            plane: r.u32()?,
+            planes: r.u32()?,
+            row_pitch: r.u32()?,
+            col_pitch: r.u32()?,
+            points_v: r.u32()?,
+            points_h: r.u32()?,
+            spacing_v: r.f64()?,
+            spacing_h: r.f64()?,
+            origin_v: r.f64()?,
+            origin_h: r.f64()?,
+            map_planes: r.u32()?,
+            gains: Vec::new(),
+        };
+        let count = (map.points_v as usize)
+            .checked_mul(map.points_h as usize)?
+            .checked_mul(map.map_planes as usize)?;
+        if count == 0 || map.row_pitch == 0 || map.col_pitch == 0 || r.0.len() < count * 4 {
+            return None;
+        }
+        map.gains = (0..count).map(|_| r.f32()).collect::<Option<_>>()?;
+        Some(map)
+    }
+
+    // `black` and `white` bracket the linear range the gain scales
+    fn apply(&self, mosaic: &mut [u16], pitch: usize, area: Area, black: &Black, white: u16) {
+        let bottom = (self.bottom as usize).min(area.height);
+        let right = (self.right as usize).min(area.width);
+        let rows = (self.top as usize..bottom).step_by(self.row_pitch as usize);
+        for row in rows {
+            let v = (row as f64 + 0.5) / area.height as f64;
+            let line = &mut mosaic[(area.top + row) * pitch + area.left..][..area.width];
+            for col in (self.left as usize..right).step_by(self.col_pitch as usize) {
+                let gain = self.gain(v, (col as f64 + 0.5) / area.width as f64, 0);
+                let black = black.at(row, col) as f32;
+                let value = black + (line[col] as f32 - black).max(0.0) * gain;
+                line[col] = value.round().clamp(0.0, white as f32) as u16;
+            }
+        }
+    }
+}
+
+impl RawImage {
+    // the gain maps in a DNG's OpcodeList2, empty for other formats
+    pub fn gain_maps(&self) -> Vec<GainMap> {
+        self.opcode_list2()
+            .iter()
+            .filter(|op| op.id == OPCODE_GAIN_MAP)
+            .filter_map(|op| GainMap::parse(&op.params))
+            .collect()
+    }
+
+    // Scales the unpacked mosaic by the DNG's gain maps, so the processed
+    // image matches the vendor's rendering instead of showing the lens
+    // shading. Call once after each unpack, returns how many maps were applied.
+    pub fn apply_gain_maps(&mut self) -> Result<usize> {
+        if !self.is_unpacked() {
+            return Err(Error::OutOfOrderCall);
+        }
+        let maps = self.gain_maps();
+        if maps.is_empty() {
+            return Ok(0);
+        }
+        let data = self.as_ref();
+        let ptr = data.rawdata.raw_image;
+        if ptr.is_null() {
+            // linear DNGs, only mosaics are handled so far
+            return Err(Error::NotImplemented);
+        }
+        let sizes = &data.sizes;
+        let pitch = sizes.raw_width as usize;
+        let area = Area {
+            top: sizes.top_margin as usize,
+            left: sizes.left_margin as usize,
+            width: sizes.width as usize,
+            height: sizes.height as usize,
+        };
+        let black = Black::new(&data.rawdata.color, data.rawdata.iparams.filters);
+        let white = data.rawdata.color.maximum.min(u16::MAX as _) as u16;
+        let mosaic =
+            unsafe { std::slice::from_raw_parts_mut(ptr, pitch * sizes.raw_height as usize) };
+        // mosaics only have plane 0
+        let maps: Vec<_> = maps.into_iter().filter(|m| m.plane == 0).collect();
+        for map in &maps {
+            map.apply(mosaic, pitch, area, &black, white);
+        }
+        Ok(maps.len())
+    }
+}
+
+// the active area within the mosaic, opcode coordinates are relative to it
+#[derive(Clone, Copy)]
+struct Area {
+    top: usize,
+    left: usize,
+    width: usize,
+    height: usize,
+}
+
+// LibRaw's black level: a base, one per cfa color and an optional repeating pattern
+pub(crate) struct Black {
+    base: u32,
+    per_color: [u32; 4],
+    pattern: Vec<u32>,
+    pattern_size: (usize, usize),
+    filters: u32,
+}
+
+impl Black {
+    pub(crate) fn new(color: &sys::libraw_colordata_t, filters: u32) -> Self {
+        let (rows, cols) = (color.cblack[4] as usize, color.cblack[5] as usize);
+        let pattern = match rows * cols {
+            n if n > 0 && n <= color.cblack.len() - 6 => color.cblack[6..6 + n].to_vec(),
+            _ => Vec::new(),
+        };
+        Self {
+            base: color.black,
+            per_color: [
+                color.cblack[0],
+                color.cblack[1],
+                color.cblack[2],
+                color.cblack[3],
+            ],
+            pattern_size: (rows, cols),
+            pattern,
+            filters,
+        }
+    }
+
+    pub(crate) fn at(&self, row: usize, col: usize) -> u32 {
+        // dcraw's FC(): the cfa color at a position of a 2x8 repeating layout
+        let color = (self.filters >> ((((row << 1) & 14) | (col & 1)) << 1)) & 3;
+        let mut black = self.base + self.per_color[color as usize];
+        if !self.pattern.is_empty() {
+            let (rows, cols) = self.pattern_size;
+            black += self.pattern[(row % rows) * cols + col % cols];
+        }
+        black
+    }
+}
+
+// index of the map point at or before a position and how far past it the position is
+pub(crate) fn map_index(pos: f64, origin: f64, spacing: f64, points: usize) -> (usize, f64) {
+    if points < 2 || spacing <= 0.0 {
+        return (0, 0.0);
+    }
+    let index = ((pos - origin) / spacing).clamp(0.0, (points - 1) as f64);
+    (index as usize, index.fract())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gain_map_params(left: u32, gains: &[f32]) -> Vec<u8> {
+        let mut params = Vec::new();
+        for v in [0, left, 4, 4, 0, 1, 1, 2, 2, 2] {
+            params.extend_from_slice(&u32::to_be_bytes(v));
+        }
+        for v in [1.0f64, 1.0, 0.0, 0.0] {
+            params.extend_from_slice(&v.to_be_bytes());
+        }
+        params.extend_from_slice(&1u32.to_be_bytes());
+        for g in gains {
+            params.extend_from_slice(&g.to_be_bytes());
+        }
+        params
+    }
+
+    #[test]
+    fn test_gain_map() {
+        let map = GainMap::parse(&gain_map_params(1, &[1.0, 2.0, 3.0, 4.0])).expect("valid");
+        assert_eq!((map.points_v, map.points_h, map.col_pitch), (2, 2, 2));
+        assert_eq!(map.gain(0.0, 0.0, 0), 1.0);
+        assert_eq!(map.gain(1.0, 1.0, 0), 4.0);
+        assert_eq!(map.gain(0.5, 0.5, 0), 2.5);
+        assert!(GainMap::parse(&gain_map_params(1, &[1.0])).is_none());
+
+        // a 4x4 active area inside a 6 wide mosaic, the map covers every other column
+        let mut mosaic = vec![100u16; 6 * 5];
+        let area = Area {
+            top: 1,
+            left: 2,
+            width: 4,
+            height: 4,
+        };
+        let black = Black {
+            base: 20,
+            per_color: [0; 4],
+            pattern: Vec::new(),
+            pattern_size: (0, 0),
+            filters: 0,
+        };
+        let map = GainMap::parse(&gain_map_params(1, &[2.0; 4])).unwrap();
+        map.apply(&mut mosaic, 6, area, &black, 1000);
+        assert_eq!(&mosaic[6..12], &[100, 100, 100, 180, 100, 180]);
+        assert!(mosaic[..6].iter().all(|&v| v == 100));
+    }
+}
@@ -1,11 +1,49 @@
 use rsraw_sys as sys;
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+// more of LibRaw's GPS block may follow, build one from `Default`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct GpsInfo {
     pub latitude: [f32; 3],
     pub longitude: [f32; 3],
     pub gpstimestamp: [f32; 3],
     pub altitude: f32,
+    // 'N' or 'S', '\0' without a GPS block
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub latref: char,
+    // 'E' or 'W'
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub longref: char,
+    // 0 above sea level, 1 below
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub altref: u8,
+}
+
+impl GpsInfo {
+    // cameras without a GPS fix leave the position zeroed
+    pub fn has_location(&self) -> bool {
+        self.latitude != [0.0; 3] || self.longitude != [0.0; 3]
+    }
+
+    // latitude and longitude in signed decimal degrees, south and west negative
+    pub fn decimal_degrees(&self) -> Option<(f64, f64)> {
+        if !self.has_location() {
+            return None;
+        }
+        let degrees = |[d, m, s]: [f32; 3], negative: bool| {
+            let v = d as f64 + m as f64 / 60.0 + s as f64 / 3600.0;
+            if negative {
+                -v
+            } else {
+                v
+            }
+        };
+        Some((
+            degrees(self.latitude, self.latref == 'S'),
+            degrees(self.longitude, self.longref == 'W'),
+        ))
+    }
 }
 
 impl From<sys::libraw_gps_info_t> for GpsInfo {
@@ -15,6 +53,9 @@ impl From<sys::libraw_gps_info_t> for GpsInfo {
             longitude: data.longitude,
             gpstimestamp: data.gpstimestamp,
             altitude: data.altitude,
+            latref: data.latref as u8 as char,
+            longref: data.longref as u8 as char,
+            altref: data.altref as u8,
         }
     }
 }
@@ -0,0 +1,221 @@
+// The H.265 previews Canon (CR3) and some Sony bodies embed, decoded with
+// libheif. LibRaw hands these out as Canon's own box sequence rather than a
+// HEIF file: a CISZ box, an HVCC box with the decoder configuration and then
+// the coded picture. We rewrap that in the smallest HEIF container libheif
+// accepts, a single `hvc1` item pointing into `mdat`.
+
+use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+use crate::{
+    err::{Error, Result},
+    ThumbFormat, ThumbnailImage,
+};
+
+// the bitstream's NAL units are prefixed with 4 byte lengths
+const NAL_LENGTH_SIZE: u8 = 4;
+
+// RGB, `Error::UnsupportedThumbnail` for anything libheif can't make sense of
+pub(crate) fn decode_h265(thumb: &ThumbnailImage) -> Result<ThumbnailImage> {
+    let file = if thumb.data.get(4..8) == Some(b"ftyp") {
+        thumb.data.clone()
+    } else {
+        container(&thumb.data, thumb.width, thumb.height).ok_or(Error::UnsupportedThumbnail)?
+    };
+    let context = HeifContext::read_from_bytes(&file).map_err(|_| Error::UnsupportedThumbnail)?;
+    let handle = context
+        .primary_image_handle()
+        .map_err(|_| Error::UnsupportedThumbnail)?;
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)
+        .map_err(|_| Error::UnsupportedThumbnail)?;
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or(Error::UnsupportedThumbnail)?;
+    let row = plane.width as usize * 3;
+    let mut data = Vec::new();
+    if data.try_reserve_exact(row * plane.height as usize).is_err() {
+        return Err(Error::UnsufficientMemory);
+    }
+    for line in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        data.extend_from_slice(&line[..row]);
+    }
+    Ok(ThumbnailImage {
+        format: ThumbFormat::Bitmap,
+        width: plane.width,
+        height: plane.height,
+        colors: 3,
+        data,
+    })
+}
+
+// A HEIF file around Canon's boxes, `None` without a decoder configuration
+fn container(data: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+    let mut config = None;
+    let mut pos = 0;
+    // boxes up to the first one we don't know, the coded picture follows
+    while let [a, b, c, d, kind @ ..] = &data[pos..] {
+        let size = u32::from_be_bytes([*a, *b, *c, *d]) as usize;
+        if size < 8 || pos + size > data.len() {
+            break;
+        }
+        match &kind[..4] {
+            b"CISZ" => {}
+            b"HVCC" => config = Some(&data[pos + 8..pos + size]),
+            _ => break,
+        }
+        pos += size;
+    }
+    let config = config?;
+    let mut bitstream = Vec::new();
+    // A proper HEVCDecoderConfigurationRecord is used as stored. Bare
+    // parameter sets go in front of the picture instead, next to an empty
+    // record, the decoder picks them up from the bitstream just the same.
+    let mut config = if config.first() == Some(&1) && config.len() >= 23 {
+        config.to_vec()
+    } else {
+        length_prefixed(config, &mut bitstream);
+        empty_config()
+    };
+    let picture = &data[pos..];
+    match picture {
+        [0, 0, 1, ..] | [0, 0, 0, 1, ..] => {
+            length_prefixed(picture, &mut bitstream);
+            config[21] = (config[21] & !3) | (NAL_LENGTH_SIZE - 1);
+        }
+        // a box around the length prefixed picture
+        [_, _, _, _, a, b, c, d, ..] if [a, b, c, d].iter().all(|c| c.is_ascii_alphanumeric()) => {
+            bitstream.extend_from_slice(&picture[8..])
+        }
+        _ => bitstream.extend_from_slice(picture),
+    }
+    if bitstream.is_empty() {
+        return None;
+    }
+    Some(heif_file(&config, width, height, &bitstream))
+}
+
+// Annex B start codes to 4 byte lengths, appended to `out`
+fn length_prefixed(data: &[u8], out: &mut Vec<u8>) {
+    let starts: Vec<(usize, usize)> = (0..data.len().saturating_sub(2))
+        .filter(|&i| data[i..i + 3] == [0, 0, 1])
+        .map(|i| (i, i + 3))
+        .collect();
+    for (n, &(_, begin)) in starts.iter().enumerate() {
+        let mut end = starts.get(n + 1).map_or(data.len(), |&(next, _)| next);
+        // the leading zero of a 4 byte start code
+        while end > begin && data[end - 1] == 0 && end < data.len() {
+            end -= 1;
+        }
+        if end > begin {
+            out.extend_from_slice(&((end - begin) as u32).to_be_bytes());
+            out.extend_from_slice(&data[begin..end]);
+        }
+    }
+}
+
+// version 1, nothing known about profile or level, no parameter set arrays
+fn empty_config() -> Vec<u8> {
+    let mut config = vec![0; 23];
+    config[0] = 1;
+    // min_spatial_segmentation_idc, parallelismType, chroma 4:2:0, 8 bit luma and chroma
+    config[13] = 0xf0;
+    config[15] = 0xfc;
+    config[16] = 0xfd;
+    config[17] = 0xf8;
+    config[18] = 0xf8;
+    config[21] = 0x0c | (NAL_LENGTH_SIZE - 1);
+    config
+}
+
+fn heif_file(config: &[u8], width: u32, height: u32, bitstream: &[u8]) -> Vec<u8> {
+    let hdlr = full_box(b"hdlr", 0, &[&[0; 4], b"pict", &[0; 12], &[0]]);
+    let pitm = full_box(b"pitm", 0, &[&1u16.to_be_bytes()]);
+    let infe = full_box(b"infe", 2, &[&1u16.to_be_bytes(), &[0, 0], b"hvc1", &[0]]);
+    let iinf = full_box(b"iinf", 0, &[&1u16.to_be_bytes(), &infe]);
+    let ispe = full_box(b"ispe", 0, &[&width.to_be_bytes(), &height.to_be_bytes()]);
+    let ipco = plain_box(b"ipco", &[&plain_box(b"hvcC", &[config]), &ispe]);
+    // item 1 has the hvcC (essential) and ispe properties
+    let ipma = full_box(
+        b"ipma",
+        0,
+        &[&1u32.to_be_bytes(), &1u16.to_be_bytes(), &[2, 0x81, 0x02]],
+    );
+    let iprp = plain_box(b"iprp", &[&ipco, &ipma]);
+    let ftyp = plain_box(b"ftyp", &[b"heic", &[0; 4], b"mif1", b"heic"]);
+
+    // iloc's extent points into mdat, which comes right after meta
+    let iloc_len = 12 + 2 + 2 + 2 + 2 + 2 + 4 + 4;
+    let meta_len = 12 + hdlr.len() + pitm.len() + iinf.len() + iprp.len() + iloc_len;
+    let extent = (ftyp.len() + meta_len + 8) as u32;
+    let iloc = full_box(
+        b"iloc",
+        0,
+        &[
+            // 4 byte offsets and lengths, no base offset
+            &[0x44, 0x00],
+            &1u16.to_be_bytes(),
+            &1u16.to_be_bytes(),
+            &0u16.to_be_bytes(),
+            &1u16.to_be_bytes(),
+            &extent.to_be_bytes(),
+            &(bitstream.len() as u32).to_be_bytes(),
+        ],
+    );
+    let meta = full_box(b"meta", 0, &[&hdlr, &pitm, &iloc, &iinf, &iprp]);
+    debug_assert_eq!(meta.len(), meta_len);
+
+    let mut file = ftyp;
+    file.extend_from_slice(&meta);
+    file.extend_from_slice(&plain_box(b"mdat", &[bitstream]));
+    file
+}
+
+fn plain_box(kind: &[u8; 4], parts: &[&[u8]]) -> Vec<u8> {
+    let len = 8 + parts.iter().map(|p| p.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(len);
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+// version and zero flags in front of the payload
+fn full_box(kind: &[u8; 4], version: u8, parts: &[&[u8]]) -> Vec<u8> {
+    let header = [version, 0, 0, 0];
+    let mut all = vec![&header[..]];
+    all.extend_from_slice(parts);
+    plain_box(kind, &all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container() {
+        let mut out = Vec::new();
+        length_prefixed(&[0, 0, 0, 1, 0x40, 1, 0, 0, 1, 0x42, 1, 2], &mut out);
+        assert_eq!(out, [0, 0, 0, 2, 0x40, 1, 0, 0, 0, 3, 0x42, 1, 2]);
+
+        // CISZ, HVCC with bare parameter sets, then the picture
+        let mut canon = plain_box(b"CISZ", &[&[0, 0, 0, 0]]);
+        canon.extend_from_slice(&plain_box(b"HVCC", &[&[0, 0, 0, 1, 0x40, 1]]));
+        canon.extend_from_slice(&[0, 0, 0, 1, 0x26, 1, 0xaf]);
+        let file = container(&canon, 1620, 1080).expect("container");
+        assert_eq!(&file[4..12], b"ftypheic");
+        let mdat = file.windows(4).position(|w| w == b"mdat").unwrap() + 4;
+        assert_eq!(
+            &file[mdat..],
+            [0, 0, 0, 2, 0x40, 1, 0, 0, 0, 3, 0x26, 1, 0xaf]
+        );
+        // the iloc extent offset is where the bitstream starts
+        let iloc = file.windows(4).position(|w| w == b"iloc").unwrap();
+        let extent = u32::from_be_bytes(file[iloc + 18..iloc + 22].try_into().unwrap());
+        assert_eq!(extent as usize, mdat);
+
+        assert!(container(&plain_box(b"CISZ", &[&[0; 4]]), 16, 16).is_none());
+    }
+}
@@ -0,0 +1,249 @@
+// The IFDs of a TIFF based file, read straight from the file LibRaw has
+// open for the DNG tags LibRaw skips, or from a buffer when sniffing one
+// before it's opened. Reading from the file doesn't need it all in memory.
+
+use std::borrow::Cow;
+
+use crate::RawImage;
+
+const TAG_NEW_SUBFILE_TYPE: u16 = 0xfe;
+pub(crate) const TAG_SUB_IFDS: u16 = 0x14a;
+
+// DNGs have the main IFD, a few previews and the raw, anything past this is a loop
+const MAX_IFDS: usize = 32;
+// masks and gain tables are a few MB at most
+const MAX_DATA_LEN: u64 = 64 << 20;
+
+const TIFF_SHORT: u16 = 3;
+const TIFF_LONG: u16 = 4;
+const TIFF_RATIONAL: u16 = 5;
+const TIFF_SRATIONAL: u16 = 10;
+const TIFF_FLOAT: u16 = 11;
+const TIFF_DOUBLE: u16 = 12;
+
+pub(crate) struct FileEntry {
+    pub tag: u16,
+    pub kind: u16,
+    pub count: u32,
+    // inline shorts and longs, the data's offset for anything longer than 4 bytes
+    pub value: u32,
+    // where the value field sits in the source
+    pos: u64,
+}
+
+pub(crate) struct FileIfd {
+    // where the entry count sits in the source
+    pub offset: u64,
+    pub entries: Vec<FileEntry>,
+}
+
+impl FileIfd {
+    pub fn get(&self, tag: u16) -> Option<&FileEntry> {
+        self.entries.iter().find(|e| e.tag == tag)
+    }
+
+    // 0 for the full resolution image, also when the tag is missing
+    pub fn subfile_type(&self) -> u32 {
+        self.get(TAG_NEW_SUBFILE_TYPE).map_or(0, |e| e.value)
+    }
+}
+
+// where a TIFF's bytes come from, offsets are from the start of the header
+pub(crate) trait TiffSource {
+    // `len` bytes at `offset`, `None` past the end
+    fn bytes_at(&self, offset: u64, len: u32) -> Option<Cow<'_, [u8]>>;
+}
+
+impl TiffSource for RawImage {
+    fn bytes_at(&self, offset: u64, len: u32) -> Option<Cow<'_, [u8]>> {
+        self.read_at(offset, len).map(Cow::Owned)
+    }
+}
+
+impl TiffSource for [u8] {
+    fn bytes_at(&self, offset: u64, len: u32) -> Option<Cow<'_, [u8]>> {
+        let start = usize::try_from(offset).ok()?;
+        self.get(start..start.checked_add(len as usize)?)
+            .map(Cow::Borrowed)
+    }
+}
+
+pub(crate) struct FileTiff<'a, S: TiffSource + ?Sized = RawImage> {
+    source: &'a S,
+    le: bool,
+}
+
+impl<'a, S: TiffSource + ?Sized> FileTiff<'a, S> {
+    // `None` unless the source starts with a TIFF header
+    pub fn new(source: &'a S) -> Option<Self> {
+        let le = match &source.bytes_at(0, 4)?[..] {
+            [b'I', b'I', 42, 0] => true,
+            [b'M', b'M', 0, 42] => false,
+            _ => return None,
+        };
+        Some(Self::with_order(source, le))
+    }
+
+    // for the TIFF variants with a magic number of their own, RW2 and ORF
+    pub fn with_order(source: &'a S, le: bool) -> Self {
+        Self { source, le }
+    }
+
+    // IFD0, its chain and every SubIFD, each parent before its SubIFDs
+    pub fn ifds(&self) -> Vec<FileIfd> {
+        let mut ifds = Vec::new();
+        let mut pending: Vec<u32> = self.u32_at(4).into_iter().collect();
+        let mut visited = 0;
+        while let Some(offset) = pending.pop() {
+            visited += 1;
+            if offset == 0 || visited > MAX_IFDS {
+                continue;
+            }
+            let Some((ifd, next)) = self.ifd(offset as u64) else {
+                continue;
+            };
+            for entry in &ifd.entries {
+                match entry.tag {
+                    TAG_SUB_IFDS if entry.count == 1 => pending.push(entry.value),
+                    TAG_SUB_IFDS => pending.extend(
+                        (0..entry.count.min(MAX_IFDS as u32))
+                            .filter_map(|i| self.u32_at(entry.value as u64 + i as u64 * 4)),
+                    ),
+                    _ => {}
+                }
+            }
+            pending.extend(next);
+            ifds.push(ifd);
+        }
+        ifds
+    }
+
+    // the IFD at `offset` and where the next one in its chain is. Of an IFD
+    // cut off by the end of the source the entries before the cut are kept.
+    pub fn ifd(&self, offset: u64) -> Option<(FileIfd, Option<u32>)> {
+        let count = self.u16(&self.source.bytes_at(offset, 2)?) as u64;
+        let start = offset + 2;
+        let entries = match self.source.bytes_at(start, count as u32 * 12) {
+            Some(buf) => buf
+                .chunks_exact(12)
+                .zip((start..).step_by(12))
+                .map(|(entry, pos)| self.entry(entry, pos))
+                .collect(),
+            None => (start..start + count * 12)
+                .step_by(12)
+                .map_while(|pos| Some(self.entry(&self.source.bytes_at(pos, 12)?, pos)))
+                .collect(),
+        };
+        Some((FileIfd { offset, entries }, self.u32_at(start + count * 12)))
+    }
+
+    // the 12 bytes of an entry that starts at `pos`
+    fn entry(&self, entry: &[u8], pos: u64) -> FileEntry {
+        let (kind, count) = (self.u16(&entry[2..]), self.u32(&entry[4..]));
+        // a single short sits in the first half of the value field
+        let value = match (kind, count) {
+            (TIFF_SHORT, 1) => self.u16(&entry[8..]) as u32,
+            _ => self.u32(&entry[8..]),
+        };
+        FileEntry {
+            tag: self.u16(entry),
+            kind,
+            count,
+            value,
+            pos: pos + 8,
+        }
+    }
+
+    // the entry's value bytes as stored, `None` past the end of the file
+    pub fn data(&self, entry: &FileEntry) -> Option<Vec<u8>> {
+        let size = match entry.kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 | 13 => 4,
+            _ => 8,
+        };
+        let len = entry.count as u64 * size;
+        if len > MAX_DATA_LEN {
+            return None;
+        }
+        let data = match len as u32 {
+            len @ 0..=4 => self.source.bytes_at(entry.pos, len),
+            len => self.source.bytes_at(entry.value as u64, len),
+        };
+        data.map(Cow::into_owned)
+    }
+
+    // shorts and longs widened, `None` for other types
+    pub fn u32s(&self, entry: &FileEntry) -> Option<Vec<u32>> {
+        let data = self.data(entry)?;
+        match entry.kind {
+            TIFF_SHORT => Some(data.chunks_exact(2).map(|b| self.u16(b) as u32).collect()),
+            TIFF_LONG => Some(data.chunks_exact(4).map(|b| self.u32(b)).collect()),
+            _ => None,
+        }
+    }
+
+    // floats, doubles and rationals, `None` for other types
+    pub fn f64s(&self, entry: &FileEntry) -> Option<Vec<f64>> {
+        let data = self.data(entry)?;
+        let ratio = |num: f64, den: f64| if den == 0.0 { 0.0 } else { num / den };
+        match entry.kind {
+            TIFF_FLOAT => Some(
+                data.chunks_exact(4)
+                    .map(|b| f32::from_bits(self.u32(b)) as f64)
+                    .collect(),
+            ),
+            TIFF_DOUBLE => Some(
+                data.chunks_exact(8)
+                    .map(|b| f64::from_bits(self.u64(b)))
+                    .collect(),
+            ),
+            TIFF_RATIONAL => Some(
+                data.chunks_exact(8)
+                    .map(|b| ratio(self.u32(b) as f64, self.u32(&b[4..]) as f64))
+                    .collect(),
+            ),
+            TIFF_SRATIONAL => Some(
+                data.chunks_exact(8)
+                    .map(|b| ratio(self.u32(b) as i32 as f64, self.u32(&b[4..]) as i32 as f64))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    // up to the first nul, lossy for anything that isn't UTF-8
+    pub fn ascii(&self, entry: &FileEntry) -> Option<String> {
+        let data = self.data(entry)?;
+        let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        Some(String::from_utf8_lossy(&data[..end]).into_owned())
+    }
+
+    pub fn u32_at(&self, offset: u64) -> Option<u32> {
+        Some(self.u32(&self.source.bytes_at(offset, 4)?))
+    }
+
+    fn u16(&self, b: &[u8]) -> u16 {
+        let b = [b[0], b[1]];
+        match self.le {
+            true => u16::from_le_bytes(b),
+            false => u16::from_be_bytes(b),
+        }
+    }
+
+    fn u32(&self, b: &[u8]) -> u32 {
+        let b = [b[0], b[1], b[2], b[3]];
+        match self.le {
+            true => u32::from_le_bytes(b),
+            false => u32::from_be_bytes(b),
+        }
+    }
+
+    fn u64(&self, b: &[u8]) -> u64 {
+        let b: [u8; 8] = b[..8].try_into().unwrap();
+        match self.le {
+            true => u64::from_le_bytes(b),
+            false => u64::from_be_bytes(b),
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    batch::{has_extension, BatchItem, Walk, RAW_EXTENSIONS},
+    err::{Error, Result},
+    RawImage, RawMetadata, ThumbFormat,
+};
+
+const JPEG_EXTENSIONS: &[&str] = &["jpg", "jpeg"];
+
+// The walk, open, skip-what-isn't-raw loop of catalog imports. Files are
+// opened one at a time as the iterator is advanced, only metadata is read.
+#[derive(Debug, Clone)]
+pub struct Ingest {
+    root: PathBuf,
+    recursive: bool,
+    extensions: Option<Vec<String>>,
+    min_date: Option<SystemTime>,
+    max_date: Option<SystemTime>,
+}
+
+pub struct IngestIter {
+    ingest: Ingest,
+    walk: Walk,
+}
+
+impl Ingest {
+    // recursive and trying every file until narrowed down
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            recursive: true,
+            extensions: None,
+            min_date: None,
+            max_date: None,
+        }
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    // only files with one of these extensions, matched case-insensitively
+    pub fn extensions<S: Into<String>>(mut self, extensions: impl IntoIterator<Item = S>) -> Self {
+        self.extensions = Some(extensions.into_iter().map(Into::into).collect());
+        self
+    }
+
+    // the extensions of every format LibRaw decodes, see `batch::RAW_EXTENSIONS`
+    pub fn extensions_from_libraw(self) -> Self {
+        self.extensions(RAW_EXTENSIONS.iter().copied())
+    }
+
+    // Capture time bounds, inclusive. With either set, files that don't
+    // record a capture time are skipped too.
+    pub fn min_date(mut self, date: impl Into<SystemTime>) -> Self {
+        self.min_date = Some(date.into());
+        self
+    }
+
+    pub fn max_date(mut self, date: impl Into<SystemTime>) -> Self {
+        self.max_date = Some(date.into());
+        self
+    }
+
+    pub fn iter(&self) -> IngestIter {
+        IngestIter {
+            walk: Walk::new(self.root.clone(), self.recursive),
+            ingest: self.clone(),
+        }
+    }
+
+    fn wanted_path(&self, path: &Path) -> bool {
+        match &self.extensions {
+            Some(extensions) => has_extension(path, extensions),
+            None => true,
+        }
+    }
+
+    fn wanted_date(&self, raw_image: &RawImage) -> bool {
+        if self.min_date.is_none() && self.max_date.is_none() {
+            return true;
+        }
+        raw_image.capture_time().is_some_and(|time| {
+            self.min_date.is_none_or(|min| time >= min)
+                && self.max_date.is_none_or(|max| time <= max)
+        })
+    }
+
+    // `None` for files that are skipped. LibRaw reads just the headers, a
+    // video or anything else big that isn't raw costs no more than a raw.
+    fn open(&self, path: &Path) -> Option<Result<RawMetadata>> {
+        match RawImage::open_file(path) {
+            Ok(raw_image) => self
+                .wanted_date(&raw_image)
+                .then(|| Ok(raw_image.metadata())),
+            Err(err) if matches!(err.error, Error::FileUnsupported) => None,
+            Err(err) => Some(Err(err.into())),
+        }
+    }
+}
+
+// A raw and the JPEG the camera wrote along with it, as one item. The JPEG,
+// developed in camera, makes the quicker preview, the raw is what gets
+// processed.
+#[derive(Debug, Clone)]
+pub struct RawJpegPair {
+    pub raw: PathBuf,
+    pub jpeg: Option<PathBuf>,
+    pub metadata: RawMetadata,
+}
+
+impl RawJpegPair {
+    // the paired JPEG's bytes, or the raw's largest embedded preview when
+    // it's a JPEG, `Error::UnsupportedThumbnail` otherwise
+    pub fn preview(&self) -> Result<Vec<u8>> {
+        if let Some(jpeg) = &self.jpeg {
+            return Ok(std::fs::read(jpeg)?);
+        }
+        let thumb = self.open_raw()?.extract_best_thumb()?;
+        match thumb.format {
+            ThumbFormat::Jpeg => Ok(thumb.data),
+            _ => Err(Error::UnsupportedThumbnail),
+        }
+    }
+
+    pub fn open_raw(&self) -> Result<RawImage> {
+        Ok(RawImage::open_file(&self.raw)?)
+    }
+}
+
+// Yields what `IngestIter` does with each raw paired up, see `Ingest::pairs`
+pub struct IngestPairs {
+    iter: IngestIter,
+    // the directory last looked at, the walk goes through one at a time
+    listing: Option<Listing>,
+}
+
+struct Listing {
+    dir: PathBuf,
+    // with their capture times, as LibRaw converts the raw's
+    jpegs: Vec<(PathBuf, Option<i64>)>,
+    // of the files with a raw extension, for JPEGs that belong to another raw
+    raw_stems: Vec<String>,
+    // the JPEGs already paired with a raw, each goes to one at most
+    paired: Vec<PathBuf>,
+}
+
+impl Ingest {
+    // Like `iter`, each raw with the JPEG next to it that has the same name
+    // but for the extension or, failing that, one whose EXIF
+    // DateTimeOriginal is the raw's capture time and whose name matches no
+    // raw in the directory. No JPEG is paired twice, and the JPEGs
+    // themselves aren't yielded. Matching by time needs the `chrono` feature.
+    pub fn pairs(&self) -> IngestPairs {
+        IngestPairs {
+            iter: self.iter(),
+            listing: None,
+        }
+    }
+}
+
+impl Listing {
+    fn read(dir: &Path) -> Self {
+        let mut listing = Self {
+            dir: dir.to_path_buf(),
+            jpegs: Vec::new(),
+            raw_stems: Vec::new(),
+            paired: Vec::new(),
+        };
+        let entries = std::fs::read_dir(dir).into_iter().flatten().flatten();
+        for path in entries.map(|entry| entry.path()) {
+            let jpeg = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| JPEG_EXTENSIONS.iter().any(|e| e.eq_ignore_ascii_case(ext)));
+            if jpeg {
+                let time = jpeg_capture_time(&path);
+                listing.jpegs.push((path, time));
+            } else if let Some(stem) = raw_stem(&path) {
+                listing.raw_stems.push(stem);
+            }
+        }
+        listing.jpegs.sort();
+        listing
+    }
+
+    fn pair(&mut self, raw: &Path, metadata: &RawMetadata) -> Option<PathBuf> {
+        let stem = raw_stem(raw)?;
+        let mut unpaired = self
+            .jpegs
+            .iter()
+            .filter(|(jpeg, _)| !self.paired.contains(jpeg));
+        let named = unpaired.clone().find(|(jpeg, _)| stem_of(jpeg) == stem);
+        // both are stamped with the shutter release, to the second
+        let timed = || {
+            let time = Some(metadata.info.timestamp()).filter(|&ts| ts > 0);
+            unpaired.find(|(jpeg, jpeg_time)| {
+                time.is_some() && *jpeg_time == time && !self.raw_stems.contains(&stem_of(jpeg))
+            })
+        };
+        let (jpeg, _) = named.or_else(timed)?;
+        let jpeg = jpeg.clone();
+        self.paired.push(jpeg.clone());
+        Some(jpeg)
+    }
+}
+
+// The JPEG's DateTimeOriginal in seconds since the epoch, read as local time
+// like LibRaw does the raw's. Only the head of the file is read.
+#[cfg(feature = "chrono")]
+fn jpeg_capture_time(path: &Path) -> Option<i64> {
+    use std::io::Read;
+
+    use chrono::{Local, NaiveDateTime, TimeZone};
+
+    use crate::{ifd::FileTiff, thumb::jpeg_exif};
+
+    // the APP1 segment EXIF sits in is at most 64 KiB, with room for an APP0 before it
+    const JPEG_HEAD_LEN: u64 = 80 << 10;
+    const TAG_EXIF_IFD: u16 = 0x8769;
+    const TAG_DATETIME_ORIGINAL: u16 = 0x9003;
+
+    let mut head = Vec::new();
+    std::fs::File::open(path)
+        .ok()?
+        .take(JPEG_HEAD_LEN)
+        .read_to_end(&mut head)
+        .ok()?;
+    let (_, tiff) = jpeg_exif(&head)?;
+    let reader = FileTiff::with_order(tiff.buf, tiff.le);
+    let find = |pos: u32, tag: u16| {
+        let (ifd, _) = reader.ifd(pos as u64)?;
+        ifd.entries.into_iter().find(|entry| entry.tag == tag)
+    };
+    let exif = find(reader.u32_at(4)?, TAG_EXIF_IFD)?;
+    let datetime =
+        find(exif.value, TAG_DATETIME_ORIGINAL).filter(|e| e.kind == 2 && e.count >= 19)?;
+    let text = tiff
+        .buf
+        .get(datetime.value as usize..datetime.value as usize + 19)?;
+    let naive =
+        NaiveDateTime::parse_from_str(std::str::from_utf8(text).ok()?, "%Y:%m:%d %H:%M:%S").ok()?;
+    Some(Local.from_local_datetime(&naive).earliest()?.timestamp())
+}
+
+#[cfg(not(feature = "chrono"))]
+fn jpeg_capture_time(_path: &Path) -> Option<i64> {
+    None
+}
+
+fn stem_of(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn raw_stem(path: &Path) -> Option<String> {
+    let ext = path.extension()?.to_str()?;
+    RAW_EXTENSIONS
+        .iter()
+        .any(|raw| raw.eq_ignore_ascii_case(ext))
+        .then(|| stem_of(path))
+}
+
+impl Iterator for IngestPairs {
+    type Item = BatchItem<RawJpegPair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let BatchItem { path, result } = self.iter.next()?;
+        let result = result.map(|metadata| {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            if self
+                .listing
+                .as_ref()
+                .is_none_or(|listing| listing.dir != dir)
+            {
+                self.listing = Some(Listing::read(dir));
+            }
+            RawJpegPair {
+                raw: path.clone(),
+                jpeg: self
+                    .listing
+                    .as_mut()
+                    .and_then(|listing| listing.pair(&path, &metadata)),
+                metadata,
+            }
+        });
+        Some(BatchItem { path, result })
+    }
+}
+
+impl IntoIterator for &Ingest {
+    type Item = BatchItem<RawMetadata>;
+    type IntoIter = IngestIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// Yields raw files and the errors reading the tree or a file, files LibRaw
+// doesn't recognize and files outside the date range are left out.
+impl Iterator for IngestIter {
+    type Item = BatchItem<RawMetadata>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.walk.next()? {
+                Ok(path) if self.ingest.wanted_path(&path) => {
+                    if let Some(result) = self.ingest.open(&path) {
+                        return Some(BatchItem { path, result });
+                    }
+                }
+                Ok(_) => {}
+                Err((path, err)) => {
+                    return Some(BatchItem {
+                        path,
+                        result: Err(err.into()),
+                    })
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_ingest() {
+        let ingest = Ingest::new(get_test_assets_path()).extensions_from_libraw();
+        let mut models: Vec<_> = ingest
+            .iter()
+            .map(|item| item.result.expect("opened").info.model)
+            .collect();
+        models.sort();
+        assert_eq!(models, ["ILCE-7RM4", "Z 8"]);
+        let every_file = Ingest::new(get_test_assets_path()).iter();
+        assert_eq!(every_file.filter(|item| item.result.is_ok()).count(), 2);
+
+        // the z8 shot is from november 2024, the a7r iv one a year earlier
+        let since = SystemTime::UNIX_EPOCH + Duration::from_secs(1_720_000_000);
+        let items: Vec<_> = ingest.min_date(since).iter().collect();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].result.as_ref().unwrap().info.model, "Z 8");
+    }
+
+    // a JPEG whose EXIF has only DateTimeOriginal, in an EXIF IFD
+    #[cfg(feature = "chrono")]
+    fn dated_jpeg(datetime: &str) -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0".to_vec();
+        // IFD0 with the EXIF IFD pointer at 26, that with the string at 44
+        tiff.extend([1, 0, 0x69, 0x87, 4, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0, 0, 0, 0]);
+        tiff.extend([1, 0, 0x03, 0x90, 2, 0, 20, 0, 0, 0, 44, 0, 0, 0, 0, 0, 0, 0]);
+        tiff.extend(datetime.as_bytes());
+        tiff.push(0);
+        let mut data = vec![0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xe1];
+        data.extend((8 + tiff.len() as u16).to_be_bytes());
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend(b"\xff\xda\0\x02timed");
+        data
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_pairs() {
+        use chrono::{Local, TimeZone};
+
+        use crate::raw::tests::TempDir;
+
+        let dir = TempDir::new("pairs");
+        let assets = get_test_assets_path();
+        std::fs::copy(assets.join("test-z8.NEF"), dir.join("DSC_0001.NEF")).unwrap();
+        std::fs::copy(assets.join("test-a7rm4.ARW"), dir.join("DSC_0002.ARW")).unwrap();
+        // the same shot again, the renamed JPEG can only go to one of them
+        std::fs::copy(assets.join("test-a7rm4.ARW"), dir.join("DSC_0003.ARW")).unwrap();
+        std::fs::write(dir.join("dsc_0001.jpg"), b"\xff\xd8named").unwrap();
+        // renamed, found by its capture time rather than when it was written
+        let data = std::fs::read(assets.join("test-a7rm4.ARW")).unwrap();
+        let ts = RawImage::open_owned(data).expect("opened").timestamp();
+        let taken = Local.timestamp_opt(ts, 0).unwrap().naive_local();
+        let jpeg = dated_jpeg(&taken.format("%Y:%m:%d %H:%M:%S").to_string());
+        std::fs::write(dir.join("holiday.JPEG"), &jpeg).unwrap();
+        // another one taken a second later, and one with the raw's mtime
+        let later = dated_jpeg(
+            &(taken + chrono::Duration::seconds(1))
+                .format("%Y:%m:%d %H:%M:%S")
+                .to_string(),
+        );
+        std::fs::write(dir.join("later.jpg"), later).unwrap();
+        std::fs::write(dir.join("written.jpg"), b"\xff\xd8written").unwrap();
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for name in ["DSC_0002.ARW", "DSC_0003.ARW", "written.jpg"] {
+            let file = std::fs::File::options()
+                .write(true)
+                .open(dir.join(name))
+                .unwrap();
+            file.set_modified(time).unwrap();
+        }
+
+        let ingest = Ingest::new(dir.path()).extensions_from_libraw();
+        let mut pairs: Vec<_> = ingest.pairs().map(|item| item.result.unwrap()).collect();
+        pairs.sort_by(|a, b| a.raw.cmp(&b.raw));
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs[0].jpeg, Some(dir.join("dsc_0001.jpg")));
+        assert_eq!(pairs[1].metadata.info.model, "ILCE-7RM4");
+        let timed: Vec<_> = pairs[1..].iter().flat_map(|pair| &pair.jpeg).collect();
+        assert_eq!(timed, [&dir.join("holiday.JPEG")]);
+        assert_eq!(pairs[0].preview().unwrap(), b"\xff\xd8named");
+
+        // without one, the raw's own preview
+        std::fs::remove_file(dir.join("holiday.JPEG")).unwrap();
+        let pair = ingest
+            .pairs()
+            .map(|item| item.result.unwrap())
+            .find(|pair| pair.metadata.info.model == "ILCE-7RM4")
+            .unwrap();
+        assert_eq!(pair.jpeg, None);
+        assert_eq!(pair.preview().unwrap()[..2], [0xff, 0xd8]);
+    }
+}
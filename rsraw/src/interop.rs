@@ -0,0 +1,174 @@
+// Hands rsraw's pixels to other Rust imaging crates. `IntoImageBuffer` turns
+// processed images and thumbnails into one owned, interleaved buffer, the
+// `image` and `zune-image` features convert that into their image types.
+
+use crate::{
+    err::{Error, Result},
+    preview::decode_jpeg,
+    ProcessedImage, ThumbFormat, ThumbnailImage, BIT_DEPTH_16, BIT_DEPTH_8,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Samples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+}
+
+// interleaved, rows tightly packed, `channels` is 1 (gray), 3 (RGB) or 4
+// (LibRaw's four color output, the last channel is a second green, not alpha)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub channels: u16,
+    pub samples: Samples,
+}
+
+pub trait IntoImageBuffer {
+    fn into_image_buffer(self) -> Result<ImageBuffer>;
+
+    // gray and RGB only, four color buffers have no `DynamicImage` equivalent
+    #[cfg(feature = "image")]
+    fn into_dynamic_image(self) -> Result<image::DynamicImage>
+    where
+        Self: Sized,
+    {
+        self.into_image_buffer()?.try_into()
+    }
+
+    #[cfg(feature = "zune-image")]
+    fn into_zune_image(self) -> Result<zune_image::image::Image>
+    where
+        Self: Sized,
+    {
+        self.into_image_buffer()?.try_into()
+    }
+}
+
+impl IntoImageBuffer for ProcessedImage<BIT_DEPTH_8> {
+    fn into_image_buffer(self) -> Result<ImageBuffer> {
+        Ok(ImageBuffer {
+            width: self.width(),
+            height: self.height(),
+            channels: self.colors(),
+            samples: Samples::U8(self.to_vec()),
+        })
+    }
+}
+
+impl IntoImageBuffer for ProcessedImage<BIT_DEPTH_16> {
+    fn into_image_buffer(self) -> Result<ImageBuffer> {
+        Ok(ImageBuffer {
+            width: self.width(),
+            height: self.height(),
+            channels: self.colors(),
+            samples: Samples::U16(self.to_vec()),
+        })
+    }
+}
+
+// JPEG thumbnails are decoded to RGB, bitmaps are passed through
+impl IntoImageBuffer for ThumbnailImage {
+    fn into_image_buffer(self) -> Result<ImageBuffer> {
+        match self.format {
+            ThumbFormat::Jpeg => {
+                let (width, height, data) = decode_jpeg(&self.data, 0)?;
+                Ok(ImageBuffer {
+                    width,
+                    height,
+                    channels: 3,
+                    samples: Samples::U8(data),
+                })
+            }
+            ThumbFormat::Bitmap => Ok(ImageBuffer {
+                width: self.width,
+                height: self.height,
+                channels: self.colors,
+                samples: Samples::U8(self.data),
+            }),
+            _ => Err(Error::UnsupportedThumbnail),
+        }
+    }
+}
+
+impl IntoImageBuffer for ImageBuffer {
+    fn into_image_buffer(self) -> Result<ImageBuffer> {
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "image")]
+impl TryFrom<ImageBuffer> for image::DynamicImage {
+    type Error = Error;
+
+    fn try_from(buf: ImageBuffer) -> Result<Self> {
+        use image::{DynamicImage, ImageBuffer as Buf};
+
+        let (w, h) = (buf.width, buf.height);
+        let image = match (buf.channels, buf.samples) {
+            (1, Samples::U8(v)) => Buf::from_raw(w, h, v).map(DynamicImage::ImageLuma8),
+            (3, Samples::U8(v)) => Buf::from_raw(w, h, v).map(DynamicImage::ImageRgb8),
+            (1, Samples::U16(v)) => Buf::from_raw(w, h, v).map(DynamicImage::ImageLuma16),
+            (3, Samples::U16(v)) => Buf::from_raw(w, h, v).map(DynamicImage::ImageRgb16),
+            _ => return Err(Error::NotImplemented),
+        };
+        image.ok_or(Error::Unspecified)
+    }
+}
+
+#[cfg(feature = "zune-image")]
+impl TryFrom<ImageBuffer> for zune_image::image::Image {
+    type Error = Error;
+
+    fn try_from(buf: ImageBuffer) -> Result<Self> {
+        use zune_core::colorspace::ColorSpace;
+        use zune_image::image::Image;
+
+        let colorspace = match buf.channels {
+            1 => ColorSpace::Luma,
+            3 => ColorSpace::RGB,
+            _ => return Err(Error::NotImplemented),
+        };
+        let (w, h) = (buf.width as usize, buf.height as usize);
+        // zune panics on a length mismatch, check first
+        let len = match &buf.samples {
+            Samples::U8(v) => v.len(),
+            Samples::U16(v) => v.len(),
+        };
+        if len != w * h * buf.channels as usize {
+            return Err(Error::Unspecified);
+        }
+        Ok(match &buf.samples {
+            Samples::U8(v) => Image::from_u8(v, w, h, colorspace),
+            Samples::U16(v) => Image::from_u16(v, w, h, colorspace),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, RawImage};
+
+    #[test]
+    fn test_into_image_buffer() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let thumb = raw_image.extract_thumb(0).expect("thumb");
+        let (width, height) = (thumb.width, thumb.height);
+        let buf = thumb.into_image_buffer().expect("decoded");
+        assert_eq!((buf.width, buf.height, buf.channels), (width, height, 3));
+        assert!(matches!(&buf.samples, Samples::U8(v) if v.len() == (width * height * 3) as usize));
+
+        #[cfg(feature = "image")]
+        {
+            let image = image::DynamicImage::try_from(buf.clone()).unwrap();
+            assert_eq!((image.width(), image.height()), (width, height));
+        }
+        #[cfg(feature = "zune-image")]
+        {
+            let image = zune_image::image::Image::try_from(buf).unwrap();
+            assert_eq!(image.dimensions(), (width as usize, height as usize));
+        }
+    }
+}
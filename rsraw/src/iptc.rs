@@ -0,0 +1,223 @@
+// The IPTC-IIM block news agencies fill in, in TIFF based raws with an IPTC
+// tag or one in Photoshop's image resources, mostly DNGs written by an
+// editor. Only the application record (2) is read.
+
+use crate::{ifd::FileTiff, RawImage};
+
+const TAG_IPTC: u16 = 0x83bb;
+const TAG_PHOTOSHOP: u16 = 0x8649;
+const RESOURCE_IPTC: u16 = 0x0404;
+
+const RECORD_ENVELOPE: u8 = 1;
+const RECORD_APPLICATION: u8 = 2;
+const CODED_CHARACTER_SET: u8 = 90;
+const OBJECT_NAME: u8 = 5;
+const KEYWORDS: u8 = 25;
+const BYLINE: u8 = 80;
+const CITY: u8 = 90;
+const COUNTRY: u8 = 101;
+const HEADLINE: u8 = 105;
+const CREDIT: u8 = 110;
+const SOURCE: u8 = 115;
+const COPYRIGHT: u8 = 116;
+const CAPTION: u8 = 120;
+// ESC % G
+const UTF8: &[u8] = b"\x1b%G";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Iptc {
+    // the title, a short reference for the shot
+    pub object_name: Option<String>,
+    pub headline: Option<String>,
+    // the description, Caption/Abstract
+    pub caption: Option<String>,
+    pub keywords: Vec<String>,
+    // the photographers
+    pub byline: Vec<String>,
+    // who to credit, usually the agency
+    pub credit: Option<String>,
+    // who holds the image, where it came from originally
+    pub source: Option<String>,
+    pub copyright: Option<String>,
+    pub city: Option<String>,
+    pub country: Option<String>,
+}
+
+impl Iptc {
+    // IIM datasets as stored, `None` when there's none of the fields above.
+    // Text is UTF-8 if the envelope says so, otherwise Latin-1 where it
+    // isn't valid UTF-8 anyway.
+    pub fn parse(iim: &[u8]) -> Option<Self> {
+        let mut datasets = Vec::new();
+        let mut pos = 0;
+        while let Some(&[0x1c, record, dataset, hi, lo]) = iim.get(pos..pos + 5) {
+            pos += 5;
+            let mut len = u16::from_be_bytes([hi, lo]) as usize;
+            // the extended form gives the size of the length that follows
+            if len & 0x8000 != 0 {
+                let size = len & 0x7fff;
+                let Some(field) = iim.get(pos..pos + size).filter(|_| size <= 4) else {
+                    break;
+                };
+                len = field.iter().fold(0, |len, &b| len << 8 | b as usize);
+                pos += size;
+            }
+            // a block cut short keeps what came before
+            let Some(data) = iim.get(pos..).and_then(|rest| rest.get(..len)) else {
+                break;
+            };
+            datasets.push((record, dataset, data));
+            pos += len;
+        }
+
+        let utf8 = datasets.iter().any(|&(record, dataset, data)| {
+            (record, dataset) == (RECORD_ENVELOPE, CODED_CHARACTER_SET) && data == UTF8
+        });
+        let text = |data: &[u8]| {
+            let data = data.strip_suffix(&[0]).unwrap_or(data);
+            match std::str::from_utf8(data) {
+                Ok(s) => s.trim().to_owned(),
+                Err(_) if utf8 => String::from_utf8_lossy(data).trim().to_owned(),
+                Err(_) => data
+                    .iter()
+                    .map(|&b| b as char)
+                    .collect::<String>()
+                    .trim()
+                    .to_owned(),
+            }
+        };
+        let mut iptc = Self::default();
+        for (record, dataset, data) in datasets {
+            if record != RECORD_APPLICATION {
+                continue;
+            }
+            let text = text(data);
+            if text.is_empty() {
+                continue;
+            }
+            let field = match dataset {
+                KEYWORDS => {
+                    iptc.keywords.push(text);
+                    continue;
+                }
+                BYLINE => {
+                    iptc.byline.push(text);
+                    continue;
+                }
+                OBJECT_NAME => &mut iptc.object_name,
+                HEADLINE => &mut iptc.headline,
+                CAPTION => &mut iptc.caption,
+                CREDIT => &mut iptc.credit,
+                SOURCE => &mut iptc.source,
+                COPYRIGHT => &mut iptc.copyright,
+                CITY => &mut iptc.city,
+                COUNTRY => &mut iptc.country,
+                _ => continue,
+            };
+            field.get_or_insert(text);
+        }
+        (iptc != Self::default()).then_some(iptc)
+    }
+}
+
+impl RawImage {
+    // `None` for files without an IPTC block and for formats that aren't
+    // TIFF based, such as CR3 and RAF
+    pub fn iptc(&self) -> Option<Iptc> {
+        let tiff = FileTiff::new(self)?;
+        let ifd0 = tiff.ifds().into_iter().next()?;
+        if let Some(iptc) = ifd0
+            .get(TAG_IPTC)
+            .and_then(|entry| tiff.data(entry))
+            .and_then(|iim| Iptc::parse(&iim))
+        {
+            return Some(iptc);
+        }
+        let resources = tiff.data(ifd0.get(TAG_PHOTOSHOP)?)?;
+        Iptc::parse(photoshop_resource(&resources, RESOURCE_IPTC)?)
+    }
+}
+
+// A resource of Photoshop's image resource blocks: "8BIM", the id, a padded
+// Pascal string name, then the size and the data, padded to even lengths
+fn photoshop_resource(mut blocks: &[u8], id: u16) -> Option<&[u8]> {
+    while let Some(rest) = blocks.strip_prefix(b"8BIM") {
+        let this = u16::from_be_bytes(rest.get(..2)?.try_into().ok()?);
+        let name = (*rest.get(2)? as usize + 1).next_multiple_of(2);
+        let rest = rest.get(2 + name..)?;
+        let size = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let data = rest.get(4..4 + size)?;
+        if this == id {
+            return Some(data);
+        }
+        blocks = rest.get(4 + size.next_multiple_of(2)..)?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    fn dataset(record: u8, dataset: u8, data: &[u8]) -> Vec<u8> {
+        let len = (data.len() as u16).to_be_bytes();
+        [&[0x1c, record, dataset, len[0], len[1]][..], data].concat()
+    }
+
+    #[test]
+    fn test_iptc() {
+        let iim = [
+            dataset(RECORD_ENVELOPE, CODED_CHARACTER_SET, UTF8),
+            dataset(RECORD_APPLICATION, 0, &[0, 4]),
+            dataset(RECORD_APPLICATION, KEYWORDS, b"election"),
+            dataset(RECORD_APPLICATION, KEYWORDS, b"Berlin"),
+            dataset(RECORD_APPLICATION, BYLINE, b"Jane Doe"),
+            dataset(RECORD_APPLICATION, CREDIT, b"Agency"),
+            dataset(
+                RECORD_APPLICATION,
+                CAPTION,
+                "Stimmabgabe in Köpenick".as_bytes(),
+            ),
+        ]
+        .concat();
+        let iptc = Iptc::parse(&iim).expect("parsed");
+        assert_eq!(
+            iptc,
+            Iptc {
+                caption: Some("Stimmabgabe in Köpenick".into()),
+                keywords: vec!["election".into(), "Berlin".into()],
+                byline: vec!["Jane Doe".into()],
+                credit: Some("Agency".into()),
+                ..Default::default()
+            }
+        );
+
+        // Latin-1 without a character set, and an extended length
+        let mut long = vec![0x1c, RECORD_APPLICATION, HEADLINE, 0x80, 4, 0, 0, 0, 3];
+        long.extend_from_slice(b"Big");
+        let iim = [dataset(RECORD_APPLICATION, CITY, b"K\xf6ln"), long].concat();
+        let iptc = Iptc::parse(&iim).expect("parsed");
+        assert_eq!(iptc.city.as_deref(), Some("Köln"));
+        assert_eq!(iptc.headline.as_deref(), Some("Big"));
+        let cut = Iptc::parse(&iim[..iim.len() - 1]).expect("parsed");
+        assert_eq!((cut.city.as_deref(), cut.headline), (Some("Köln"), None));
+        assert!(Iptc::parse(&dataset(RECORD_APPLICATION, 0, &[0, 4])).is_none());
+
+        let mut resources = b"8BIM\x03\xed\0\0".to_vec();
+        resources.extend_from_slice(&[0, 0, 0, 1, 7, 0]);
+        resources.extend_from_slice(b"8BIM\x04\x04\x02ab\0");
+        resources.extend_from_slice(&(iim.len() as u32).to_be_bytes());
+        resources.extend_from_slice(&iim);
+        assert_eq!(
+            photoshop_resource(&resources, RESOURCE_IPTC),
+            Some(&iim[..])
+        );
+        assert_eq!(photoshop_resource(&resources, 0x040c), None);
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let raw_image = RawImage::open_owned(data).expect("opened");
+        assert_eq!(raw_image.iptc(), None);
+    }
+}
@@ -0,0 +1,172 @@
+use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+
+use crate::{
+    convert,
+    err::{Error, Result},
+    preview::{decode_jpeg, fit_size, orient},
+    raw::BitDepth,
+    ProcessedImage, RawImage, ThumbFormat, ThumbnailImage,
+};
+
+// How chroma is stored when a thumbnail is encoded, 4:4:4 keeps thin colored
+// edges and red text crisp at about half again the size of 4:2:0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ChromaSubsampling {
+    Yuv444,
+    Yuv422,
+    #[default]
+    Yuv420,
+}
+
+// For `extract_best_thumb_jpeg` and `ProcessedImage::to_jpeg`. A JPEG preview
+// that needs neither scaling nor rotating is passed through as is, these only
+// apply to re-encodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JpegOptions {
+    // 1 to 100
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+    // scale down to fit, 0 keeps the size
+    pub max_edge: u32,
+    // turn the pixels upright by the camera's orientation, embedded previews
+    // are stored the way the sensor saw them
+    pub apply_flip: bool,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self {
+            quality: 90,
+            subsampling: ChromaSubsampling::default(),
+            max_edge: 0,
+            apply_flip: false,
+        }
+    }
+}
+
+impl RawImage {
+    // `extract_best_thumb` as a JPEG whatever the camera embedded, bitmaps and
+    // decoded H.265 previews are encoded with `options`. Thumbnails that are
+    // neither RGB nor gray fail with `Error::UnsupportedThumbnail`.
+    pub fn extract_best_thumb_jpeg(&mut self, options: &JpegOptions) -> Result<ThumbnailImage> {
+        let (info, thumb) = self.best_thumb()?;
+        let flip = match options.apply_flip {
+            true => self.thumb_flip(&info),
+            false => 0,
+        };
+        let (w, h) = fit_size(thumb.width, thumb.height, options.max_edge);
+        if thumb.format == ThumbFormat::Jpeg
+            && flip & 7 == 0
+            && (w, h) == (thumb.width, thumb.height)
+        {
+            return Ok(thumb);
+        }
+
+        let (width, height, rgb) = match (thumb.format, thumb.colors) {
+            (ThumbFormat::Jpeg, _) => decode_jpeg(&thumb.data, options.max_edge)?,
+            (ThumbFormat::Bitmap, 3) => (thumb.width, thumb.height, thumb.data),
+            (ThumbFormat::Bitmap, 1) => {
+                let rgb = thumb.data.iter().flat_map(|&l| [l; 3]).collect();
+                (thumb.width, thumb.height, rgb)
+            }
+            (ThumbFormat::Bitmap16, 3) => {
+                let rgb = thumb
+                    .data
+                    .chunks_exact(2)
+                    .map(|s| (u16::from_ne_bytes([s[0], s[1]]) >> 8) as u8)
+                    .collect();
+                (thumb.width, thumb.height, rgb)
+            }
+            _ => return Err(Error::UnsupportedThumbnail),
+        };
+        // DCT scaling only gets the JPEG roughly there
+        let (w, h) = fit_size(width, height, options.max_edge);
+        let rgb = match (w, h) == (width, height) {
+            true => rgb,
+            false => convert::downscale_rgb8(&rgb, width as _, height as _, w as _, h as _),
+        };
+        let (w, h, rgb) = orient(&rgb, w, h, flip);
+        Ok(ThumbnailImage {
+            format: ThumbFormat::Jpeg,
+            width: w,
+            height: h,
+            colors: 3,
+            data: encode(&rgb, w, h, options)?,
+        })
+    }
+}
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // Scaled down to `options.max_edge` and 16 bits dithered to 8, RGB only,
+    // `Error::NotImplemented` for other outputs. Processing already turned
+    // the image upright, `apply_flip` is ignored.
+    pub fn to_jpeg(&self, options: &JpegOptions) -> Result<Vec<u8>> {
+        if self.colors() != 3 {
+            return Err(Error::NotImplemented);
+        }
+        let (width, height) = (self.width(), self.height());
+        let rgb = self.to_u8();
+        let (w, h) = fit_size(width, height, options.max_edge);
+        match (w, h) == (width, height) {
+            true => encode(&rgb, w, h, options),
+            false => {
+                let scaled = convert::downscale_rgb8(&rgb, width as _, height as _, w as _, h as _);
+                encode(&scaled, w, h, options)
+            }
+        }
+    }
+}
+
+pub(crate) fn encode(
+    rgb: &[u8],
+    width: u32,
+    height: u32,
+    options: &JpegOptions,
+) -> Result<Vec<u8>> {
+    let width = u16::try_from(width).map_err(|_| Error::TooBig)?;
+    let height = u16::try_from(height).map_err(|_| Error::TooBig)?;
+    let mut out = Vec::new();
+    let mut encoder = Encoder::new(&mut out, options.quality.clamp(1, 100));
+    encoder.set_sampling_factor(match options.subsampling {
+        ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+        ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+        ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+    });
+    encoder
+        .encode(rgb, width, height, ColorType::Rgb)
+        .map_err(|_| Error::Unspecified)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_extract_best_thumb_jpeg() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let best = raw_image.extract_best_thumb().expect("best thumb");
+        let kept = raw_image
+            .extract_best_thumb_jpeg(&Default::default())
+            .expect("kept");
+        assert_eq!(kept.data, best.data);
+
+        let mut sized = |subsampling| {
+            let options = JpegOptions {
+                max_edge: 640,
+                subsampling,
+                ..Default::default()
+            };
+            raw_image
+                .extract_best_thumb_jpeg(&options)
+                .expect("encoded")
+        };
+        let small = sized(ChromaSubsampling::Yuv420);
+        assert_eq!(small.width.max(small.height), 640);
+        let (width, height, _) = decode_jpeg(&small.data, 0).expect("decodes");
+        assert_eq!((width, height), (small.width, small.height));
+        assert!(sized(ChromaSubsampling::Yuv444).data.len() > small.data.len());
+    }
+}
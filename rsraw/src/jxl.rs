@@ -0,0 +1,299 @@
+// Lossless JPEG XL with zune-jpegxl. Its encoder always declares sRGB, so
+// the colour encoding in the image header is rewritten to the primaries the
+// image was developed into and LibRaw's BT.709 curve. Everything after the
+// header starts on a byte boundary and is copied as encoded.
+
+use zune_core::{
+    bit_depth::BitDepth as ZuneDepth, colorspace::ColorSpace, options::EncoderOptions,
+};
+use zune_jpegxl::JxlSimpleEncoder;
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    ImageLayout, OutputColor, ProcessedImage,
+};
+
+// the JXL enum values of ColourEncoding
+const WHITE_D65: u32 = 1;
+const PRIMARIES_SRGB: u32 = 1;
+const PRIMARIES_CUSTOM: u32 = 2;
+const PRIMARIES_BT2100: u32 = 9;
+const PRIMARIES_P3: u32 = 11;
+const TRANSFER_BT709: u32 = 1;
+const INTENT_RELATIVE: u32 = 1;
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // At the image's own 8 or 16 bits, nothing lost. RGB only, camera space
+    // and XYZ output have no JXL description and are `Error::NotImplemented`.
+    pub fn to_jxl(&self) -> Result<Vec<u8>> {
+        let layout = ImageLayout {
+            width: self.width(),
+            height: self.height(),
+            colors: self.colors(),
+            bits: D as _,
+        };
+        encode(&layout, self.bytes(), self.output_color())
+    }
+}
+
+pub(crate) fn encode(layout: &ImageLayout, data: &[u8], color: OutputColor) -> Result<Vec<u8>> {
+    let primaries = primaries(color).ok_or(Error::NotImplemented)?;
+    if layout.colors != 3 || data.len() != layout.len() {
+        return Err(Error::NotImplemented);
+    }
+    let depth = match layout.bits {
+        8 => ZuneDepth::Eight,
+        _ => ZuneDepth::Sixteen,
+    };
+    let options = EncoderOptions::new(
+        layout.width as _,
+        layout.height as _,
+        ColorSpace::RGB,
+        depth,
+    );
+    let mut encoded = Vec::new();
+    JxlSimpleEncoder::new(data, options)
+        .encode(&mut encoded)
+        .map_err(|_| Error::Unspecified)?;
+    with_colour_encoding(&encoded, layout, primaries).ok_or(Error::Unspecified)
+}
+
+enum Primaries {
+    Known(u32),
+    // red, green and blue xy chromaticities
+    Custom([[f64; 2]; 3]),
+}
+
+fn primaries(color: OutputColor) -> Option<Primaries> {
+    Some(match color {
+        OutputColor::Srgb => Primaries::Known(PRIMARIES_SRGB),
+        OutputColor::DciP3 => Primaries::Known(PRIMARIES_P3),
+        OutputColor::Rec2020 => Primaries::Known(PRIMARIES_BT2100),
+        OutputColor::Adobe => Primaries::Custom([[0.64, 0.33], [0.21, 0.71], [0.15, 0.06]]),
+        OutputColor::WideGamut => {
+            Primaries::Custom([[0.7347, 0.2653], [0.1152, 0.8264], [0.1566, 0.0177]])
+        }
+        OutputColor::ProPhoto => {
+            Primaries::Custom([[0.7347, 0.2653], [0.1596, 0.8404], [0.0366, 0.0001]])
+        }
+        OutputColor::Aces => Primaries::Custom([[0.7347, 0.2653], [0.0, 1.0], [0.0001, -0.077]]),
+        OutputColor::Raw | OutputColor::Xyz => return None,
+    })
+}
+
+// `None` if the header isn't laid out the way zune-jpegxl writes it
+fn with_colour_encoding(
+    encoded: &[u8],
+    layout: &ImageLayout,
+    primaries: Primaries,
+) -> Option<Vec<u8>> {
+    let size_bits = |v: u32| -> usize {
+        match v - 1 {
+            v if v < 1 << 9 => 2 + 9,
+            v if v < 1 << 13 => 2 + 13,
+            v if v < 1 << 18 => 2 + 18,
+            _ => 2 + 30,
+        }
+    };
+    // signature, SizeHeader, ImageMetadata up to the colour encoding
+    let depth_bits = if layout.bits == 8 { 2 } else { 2 + 6 };
+    let before =
+        16 + 1 + size_bits(layout.height) + 3 + size_bits(layout.width) + 3 + depth_bits + 4;
+    // its all_default bit, then extensions and the default transform data
+    let header = before + 1 + 3;
+    if encoded.len() < header.div_ceil(8) || encoded[..2] != [0xff, 0x0a] {
+        return None;
+    }
+    let bit = |pos: usize| (encoded[pos / 8] >> (pos % 8)) & 1 == 1;
+    if !bit(before) {
+        return None;
+    }
+
+    let mut out = BitWriter::default();
+    for pos in 0..before {
+        out.put(1, bit(pos) as u64);
+    }
+    out.put(1, 0); // all_default
+    out.put(1, 0); // want_icc
+    out.put_enum(0); // RGB
+    out.put_enum(WHITE_D65);
+    match primaries {
+        Primaries::Known(value) => out.put_enum(value),
+        Primaries::Custom(xy) => {
+            out.put_enum(PRIMARIES_CUSTOM);
+            for v in xy.iter().flatten() {
+                out.put_customxy(*v);
+            }
+        }
+    }
+    out.put(1, 0); // have_gamma
+    out.put_enum(TRANSFER_BT709);
+    out.put_enum(INTENT_RELATIVE);
+    for pos in before + 1..header {
+        out.put(1, bit(pos) as u64);
+    }
+    let mut file = out.finish();
+    file.extend_from_slice(&encoded[header.div_ceil(8)..]);
+    Some(file)
+}
+
+// least significant bit first, as JXL's bit reader expects
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn put(&mut self, count: u32, value: u64) {
+        for i in 0..count {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (self.bits % 8);
+            self.bits += 1;
+        }
+    }
+
+    // U32(Val(0), Val(1), BitsOffset(4, 2), BitsOffset(6, 18))
+    fn put_enum(&mut self, value: u32) {
+        match value {
+            0 | 1 => self.put(2, value as _),
+            2..=17 => {
+                self.put(2, 2);
+                self.put(4, (value - 2) as _);
+            }
+            _ => {
+                self.put(2, 3);
+                self.put(6, (value - 18) as _);
+            }
+        }
+    }
+
+    // a signed millionth, U32(Bits(19), BitsOffset(19, 2^19), BitsOffset(20, 2^20),
+    // BitsOffset(21, 2^21))
+    fn put_customxy(&mut self, v: f64) {
+        let v = (v * 1e6).round() as i64;
+        let packed = if v >= 0 { 2 * v } else { -2 * v - 1 } as u64;
+        match packed {
+            p if p < 1 << 19 => {
+                self.put(2, 0);
+                self.put(19, p);
+            }
+            p if p < 1 << 20 => {
+                self.put(2, 1);
+                self.put(19, p - (1 << 19));
+            }
+            p if p < 1 << 21 => {
+                self.put(2, 2);
+                self.put(20, p - (1 << 20));
+            }
+            p => {
+                self.put(2, 3);
+                self.put(21, p - (1 << 21));
+            }
+        }
+    }
+
+    // zero padded to a byte boundary
+    fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jxl_oxide::{
+        color::{
+            ColourEncoding, Customxy, Primaries as JxlPrimaries, TransferFunction, WhitePoint,
+        },
+        JxlImage,
+    };
+
+    use super::*;
+
+    // what jxl-oxide reads back: the primaries and the samples, interleaved
+    fn decode<S: jxl_oxide::FrameBufferSample + Clone>(
+        jxl: &[u8],
+        len: usize,
+    ) -> (JxlPrimaries, Vec<S>) {
+        let image = JxlImage::builder().read(jxl).expect("decoded");
+        let ColourEncoding::Enum(encoding) = &image.image_header().metadata.colour_encoding else {
+            panic!("no enum colour encoding");
+        };
+        assert_eq!(encoding.white_point, WhitePoint::D65);
+        assert_eq!(encoding.tf, TransferFunction::Bt709);
+        let render = image.render_frame(0).expect("rendered");
+        let mut stream = render.stream();
+        let mut samples = vec![S::default(); len];
+        assert_eq!(stream.write_to_buffer(&mut samples), len);
+        (encoding.primaries, samples)
+    }
+
+    #[test]
+    fn test_jxl() {
+        let layout = ImageLayout {
+            width: 300,
+            height: 40,
+            colors: 3,
+            bits: 16,
+        };
+        let samples: Vec<u16> = (0..layout.len() / 2)
+            .map(|i| (i * 97 % 65536) as u16)
+            .collect();
+        let data: Vec<u8> = samples.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let xy = |x: f64, y: f64| Customxy {
+            x: (x * 1e6) as i32,
+            y: (y * 1e6) as i32,
+        };
+        for (color, primaries) in [
+            (OutputColor::Srgb, JxlPrimaries::Srgb),
+            (OutputColor::DciP3, JxlPrimaries::P3),
+            (OutputColor::Rec2020, JxlPrimaries::Bt2100),
+            (
+                OutputColor::ProPhoto,
+                JxlPrimaries::Custom {
+                    red: xy(0.7347, 0.2653),
+                    green: xy(0.1596, 0.8404),
+                    blue: xy(0.0366, 0.0001),
+                },
+            ),
+            (
+                OutputColor::Aces,
+                JxlPrimaries::Custom {
+                    red: xy(0.7347, 0.2653),
+                    green: xy(0.0, 1.0),
+                    blue: xy(0.0001, -0.077),
+                },
+            ),
+        ] {
+            let jxl = encode(&layout, &data, color).expect("jxl");
+            assert_eq!(jxl[..2], [0xff, 0x0a]);
+            let (decoded_primaries, decoded) = decode::<u16>(&jxl, samples.len());
+            assert_eq!(decoded_primaries, primaries, "{color:?}");
+            assert!(decoded == samples, "{color:?} isn't lossless");
+        }
+        assert!(matches!(
+            encode(&layout, &data, OutputColor::Raw),
+            Err(Error::NotImplemented)
+        ));
+
+        // and at 8 bits
+        let layout = ImageLayout { bits: 8, ..layout };
+        let data: Vec<u8> = (0..layout.len()).map(|i| (i * 7 % 256) as u8).collect();
+        let jxl = encode(&layout, &data, OutputColor::Srgb).expect("jxl");
+        let (_, decoded) = decode::<u8>(&jxl, data.len());
+        assert!(decoded == data, "8 bits isn't lossless");
+
+        let mut bits = BitWriter::default();
+        bits.put_enum(PRIMARIES_P3);
+        bits.put(1, 1);
+        // selector 2, then 11 - 2 in four bits
+        assert_eq!(bits.finish(), [0b0110_0110]);
+        let mut bits = BitWriter::default();
+        bits.put_customxy(-0.077);
+        assert_eq!(bits.bits, 21);
+    }
+}
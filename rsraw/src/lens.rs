@@ -2,7 +2,8 @@ use rsraw_sys as sys;
 
 use crate::Mounts;
 
-#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LensInfo {
     pub min_focal: f32,
     pub max_focal: f32,
@@ -19,7 +20,8 @@ pub struct LensInfo {
     pub feture_suf: String,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FocusType {
     Unknown,
     Prime,
@@ -0,0 +1,202 @@
+use std::{ops::DerefMut, path::Path};
+
+use lensfun::{Camera, Database, Lens, Modifier};
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    resample::{Sample, Source},
+    FullRawInfo, ProcessedImage,
+};
+
+// subject distance in meters when the file doesn't record one, vignetting
+// profiles barely change past a few meters
+const DEFAULT_DISTANCE: f32 = 1000.0;
+
+// Which lensfun corrections to apply, and after `correct` which ones the
+// matched profile actually had calibration data for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LensCorrections {
+    pub distortion: bool,
+    pub tca: bool,
+    pub vignetting: bool,
+}
+
+// The lensfun calibration database, matched against the camera and lens
+// LibRaw read from the file.
+pub struct LensDatabase {
+    db: Database,
+}
+
+impl LensCorrections {
+    pub const ALL: Self = Self {
+        distortion: true,
+        tca: true,
+        vignetting: true,
+    };
+
+    pub fn any(&self) -> bool {
+        self.distortion || self.tca || self.vignetting
+    }
+}
+
+impl LensDatabase {
+    // the database compiled into the lensfun crate
+    pub fn bundled() -> Result<Self> {
+        Ok(Self {
+            db: Database::load_bundled().map_err(db_error)?,
+        })
+    }
+
+    // a directory of lensfun XML files, e.g. /usr/share/lensfun/version_1
+    pub fn load_dir(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            db: Database::load_dir(path).map_err(db_error)?,
+        })
+    }
+
+    fn find(&self, info: &FullRawInfo) -> Option<(Option<&Camera>, &Lens)> {
+        let camera = self
+            .db
+            .find_cameras(Some(&info.normalized_make), &info.normalized_model)
+            .into_iter()
+            .next();
+        let lens = self
+            .db
+            .find_lenses(camera, &info.lens_info.lens_name)
+            .into_iter()
+            .next()?;
+        Some((camera, lens))
+    }
+
+    // "maker model" of the lensfun profile `correct` would use
+    pub fn matched_lens(&self, info: &FullRawInfo) -> Option<String> {
+        let (_, lens) = self.find(info)?;
+        Some(format!("{} {}", lens.maker, lens.model))
+    }
+
+    // Corrects `image` in place, `info` has to describe the file it was
+    // processed from. Returns the corrections that were applied, none when
+    // the lens isn't in the database.
+    pub fn correct<const D: BitDepth, T: Sample>(
+        &self,
+        info: &FullRawInfo,
+        image: &mut ProcessedImage<D>,
+        wanted: LensCorrections,
+    ) -> Result<LensCorrections>
+    where
+        ProcessedImage<D>: DerefMut<Target = [T]>,
+    {
+        let Some((camera, lens)) = self.find(info) else {
+            return Ok(LensCorrections::default());
+        };
+        let crop = camera.map_or(lens.crop_factor, |c| c.crop_factor);
+        let (width, height) = (image.width(), image.height());
+        let mut modifier = Modifier::new(lens, info.focal_len, crop, width, height, true);
+        let applied = LensCorrections {
+            distortion: wanted.distortion && modifier.enable_distortion_correction(lens),
+            tca: wanted.tca && modifier.enable_tca_correction(lens),
+            vignetting: wanted.vignetting
+                && modifier.enable_vignetting_correction(lens, info.aperture, DEFAULT_DISTANCE),
+        };
+        let channels = image.colors() as usize;
+        correct_pixels(
+            &modifier,
+            applied,
+            image,
+            width as usize,
+            height as usize,
+            channels,
+        );
+        Ok(applied)
+    }
+}
+
+fn correct_pixels<T: Sample>(
+    modifier: &Modifier,
+    applied: LensCorrections,
+    pixels: &mut [T],
+    width: usize,
+    height: usize,
+    channels: usize,
+) {
+    // vignetting is a property of the uncorrected geometry, fix it first
+    if applied.vignetting {
+        T::devignette(modifier, pixels, width, channels);
+    }
+    if !applied.distortion && !applied.tca {
+        return;
+    }
+    let src = pixels.to_vec();
+    let src = Source {
+        pixels: &src,
+        width,
+        height,
+        channels,
+    };
+    let mut geometry = vec![0.0; width * 2];
+    let mut subpixel = vec![0.0; width * 6];
+    for (y, row) in pixels.chunks_mut(width * channels).enumerate() {
+        if applied.distortion {
+            modifier.apply_geometry_distortion(0.0, y as f32, width, 1, &mut geometry);
+        }
+        if applied.tca {
+            modifier.apply_subpixel_distortion(0.0, y as f32, width, 1, &mut subpixel);
+        }
+        for (x, px) in row.chunks_mut(channels).enumerate() {
+            let (gx, gy) = match applied.distortion {
+                true => (geometry[x * 2], geometry[x * 2 + 1]),
+                false => (x as f32, y as f32),
+            };
+            for (c, out) in px.iter_mut().enumerate() {
+                // tca shifts the r and b planes relative to the undistorted grid,
+                // stack that on top of the geometric correction
+                let (sx, sy) = match applied.tca && c < 3 {
+                    true => (
+                        gx + subpixel[x * 6 + c * 2] - x as f32,
+                        gy + subpixel[x * 6 + c * 2 + 1] - y as f32,
+                    ),
+                    false => (gx, gy),
+                };
+                *out = src.bilinear(c, sx, sy);
+            }
+        }
+    }
+}
+
+fn db_error(err: lensfun::Error) -> Error {
+    match err {
+        lensfun::Error::Io { source, .. } => source.into(),
+        _ => Error::Data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, ProcessParams, RawImage, BIT_DEPTH_8};
+
+    #[test]
+    fn test_lens_correction() {
+        let db = LensDatabase::bundled().expect("bundled database");
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let info = raw_image.full_info();
+        assert_eq!(
+            db.matched_lens(&info).as_deref(),
+            Some("Nikon Nikkor Z MC 105mm f/2.8 VR S")
+        );
+
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        let mut image = raw_image.process_with::<BIT_DEPTH_8>(&params).unwrap();
+        let original = image.to_vec();
+        let applied = db
+            .correct(&info, &mut image, LensCorrections::ALL)
+            .expect("corrected");
+        assert_eq!(applied, LensCorrections::ALL);
+        assert_ne!(&image[..], &original[..]);
+    }
+}
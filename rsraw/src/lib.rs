@@ -1,14 +1,149 @@
+mod audit;
+#[cfg(feature = "avif")]
+mod avif;
+#[cfg(feature = "fs")]
+pub mod batch;
+mod bayer;
+mod borders;
+#[cfg(feature = "cache")]
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod compare;
+pub mod convert;
+mod corrections;
+mod cr3;
+mod data_errors;
+mod datastream;
+mod decoder;
+mod diagnose;
 mod err;
+mod events;
+mod exif;
+pub mod export;
+#[cfg(feature = "fallback")]
+mod fallback;
+mod flight;
+mod format;
+mod frames;
+mod gain_map;
 mod gps;
+#[cfg(feature = "heif")]
+mod heif;
+mod ifd;
+#[cfg(feature = "fs")]
+mod ingest;
+mod interop;
+mod iptc;
+#[cfg(feature = "jpeg")]
+mod jpeg;
+#[cfg(feature = "jxl")]
+mod jxl;
 mod lens;
+#[cfg(feature = "lensfun")]
+mod lens_correction;
+#[cfg(feature = "opencv")]
+mod mat;
+mod metadata;
+mod metrics;
 mod mounts;
+mod noise;
+mod opcodes;
+mod open_options;
+mod params;
+mod placeholder;
+#[cfg(feature = "png")]
+mod png;
+mod pool;
+mod preview;
 mod processed;
+mod profiling;
+mod progress;
+mod proraw;
 mod raw;
+mod region;
+mod resample;
+#[cfg(feature = "resize")]
+mod resize;
+pub mod sequence;
+mod shared;
+mod sizes;
+mod source;
+mod stats;
+mod structure;
+#[cfg(any(feature = "candle", feature = "tch", feature = "npy"))]
+mod tensor;
+#[cfg(feature = "fs")]
+pub mod tether;
 mod thumb;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+mod trace;
+mod version;
+mod warnings;
+mod warp;
+#[cfg(feature = "webp")]
+mod webp;
+mod xmp;
 
+pub use audit::ProcessingLog;
+#[cfg(feature = "avif")]
+pub use avif::{AvifHdr, AvifOptions, HdrTransfer};
+pub use bayer::{BayerOptions, BayerPattern};
+pub use borders::Borders;
+pub use compare::DeltaEStats;
+pub use corrections::{CorrectionSource, Corrections};
+pub use cr3::{Ctmd, CtmdExposure, CtmdRecord, CtmdTime, LevelInfo};
+pub use data_errors::{DataErrors, PartialDecode};
+pub use datastream::{RawDataSource, ReaderImage};
+pub use decoder::{Decoder, DecoderInfo, DecoderPreference};
+pub use diagnose::{OpenDiagnosis, OpenFailure};
+pub use err::{ContextError, Error, ErrorStage, Result};
+pub use events::{DecodeEvent, DecodeEvents};
+pub use exif::ExifTags;
+#[cfg(feature = "fallback")]
+pub use fallback::FallbackImage;
+pub use flight::FlightInfo;
+pub use format::{probe, RawFormat, RawProbe};
+pub use frames::{merge_exposures, FrameLayout};
+pub use gain_map::GainMap;
 pub use gps::GpsInfo;
+#[cfg(feature = "fs")]
+pub use ingest::{Ingest, IngestIter, IngestPairs, RawJpegPair};
+pub use interop::{ImageBuffer, IntoImageBuffer, Samples};
+pub use iptc::Iptc;
+#[cfg(feature = "jpeg")]
+pub use jpeg::{ChromaSubsampling, JpegOptions};
 pub use lens::{FocusType, LensInfo};
+#[cfg(feature = "lensfun")]
+pub use lens_correction::{LensCorrections, LensDatabase};
+pub use metadata::RawMetadata;
+pub use metrics::Metrics;
 pub use mounts::Mounts;
-pub use processed::{ImageFormat, ProcessedImage};
-pub use raw::{FullRawInfo, RawImage, BIT_DEPTH_16, BIT_DEPTH_8};
-pub use thumb::{ThumbFormat, ThumbnailImage, Thumbnails};
+pub use noise::NoiseProfile;
+pub use open_options::{DngStage, OpenOptions};
+pub use params::{DcrawArgsError, OutputColor, ProcessParams};
+pub use pool::{BufferPool, PooledBuffer};
+pub use preview::{Preview, PreviewSource};
+pub use processed::{ImageFormat, ImageLayout, ProcessedImage};
+pub use profiling::ColorProfile;
+pub use progress::{CancellationToken, ProgressStage};
+pub use proraw::{ProfileGainTableMap, SemanticMask};
+pub use raw::{
+    FullRawInfo, RawHeader, RawImage, BIT_DEPTH_16, BIT_DEPTH_8, DEFAULT_MEMORY_LIMIT_MB,
+};
+pub use region::Rect;
+#[cfg(feature = "resize")]
+pub use resize::ResizedImage;
+pub use shared::SharedRawImage;
+pub use sizes::SizesInfo;
+pub use source::{open_any, Decoded, MetadataAccess, MosaicAccess};
+pub use stats::ChannelStats;
+pub use structure::{Block, BlockKind};
+pub use thumb::{ThumbFormat, ThumbInfo, ThumbnailImage, Thumbnails};
+pub use version::{build_info, capabilities, version, version_number, BuildInfo, Capabilities};
+pub use warnings::Warnings;
+pub use warp::WarpRectilinear;
+#[cfg(feature = "webp")]
+pub use webp::WebpOptions;
+pub use xmp::Xmp;
@@ -0,0 +1,122 @@
+// OpenCV `Mat`s for alignment, stitching and detection. `as_mat` borrows the
+// bitmap as is, channels in LibRaw's RGB order; `to_mat` copies once into
+// OpenCV's BGR so `imwrite`, `cvtColor` and friends see the colors they expect.
+// The mosaic is copied too, the visible area of it being a window into rows
+// with masked pixels on either side.
+
+use opencv::{
+    boxed_ref::BoxedRef,
+    core::{self, Mat, Scalar, Vec3b, Vec3w, Vec4b, Vec4w},
+    prelude::*,
+};
+
+use crate::{
+    err::{Error, Result},
+    raw::BitDepth,
+    ProcessedImage, RawImage,
+};
+
+fn cv<T>(result: opencv::Result<T>) -> Result<T> {
+    result.map_err(|_| Error::Unspecified)
+}
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // Without copying: 8 or 16 bit, 1, 3 or 4 channels in RGB(G) order.
+    // The `Mat` can't outlive the image.
+    pub fn as_mat(&self) -> Result<BoxedRef<'_, Mat>> {
+        let (rows, cols) = (self.height() as i32, self.width() as i32);
+        let bytes = self.bytes();
+        cv(match (self.bits(), self.colors()) {
+            (8, 1) => Mat::new_rows_cols_with_bytes::<u8>(rows, cols, bytes),
+            (8, 3) => Mat::new_rows_cols_with_bytes::<Vec3b>(rows, cols, bytes),
+            (8, 4) => Mat::new_rows_cols_with_bytes::<Vec4b>(rows, cols, bytes),
+            (16, 1) => Mat::new_rows_cols_with_bytes::<u16>(rows, cols, bytes),
+            (16, 3) => Mat::new_rows_cols_with_bytes::<Vec3w>(rows, cols, bytes),
+            (16, 4) => Mat::new_rows_cols_with_bytes::<Vec4w>(rows, cols, bytes),
+            _ => return Err(Error::NotImplemented),
+        })
+    }
+
+    // An owned copy, three color images as BGR
+    pub fn to_mat(&self) -> Result<Mat> {
+        let channels = self.colors() as usize;
+        let depth = match self.bits() {
+            8 => core::CV_8U,
+            16 => core::CV_16U,
+            _ => return Err(Error::NotImplemented),
+        };
+        let mut mat = cv(Mat::new_rows_cols_with_default(
+            self.height() as i32,
+            self.width() as i32,
+            core::CV_MAKETYPE(depth, channels as i32),
+            Scalar::all(0.0),
+        ))?;
+        let dst = cv(mat.data_bytes_mut())?;
+        let src = self.bytes();
+        if channels != 3 {
+            dst.copy_from_slice(src);
+            return Ok(mat);
+        }
+        let sample = self.bits() as usize / 8;
+        let pixel = sample * 3;
+        for (dst, src) in dst.chunks_exact_mut(pixel).zip(src.chunks_exact(pixel)) {
+            dst[..sample].copy_from_slice(&src[2 * sample..]);
+            dst[sample..2 * sample].copy_from_slice(&src[sample..2 * sample]);
+            dst[2 * sample..].copy_from_slice(&src[..sample]);
+        }
+        Ok(mat)
+    }
+}
+
+impl RawImage {
+    // The visible area of the unpacked mosaic as one 16 bit channel, the
+    // sensor values as they are. `cvtColor` with `COLOR_Bayer*` demosaics it.
+    // `Error::OutOfOrderCall` before `unpack`.
+    pub fn mosaic_to_mat(&self) -> Result<Mat> {
+        let visible = self.visible_mosaic()?;
+        let mut mat = cv(Mat::new_rows_cols_with_default(
+            visible.height as i32,
+            visible.width as i32,
+            core::CV_16UC1,
+            Scalar::all(0.0),
+        ))?;
+        for row in 0..visible.height {
+            let dst = cv(mat.at_row_mut::<u16>(row as i32))?;
+            dst.copy_from_slice(visible.row(row));
+        }
+        Ok(mat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    #[test]
+    fn test_mat() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let image = raw_image
+            .process_with::<BIT_DEPTH_8>(&Default::default())
+            .expect("processed");
+        let view = image.as_mat().expect("view");
+        assert_eq!(view.typ(), core::CV_8UC3);
+        assert_eq!(
+            view.at_2d::<Vec3b>(0, 1).unwrap().0,
+            [image[3], image[4], image[5]]
+        );
+        let mat = image.to_mat().expect("copy");
+        assert_eq!(
+            mat.at_2d::<Vec3b>(0, 1).unwrap().0,
+            [image[5], image[4], image[3]]
+        );
+
+        let mosaic = raw_image.mosaic_to_mat().expect("mosaic");
+        assert_eq!(mosaic.typ(), core::CV_16UC1);
+        assert_eq!(
+            (mosaic.cols(), mosaic.rows()),
+            (raw_image.width() as i32, raw_image.height() as i32)
+        );
+    }
+}
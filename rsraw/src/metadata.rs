@@ -0,0 +1,75 @@
+use crate::{
+    source::MetadataAccess, FullRawInfo, Iptc, NoiseProfile, RawImage, ThumbInfo, Warnings, Xmp,
+};
+
+// Owned copy of everything `RawImage` knows without decoding pixels. Unlike
+// the image it holds no LibRaw state, so it is Send + Sync and can be shared
+// (e.g. in an `Arc`) while the image itself goes on to be processed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawMetadata {
+    pub info: FullRawInfo,
+    pub thumbs: Vec<ThumbInfo>,
+    pub raw_width: u32,
+    pub raw_height: u32,
+    pub filters: u32,
+    pub body_serial: String,
+    pub drive_mode: i16,
+    pub warnings: Warnings,
+    // DNG only, see `RawImage::noise_profile`
+    pub noise_profile: Option<NoiseProfile>,
+    // see `RawImage::iptc`
+    pub iptc: Option<Iptc>,
+    // see `RawImage::merged_xmp`
+    pub xmp: Xmp,
+    // empty until `merge_exif`, which needs the exif feature
+    pub exif: crate::ExifTags,
+}
+
+impl RawImage {
+    pub fn metadata(&self) -> RawMetadata {
+        let sizes = &self.as_ref().sizes;
+        RawMetadata {
+            info: self.full_info(),
+            thumbs: self.thumb_infos(),
+            raw_width: sizes.raw_width as _,
+            raw_height: sizes.raw_height as _,
+            filters: self.filters(),
+            body_serial: self.body_serial().into_owned(),
+            drive_mode: self.drive_mode(),
+            warnings: self.warnings(),
+            noise_profile: self.noise_profile(),
+            iptc: self.iptc(),
+            xmp: self.merged_xmp(),
+            exif: Default::default(),
+        }
+    }
+}
+
+impl MetadataAccess for RawMetadata {
+    fn full_info(&self) -> FullRawInfo {
+        self.info.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_metadata_is_shareable() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let metadata = std::sync::Arc::new(raw_image.metadata());
+        assert_send_sync(&metadata);
+
+        let shared = metadata.clone();
+        let reader = std::thread::spawn(move || shared.info.model.clone());
+        raw_image.unpack().expect("unpacked");
+        assert_eq!(reader.join().unwrap(), "Z 8");
+        assert_eq!(metadata.info, raw_image.full_info());
+        assert_eq!(metadata.thumbs, raw_image.thumb_infos());
+    }
+}
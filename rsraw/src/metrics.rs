@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use crate::Decoder;
+
+// Collected by `RawImage` as it goes through open, unpack and process.
+// Stages that haven't run yet are `None`; repeated calls keep the latest run.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metrics {
+    pub open: Duration,
+    pub unpack: Option<Duration>,
+    pub process: Option<Duration>,
+    // largest estimated LibRaw working set seen so far: the raw mosaic, the
+    // 4 channel working image and the output bitmap, in bytes
+    pub peak_memory: u64,
+    pub decoder: String,
+    // what the last unpack decoded the raw data with
+    pub decoded_by: Option<Decoder>,
+}
+
+impl Metrics {
+    pub fn total(&self) -> Duration {
+        self.open + self.unpack.unwrap_or_default() + self.process.unwrap_or_default()
+    }
+}
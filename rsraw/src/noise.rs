@@ -0,0 +1,102 @@
+use crate::{ifd::FileTiff, RawImage};
+
+const TAG_NOISE_PROFILE: u16 = 0xc761;
+
+// The DNG NoiseProfile: per plane (scale, offset) pairs of the noise model
+// `variance = scale * x + offset`, x being the signal normalized to 0..1.
+// One pair applies to every plane.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NoiseProfile {
+    pub coefficients: Vec<(f64, f64)>,
+}
+
+impl NoiseProfile {
+    // the (scale, offset) pair of `plane`, the only one when it covers all planes
+    pub fn plane(&self, plane: usize) -> (f64, f64) {
+        self.coefficients[plane.min(self.coefficients.len() - 1)]
+    }
+
+    // of a signal normalized to 0..1, never negative
+    pub fn variance(&self, plane: usize, signal: f64) -> f64 {
+        let (scale, offset) = self.plane(plane);
+        (scale * signal + offset).max(0.0)
+    }
+
+    // the standard deviation of the noise, in the units of `signal`
+    pub fn sigma(&self, plane: usize, signal: f64) -> f64 {
+        self.variance(plane, signal).sqrt()
+    }
+
+    // The same model for raw values above `black`, for denoisers working on
+    // the unpacked mosaic rather than normalized data.
+    pub fn to_raw_units(&self, black: f64, white: f64) -> Self {
+        let range = (white - black).max(1.0);
+        Self {
+            coefficients: self
+                .coefficients
+                .iter()
+                .map(|&(scale, offset)| (scale * range, offset * range * range))
+                .collect(),
+        }
+    }
+
+    fn parse(values: &[f64]) -> Option<Self> {
+        if values.is_empty() || !values.len().is_multiple_of(2) {
+            return None;
+        }
+        Some(Self {
+            coefficients: values.chunks_exact(2).map(|p| (p[0], p[1])).collect(),
+        })
+    }
+}
+
+impl RawImage {
+    // from the raw IFD, or IFD0 where older writers put it, `None` without one
+    pub fn noise_profile(&self) -> Option<NoiseProfile> {
+        if self.dng_version() == 0 {
+            return None;
+        }
+        let tiff = FileTiff::new(self)?;
+        let ifds = tiff.ifds();
+        let raw = ifds.iter().filter(|ifd| ifd.subfile_type() == 0);
+        raw.chain(ifds.first())
+            .filter_map(|ifd| ifd.get(TAG_NOISE_PROFILE))
+            .find_map(|entry| NoiseProfile::parse(&tiff.f64s(entry)?))
+    }
+
+    // `noise_profile` scaled to raw values above LibRaw's black level
+    pub fn raw_noise_profile(&self) -> Option<NoiseProfile> {
+        let color = &self.as_ref().rawdata.color;
+        self.noise_profile()
+            .map(|profile| profile.to_raw_units(color.black as f64, color.maximum as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_profile() {
+        let profile = NoiseProfile::parse(&[2e-5, 1e-7, 3e-5, 2e-7]).expect("two planes");
+        assert_eq!(profile.coefficients, vec![(2e-5, 1e-7), (3e-5, 2e-7)]);
+        assert!(NoiseProfile::parse(&[2e-5]).is_none());
+        assert!(NoiseProfile::parse(&[]).is_none());
+
+        assert_eq!(profile.plane(1), (3e-5, 2e-7));
+        assert_eq!(profile.plane(2), (3e-5, 2e-7));
+        assert_eq!(profile.variance(0, 0.5), 2e-5 * 0.5 + 1e-7);
+        let shared = NoiseProfile::parse(&[0.04, 0.0009]).unwrap();
+        assert_eq!(shared.sigma(2, 0.0), 0.03);
+        assert_eq!(
+            NoiseProfile::parse(&[-1.0, 0.0]).unwrap().sigma(0, 1.0),
+            0.0
+        );
+
+        // the same noise, measured on a 1000 step range above black
+        let raw = shared.to_raw_units(24.0, 1024.0);
+        assert_eq!(raw.coefficients, vec![(40.0, 900.0)]);
+        assert!((raw.sigma(0, 500.0) - shared.sigma(0, 0.5) * 1000.0).abs() < 1e-9);
+    }
+}
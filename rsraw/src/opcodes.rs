@@ -0,0 +1,88 @@
+// DNG opcode lists, read straight from the file LibRaw has open. LibRaw
+// tracks where OpcodeList2 starts, OpcodeList3 is found by walking the IFDs.
+
+use crate::{ifd::FileTiff, RawImage};
+
+pub(crate) const OPCODE_WARP_RECTILINEAR: u32 = 1;
+pub(crate) const OPCODE_GAIN_MAP: u32 = 9;
+
+const TAG_OPCODE_LIST3: u16 = 0xc74e;
+
+// a list is a handful of opcodes, gain maps are a few thousand points each
+const MAX_OPCODES: u32 = 256;
+const MAX_PARAMS_LEN: u32 = 64 << 20;
+
+pub(crate) struct Opcode {
+    pub id: u32,
+    pub params: Vec<u8>,
+}
+
+impl RawImage {
+    pub(crate) fn opcode_list2(&self) -> Vec<Opcode> {
+        match self.meta_offset() {
+            0 => Vec::new(),
+            offset => self.opcode_list(offset).unwrap_or_default(),
+        }
+    }
+
+    pub(crate) fn opcode_list3(&self) -> Vec<Opcode> {
+        self.raw_ifd_tag_offset(TAG_OPCODE_LIST3)
+            .and_then(|offset| self.opcode_list(offset))
+            .unwrap_or_default()
+    }
+
+    fn opcode_list(&self, offset: u64) -> Option<Vec<Opcode>> {
+        if self.dng_version() == 0 {
+            return None;
+        }
+        // opcode lists are big endian no matter the file's byte order
+        let count = Reader(&self.read_at(offset, 4)?).u32()?;
+        let mut pos = offset + 4;
+        let mut opcodes = Vec::new();
+        for _ in 0..count.min(MAX_OPCODES) {
+            let header = self.read_at(pos, 16)?;
+            let mut r = Reader(&header);
+            let (id, _version, _flags, len) = (r.u32()?, r.u32()?, r.u32()?, r.u32()?);
+            if len > MAX_PARAMS_LEN {
+                return None;
+            }
+            let params = self.read_at(pos + 16, len)?;
+            opcodes.push(Opcode { id, params });
+            pos += 16 + len as u64;
+        }
+        Some(opcodes)
+    }
+
+    // where the value of `tag` in the full resolution raw IFD starts
+    fn raw_ifd_tag_offset(&self, tag: u16) -> Option<u64> {
+        FileTiff::new(self)?
+            .ifds()
+            .into_iter()
+            .filter(|ifd| ifd.subfile_type() == 0)
+            // undefined bytes, always longer than the 4 that fit inline
+            .find_map(|ifd| ifd.get(tag).map(|e| e.value as u64))
+    }
+}
+
+// big endian fields of opcode parameters
+pub(crate) struct Reader<'a>(pub &'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.0.split_first_chunk::<N>()?;
+        self.0 = rest;
+        Some(*head)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.take().map(u32::from_be_bytes)
+    }
+
+    pub fn f32(&mut self) -> Option<f32> {
+        self.take().map(f32::from_be_bytes)
+    }
+
+    pub fn f64(&mut self) -> Option<f64> {
+        self.take().map(f64::from_be_bytes)
+    }
+}
@@ -0,0 +1,149 @@
+use rsraw_sys as sys;
+
+// Which stage of a DNG unpack hands over: the mosaic as stored or the image
+// after the file's opcode lists 2 or 3 ran. Only the DNG SDK runs them, i.e.
+// builds with the gpr feature; elsewhere every stage unpacks the raw data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DngStage {
+    #[default]
+    Raw,
+    // linearized and with OpcodeList2 applied
+    Linear,
+    // demosaiced and with OpcodeList3 applied, which may change the size
+    Rendered,
+}
+
+// What LibRaw is told before it reads a file, for `RawImage::open_with` and
+// `open_file_with`. The defaults are what `open` uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenOptions {
+    // the frame of files holding several, switched to with
+    // `RawImage::select_shot` once the file is open
+    pub shot_select: u32,
+    // see `RawImage::set_memory_limit_mb`, `None` keeps
+    // `DEFAULT_MEMORY_LIMIT_MB`
+    pub memory_limit_mb: Option<u32>,
+    // Leaves corrupt data to LibRaw's own count: `data_errors` still has it
+    // but no offset or end of file flag.
+    pub no_data_error_callback: bool,
+    pub dng_stage: DngStage,
+    // Of a DNG's full resolution images the largest rather than the first,
+    // for files that also carry a reduced copy of the raw.
+    pub dng_prefer_largest_image: bool,
+    // count a DNG's enhanced image, its previews and its masks as frames
+    // for `raw_count` and `shot_select`
+    pub dng_add_enhanced: bool,
+    pub dng_add_previews: bool,
+    pub dng_add_masks: bool,
+}
+
+impl OpenOptions {
+    pub(crate) fn init_flags(&self) -> u32 {
+        match self.no_data_error_callback {
+            true => sys::LibRaw_constructor_flags_LIBRAW_OPTIONS_NO_DATAERR_CALLBACK as _,
+            false => sys::LibRaw_constructor_flags_LIBRAW_OPTIONS_NONE as _,
+        }
+    }
+
+    pub(crate) fn apply(&self, params: &mut sys::libraw_raw_unpack_params_t) {
+        let stage = match self.dng_stage {
+            DngStage::Raw => 0,
+            DngStage::Linear => sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_STAGE2,
+            DngStage::Rendered => {
+                sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_STAGE3
+                    | sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_ALLOWSIZECHANGE
+            }
+        };
+        for (set, option) in [
+            (true, stage),
+            (
+                self.dng_prefer_largest_image,
+                sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_PREFER_LARGEST_IMAGE,
+            ),
+            (
+                self.dng_add_enhanced,
+                sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_ADD_ENHANCED,
+            ),
+            (
+                self.dng_add_previews,
+                sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_ADD_PREVIEWS,
+            ),
+            (
+                self.dng_add_masks,
+                sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_ADD_MASKS,
+            ),
+        ] {
+            if set {
+                params.options |= option as u32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        raw::tests::{get_test_assets_path, linear_dng},
+        Error, RawImage, DEFAULT_MEMORY_LIMIT_MB,
+    };
+
+    #[test]
+    fn test_open_with() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let raw_image =
+            RawImage::open_owned_with(data.clone(), &OpenOptions::default()).expect("opened");
+        assert_eq!(raw_image.memory_limit_mb(), DEFAULT_MEMORY_LIMIT_MB);
+        let options = OpenOptions {
+            memory_limit_mb: Some(16),
+            ..Default::default()
+        };
+        let mut raw_image = RawImage::open_owned_with(data.clone(), &options).expect("opened");
+        assert_eq!(raw_image.memory_limit_mb(), 16);
+        assert!(matches!(
+            raw_image.unpack().map_err(Error::from),
+            Err(Error::TooBig)
+        ));
+        let options = OpenOptions {
+            no_data_error_callback: true,
+            ..Default::default()
+        };
+        let mut raw_image = RawImage::open_owned_with(data, &options).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        assert!(raw_image.data_errors().is_empty());
+
+        let options = OpenOptions {
+            dng_stage: DngStage::Rendered,
+            dng_prefer_largest_image: true,
+            dng_add_previews: true,
+            ..Default::default()
+        };
+        let data = linear_dng();
+        let raw_image = RawImage::open_owned_with(data.clone(), &options).expect("opened");
+        let set = raw_image.as_ref().rawparams.options;
+        for option in [
+            sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_CONVERTFLOAT_TO_INT,
+            sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_STAGE3,
+            sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_PREFER_LARGEST_IMAGE,
+            sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_ADD_PREVIEWS,
+        ] {
+            assert_ne!(set & option as u32, 0);
+        }
+        assert_eq!(
+            set & sys::LibRaw_processing_options_LIBRAW_RAWOPTIONS_DNG_ADD_MASKS as u32,
+            0
+        );
+
+        // frames are picked like `select_shot` does, also past the last one
+        let options = OpenOptions {
+            shot_select: 1,
+            ..Default::default()
+        };
+        assert!(matches!(
+            RawImage::open_owned_with(data, &options).map_err(Error::from),
+            Err(Error::RequestForNonexistentImage)
+        ));
+    }
+}
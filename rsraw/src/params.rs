@@ -0,0 +1,671 @@
+use std::{
+    error::Error as StdError,
+    fmt::{self, Display, Formatter},
+};
+
+use rsraw_sys as sys;
+
+use crate::Rect;
+
+// LibRaw's BT.709 curve, `gamm[0]` being the inverse of the power
+const DEFAULT_GAMM0: f64 = 0.45;
+const DEFAULT_GAMM1: f64 = 4.5;
+// LIBRAW_DEFAULT_ADJUST_MAXIMUM_THRESHOLD
+const DEFAULT_ADJUST_MAXIMUM_THR: f32 = 0.75;
+// LibRaw's cropbox when there's none, everything, also its grey box
+const FULL_CROPBOX: [u32; 4] = [0, 0, u32::MAX, u32::MAX];
+// LibRaw's use_camera_matrix: for DNGs and with the camera's white balance
+const DEFAULT_CAMERA_MATRIX: i32 = 1;
+
+// `None` leaves a setting at LibRaw's default. The dcraw flag each field
+// stands for is given, see `from_dcraw_args`.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProcessParams {
+    // -h
+    pub half_size: bool,
+    // -w
+    pub use_camera_wb: bool,
+    // -a
+    pub use_auto_wb: bool,
+    // -r, multipliers for R, G, B and the second green
+    pub user_mul: Option<[f32; 4]>,
+    // -W
+    pub no_auto_bright: bool,
+    // -b, 1.0 by default
+    pub bright: Option<f32>,
+    // -o
+    pub output_color: OutputColor,
+    // -q, the demosaic: 0 bilinear, 1 VNG, 2 PPG, 3 AHD (the default),
+    // 4 DCB, 11 DHT, 12 AAHD
+    pub quality: Option<u8>,
+    // -H, 0 clips, 1 leaves them unclipped, 2 blends, 3 to 9 rebuild
+    pub highlight: u8,
+    // -n, wavelet denoising threshold, 0 is off
+    pub threshold: f32,
+    // -m, median filter passes over the color differences
+    pub median_passes: u32,
+    // -f, interpolate RGGB as four colors
+    pub four_color_rgb: bool,
+    // -k and -S, override the black and saturation levels
+    pub black: Option<i32>,
+    pub saturation: Option<i32>,
+    // -g, power and toe slope, BT.709's 2.222 and 4.5 by default. 1 and 1
+    // give linear output.
+    pub gamma: Option<[f64; 2]>,
+    // Overrides auto white balance and auto brightness to off, keeps LibRaw
+    // from lowering the white level to the brightest pixel and runs on one
+    // thread, so the same file and settings give the same bits on every run
+    // and every machine of the same target. Only the `openmp` feature makes
+    // LibRaw run on several threads, without it nothing needs pinning.
+    pub deterministic: bool,
+    // -s, the frame of a file with several to develop, see
+    // `RawImage::select_shot`. `None` keeps the one opened.
+    pub shot_select: Option<u32>,
+    // -B, the part of the visible area to develop, in its pixels before
+    // half size and rotation. Ignored by `RawImage::process_region`.
+    pub crop: Option<Rect>,
+    // -t, LibRaw's flip instead of the file's: 0 for none, 3 for 180°, 5
+    // for 90° counter clockwise, 6 for 90° clockwise
+    pub flip: Option<u8>,
+    // -A, the part of the raw image white balance is averaged over
+    pub grey_box: Option<Rect>,
+    // -C, what the red and the blue plane are scaled by for lateral
+    // chromatic aberration, 1 leaves a plane as it is. Not for Bayer data.
+    pub aberration: Option<[f64; 2]>,
+    // +M and -M, the file's own color matrix always or never. `None` uses
+    // it for DNGs and with `use_camera_wb`.
+    pub camera_matrix: Option<bool>,
+    // -j, leaves Fuji's 45° SuperCCD and the Nikon D1X's stretched pixels
+    // as stored
+    pub no_fuji_rotate: bool,
+}
+
+// Why `ProcessParams::from_dcraw_args` gave up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DcrawArgsError {
+    // the flag needs more values than followed it
+    MissingValue {
+        flag: String,
+        expected: &'static str,
+    },
+    InvalidValue {
+        flag: String,
+        value: String,
+    },
+    Unsupported(String),
+}
+
+impl Display for DcrawArgsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingValue { flag, expected } => write!(f, "{flag} needs {expected}"),
+            Self::InvalidValue { flag, value } => write!(f, "{flag}: {value} isn't a valid value"),
+            Self::Unsupported(flag) => write!(f, "unsupported option {flag}"),
+        }
+    }
+}
+
+impl StdError for DcrawArgsError {}
+
+// LibRaw's output color spaces (`-o`). All but `Raw` are adapted to D65 and
+// every one gets the same BT.709 tone curve.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutputColor {
+    // the camera's own space
+    Raw,
+    #[default]
+    Srgb,
+    Adobe,
+    WideGamut,
+    ProPhoto,
+    Xyz,
+    Aces,
+    DciP3,
+    Rec2020,
+}
+
+impl OutputColor {
+    pub(crate) fn from_libraw(value: i32) -> Self {
+        match value {
+            0 => Self::Raw,
+            2 => Self::Adobe,
+            3 => Self::WideGamut,
+            4 => Self::ProPhoto,
+            5 => Self::Xyz,
+            6 => Self::Aces,
+            7 => Self::DciP3,
+            8 => Self::Rec2020,
+            _ => Self::Srgb,
+        }
+    }
+}
+
+impl ProcessParams {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Flags as given to dcraw or LibRaw's dcraw_emu, e.g. "-w -q 3 -H 2 -o 1".
+    // Parsing stops at the first argument that isn't a flag, like dcraw's at
+    // the file names. -c, -v, -T and -6 only choose what dcraw writes and are
+    // ignored, as is the 16 bits of -4: pick `BIT_DEPTH_16` when processing.
+    // Unsupported on purpose: -P and -K, which have LibRaw read another
+    // file, -p, which needs LCMS, and the document modes -d and -D, see
+    // `RawImage::raw_image` for the undeveloped mosaic instead.
+    pub fn from_dcraw_args(args: &str) -> std::result::Result<Self, DcrawArgsError> {
+        let mut params = Self::default();
+        let mut args = args.split_whitespace();
+        while let Some(flag) = args.next() {
+            if !flag.starts_with(['-', '+']) {
+                break;
+            }
+            let mut value = |expected: &'static str| {
+                args.next().ok_or_else(|| DcrawArgsError::MissingValue {
+                    flag: flag.into(),
+                    expected,
+                })
+            };
+            match flag {
+                "-h" => params.half_size = true,
+                "-w" => params.use_camera_wb = true,
+                "-a" => params.use_auto_wb = true,
+                "-W" => params.no_auto_bright = true,
+                "-f" => params.four_color_rgb = true,
+                "-j" => params.no_fuji_rotate = true,
+                "+M" => params.camera_matrix = Some(true),
+                "-M" => params.camera_matrix = Some(false),
+                "-r" => {
+                    let mut mul = [0.0; 4];
+                    for m in &mut mul {
+                        *m = number(flag, value("four multipliers")?)?;
+                    }
+                    params.user_mul = Some(mul);
+                }
+                "-b" => params.bright = Some(number(flag, value("a brightness")?)?),
+                "-o" => {
+                    let space = value("a color space")?;
+                    params.output_color = match number::<i32>(flag, space)? {
+                        0 => OutputColor::Raw,
+                        1 => OutputColor::Srgb,
+                        2 => OutputColor::Adobe,
+                        3 => OutputColor::WideGamut,
+                        4 => OutputColor::ProPhoto,
+                        5 => OutputColor::Xyz,
+                        6 => OutputColor::Aces,
+                        7 => OutputColor::DciP3,
+                        8 => OutputColor::Rec2020,
+                        _ => return Err(invalid(flag, space)),
+                    }
+                }
+                "-q" => params.quality = Some(number(flag, value("a quality")?)?),
+                "-H" => {
+                    let mode = value("a highlight mode")?;
+                    match number(flag, mode)? {
+                        mode @ 0..=9 => params.highlight = mode,
+                        _ => return Err(invalid(flag, mode)),
+                    }
+                }
+                "-n" => params.threshold = number(flag, value("a threshold")?)?,
+                "-m" => params.median_passes = number(flag, value("a number of passes")?)?,
+                "-k" => params.black = Some(number(flag, value("a black level")?)?),
+                "-S" => params.saturation = Some(number(flag, value("a saturation level")?)?),
+                "-g" => {
+                    let power = number(flag, value("a power and a toe slope")?)?;
+                    let slope = number(flag, value("a power and a toe slope")?)?;
+                    params.gamma = Some([power, slope]);
+                }
+                "-C" => {
+                    let red = number(flag, value("a red and a blue scale")?)?;
+                    let blue = number(flag, value("a red and a blue scale")?)?;
+                    params.aberration = Some([red, blue]);
+                }
+                "-s" => params.shot_select = Some(number(flag, value("a frame")?)?),
+                "-t" => {
+                    let flip = value("a flip")?;
+                    match number(flag, flip)? {
+                        flip @ 0..=7 => params.flip = Some(flip),
+                        _ => return Err(invalid(flag, flip)),
+                    }
+                }
+                "-A" | "-B" => {
+                    let mut rect = [0; 4];
+                    for v in &mut rect {
+                        *v = number(flag, value("x, y, width and height")?)?;
+                    }
+                    let [x, y, width, height] = rect;
+                    let rect = Some(Rect::new(x, y, width, height));
+                    match flag {
+                        "-A" => params.grey_box = rect,
+                        _ => params.crop = rect,
+                    }
+                }
+                "-4" => {
+                    params.gamma = Some([1.0, 1.0]);
+                    params.no_auto_bright = true;
+                }
+                "-c" | "-v" | "-T" | "-6" => {}
+                _ => return Err(DcrawArgsError::Unsupported(flag.into())),
+            }
+        }
+        Ok(params)
+    }
+
+    pub(crate) fn apply(&self, params: &mut sys::libraw_output_params_t) {
+        params.half_size = self.half_size as _;
+        params.use_camera_wb = self.use_camera_wb as _;
+        params.use_auto_wb = self.use_auto_wb as _;
+        params.user_mul = self.user_mul.unwrap_or_default();
+        params.no_auto_bright = self.no_auto_bright as _;
+        params.bright = self.bright.unwrap_or(1.0);
+        params.output_color = self.output_color as _;
+        params.user_qual = self.quality.map_or(-1, |q| q as _);
+        params.highlight = self.highlight as _;
+        params.threshold = self.threshold;
+        params.med_passes = self.median_passes as _;
+        params.four_color_rgb = self.four_color_rgb as _;
+        params.user_black = self.black.unwrap_or(-1);
+        params.user_sat = self.saturation.unwrap_or(-1);
+        let [gamm0, gamm1] = match self.gamma {
+            Some([power, slope]) => [1.0 / power, slope],
+            None => [DEFAULT_GAMM0, DEFAULT_GAMM1],
+        };
+        params.gamm[0] = gamm0;
+        params.gamm[1] = gamm1;
+        params.adjust_maximum_thr = DEFAULT_ADJUST_MAXIMUM_THR;
+        params.cropbox = self.crop.map_or(FULL_CROPBOX, |crop| {
+            [crop.x, crop.y, crop.width, crop.height]
+        });
+        params.user_flip = self.flip.map_or(-1, i32::from);
+        params.greybox = self.grey_box.map_or(FULL_CROPBOX, |rect| {
+            [rect.x, rect.y, rect.width, rect.height]
+        });
+        // LibRaw scales by the inverse, like dcraw
+        let [red, blue] = self.aberration.unwrap_or([1.0, 1.0]);
+        params.aber = [1.0 / red, 1.0, 1.0 / blue, 1.0];
+        params.use_camera_matrix = match self.camera_matrix {
+            Some(true) => 3,
+            Some(false) => 0,
+            None => DEFAULT_CAMERA_MATRIX,
+        };
+        params.use_fuji_rotate = (!self.no_fuji_rotate) as _;
+        if self.deterministic {
+            params.use_auto_wb = 0;
+            params.no_auto_bright = 1;
+            params.adjust_maximum_thr = 0.0;
+        }
+    }
+
+    // what `apply` wrote, or LibRaw's own changes to it after processing.
+    // Settings at LibRaw's defaults come back as `None`.
+    pub(crate) fn from_libraw(
+        params: &sys::libraw_output_params_t,
+        rawparams: &sys::libraw_raw_unpack_params_t,
+    ) -> Self {
+        let set = |value: i32| (value >= 0).then_some(value);
+        Self {
+            half_size: params.half_size != 0,
+            use_camera_wb: params.use_camera_wb != 0,
+            use_auto_wb: params.use_auto_wb != 0,
+            user_mul: params
+                .user_mul
+                .iter()
+                .any(|&m| m != 0.0)
+                .then_some(params.user_mul),
+            no_auto_bright: params.no_auto_bright != 0,
+            bright: (params.bright != 1.0).then_some(params.bright),
+            output_color: OutputColor::from_libraw(params.output_color),
+            quality: set(params.user_qual).map(|q| q as _),
+            highlight: params.highlight as _,
+            threshold: params.threshold,
+            median_passes: params.med_passes.max(0) as _,
+            four_color_rgb: params.four_color_rgb != 0,
+            black: set(params.user_black),
+            saturation: set(params.user_sat),
+            gamma: (params.gamm[0] != DEFAULT_GAMM0 || params.gamm[1] != DEFAULT_GAMM1)
+                .then(|| [1.0 / params.gamm[0], params.gamm[1]]),
+            deterministic: params.adjust_maximum_thr == 0.0,
+            // not an output param, LibRaw keeps it with the raw ones
+            shot_select: (rawparams.shot_select != 0).then_some(rawparams.shot_select),
+            crop: (params.cropbox != FULL_CROPBOX).then(|| {
+                let [x, y, width, height] = params.cropbox;
+                Rect::new(x, y, width, height)
+            }),
+            flip: u8::try_from(params.user_flip).ok(),
+            grey_box: (params.greybox != FULL_CROPBOX).then(|| {
+                let [x, y, width, height] = params.greybox;
+                Rect::new(x, y, width, height)
+            }),
+            aberration: (params.aber[0] != 1.0 || params.aber[2] != 1.0)
+                .then(|| [1.0 / params.aber[0], 1.0 / params.aber[2]]),
+            camera_matrix: match params.use_camera_matrix {
+                DEFAULT_CAMERA_MATRIX => None,
+                matrix => Some(matrix != 0),
+            },
+            no_fuji_rotate: params.use_fuji_rotate == 0,
+        }
+    }
+}
+
+// Setters for building params in one expression, e.g.
+// `ProcessParams::new().use_camera_wb(true).quality(3)`. Each stands for the
+// field of the same name, those of optional fields set them.
+impl ProcessParams {
+    pub fn half_size(mut self, half_size: bool) -> Self {
+        self.half_size = half_size;
+        self
+    }
+
+    pub fn use_camera_wb(mut self, use_camera_wb: bool) -> Self {
+        self.use_camera_wb = use_camera_wb;
+        self
+    }
+
+    pub fn use_auto_wb(mut self, use_auto_wb: bool) -> Self {
+        self.use_auto_wb = use_auto_wb;
+        self
+    }
+
+    pub fn user_mul(mut self, user_mul: [f32; 4]) -> Self {
+        self.user_mul = Some(user_mul);
+        self
+    }
+
+    pub fn no_auto_bright(mut self, no_auto_bright: bool) -> Self {
+        self.no_auto_bright = no_auto_bright;
+        self
+    }
+
+    pub fn bright(mut self, bright: f32) -> Self {
+        self.bright = Some(bright);
+        self
+    }
+
+    pub fn output_color(mut self, output_color: OutputColor) -> Self {
+        self.output_color = output_color;
+        self
+    }
+
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    pub fn highlight(mut self, highlight: u8) -> Self {
+        self.highlight = highlight;
+        self
+    }
+
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn median_passes(mut self, median_passes: u32) -> Self {
+        self.median_passes = median_passes;
+        self
+    }
+
+    pub fn four_color_rgb(mut self, four_color_rgb: bool) -> Self {
+        self.four_color_rgb = four_color_rgb;
+        self
+    }
+
+    pub fn black(mut self, black: i32) -> Self {
+        self.black = Some(black);
+        self
+    }
+
+    pub fn saturation(mut self, saturation: i32) -> Self {
+        self.saturation = Some(saturation);
+        self
+    }
+
+    pub fn gamma(mut self, power: f64, slope: f64) -> Self {
+        self.gamma = Some([power, slope]);
+        self
+    }
+
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn shot_select(mut self, shot_select: u32) -> Self {
+        self.shot_select = Some(shot_select);
+        self
+    }
+
+    pub fn crop(mut self, crop: Rect) -> Self {
+        self.crop = Some(crop);
+        self
+    }
+
+    pub fn flip(mut self, flip: u8) -> Self {
+        self.flip = Some(flip);
+        self
+    }
+
+    pub fn grey_box(mut self, grey_box: Rect) -> Self {
+        self.grey_box = Some(grey_box);
+        self
+    }
+
+    pub fn aberration(mut self, red: f64, blue: f64) -> Self {
+        self.aberration = Some([red, blue]);
+        self
+    }
+
+    pub fn camera_matrix(mut self, camera_matrix: bool) -> Self {
+        self.camera_matrix = Some(camera_matrix);
+        self
+    }
+
+    pub fn no_fuji_rotate(mut self, no_fuji_rotate: bool) -> Self {
+        self.no_fuji_rotate = no_fuji_rotate;
+        self
+    }
+}
+
+fn invalid(flag: &str, value: &str) -> DcrawArgsError {
+    DcrawArgsError::InvalidValue {
+        flag: flag.into(),
+        value: value.into(),
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> std::result::Result<T, DcrawArgsError> {
+    value.parse().map_err(|_| invalid(flag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, RawImage, BIT_DEPTH_8};
+
+    #[test]
+    fn test_from_dcraw_args() {
+        let params =
+            ProcessParams::from_dcraw_args("-w -q 3 -H 2 -o 4 -b 1.5 -s 1 -T -6 a.NEF").unwrap();
+        assert_eq!(
+            params,
+            ProcessParams {
+                use_camera_wb: true,
+                quality: Some(3),
+                highlight: 2,
+                output_color: OutputColor::ProPhoto,
+                bright: Some(1.5),
+                shot_select: Some(1),
+                ..Default::default()
+            }
+        );
+        let params = ProcessParams::from_dcraw_args("-4 -r 2 1 1.5 1 -g 2.4 12.92").unwrap();
+        assert_eq!(params.gamma, Some([2.4, 12.92]));
+        assert_eq!(params.user_mul, Some([2.0, 1.0, 1.5, 1.0]));
+        assert!(params.no_auto_bright);
+
+        assert!(ProcessParams::from_dcraw_args("-q").is_err());
+        assert!(ProcessParams::from_dcraw_args("-H 10").is_err());
+        assert!(ProcessParams::from_dcraw_args("-o 9").is_err());
+        assert!(ProcessParams::from_dcraw_args("-b bright").is_err());
+        assert!(ProcessParams::from_dcraw_args("-X").is_err());
+        assert_eq!(
+            ProcessParams::from_dcraw_args("-g 2.4"),
+            Err(DcrawArgsError::MissingValue {
+                flag: "-g".into(),
+                expected: "a power and a toe slope"
+            })
+        );
+        assert_eq!(
+            ProcessParams::from_dcraw_args("-t 8"),
+            Err(DcrawArgsError::InvalidValue {
+                flag: "-t".into(),
+                value: "8".into()
+            })
+        );
+        for flag in ["-P dead.txt", "-K dark.pgm", "-p embed", "-d", "-D"] {
+            let err = ProcessParams::from_dcraw_args(flag).unwrap_err();
+            assert_eq!(err, DcrawArgsError::Unsupported(flag[..2].into()));
+        }
+        assert_eq!(
+            ProcessParams::from_dcraw_args("-o 9")
+                .unwrap_err()
+                .to_string(),
+            "-o: 9 isn't a valid value"
+        );
+
+        let params = ProcessParams::from_dcraw_args("-t 6 -A 10 20 30 40 -C 0.5 2 -M -j").unwrap();
+        assert_eq!(
+            params,
+            ProcessParams::new()
+                .flip(6)
+                .grey_box(Rect::new(10, 20, 30, 40))
+                .aberration(0.5, 2.0)
+                .camera_matrix(false)
+                .no_fuji_rotate(true)
+        );
+        let mut raw: sys::libraw_output_params_t = unsafe { std::mem::zeroed() };
+        params.apply(&mut raw);
+        assert_eq!((raw.user_flip, raw.greybox), (6, [10, 20, 30, 40]));
+        assert_eq!(raw.aber, [2.0, 1.0, 0.5, 1.0]);
+        assert_eq!((raw.use_camera_matrix, raw.use_fuji_rotate), (0, 0));
+        let rawparams = unsafe { std::mem::zeroed() };
+        assert_eq!(ProcessParams::from_libraw(&raw, &rawparams), params);
+        let always = ProcessParams::from_dcraw_args("+M").unwrap();
+        assert_eq!(always.camera_matrix, Some(true));
+
+        // unset fields keep LibRaw's defaults
+        let mut raw: sys::libraw_output_params_t = unsafe { std::mem::zeroed() };
+        ProcessParams::default().apply(&mut raw);
+        assert_eq!((raw.user_qual, raw.user_black, raw.bright), (-1, -1, 1.0));
+        assert!((raw.gamm[0] - 0.45).abs() < 1e-9 && raw.gamm[1] == 4.5);
+        let mut rawparams: sys::libraw_raw_unpack_params_t = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            ProcessParams::from_libraw(&raw, &rawparams),
+            ProcessParams::default()
+        );
+        rawparams.shot_select = 1;
+        assert_eq!(
+            ProcessParams::from_libraw(&raw, &rawparams).shot_select,
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let params = ProcessParams::new()
+            .use_camera_wb(true)
+            .quality(3)
+            .highlight(2)
+            .output_color(OutputColor::ProPhoto)
+            .bright(1.5)
+            .shot_select(1);
+        assert_eq!(
+            params,
+            ProcessParams::from_dcraw_args("-w -q 3 -H 2 -o 4 -b 1.5 -s 1").unwrap()
+        );
+        let crop = Rect::new(100, 50, 640, 480);
+        assert_eq!(
+            ProcessParams::from_dcraw_args("-B 100 50 640 480").unwrap(),
+            ProcessParams::new().crop(crop)
+        );
+        assert!(ProcessParams::from_dcraw_args("-B 100 50").is_err());
+
+        let mut raw: sys::libraw_output_params_t = unsafe { std::mem::zeroed() };
+        ProcessParams::new()
+            .crop(crop)
+            .gamma(1.0, 1.0)
+            .apply(&mut raw);
+        assert_eq!(raw.cropbox, [100, 50, 640, 480]);
+        let rawparams = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            ProcessParams::from_libraw(&raw, &rawparams).crop,
+            Some(crop)
+        );
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let image = raw_image
+            .process_with::<BIT_DEPTH_8>(&ProcessParams::new().crop(crop).half_size(true))
+            .expect("processed");
+        assert_eq!((image.width(), image.height()), (320, 240));
+        let image = raw_image
+            .process_with::<BIT_DEPTH_8>(&ProcessParams::new().half_size(true))
+            .expect("processed");
+        assert_eq!((image.width(), image.height()), (4140, 2760));
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let params = ProcessParams {
+            use_auto_wb: true,
+            half_size: true,
+            deterministic: true,
+            ..Default::default()
+        };
+        let mut raw: sys::libraw_output_params_t = unsafe { std::mem::zeroed() };
+        params.apply(&mut raw);
+        assert_eq!((raw.use_auto_wb, raw.no_auto_bright), (0, 1));
+        assert_eq!(raw.adjust_maximum_thr, 0.0);
+        let rawparams = unsafe { std::mem::zeroed() };
+        assert!(ProcessParams::from_libraw(&raw, &rawparams).deterministic);
+
+        // auto white balance is off whatever the params say
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        let first = raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        let manual = ProcessParams {
+            use_auto_wb: false,
+            ..params.clone()
+        };
+        let second = raw_image
+            .process_with::<BIT_DEPTH_8>(&manual)
+            .expect("processed");
+        assert!(first[..] == second[..]);
+
+        // nothing carries over from a run with other params, or from one image to the next
+        let other = ProcessParams {
+            half_size: true,
+            bright: Some(2.0),
+            quality: Some(0),
+            highlight: 2,
+            ..Default::default()
+        };
+        let brighter = raw_image
+            .process_with::<BIT_DEPTH_8>(&other)
+            .expect("processed");
+        assert!(brighter[..] != first[..]);
+        let again = raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        assert!(again[..] == first[..]);
+        let mut reopened = RawImage::open_owned(data).expect("opened");
+        let fresh = reopened
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        assert!(fresh[..] == first[..]);
+    }
+}
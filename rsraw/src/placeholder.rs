@@ -0,0 +1,424 @@
+// Loading placeholders for web galleries: BlurHash (https://blurha.sh) and
+// ThumbHash (https://evanw.github.io/thumbhash). Both only look at a tiny
+// version of the image, so everything is scaled down to `MAX_EDGE` first.
+
+use std::f32::consts::PI;
+
+use crate::{
+    convert,
+    err::{Error, Result},
+    preview::{decode_jpeg, fit_size},
+    ProcessedImage, ThumbFormat, ThumbnailImage, BIT_DEPTH_16, BIT_DEPTH_8,
+};
+
+// ThumbHash's limit, plenty for BlurHash too
+const MAX_EDGE: u32 = 100;
+const BLURHASH_COMPONENTS: (u32, u32) = (4, 3);
+const BASE83: &[u8] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+// 8 bit RGB no larger than `MAX_EDGE`
+struct Small {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+impl Small {
+    fn new(width: u32, height: u32, rgb: Vec<u8>) -> Self {
+        let (w, h) = fit_size(width, height, MAX_EDGE);
+        let rgb = match (w, h) == (width, height) {
+            true => rgb,
+            false => convert::downscale_rgb8(&rgb, width as _, height as _, w as _, h as _),
+        };
+        Self {
+            width: w,
+            height: h,
+            rgb,
+        }
+    }
+
+    fn from_thumb(thumb: &ThumbnailImage) -> Result<Self> {
+        match thumb.format {
+            ThumbFormat::Jpeg => {
+                let (width, height, rgb) = decode_jpeg(&thumb.data, MAX_EDGE)?;
+                Ok(Self::new(width, height, rgb))
+            }
+            ThumbFormat::Bitmap if thumb.colors == 3 => {
+                Ok(Self::new(thumb.width, thumb.height, thumb.data.clone()))
+            }
+            _ => Err(Error::UnsupportedThumbnail),
+        }
+    }
+
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32, [u8; 3])> + '_ {
+        let width = self.width;
+        self.rgb
+            .chunks_exact(3)
+            .enumerate()
+            .map(move |(i, p)| (i as u32 % width, i as u32 / width, [p[0], p[1], p[2]]))
+    }
+}
+
+impl ThumbnailImage {
+    // 4x3 components, JPEG and RGB bitmap thumbnails only
+    pub fn blurhash(&self) -> Result<String> {
+        Ok(blurhash(&Small::from_thumb(self)?))
+    }
+
+    pub fn thumbhash(&self) -> Result<Vec<u8>> {
+        Ok(thumbhash(&Small::from_thumb(self)?))
+    }
+}
+
+impl ProcessedImage<BIT_DEPTH_8> {
+    // RGB only, `Error::NotImplemented` for other outputs
+    pub fn blurhash(&self) -> Result<String> {
+        Ok(blurhash(&self.small()?))
+    }
+
+    pub fn thumbhash(&self) -> Result<Vec<u8>> {
+        Ok(thumbhash(&self.small()?))
+    }
+
+    fn small(&self) -> Result<Small> {
+        match self.colors() {
+            3 => Ok(Small::new(self.width(), self.height(), self.to_vec())),
+            _ => Err(Error::NotImplemented),
+        }
+    }
+}
+
+impl ProcessedImage<BIT_DEPTH_16> {
+    pub fn blurhash(&self) -> Result<String> {
+        Ok(blurhash(&self.small()?))
+    }
+
+    pub fn thumbhash(&self) -> Result<Vec<u8>> {
+        Ok(thumbhash(&self.small()?))
+    }
+
+    fn small(&self) -> Result<Small> {
+        match self.colors() {
+            3 => Ok(Small::new(
+                self.width(),
+                self.height(),
+                self.to_u8_dithered(),
+            )),
+            _ => Err(Error::NotImplemented),
+        }
+    }
+}
+
+fn blurhash(image: &Small) -> String {
+    let (nx, ny) = BLURHASH_COMPONENTS;
+    let (w, h) = (image.width as f32, image.height as f32);
+    let mut factors = Vec::with_capacity((nx * ny) as usize);
+    for j in 0..ny {
+        for i in 0..nx {
+            let mut sum = [0.0f32; 3];
+            for (x, y, rgb) in image.pixels() {
+                let basis =
+                    (PI * i as f32 * x as f32 / w).cos() * (PI * j as f32 * y as f32 / h).cos();
+                for (sum, v) in sum.iter_mut().zip(rgb) {
+                    *sum += basis * srgb_to_linear(v);
+                }
+            }
+            let scale = if i == 0 && j == 0 { 1.0 } else { 2.0 } / (w * h);
+            factors.push(sum.map(|v| v * scale));
+        }
+    }
+
+    let mut hash = String::new();
+    base83(&mut hash, (nx - 1) + (ny - 1) * 9, 1);
+    let ac = &factors[1..];
+    let max = ac.iter().flatten().fold(0.0f32, |max, v| max.max(v.abs()));
+    let (quantized_max, max) = match ac.is_empty() {
+        true => (0, 1.0),
+        false => {
+            let quantized = (max * 166.0 - 0.5).floor().clamp(0.0, 82.0);
+            (quantized as u32, (quantized + 1.0) / 166.0)
+        }
+    };
+    base83(&mut hash, quantized_max, 1);
+    let [r, g, b] = factors[0].map(linear_to_srgb);
+    base83(&mut hash, (r << 16) | (g << 8) | b, 4);
+    for factor in ac {
+        let [r, g, b] = factor.map(|v| {
+            let v = v / max;
+            (v.signum() * v.abs().sqrt() * 9.0 + 9.5)
+                .floor()
+                .clamp(0.0, 18.0) as u32
+        });
+        base83(&mut hash, r * 19 * 19 + g * 19 + b, 2);
+    }
+    hash
+}
+
+fn base83(out: &mut String, value: u32, digits: u32) {
+    for i in (0..digits).rev() {
+        out.push(BASE83[(value / 83u32.pow(i) % 83) as usize] as char);
+    }
+}
+
+fn srgb_to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    match v {
+        v if v <= 0.04045 => v / 12.92,
+        v => ((v + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+fn linear_to_srgb(v: f32) -> u32 {
+    let v = v.clamp(0.0, 1.0);
+    let v = match v {
+        v if v <= 0.003_130_8 => v * 12.92,
+        v => 1.055 * v.powf(1.0 / 2.4) - 0.055,
+    };
+    (v * 255.0 + 0.5) as u32
+}
+
+// ThumbHash of an opaque image, in the reference encoder's byte layout
+fn thumbhash(image: &Small) -> Vec<u8> {
+    let (w, h) = (image.width as usize, image.height as usize);
+    let n = w * h;
+    let (mut l, mut p, mut q) = (vec![0.0; n], vec![0.0; n], vec![0.0; n]);
+    for (i, (_, _, rgb)) in image.pixels().enumerate() {
+        let [r, g, b] = rgb.map(|v| v as f32 / 255.0);
+        l[i] = (r + g + b) / 3.0;
+        p[i] = (r + g) / 2.0 - b;
+        q[i] = r - g;
+    }
+
+    let long = w.max(h) as f32;
+    let lx = ((7.0 * w as f32 / long).round() as usize).max(1);
+    let ly = ((7.0 * h as f32 / long).round() as usize).max(1);
+    let (l_dc, l_ac, l_scale) = dct(&l, w, h, lx.max(3), ly.max(3));
+    let (p_dc, p_ac, p_scale) = dct(&p, w, h, 3, 3);
+    let (q_dc, q_ac, q_scale) = dct(&q, w, h, 3, 3);
+
+    let landscape = w > h;
+    let header24 = (63.0 * l_dc).round() as u32
+        | ((31.5 + 31.5 * p_dc).round() as u32) << 6
+        | ((31.5 + 31.5 * q_dc).round() as u32) << 12
+        | ((31.0 * l_scale).round() as u32) << 18;
+    let header16 = (if landscape { ly } else { lx }) as u32
+        | ((63.0 * p_scale).round() as u32) << 3
+        | ((63.0 * q_scale).round() as u32) << 9
+        | (landscape as u32) << 15;
+    let mut hash = vec![
+        header24 as u8,
+        (header24 >> 8) as u8,
+        (header24 >> 16) as u8,
+        header16 as u8,
+        (header16 >> 8) as u8,
+    ];
+    // two AC terms per byte, low nibble first
+    for (i, f) in l_ac.iter().chain(&p_ac).chain(&q_ac).enumerate() {
+        if i % 2 == 0 {
+            hash.push(0);
+        }
+        *hash.last_mut().unwrap() |= ((15.0 * f).round() as u8) << ((i & 1) * 4);
+    }
+    hash
+}
+
+// The DC term and the AC terms of the upper left triangle, the AC terms
+// mapped to 0..1 by their largest magnitude, which is returned too.
+fn dct(channel: &[f32], w: usize, h: usize, nx: usize, ny: usize) -> (f32, Vec<f32>, f32) {
+    let (mut dc, mut ac, mut scale) = (0.0, Vec::new(), 0.0f32);
+    for cy in 0..ny {
+        let mut cx = 0;
+        while cx * ny < nx * (ny - cy) {
+            let fx: Vec<f32> = (0..w)
+                .map(|x| (PI / w as f32 * cx as f32 * (x as f32 + 0.5)).cos())
+                .collect();
+            let mut f = 0.0;
+            for y in 0..h {
+                let fy = (PI / h as f32 * cy as f32 * (y as f32 + 0.5)).cos();
+                for x in 0..w {
+                    f += channel[x + y * w] * fx[x] * fy;
+                }
+            }
+            f /= (w * h) as f32;
+            if cx > 0 || cy > 0 {
+                ac.push(f);
+                scale = scale.max(f.abs());
+            } else {
+                dc = f;
+            }
+            cx += 1;
+        }
+    }
+    if scale > 0.0 {
+        for f in &mut ac {
+            *f = 0.5 + 0.5 / scale * *f;
+        }
+    }
+    (dc, ac, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, RawImage};
+
+    // The reference decoder's thumbHashToRGBA for hashes without alpha:
+    // width, height and RGB in 0..=255, the longer edge 32 pixels.
+    fn decode_thumbhash(hash: &[u8]) -> (usize, usize, Vec<[f32; 3]>) {
+        let header24 = hash[0] as u32 | (hash[1] as u32) << 8 | (hash[2] as u32) << 16;
+        let header16 = hash[3] as u32 | (hash[4] as u32) << 8;
+        assert_eq!(header24 >> 23, 0, "no alpha");
+        let l_dc = (header24 & 63) as f32 / 63.0;
+        let p_dc = ((header24 >> 6) & 63) as f32 / 31.5 - 1.0;
+        let q_dc = ((header24 >> 12) & 63) as f32 / 31.5 - 1.0;
+        let l_scale = ((header24 >> 18) & 31) as f32 / 31.0;
+        let p_scale = ((header16 >> 3) & 63) as f32 / 63.0;
+        let q_scale = ((header16 >> 9) & 63) as f32 / 63.0;
+        let (lx, ly) = match header16 >> 15 {
+            1 => (7, header16 as usize & 7),
+            _ => (header16 as usize & 7, 7),
+        };
+        let ratio = lx as f32 / ly as f32;
+        let (w, h) = match ratio > 1.0 {
+            true => (32, (32.0 / ratio).round() as usize),
+            false => ((32.0 * ratio).round() as usize, 32),
+        };
+
+        let mut nibbles = hash[5..].iter().flat_map(|b| [b & 15, b >> 4]);
+        let mut channel = |nx: usize, ny: usize, scale: f32| {
+            let mut ac = Vec::new();
+            for cy in 0..ny {
+                let mut cx = (cy == 0) as usize;
+                while cx * ny < nx * (ny - cy) {
+                    let f = (nibbles.next().expect("ac term") as f32 / 7.5 - 1.0) * scale;
+                    ac.push((cx, cy, f));
+                    cx += 1;
+                }
+            }
+            ac
+        };
+        let l_ac = channel(lx.max(3), ly.max(3), l_scale);
+        let p_ac = channel(3, 3, p_scale * 1.25);
+        let q_ac = channel(3, 3, q_scale * 1.25);
+
+        let mut rgb = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let value = |dc: f32, ac: &[(usize, usize, f32)]| {
+                    ac.iter().fold(dc, |v, &(cx, cy, f)| {
+                        let fx = (PI / w as f32 * (x as f32 + 0.5) * cx as f32).cos();
+                        let fy = (PI / h as f32 * (y as f32 + 0.5) * cy as f32).cos();
+                        v + f * fx * fy * 2.0
+                    })
+                };
+                let (l, p, q) = (value(l_dc, &l_ac), value(p_dc, &p_ac), value(q_dc, &q_ac));
+                let b = l - 2.0 / 3.0 * p;
+                let r = (3.0 * l - b + q) / 2.0;
+                let g = r - q;
+                rgb.push([r, g, b].map(|v| 255.0 * v.clamp(0.0, 1.0)));
+            }
+        }
+        (w, h, rgb)
+    }
+
+    // the largest difference of a decoded hash from `source`, which gives the
+    // color at relative coordinates, and the correlation of the two
+    fn thumbhash_fit(hash: &[u8], source: impl Fn(f32, f32) -> [u8; 3]) -> (f32, f32) {
+        let (w, h, decoded) = decode_thumbhash(hash);
+        let pairs: Vec<(f32, f32)> = decoded
+            .iter()
+            .enumerate()
+            .flat_map(|(i, rgb)| {
+                let (x, y) = ((i % w) as f32 + 0.5, (i / w) as f32 + 0.5);
+                let expected = source(x / w as f32, y / h as f32);
+                rgb.iter().zip(expected).map(|(&v, e)| (v, e as f32))
+            })
+            .collect();
+        let n = pairs.len() as f32;
+        let max = pairs
+            .iter()
+            .fold(0.0f32, |max, (v, e)| max.max((v - e).abs()));
+        let (mean_v, mean_e) = pairs
+            .iter()
+            .fold((0.0, 0.0), |(sv, se), (v, e)| (sv + v / n, se + e / n));
+        let (mut cov, mut var_v, mut var_e) = (0.0, 0.0, 0.0);
+        for (v, e) in &pairs {
+            cov += (v - mean_v) * (e - mean_e);
+            var_v += (v - mean_v).powi(2);
+            var_e += (e - mean_e).powi(2);
+        }
+        (max, cov / (var_v * var_e).sqrt())
+    }
+
+    fn small(width: u32, height: u32, source: impl Fn(f32, f32) -> [u8; 3]) -> Small {
+        let rgb = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                source(
+                    (x as f32 + 0.5) / width as f32,
+                    (y as f32 + 0.5) / height as f32,
+                )
+            })
+            .collect();
+        Small::new(width, height, rgb)
+    }
+
+    #[test]
+    fn test_placeholders() {
+        let gray = Small::new(32, 32, vec![128; 32 * 32 * 3]);
+        // as the reference encoders have it
+        assert_eq!(blurhash(&gray), "L1Eyb[~qfQ~q~qoffQoffQfQfQfQ");
+        // a flat image decodes flat and square, up to the DC quantization
+        let (w, h, decoded) = decode_thumbhash(&thumbhash(&gray));
+        assert_eq!((w, h), (32, 32));
+        assert!(decoded.iter().flatten().all(|v| (v - 128.0).abs() < 6.0));
+
+        // so does a flat color, DC terms only
+        let orange = |_, _| [200, 80, 40];
+        let (max, _) = thumbhash_fit(&thumbhash(&small(32, 32, orange)), orange);
+        assert!(max < 6.0, "{max}");
+
+        // Luminance waves come back in a box of about their shape. Each AC
+        // term is 4 bits without an exact zero, so they're only close.
+        let gray = |v: f32| [v as u8; 3];
+        let waves = |u: f32, v: f32| gray(128.0 + 80.0 * (PI * u).cos() + 40.0 * (PI * v).cos());
+        let hash = thumbhash(&small(64, 32, waves));
+        let (w, h, _) = decode_thumbhash(&hash);
+        assert_eq!((w, h), (32, 18));
+        let (_, correlation) = thumbhash_fit(&hash, waves);
+        assert!(correlation > 0.95, "{correlation}");
+        let waves =
+            |u: f32, v: f32| gray(128.0 + 40.0 * (PI * u).cos() + 80.0 * (2.0 * PI * v).cos());
+        let hash = thumbhash(&small(30, 60, waves));
+        let (w, h, _) = decode_thumbhash(&hash);
+        assert_eq!((w, h), (18, 32));
+        let (_, correlation) = thumbhash_fit(&hash, waves);
+        assert!(correlation > 0.95, "{correlation}");
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let thumb = raw_image.largest_jpeg_preview().expect("jpeg preview");
+        let hash = thumb.blurhash().expect("blurhash");
+        assert_eq!(hash.len(), 28);
+        assert!(hash.starts_with('L'));
+        // a landscape frame, its average color kept
+        let hash = thumb.thumbhash().expect("thumbhash");
+        let (w, h, decoded) = decode_thumbhash(&hash);
+        assert!(w > h);
+        let pixels = Small::from_thumb(&thumb).expect("small");
+        let average = |rgb: &mut dyn Iterator<Item = [f32; 3]>, n: usize| {
+            rgb.fold([0.0; 3], |sum, p| {
+                [0, 1, 2].map(|c| sum[c] + p[c] / n as f32)
+            })
+        };
+        let expected = average(
+            &mut pixels.pixels().map(|(_, _, p)| p.map(f32::from)),
+            (pixels.width * pixels.height) as usize,
+        );
+        let decoded = average(&mut decoded.into_iter(), w * h);
+        for (d, e) in decoded.iter().zip(expected) {
+            assert!((d - e).abs() < 8.0, "{decoded:?} {expected:?}");
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::borrow::Cow;
+
+use crate::{
+    err::{Error, Result},
+    export::ExportMetadata,
+    raw::BitDepth,
+    ProcessedImage, BIT_DEPTH_16,
+};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+// a middle ground, higher levels barely shrink photographs
+const LEVEL: u8 = 6;
+// the Up filter: photographs change slowly from row to row
+const FILTER_UP: u8 = 2;
+
+impl<const D: BitDepth> ProcessedImage<D> {
+    // Lossless at the image's bit depth with `metadata` in an `eXIf` chunk.
+    // RGB and grayscale only, anything else is `Error::NotImplemented`.
+    pub fn to_png(&self, metadata: &ExportMetadata) -> Result<Vec<u8>> {
+        let colors = self.colors();
+        let color_type = match colors {
+            1 => 0,
+            3 => 2,
+            _ => return Err(Error::NotImplemented),
+        };
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width().to_be_bytes());
+        ihdr.extend_from_slice(&self.height().to_be_bytes());
+        ihdr.extend_from_slice(&[D as u8, color_type, 0, 0, 0]);
+
+        // samples are big endian in PNG
+        let data = match D {
+            BIT_DEPTH_16 => Cow::Owned(
+                self.bytes()
+                    .chunks_exact(2)
+                    .flat_map(|b| u16::from_ne_bytes([b[0], b[1]]).to_be_bytes())
+                    .collect(),
+            ),
+            _ => Cow::Borrowed(self.bytes()),
+        };
+        let stride = self.width() as usize * colors as usize * (D as usize / 8);
+        let mut filtered = Vec::with_capacity(data.len() + self.height() as usize);
+        let mut above: &[u8] = &[];
+        for row in data.chunks_exact(stride.max(1)) {
+            filtered.push(FILTER_UP);
+            match above.is_empty() {
+                true => filtered.extend_from_slice(row),
+                false => filtered.extend(row.iter().zip(above).map(|(v, a)| v.wrapping_sub(*a))),
+            }
+            above = row;
+        }
+        let idat = miniz_oxide::deflate::compress_to_vec_zlib(&filtered, LEVEL);
+
+        let mut out = SIGNATURE.to_vec();
+        chunk(&mut out, b"IHDR", &ihdr);
+        chunk(&mut out, b"eXIf", &metadata.exif());
+        chunk(&mut out, b"IDAT", &idat);
+        chunk(&mut out, b"IEND", &[]);
+        Ok(out)
+    }
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32fast::hash(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
@@ -0,0 +1,167 @@
+use std::{
+    fmt::{self, Debug, Formatter},
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+};
+
+// Recycles output buffers for `RawImage::process_into`. A buffer handed out by
+// `get` goes back to the pool when dropped, keeping its allocation (and the
+// pages backing it) alive for the next image of a similar size.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    idle: Mutex<Vec<Vec<u8>>>,
+    max_idle: usize,
+}
+
+pub struct PooledBuffer {
+    buf: Vec<u8>,
+    pool: Arc<Inner>,
+}
+
+impl BufferPool {
+    // keeps at most `max_idle` returned buffers, extra ones are freed
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                idle: Mutex::new(Vec::new()),
+                max_idle,
+            }),
+        }
+    }
+
+    // hands out the largest idle buffer, so a big image never lands in one
+    // of the small leftovers while a big one sits unused
+    pub fn get(&self) -> PooledBuffer {
+        let mut idle = self.inner.idle();
+        let largest = idle
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, buf)| buf.capacity())
+            .map(|(i, _)| i);
+        PooledBuffer {
+            buf: largest.map(|i| idle.swap_remove(i)).unwrap_or_default(),
+            pool: self.inner.clone(),
+        }
+    }
+
+    pub fn idle(&self) -> usize {
+        self.inner.idle().len()
+    }
+
+    pub fn idle_bytes(&self) -> usize {
+        self.inner.idle().iter().map(Vec::capacity).sum()
+    }
+
+    pub fn clear(&self) {
+        self.inner.idle().clear();
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+impl Debug for BufferPool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("idle", &self.idle())
+            .field("idle_bytes", &self.idle_bytes())
+            .field("max_idle", &self.inner.max_idle)
+            .finish()
+    }
+}
+
+impl Inner {
+    fn idle(&self) -> std::sync::MutexGuard<'_, Vec<Vec<u8>>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl PooledBuffer {
+    // takes the buffer out of the pool for good
+    pub fn into_inner(mut self) -> Vec<u8> {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if self.buf.capacity() == 0 {
+            return;
+        }
+        let mut idle = self.pool.idle();
+        if idle.len() < self.pool.max_idle {
+            idle.push(std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+impl Debug for PooledBuffer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledBuffer")
+            .field("len", &self.buf.len())
+            .field("capacity", &self.buf.capacity())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, ProcessParams, RawImage, BIT_DEPTH_8};
+
+    #[test]
+    fn test_process_into_reuses_buffer() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        let pool = BufferPool::new(1);
+
+        let mut buf = pool.get();
+        let layout = raw_image
+            .process_into_with::<BIT_DEPTH_8>(&params, &mut buf)
+            .expect("processed");
+        assert_eq!(
+            (layout.width, layout.height, layout.colors),
+            (4140, 2760, 3)
+        );
+        assert_eq!(buf.len(), layout.len());
+        let ptr = buf.as_ptr();
+        drop(buf);
+        assert_eq!(pool.idle(), 1);
+
+        let mut buf = pool.get();
+        assert_eq!(pool.idle(), 0);
+        raw_image
+            .process_into_with::<BIT_DEPTH_8>(&params, &mut buf)
+            .expect("processed");
+        assert_eq!(buf.as_ptr(), ptr);
+
+        let image = raw_image.process_with::<BIT_DEPTH_8>(&params).unwrap();
+        assert_eq!(&buf[..], &image[..]);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    convert,
+    err::{Error, Result},
+    ProcessParams, RawImage, ThumbFormat, ThumbInfo, BIT_DEPTH_8,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PreviewSource {
+    EmbeddedJpeg,
+    EmbeddedBitmap,
+    Develop,
+}
+
+// interleaved 8 bit RGB, rows tightly packed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Preview {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    pub source: PreviewSource,
+}
+
+impl RawImage {
+    // Cheapest preview whose longer edge is at least `max_edge` (or as close as
+    // possible), scaled down to fit `max_edge` and upright. Prefers the smallest adequate
+    // embedded JPEG or bitmap and falls back to a half size, camera white
+    // balance develop, which unpacks the raw data if that hasn't happened yet.
+    pub fn quick_preview(&mut self, max_edge: u32) -> Result<Preview> {
+        if let Some(thumb) = self.preview_thumb(max_edge) {
+            if let Ok(preview) = self.decode_thumb(&thumb, max_edge) {
+                return Ok(preview);
+            }
+        }
+        let params = ProcessParams {
+            half_size: true,
+            use_camera_wb: true,
+            ..Default::default()
+        };
+        let image = self.process_with::<BIT_DEPTH_8>(&params)?;
+        let (width, height, data) = match image.colors() {
+            3 => (image.width(), image.height(), image.to_vec()),
+            _ => return Err(Error::NotImplemented),
+        };
+        Ok(fit(width, height, data, max_edge, PreviewSource::Develop))
+    }
+
+    fn preview_thumb(&self, max_edge: u32) -> Option<ThumbInfo> {
+        // smallest one that's large enough, otherwise we develop
+        self.thumb_infos()
+            .into_iter()
+            .filter(|t| matches!(t.format, ThumbFormat::Jpeg | ThumbFormat::Bitmap))
+            .filter(|t| t.width.max(t.height) >= max_edge)
+            .min_by_key(|t| t.pixels())
+    }
+
+    // turned upright, embedded previews are stored the way the sensor saw them
+    fn decode_thumb(&mut self, info: &ThumbInfo, max_edge: u32) -> Result<Preview> {
+        let thumb = self.extract_thumb(info.index)?;
+        let (width, height, data, source) = match thumb.format {
+            ThumbFormat::Jpeg => {
+                let (width, height, data) = decode_jpeg(&thumb.data, max_edge)?;
+                (width, height, data, PreviewSource::EmbeddedJpeg)
+            }
+            ThumbFormat::Bitmap if thumb.colors == 3 => (
+                thumb.width,
+                thumb.height,
+                thumb.data,
+                PreviewSource::EmbeddedBitmap,
+            ),
+            #[cfg(feature = "heif")]
+            ThumbFormat::H265 => {
+                let thumb = crate::heif::decode_h265(&thumb)?;
+                (
+                    thumb.width,
+                    thumb.height,
+                    thumb.data,
+                    PreviewSource::EmbeddedBitmap,
+                )
+            }
+            _ => return Err(Error::UnsupportedThumbnail),
+        };
+        let preview = fit(width, height, data, max_edge, source);
+        let flip = self.thumb_flip(info);
+        if flip & 7 == 0 {
+            return Ok(preview);
+        }
+        let (width, height, data) = orient(&preview.data, preview.width, preview.height, flip);
+        Ok(Preview {
+            width,
+            height,
+            data,
+            ..preview
+        })
+    }
+}
+
+fn fit(width: u32, height: u32, data: Vec<u8>, max_edge: u32, source: PreviewSource) -> Preview {
+    let (w, h) = fit_size(width, height, max_edge);
+    let data = if (w, h) == (width, height) {
+        data
+    } else {
+        convert::downscale_rgb8(&data, width as _, height as _, w as _, h as _)
+    };
+    Preview {
+        width: w,
+        height: h,
+        data,
+        source,
+    }
+}
+
+pub(crate) fn fit_size(width: u32, height: u32, max_edge: u32) -> (u32, u32) {
+    let edge = width.max(height);
+    if edge <= max_edge || max_edge == 0 {
+        return (width, height);
+    }
+    let scale =
+        |v: u32| ((v as u64 * max_edge as u64 + edge as u64 / 2) / edge as u64).max(1) as u32;
+    (scale(width), scale(height))
+}
+
+// RGB rows in the sensor's orientation turned upright, flip bits as LibRaw's
+// flip_index: 4 swaps rows and columns, 2 reverses rows, 1 columns
+pub(crate) fn orient(rgb: &[u8], width: u32, height: u32, flip: i32) -> (u32, u32, Vec<u8>) {
+    let (w, h) = match flip & 4 {
+        0 => (width, height),
+        _ => (height, width),
+    };
+    let mut out = Vec::with_capacity(rgb.len());
+    for orow in 0..h {
+        for ocol in 0..w {
+            let (mut row, mut col) = match flip & 4 {
+                0 => (orow, ocol),
+                _ => (ocol, orow),
+            };
+            if flip & 2 != 0 {
+                row = height - 1 - row;
+            }
+            if flip & 1 != 0 {
+                col = width - 1 - col;
+            }
+            let i = (row as usize * width as usize + col as usize) * 3;
+            out.extend_from_slice(&rgb[i..i + 3]);
+        }
+    }
+    (w, h, out)
+}
+
+// RGB, scaled down during decoding to roughly fit `max_edge`, 0 keeps the full size
+pub(crate) fn decode_jpeg(data: &[u8], max_edge: u32) -> Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    decoder
+        .read_info()
+        .map_err(|_| Error::UnsupportedThumbnail)?;
+    let info = decoder.info().ok_or(Error::UnsupportedThumbnail)?;
+    // DCT scaling decodes at 1/2, 1/4 or 1/8 size for a fraction of the work
+    let (w, h) = fit_size(info.width as _, info.height as _, max_edge);
+    let (width, height) = decoder
+        .scale(w as _, h as _)
+        .map_err(|_| Error::UnsupportedThumbnail)?;
+    let pixels = decoder.decode().map_err(|_| Error::UnsupportedThumbnail)?;
+    let format = decoder
+        .info()
+        .ok_or(Error::UnsupportedThumbnail)?
+        .pixel_format;
+    let rgb = match format {
+        jpeg_decoder::PixelFormat::RGB24 => pixels,
+        jpeg_decoder::PixelFormat::L8 => pixels.iter().flat_map(|&l| [l; 3]).collect(),
+        jpeg_decoder::PixelFormat::L16 => pixels.chunks(2).flat_map(|l| [l[0]; 3]).collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => pixels
+            .chunks(4)
+            .flat_map(|p| {
+                let k = p[3] as u32;
+                [0, 1, 2].map(|c| (p[c] as u32 * k / 255) as u8)
+            })
+            .collect(),
+    };
+    Ok((width as _, height as _, rgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_orient() {
+        // 2x1, red then green
+        let rgb = [255, 0, 0, 0, 255, 0];
+        assert_eq!(orient(&rgb, 2, 1, 0), (2, 1, rgb.to_vec()));
+        assert_eq!(orient(&rgb, 2, 1, 3), (2, 1, vec![0, 255, 0, 255, 0, 0]));
+        // 90 degrees clockwise puts the left end on top
+        assert_eq!(orient(&rgb, 2, 1, 6), (1, 2, rgb.to_vec()));
+        assert_eq!(orient(&rgb, 2, 1, 5), (1, 2, vec![0, 255, 0, 255, 0, 0]));
+    }
+
+    #[test]
+    fn test_quick_preview() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let preview = raw_image.quick_preview(1024).expect("preview");
+        assert_eq!(preview.source, PreviewSource::EmbeddedJpeg);
+        assert_eq!(preview.width.max(preview.height), 1024);
+        assert_eq!(
+            preview.data.len(),
+            preview.width as usize * preview.height as usize * 3
+        );
+
+        // shot in portrait, the embedded JPEG is turned with it
+        unsafe { raw_image.with_raw_mut(|data| data.sizes.flip = 6) };
+        let upright = raw_image.quick_preview(1024).expect("preview");
+        assert_eq!(upright.source, PreviewSource::EmbeddedJpeg);
+        assert_eq!(
+            (upright.width, upright.height),
+            (preview.height, preview.width)
+        );
+        let (_, _, turned) = orient(&preview.data, preview.width, preview.height, 6);
+        assert_eq!(upright.data, turned);
+    }
+}
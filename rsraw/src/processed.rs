@@ -1,12 +1,17 @@
 use std::{
+    borrow::Cow,
     fmt::{self, Debug, Formatter},
-    ops::Deref,
-    slice,
+    ops::{Deref, DerefMut},
+    ptr, slice,
 };
 
 use rsraw_sys as sys;
 
-use crate::raw::{BitDepth, BIT_DEPTH_16, BIT_DEPTH_8};
+use crate::{
+    convert,
+    raw::{BitDepth, BIT_DEPTH_16, BIT_DEPTH_8},
+    OutputColor, ProcessingLog,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ImageFormat {
@@ -14,19 +19,54 @@ pub enum ImageFormat {
     Bitmap,
 }
 
+// shape of a bitmap written by `RawImage::process_into`, rows are tightly packed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageLayout {
+    pub width: u32,
+    pub height: u32,
+    pub colors: u16,
+    pub bits: u16,
+}
+
+impl ImageLayout {
+    pub fn stride(&self) -> usize {
+        self.width as usize * self.colors as usize * (self.bits as usize / 8)
+    }
+
+    pub fn len(&self) -> usize {
+        self.stride() * self.height as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 pub struct ProcessedImage<const D: BitDepth> {
     inner: *mut sys::libraw_processed_image_t,
+    output_color: OutputColor,
+    log: ProcessingLog,
 }
 
+// the bitmap is a standalone allocation, detached from the RawImage that
+// produced it and only written through `&mut` after `make_mem_image` returns
 unsafe impl Sync for ProcessedImage<BIT_DEPTH_8> {}
 unsafe impl Send for ProcessedImage<BIT_DEPTH_8> {}
 unsafe impl Sync for ProcessedImage<BIT_DEPTH_16> {}
 unsafe impl Send for ProcessedImage<BIT_DEPTH_16> {}
 
 impl<const D: BitDepth> ProcessedImage<D> {
-    pub(crate) unsafe fn from_raw(ptr: *mut sys::libraw_processed_image_t) -> Self {
+    pub(crate) unsafe fn from_raw(
+        ptr: *mut sys::libraw_processed_image_t,
+        output_color: OutputColor,
+        log: ProcessingLog,
+    ) -> Self {
         debug_assert!(!ptr.is_null());
-        Self { inner: ptr }
+        Self {
+            inner: ptr,
+            output_color,
+            log,
+        }
     }
 
     pub fn width(&self) -> u32 {
@@ -56,6 +96,76 @@ impl<const D: BitDepth> ProcessedImage<D> {
     pub fn data_size(&self) -> usize {
         unsafe { (*self.inner).data_size as usize }
     }
+
+    // the color space it was developed into
+    pub fn output_color(&self) -> OutputColor {
+        self.output_color
+    }
+
+    // what made it, taken as `process` finished, see `RawImage::processing_log`
+    pub fn log(&self) -> &ProcessingLog {
+        &self.log
+    }
+
+    // the bitmap as bytes, 16 bit samples in native byte order
+    pub(crate) fn bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts((*self.inner).data.as_ptr(), self.data_size()) }
+    }
+
+    // 8 bit samples for encoders that take nothing else, 16 bit ones dithered
+    pub(crate) fn to_u8(&self) -> Cow<'_, [u8]> {
+        if D == BIT_DEPTH_8 {
+            return Cow::Borrowed(self.bytes());
+        }
+        let samples: Vec<u16> = self
+            .bytes()
+            .chunks_exact(2)
+            .map(|b| u16::from_ne_bytes([b[0], b[1]]))
+            .collect();
+        let mut out = vec![0; samples.len()];
+        convert::dither_to_u8(&samples, self.width() as _, self.colors() as _, &mut out);
+        Cow::Owned(out)
+    }
+
+    // Keeps the `width` x `height` window at `x`, `y`, its rows moved to the
+    // front of the same allocation. The window has to lie inside the bitmap.
+    pub(crate) fn crop(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        debug_assert!(x + width <= self.width() && y + height <= self.height());
+        let pixel = self.colors() as usize * (self.bits() as usize / 8);
+        let stride = self.width() as usize * pixel;
+        let row = width as usize * pixel;
+        unsafe {
+            let inner = &mut *self.inner;
+            let base = inner.data.as_mut_ptr();
+            for i in 0..height as usize {
+                let src = (y as usize + i) * stride + x as usize * pixel;
+                ptr::copy(base.add(src), base.add(i * row), row);
+            }
+            inner.width = width as _;
+            inner.height = height as _;
+            inner.data_size = (row * height as usize) as _;
+        }
+    }
+}
+
+impl ProcessedImage<BIT_DEPTH_16> {
+    pub fn to_f32(&self) -> Vec<f32> {
+        let mut out = vec![0.0; self.len()];
+        convert::normalize_u16(self, u16::MAX, &mut out);
+        out
+    }
+
+    pub fn to_planar(&self) -> Vec<u16> {
+        let mut out = vec![0; self.len()];
+        convert::to_planar_u16(self, self.colors() as _, &mut out);
+        out
+    }
+
+    pub fn to_u8_dithered(&self) -> Vec<u8> {
+        let mut out = vec![0; self.len()];
+        convert::dither_to_u8(self, self.width() as _, self.colors() as _, &mut out);
+        out
+    }
 }
 
 impl Deref for ProcessedImage<BIT_DEPTH_8> {
@@ -86,6 +196,28 @@ impl Deref for ProcessedImage<BIT_DEPTH_16> {
     }
 }
 
+impl DerefMut for ProcessedImage<BIT_DEPTH_8> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe {
+            slice::from_raw_parts_mut(
+                (*self.inner).data.as_mut_ptr(),
+                (*self.inner).data_size as usize,
+            )
+        }
+    }
+}
+
+impl DerefMut for ProcessedImage<BIT_DEPTH_16> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe {
+            slice::from_raw_parts_mut(
+                (*self.inner).data.as_mut_ptr() as *mut u16,
+                (*self.inner).data_size as usize / 2,
+            )
+        }
+    }
+}
+
 impl<const D: BitDepth> Drop for ProcessedImage<D> {
     fn drop(&mut self) {
         unsafe { sys::libraw_dcraw_clear_mem(self.inner) }
@@ -100,6 +232,7 @@ impl<const D: BitDepth> Debug for ProcessedImage<D> {
             .field("image_format", &self.image_format())
             .field("colors", &self.colors())
             .field("bits", &self.bits())
+            .field("output_color", &self.output_color)
             .field("data_size", &self.data_size())
             .finish()
     }
@@ -0,0 +1,292 @@
+// A camera matrix fitted to a shot of a 24 patch ColorChecker, for studio
+// work where the light is known and the generic matrix isn't good enough.
+// The chart is developed linear and without any matrix, the patches sampled
+// and the matrix from white balanced camera RGB to linear sRGB solved for in
+// the least squares sense.
+
+use crate::{
+    err::{Error, Result},
+    ProcessParams, RawImage, BIT_DEPTH_16,
+};
+
+// the chart's sRGB values as X-Rite publishes them, row by row from dark skin
+const PATCHES: [[u8; 3]; 24] = [
+    [115, 82, 68],
+    [194, 150, 130],
+    [98, 122, 157],
+    [87, 108, 67],
+    [133, 128, 177],
+    [103, 189, 170],
+    [214, 126, 44],
+    [80, 91, 166],
+    [193, 90, 99],
+    [94, 60, 108],
+    [157, 188, 64],
+    [224, 163, 46],
+    [56, 61, 150],
+    [70, 148, 73],
+    [175, 54, 60],
+    [231, 199, 31],
+    [187, 86, 149],
+    [8, 133, 161],
+    [243, 243, 242],
+    [200, 200, 200],
+    [160, 160, 160],
+    [122, 122, 121],
+    [85, 85, 85],
+    [52, 52, 52],
+];
+const COLUMNS: usize = 6;
+const ROWS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ColorProfile {
+    // rows are sRGB's red, green and blue, columns the camera's colors; for
+    // `RawImage::set_camera_matrix`. Scaled so white stays white on average,
+    // exposure is left to the develop.
+    pub matrix: [[f32; 3]; 3],
+    // root mean square of what the fit misses over the patches, in linear
+    // sRGB with the white patch near 0.9
+    pub rms_error: f32,
+}
+
+impl RawImage {
+    // Fits a matrix to a ColorChecker in the frame. `corners` are the chart's
+    // outer corners at dark skin, bluish green, black and white, clockwise
+    // for an upright chart, in pixels of what `process_with(params)` develops.
+    // Of `params` the white balance, demosaic and crop settings count; the
+    // develop is linear and in camera colors whatever else they say.
+    // `Error::BadCrop` for corners outside the frame, `Error::NotImplemented`
+    // for anything but three color cameras.
+    pub fn profile_color_checker(
+        &mut self,
+        corners: [[f32; 2]; 4],
+        params: &ProcessParams,
+    ) -> Result<ColorProfile> {
+        if self.colors() != 3 {
+            return Err(Error::NotImplemented);
+        }
+        let camera = self.camera_matrix();
+        let saved = self.as_ref().params;
+        let identity = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        self.set_camera_matrix(identity);
+        let linear = ProcessParams {
+            output_color: Default::default(),
+            no_auto_bright: true,
+            bright: None,
+            gamma: Some([1.0, 1.0]),
+            ..params.clone()
+        };
+        let result = self.process_with::<BIT_DEPTH_16>(&linear);
+        self.set_camera_matrix(camera);
+        unsafe { self.with_raw_mut(|data| data.params = saved) };
+        let image = result?;
+
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        if corners.iter().any(|&[x, y]| {
+            !(0.0..=width as f32).contains(&x) || !(0.0..=height as f32).contains(&y)
+        }) {
+            return Err(Error::BadCrop);
+        }
+        let samples = sample_patches(&image, width, height, corners);
+        fit(&samples).ok_or(Error::Unspecified)
+    }
+
+    // What processing maps white balanced camera colors to sRGB with, rows
+    // being sRGB's channels. Other output spaces are converted from sRGB.
+    pub fn camera_matrix(&self) -> [[f32; 3]; 3] {
+        let rgb_cam = &self.as_ref().color.rgb_cam;
+        [0, 1, 2].map(|i| [rgb_cam[i][0], rgb_cam[i][1], rgb_cam[i][2]])
+    }
+
+    // Replaces the matrix LibRaw derived from its tables or the file, e.g.
+    // with `ColorProfile::matrix`, for every later process of the image
+    pub fn set_camera_matrix(&mut self, matrix: [[f32; 3]; 3]) {
+        // processing starts over from the rawdata copy once unpacked
+        unsafe {
+            self.with_raw_mut(|data| {
+                for (i, row) in matrix.iter().enumerate() {
+                    let rgb_cam = [row[0], row[1], row[2], 0.0];
+                    data.color.rgb_cam[i] = rgb_cam;
+                    data.rawdata.color.rgb_cam[i] = rgb_cam;
+                }
+            })
+        };
+    }
+}
+
+fn srgb_to_linear(v: u8) -> f64 {
+    let v = v as f64 / 255.0;
+    match v <= 0.04045 {
+        true => v / 12.92,
+        false => ((v + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+// the mean camera color of every patch, from a box at its center a quarter
+// of the patch spacing wide
+fn sample_patches(
+    rgb: &[u16],
+    width: usize,
+    height: usize,
+    corners: [[f32; 2]; 4],
+) -> Vec<[f64; 3]> {
+    let [a, b, c, d] = corners.map(|[x, y]| [x as f64, y as f64]);
+    let at = |u: f64, v: f64| {
+        [0, 1].map(|i| {
+            (1.0 - u) * (1.0 - v) * a[i]
+                + u * (1.0 - v) * b[i]
+                + u * v * c[i]
+                + (1.0 - u) * v * d[i]
+        })
+    };
+    let distance = |p: [f64; 2], q: [f64; 2]| (p[0] - q[0]).hypot(p[1] - q[1]);
+    let spacing = (distance(a, b) / COLUMNS as f64).min(distance(a, d) / ROWS as f64);
+    let radius = (spacing / 8.0).max(0.5);
+    (0..ROWS * COLUMNS)
+        .map(|i| {
+            let u = ((i % COLUMNS) as f64 + 0.5) / COLUMNS as f64;
+            let v = ((i / COLUMNS) as f64 + 0.5) / ROWS as f64;
+            let [x, y] = at(u, v);
+            let span = |center: f64, len: usize| {
+                let from = (center - radius).round().max(0.0) as usize;
+                let to = ((center + radius).round() as usize).clamp(from + 1, len);
+                from.min(len - 1)..to
+            };
+            let (mut sum, mut n) = ([0.0; 3], 0.0);
+            for row in span(y, height) {
+                for col in span(x, width) {
+                    let pixel = &rgb[(row * width + col) * 3..][..3];
+                    for (s, &p) in sum.iter_mut().zip(pixel) {
+                        *s += p as f64 / u16::MAX as f64;
+                    }
+                    n += 1.0;
+                }
+            }
+            sum.map(|s| s / n)
+        })
+        .collect()
+}
+
+// least squares over the patches, `None` when they don't span the camera's
+// colors
+fn fit(samples: &[[f64; 3]]) -> Option<ColorProfile> {
+    let reference: Vec<[f64; 3]> = PATCHES.iter().map(|p| p.map(srgb_to_linear)).collect();
+    // matrix = (reference x samples^T) (samples x samples^T)^-1
+    let (mut ss, mut rs) = ([[0.0; 3]; 3], [[0.0; 3]; 3]);
+    for (s, r) in samples.iter().zip(&reference) {
+        for i in 0..3 {
+            for j in 0..3 {
+                ss[i][j] += s[i] * s[j];
+                rs[i][j] += r[i] * s[j];
+            }
+        }
+    }
+    let inverse = invert(ss)?;
+    let matrix = mul(rs, inverse);
+
+    let apply = |m: &[[f64; 3]; 3], s: &[f64; 3]| {
+        [0, 1, 2].map(|i| (0..3).map(|j| m[i][j] * s[j]).sum::<f64>())
+    };
+    let squares: f64 = samples
+        .iter()
+        .zip(&reference)
+        .map(|(s, r)| {
+            let fitted = apply(&matrix, s);
+            (0..3).map(|i| (fitted[i] - r[i]).powi(2)).sum::<f64>()
+        })
+        .sum();
+    let rms_error = (squares / (samples.len() * 3) as f64).sqrt() as f32;
+    let scale = 3.0 / matrix.iter().flatten().sum::<f64>();
+    (scale.is_finite() && scale > 0.0).then(|| ColorProfile {
+        matrix: matrix.map(|row| row.map(|v| (v * scale) as f32)),
+        rms_error,
+    })
+}
+
+fn mul(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+fn invert(m: [[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+        let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det: f64 = (0..3).map(|j| m[0][j] * cofactor(j, 0)).sum();
+    (det.abs() > 1e-12).then(|| [0, 1, 2].map(|i| [0, 1, 2].map(|j| cofactor(i, j) / det)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_fit() {
+        // a camera seeing the chart through a known mix of channels
+        let seen = [[0.7, 0.2, 0.1], [0.1, 0.8, 0.1], [0.05, 0.25, 0.7]];
+        let correction = invert(seen).unwrap();
+        let samples: Vec<[f64; 3]> = PATCHES
+            .iter()
+            .map(|p| {
+                let linear = p.map(srgb_to_linear);
+                [0, 1, 2].map(|i| (0..3).map(|j| seen[i][j] * linear[j]).sum())
+            })
+            .collect();
+        let profile = fit(&samples).expect("fitted");
+        assert!(profile.rms_error < 1e-6);
+        let scale = 3.0 / correction.iter().flatten().sum::<f64>();
+        for (fitted, expected) in profile
+            .matrix
+            .iter()
+            .flatten()
+            .zip(correction.iter().flatten())
+        {
+            assert!((*fitted as f64 - expected * scale).abs() < 1e-4);
+        }
+        assert!(fit(&vec![[0.5; 3]; 24]).is_none());
+    }
+
+    #[test]
+    fn test_profile_color_checker() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let camera = raw_image.camera_matrix();
+        let params = ProcessParams {
+            half_size: true,
+            use_camera_wb: true,
+            ..Default::default()
+        };
+        // no chart in the test image, any patch of scene will do for a fit
+        let corners = [
+            [500.0, 500.0],
+            [3500.0, 500.0],
+            [3500.0, 2500.0],
+            [500.0, 2500.0],
+        ];
+        let profile = raw_image
+            .profile_color_checker(corners, &params)
+            .expect("profiled");
+        assert!(profile.matrix.iter().flatten().all(|v| v.is_finite()));
+        let sum: f32 = profile.matrix.iter().flatten().sum();
+        assert!((sum - 3.0).abs() < 1e-4);
+        assert_eq!(raw_image.camera_matrix(), camera);
+        assert_eq!(
+            ProcessParams::from_libraw(&raw_image.as_ref().params, &raw_image.as_ref().rawparams),
+            ProcessParams::default()
+        );
+        assert!(matches!(
+            raw_image.profile_color_checker(
+                [[0.0, 0.0], [9000.0, 0.0], [0.0, 10.0], [0.0, 0.0]],
+                &params
+            ),
+            Err(Error::BadCrop)
+        ));
+
+        raw_image.set_camera_matrix(profile.matrix);
+        assert_eq!(raw_image.camera_matrix(), profile.matrix);
+    }
+}
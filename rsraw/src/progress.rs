@@ -0,0 +1,150 @@
+use std::{
+    ffi::{c_int, c_void},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use rsraw_sys as sys;
+
+use crate::events::{DecodeEvent, DecodeEvents};
+
+pub(crate) type ProgressCallback = Box<dyn FnMut(ProgressStage, i32, i32) + Send>;
+
+// State behind LibRaw's progress callback. It lives in a `Box` owned by the
+// `RawImage`, so the pointer handed to LibRaw stays valid until close.
+// LibRaw only reports progress between coarse steps of unpack and process,
+// a single demosaic pass runs to completion before a check can fire.
+#[derive(Default)]
+pub(crate) struct Progress {
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) cancel: Option<CancellationToken>,
+    // LibRaw may report from inside its OpenMP regions
+    pub(crate) callback: Option<Mutex<ProgressCallback>>,
+    pub(crate) events: Option<DecodeEvents>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProgressStage {
+    Open,
+    Identify,
+    SizeAdjust,
+    LoadRaw,
+    Raw2Image,
+    RemoveZeroes,
+    BadPixels,
+    DarkFrame,
+    FoveonInterpolate,
+    ScaleColors,
+    PreInterpolate,
+    Interpolate,
+    MixGreen,
+    MedianFilter,
+    Highlights,
+    FujiRotate,
+    Flip,
+    ApplyProfile,
+    ConvertRgb,
+    Stretch,
+    ThumbLoad,
+    Other(u32),
+}
+
+// Shared flag to abort an in-flight unpack or process from another thread,
+// the decode fails with `Error::CancelledByCallback` at its next progress step.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl Progress {
+    pub(crate) unsafe fn install(self: &mut Box<Self>, raw_data: *mut sys::libraw_data_t) {
+        let data = self.as_mut() as *mut Self as *mut c_void;
+        sys::libraw_set_progress_handler(raw_data, Some(on_progress), data);
+    }
+
+    fn report(&self, stage: ProgressStage, iteration: i32, expected: i32) {
+        if let Some(callback) = &self.callback {
+            let mut callback = callback.lock().unwrap_or_else(|e| e.into_inner());
+            callback(stage, iteration, expected);
+        }
+        if let Some(events) = &self.events {
+            events.send(DecodeEvent::Progress {
+                stage,
+                iteration,
+                expected,
+            });
+        }
+    }
+
+    // a nonzero return makes LibRaw bail out with LIBRAW_CANCELLED_BY_CALLBACK
+    fn check(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl From<sys::LibRaw_progress> for ProgressStage {
+    fn from(stage: sys::LibRaw_progress) -> Self {
+        match stage {
+            sys::LibRaw_progress_LIBRAW_PROGRESS_OPEN => Self::Open,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_IDENTIFY => Self::Identify,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_SIZE_ADJUST => Self::SizeAdjust,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_LOAD_RAW => Self::LoadRaw,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_RAW2_IMAGE => Self::Raw2Image,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_REMOVE_ZEROES => Self::RemoveZeroes,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_BAD_PIXELS => Self::BadPixels,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_DARK_FRAME => Self::DarkFrame,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_FOVEON_INTERPOLATE => Self::FoveonInterpolate,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_SCALE_COLORS => Self::ScaleColors,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_PRE_INTERPOLATE => Self::PreInterpolate,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_INTERPOLATE => Self::Interpolate,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_MIX_GREEN => Self::MixGreen,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_MEDIAN_FILTER => Self::MedianFilter,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_HIGHLIGHTS => Self::Highlights,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_FUJI_ROTATE => Self::FujiRotate,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_FLIP => Self::Flip,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_APPLY_PROFILE => Self::ApplyProfile,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_CONVERT_RGB => Self::ConvertRgb,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_STRETCH => Self::Stretch,
+            sys::LibRaw_progress_LIBRAW_PROGRESS_THUMB_LOAD => Self::ThumbLoad,
+            other => Self::Other(other as _),
+        }
+    }
+}
+
+unsafe extern "C" fn on_progress(
+    data: *mut c_void,
+    stage: sys::LibRaw_progress,
+    iteration: c_int,
+    expected: c_int,
+) -> c_int {
+    let progress = &*(data as *const Progress);
+    // a panicking callback must not unwind into LibRaw, treat it as a cancel
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        progress.report(stage.into(), iteration, expected);
+        progress.check()
+    }))
+    .unwrap_or(true) as _
+}
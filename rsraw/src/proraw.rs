@@ -0,0 +1,293 @@
+// Apple ProRAW specifics: the semantic masks the iPhone segments each shot
+// into and the ProfileGainTableMap carrying its local tone mapping. Both are
+// DNG 1.6 tags other phones may write too, LibRaw reads neither.
+
+use crate::{
+    gain_map::map_index,
+    ifd::{FileIfd, FileTiff},
+    opcodes::Reader,
+    RawImage,
+};
+
+const SUBFILE_SEMANTIC_MASK: u32 = 0x10004;
+
+const TAG_IMAGE_WIDTH: u16 = 0x100;
+const TAG_IMAGE_LENGTH: u16 = 0x101;
+const TAG_BITS_PER_SAMPLE: u16 = 0x102;
+const TAG_COMPRESSION: u16 = 0x103;
+const TAG_STRIP_OFFSETS: u16 = 0x111;
+const TAG_ROWS_PER_STRIP: u16 = 0x116;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x117;
+const TAG_TILE_WIDTH: u16 = 0x142;
+const TAG_TILE_LENGTH: u16 = 0x143;
+const TAG_TILE_OFFSETS: u16 = 0x144;
+const TAG_TILE_BYTE_COUNTS: u16 = 0x145;
+const TAG_PROFILE_GAIN_TABLE_MAP: u16 = 0xcd2d;
+const TAG_SEMANTIC_NAME: u16 = 0xcd2e;
+const TAG_SEMANTIC_INSTANCE_ID: u16 = 0xcd30;
+const TAG_MASK_SUB_AREA: u16 = 0xcd38;
+
+const COMPRESSION_NONE: u32 = 1;
+const COMPRESSION_JPEG: u32 = 7;
+
+// masks are a fraction of the main image's resolution
+const MAX_MASK_PIXELS: usize = 64 << 20;
+
+// A DNG semantic mask: how much each pixel belongs to a class like "Skin",
+// "Sky", "Hair", "Teeth" or "Glasses" in Apple's ProRAWs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticMask {
+    pub name: String,
+    // tells masks of the same class apart, e.g. one per person
+    pub instance_id: String,
+    pub width: u32,
+    pub height: u32,
+    // top, left, bottom and right of the part of the main image the mask
+    // covers, `None` when it spans all of it
+    pub sub_area: Option<[u32; 4]>,
+    // 8 bit coverage, 255 fully belongs to the class, rows tightly packed
+    pub data: Vec<u8>,
+}
+
+// The DNG ProfileGainTableMap: gains varying with the position in the image
+// and the brightness of the pixel, applied to the linear camera profile RGB
+// before the tone curve. `gains` rows are `points_h` wide, each point holds a
+// table of `points_n` gains.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileGainTableMap {
+    pub points_v: u32,
+    pub points_h: u32,
+    // spacing and origin of the map points, relative to the image size
+    pub spacing_v: f64,
+    pub spacing_h: f64,
+    pub origin_v: f64,
+    pub origin_h: f64,
+    pub points_n: u32,
+    // weights of R, G, B, min(R, G, B) and max(R, G, B) making up the table input
+    pub input_weights: [f32; 5],
+    pub gains: Vec<f32>,
+}
+
+impl ProfileGainTableMap {
+    // the gain at a position given relative to the image size for a linear
+    // RGB pixel, interpolated between the map points and table entries around it
+    pub fn gain(&self, v: f64, h: f64, rgb: [f32; 3]) -> f32 {
+        let [r, g, b] = rgb;
+        let inputs = [r, g, b, r.min(g).min(b), r.max(g).max(b)];
+        let weighted: f32 = inputs
+            .iter()
+            .zip(self.input_weights)
+            .map(|(x, w)| x * w)
+            .sum();
+        let (rows, cols, n) = (
+            self.points_v as usize,
+            self.points_h as usize,
+            self.points_n as usize,
+        );
+        let (row, fv) = map_index(v, self.origin_v, self.spacing_v, rows);
+        let (col, fh) = map_index(h, self.origin_h, self.spacing_h, cols);
+        let spacing_n = 1.0 / n.saturating_sub(1).max(1) as f64;
+        let (entry, fn_) = map_index(weighted.clamp(0.0, 1.0) as f64, 0.0, spacing_n, n);
+        let at = |r: usize, c: usize| {
+            let (r, c) = (r.min(rows - 1), c.min(cols - 1));
+            let table = &self.gains[(r * cols + c) * n..][..n];
+            let (lo, hi) = (table[entry] as f64, table[(entry + 1).min(n - 1)] as f64);
+            lo + (hi - lo) * fn_
+        };
+        let top = at(row, col) + (at(row, col + 1) - at(row, col)) * fh;
+        let bottom = at(row + 1, col) + (at(row + 1, col + 1) - at(row + 1, col)) * fh;
+        (top + (bottom - top) * fv) as f32
+    }
+
+    // big endian like the opcode lists, whatever the file's byte order
+    fn parse(data: &[u8]) -> Option<Self> {
+        let mut r = Reader(data);
+        let mut map = Self {
+            points_v: r.u32()?,
+            points_h: r.u32()?,
+            spacing_v: r.f64()?,
+            spacing_h: r.f64()?,
+            origin_v: r.f64()?,
+            origin_h: r.f64()?,
+            points_n: r.u32()?,
+            input_weights: [r.f32()?, r.f32()?, r.f32()?, r.f32()?, r.f32()?],
+            gains: Vec::new(),
+        };
+        let count = (map.points_v as usize)
+            .checked_mul(map.points_h as usize)?
+            .checked_mul(map.points_n as usize)?;
+        if count == 0 || r.0.len() < count * 4 {
+            return None;
+        }
+        map.gains = (0..count).map(|_| r.f32()).collect::<Option<_>>()?;
+        Some(map)
+    }
+}
+
+impl RawImage {
+    // the semantic masks of a DNG, empty for other formats and masks stored
+    // with anything but no or JPEG compression
+    pub fn semantic_masks(&self) -> Vec<SemanticMask> {
+        if self.dng_version() == 0 {
+            return Vec::new();
+        }
+        let Some(tiff) = FileTiff::new(self) else {
+            return Vec::new();
+        };
+        tiff.ifds()
+            .iter()
+            .filter(|ifd| ifd.subfile_type() == SUBFILE_SEMANTIC_MASK)
+            .filter_map(|ifd| self.semantic_mask(&tiff, ifd))
+            .collect()
+    }
+
+    // IFD0 holds the camera profile the map belongs to, `None` without one
+    pub fn profile_gain_table_map(&self) -> Option<ProfileGainTableMap> {
+        if self.dng_version() == 0 {
+            return None;
+        }
+        let tiff = FileTiff::new(self)?;
+        let ifd0 = tiff.ifds().into_iter().next()?;
+        ProfileGainTableMap::parse(&tiff.data(ifd0.get(TAG_PROFILE_GAIN_TABLE_MAP)?)?)
+    }
+
+    fn semantic_mask(&self, tiff: &FileTiff, ifd: &FileIfd) -> Option<SemanticMask> {
+        let uints = |tag| tiff.u32s(ifd.get(tag)?);
+        let uint = |tag| uints(tag)?.first().copied();
+        let (width, height) = (uint(TAG_IMAGE_WIDTH)?, uint(TAG_IMAGE_LENGTH)?);
+        let compression = uint(TAG_COMPRESSION).unwrap_or(COMPRESSION_NONE);
+        if uint(TAG_BITS_PER_SAMPLE).unwrap_or(1) != 8
+            || !matches!(compression, COMPRESSION_NONE | COMPRESSION_JPEG)
+        {
+            return None;
+        }
+        // strips are tiles spanning the whole width
+        let rows_per_strip = uint(TAG_ROWS_PER_STRIP).unwrap_or(height).min(height);
+        let (tile, offsets, counts) = match uints(TAG_TILE_OFFSETS) {
+            Some(offsets) => (
+                (uint(TAG_TILE_WIDTH)?, uint(TAG_TILE_LENGTH)?),
+                offsets,
+                uints(TAG_TILE_BYTE_COUNTS)?,
+            ),
+            None => (
+                (width, rows_per_strip),
+                uints(TAG_STRIP_OFFSETS)?,
+                uints(TAG_STRIP_BYTE_COUNTS)?,
+            ),
+        };
+        let pixels = (width as usize).checked_mul(height as usize)?;
+        if pixels > MAX_MASK_PIXELS || tile.0 == 0 || tile.1 == 0 {
+            return None;
+        }
+        let mut data = vec![0; pixels];
+        let across = width.div_ceil(tile.0);
+        for (i, (&offset, &count)) in offsets.iter().zip(&counts).enumerate() {
+            if count as usize > MAX_MASK_PIXELS {
+                return None;
+            }
+            let chunk = self.read_at(offset as u64, count)?;
+            let pixels = match compression {
+                COMPRESSION_JPEG => decode_l8(&chunk)?,
+                _ => chunk,
+            };
+            let origin = (i as u32 % across * tile.0, i as u32 / across * tile.1);
+            place(&pixels, tile, origin, &mut data, (width, height));
+        }
+        Some(SemanticMask {
+            name: ifd.get(TAG_SEMANTIC_NAME).and_then(|e| tiff.ascii(e))?,
+            instance_id: ifd
+                .get(TAG_SEMANTIC_INSTANCE_ID)
+                .and_then(|e| tiff.ascii(e))
+                .unwrap_or_default(),
+            width,
+            height,
+            sub_area: uints(TAG_MASK_SUB_AREA).and_then(|v| v.try_into().ok()),
+            data,
+        })
+    }
+}
+
+// grayscale JPEG, whatever its size
+fn decode_l8(data: &[u8]) -> Option<Vec<u8>> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder.decode().ok()?;
+    match decoder.info()?.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => Some(pixels),
+        _ => None,
+    }
+}
+
+// copies a `tile` sized block to `origin` in an image of `size`, cropping
+// whatever hangs over its edges or is missing from a short tile
+fn place(tile: &[u8], (tw, th): (u32, u32), (x, y): (u32, u32), dst: &mut [u8], size: (u32, u32)) {
+    let (tw, x, width) = (tw as usize, x as usize, size.0 as usize);
+    let cols = tw.min(width.saturating_sub(x));
+    let rows = (th.min(size.1.saturating_sub(y)) as usize).min(tile.len() / tw);
+    for row in 0..rows {
+        let start = (y as usize + row) * width + x;
+        dst[start..start + cols].copy_from_slice(&tile[row * tw..][..cols]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    fn gain_table_params(weights: [f32; 5], gains: &[f32]) -> Vec<u8> {
+        let mut params = Vec::new();
+        for v in [2u32, 1] {
+            params.extend_from_slice(&v.to_be_bytes());
+        }
+        for v in [1.0f64, 1.0, 0.0, 0.0] {
+            params.extend_from_slice(&v.to_be_bytes());
+        }
+        params.extend_from_slice(&2u32.to_be_bytes());
+        for w in weights.into_iter().chain(gains.iter().copied()) {
+            params.extend_from_slice(&w.to_be_bytes());
+        }
+        params
+    }
+
+    #[test]
+    fn test_profile_gain_table_map() {
+        // two rows of one point, each a table from shadows to highlights
+        let max = [0.0, 0.0, 0.0, 0.0, 1.0];
+        let map = ProfileGainTableMap::parse(&gain_table_params(max, &[2.0, 1.0, 4.0, 2.0]))
+            .expect("valid");
+        assert_eq!((map.points_v, map.points_h, map.points_n), (2, 1, 2));
+        assert_eq!(map.gain(0.0, 0.3, [0.0; 3]), 2.0);
+        assert_eq!(map.gain(0.0, 0.3, [0.2, 1.0, 0.5]), 1.0);
+        assert_eq!(map.gain(1.0, 0.0, [0.5, 0.1, 0.1]), 3.0);
+        assert_eq!(map.gain(0.5, 0.0, [0.0; 3]), 3.0);
+        assert!(ProfileGainTableMap::parse(&gain_table_params(max, &[1.0; 3])).is_none());
+    }
+
+    #[test]
+    fn test_place() {
+        // a 5x3 image from 2x2 tiles, the right and bottom ones hang over
+        let mut dst = vec![0; 15];
+        for (i, origin) in [(0, 0), (2, 0), (4, 0), (0, 2), (2, 2), (4, 2)]
+            .into_iter()
+            .enumerate()
+        {
+            place(&[i as u8 + 1; 4], (2, 2), origin, &mut dst, (5, 3));
+        }
+        assert_eq!(dst, [1, 1, 2, 2, 3, 1, 1, 2, 2, 3, 4, 4, 5, 5, 6]);
+        // a short last strip
+        let mut dst = vec![0; 6];
+        place(&[7; 2], (2, 2), (0, 2), &mut dst, (2, 3));
+        assert_eq!(dst, [0, 0, 0, 0, 7, 7]);
+    }
+
+    #[test]
+    fn test_not_dng() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let image = RawImage::open_owned(data).unwrap();
+        assert!(image.semantic_masks().is_empty());
+        assert!(image.profile_gain_table_map().is_none());
+        assert!(image.noise_profile().is_none());
+        let tiff = FileTiff::new(&image).expect("tiff based");
+        assert!(tiff.ifds().iter().any(|ifd| ifd.subfile_type() == 0));
+    }
+}
@@ -1,12 +1,29 @@
-use std::borrow::Cow;
+#[cfg(any(feature = "fs", feature = "mmap"))]
+use std::path::Path;
+use std::{
+    borrow::Cow,
+    io::Write,
+    time::{Duration, Instant, SystemTime},
+};
 
+#[cfg(feature = "chrono")]
 use chrono::{DateTime, Local, TimeZone};
 use rsraw_sys as sys;
 
 use crate::{
-    err::{Error, Result},
-    processed::ProcessedImage,
-    GpsInfo, LensInfo, ThumbnailImage, Thumbnails,
+    borders::Borders,
+    data_errors::{DataErrorLog, DataErrors, PartialDecode},
+    datastream::Datastream,
+    decoder::{Decoder, DecoderInfo, DecoderPreference},
+    diagnose::{OpenDiagnosis, OpenFailure},
+    err::{ContextError, Error, ErrorStage, Result},
+    events::{DecodeEvent, DecodeEvents},
+    gain_map::Black,
+    processed::{ImageLayout, ProcessedImage},
+    progress::{CancellationToken, Progress, ProgressStage},
+    trace::{event, span},
+    GpsInfo, LensInfo, Metrics, OpenOptions, OutputColor, ProcessParams, RawMetadata, SizesInfo,
+    ThumbFormat, ThumbInfo, ThumbnailImage, Thumbnails, Warnings, Xmp,
 };
 
 pub type BitDepth = u32;
@@ -14,52 +31,864 @@ pub type BitDepth = u32;
 pub const BIT_DEPTH_8: BitDepth = 8;
 pub const BIT_DEPTH_16: BitDepth = 16;
 
+// evaluated when `process::<D>` is instantiated, so any other depth fails
+// the build instead of handing LibRaw a bogus `output_bps`
+struct CheckBitDepth<const D: BitDepth>;
+
+impl<const D: BitDepth> CheckBitDepth<D> {
+    const VALID: () = assert!(
+        D == BIT_DEPTH_8 || D == BIT_DEPTH_16,
+        "bit depth must be BIT_DEPTH_8 or BIT_DEPTH_16"
+    );
+}
+
+pub const DEFAULT_MEMORY_LIMIT_MB: u32 = 1024;
+
+type MemoryErrorHook = Box<dyn FnMut(&Error, &'static str) + Send>;
+
 pub struct RawImage {
     raw_data: *mut sys::libraw_data_t,
+    threads: usize,
+    pub(crate) metrics: Metrics,
+    memory_limit_mb: u32,
+    memory_error: Option<MemoryErrorHook>,
+    progress: Box<Progress>,
+    data_errors: Box<DataErrorLog>,
+    max_data_errors: Option<u32>,
+    // zero padded copy LibRaw reads from after `open_partial`, and how many
+    // bytes of it came from the file
+    padded: Option<(Vec<u8>, u64)>,
+    // the buffer `open_owned` and `open_mmap` keep, dropped after LibRaw is
+    // closed
+    pub(crate) owned: Option<Box<dyn AsRef<[u8]> + Send>>,
+    // what `open_stream` reads from, the image's fields drop after LibRaw is
+    // closed
+    pub(crate) datastream: Option<Datastream>,
+    // see `set_auto_crop`, the borders are found on unpack
+    pub(crate) auto_crop: bool,
+    pub(crate) dark_borders: Option<Borders>,
+    // the last params applied asked for it, see `ProcessParams::deterministic`
+    deterministic: bool,
+    // what the last process developed, LibRaw's own is reset by then, see
+    // `ProcessingLog::cropbox`
+    pub(crate) cropbox: Option<[u32; 4]>,
+    // see `load_xmp_sidecar`
+    pub(crate) sidecar: Option<Xmp>,
 }
 
-unsafe impl Sync for RawImage {}
-
+// Send only: every call, getters included, reads the shared libraw_data_t
+// that unpack and process write, so the image can't be used from two threads
+// at once. Share a `RawMetadata` snapshot instead.
 unsafe impl Send for RawImage {}
 
 impl RawImage {
-    pub fn open(buf: &[u8]) -> Result<Self> {
-        let raw_data = unsafe { sys::libraw_init(0) };
-        Error::check(unsafe {
+    // Safety: LibRaw reads `buf` again to unpack and to extract thumbnails,
+    // it has to outlive the image and stay unchanged. `open_owned` keeps it
+    // with the image instead.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open(buf: &[u8]) -> std::result::Result<Self, ContextError> {
+        Self::open_diagnosed(buf).map_err(ContextError::from)
+    }
+
+    // Like `open`, without a copy: the image takes `buf`, e.g. a `Vec<u8>`,
+    // an `Arc<[u8]>` or a memory map, and drops it after LibRaw is closed
+    pub fn open_owned(
+        buf: impl AsRef<[u8]> + Send + 'static,
+    ) -> std::result::Result<Self, ContextError> {
+        Self::open_owned_diagnosed(buf).map_err(ContextError::from)
+    }
+
+    // like `open`, but a failure says whether the buffer isn't a raw file at
+    // all, is cut short, or comes from a camera LibRaw can't decode. Safety
+    // as for `open`.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open_diagnosed(buf: &[u8]) -> std::result::Result<Self, OpenDiagnosis> {
+        Self::open_inner(buf, None, &OpenOptions::default())
+    }
+
+    pub fn open_owned_diagnosed(
+        buf: impl AsRef<[u8]> + Send + 'static,
+    ) -> std::result::Result<Self, OpenDiagnosis> {
+        Self::keep(buf, |buf| unsafe { Self::open_diagnosed(buf) })
+    }
+
+    // like `open`, with LibRaw's decoder tuned before it reads the headers.
+    // Safety as for `open`.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open_with(
+        buf: &[u8],
+        options: &OpenOptions,
+    ) -> std::result::Result<Self, ContextError> {
+        Self::open_inner(buf, None, options).map_err(ContextError::from)
+    }
+
+    pub fn open_owned_with(
+        buf: impl AsRef<[u8]> + Send + 'static,
+        options: &OpenOptions,
+    ) -> std::result::Result<Self, ContextError> {
+        Self::keep(buf, |buf| unsafe { Self::open_with(buf, options) })
+    }
+
+    // like `open`, and sends what open and every later unpack and process
+    // of the image are doing to `events`. Safety as for `open`.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn open_with_events(
+        buf: &[u8],
+        events: DecodeEvents,
+    ) -> std::result::Result<Self, ContextError> {
+        Self::open_inner(buf, Some(events), &OpenOptions::default()).map_err(ContextError::from)
+    }
+
+    pub fn open_owned_with_events(
+        buf: impl AsRef<[u8]> + Send + 'static,
+        events: DecodeEvents,
+    ) -> std::result::Result<Self, ContextError> {
+        Self::keep(buf, |buf| unsafe { Self::open_with_events(buf, events) })
+    }
+
+    // opens the bytes of `buf` with `open` and keeps `buf` with the image
+    pub(crate) fn keep<E>(
+        buf: impl AsRef<[u8]> + Send + 'static,
+        open: impl FnOnce(&[u8]) -> std::result::Result<Self, E>,
+    ) -> std::result::Result<Self, E> {
+        // boxed, so the bytes stay put however the image moves
+        let buf: Box<dyn AsRef<[u8]> + Send> = Box::new(buf);
+        let mut image = open((*buf).as_ref())?;
+        image.owned = Some(buf);
+        Ok(image)
+    }
+
+    pub(crate) fn open_inner(
+        buf: &[u8],
+        events: Option<DecodeEvents>,
+        options: &OpenOptions,
+    ) -> std::result::Result<Self, OpenDiagnosis> {
+        Self::open_source(buf.len() as _, events, options, |raw_data| unsafe {
+            sys::libraw_open_buffer(raw_data, buf.as_ptr() as *const _, buf.len())
+        })
+    }
+
+    // Reads the file as LibRaw needs it instead of all of it up front, open
+    // only touches the headers. The file is kept open until the image is
+    // dropped and mustn't change meanwhile.
+    #[cfg(feature = "fs")]
+    pub fn open_file(path: impl AsRef<Path>) -> std::result::Result<Self, ContextError> {
+        Self::open_file_with(path, &OpenOptions::default())
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn open_file_with(
+        path: impl AsRef<Path>,
+        options: &OpenOptions,
+    ) -> std::result::Result<Self, ContextError> {
+        let path = path.as_ref();
+        let len = std::fs::metadata(path)
+            .map_err(|err| ContextError::new(ErrorStage::Open, err.into()))?
+            .len();
+        #[cfg(unix)]
+        let result = {
+            use std::os::unix::ffi::OsStrExt;
+            let path = std::ffi::CString::new(path.as_os_str().as_bytes())
+                .map_err(|_| ContextError::new(ErrorStage::Open, Error::Unspecified))?;
+            Self::open_source(len, None, options, |raw_data| unsafe {
+                sys::libraw_open_file(raw_data, path.as_ptr())
+            })
+        };
+        #[cfg(windows)]
+        let result = {
+            use std::os::windows::ffi::OsStrExt;
+            let path: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+            Self::open_source(len, None, options, |raw_data| unsafe {
+                sys::libraw_open_wfile(raw_data, path.as_ptr() as *const _)
+            })
+        };
+        result.map_err(ContextError::from)
+    }
+
+    // Maps the file instead of reading it: LibRaw pulls in only the pages it
+    // touches and a later open of the same file finds them in the page cache.
+    // The mapping lives as long as the image. Like any mapping it must not be
+    // truncated or rewritten meanwhile, reads past a cut would fault.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap(path: impl AsRef<Path>) -> std::result::Result<Self, ContextError> {
+        let mapped = std::fs::File::open(path)
+            .and_then(|file| unsafe { memmap2::Mmap::map(&file) })
+            .map_err(|err| ContextError::new(ErrorStage::Open, err.into()))?;
+        Self::open_owned(mapped)
+    }
+
+    // `open` calls into LibRaw once the image is set up, `len` is the size of
+    // what it opens
+    pub(crate) fn open_source(
+        len: u64,
+        events: Option<DecodeEvents>,
+        options: &OpenOptions,
+        open: impl FnOnce(*mut sys::libraw_data_t) -> std::ffi::c_int,
+    ) -> std::result::Result<Self, OpenDiagnosis> {
+        span!("open", len);
+        let raw_data = unsafe { sys::libraw_init(options.init_flags()) };
+        if raw_data.is_null() {
+            return Err(OpenDiagnosis {
+                failure: OpenFailure::Other,
+                error: Box::new(ContextError::new(
+                    ErrorStage::Open,
+                    Error::UnsufficientMemory,
+                )),
+            });
+        }
+        let mut image = Self {
+            raw_data,
+            threads: 0,
+            metrics: Metrics::default(),
+            memory_limit_mb: options.memory_limit_mb.unwrap_or(DEFAULT_MEMORY_LIMIT_MB),
+            memory_error: None,
+            progress: Box::default(),
+            data_errors: Box::default(),
+            max_data_errors: None,
+            padded: None,
+            owned: None,
+            datastream: None,
+            auto_crop: false,
+            dark_borders: None,
+            deterministic: false,
+            cropbox: None,
+            sidecar: None,
+        };
+        unsafe {
+            image.progress.install(raw_data);
+            if !options.no_data_error_callback {
+                image.data_errors.install(raw_data);
+            }
+            #[cfg(feature = "gpr")]
+            sys::rsraw_gpr_attach(raw_data);
+        }
+        image.progress.events = events;
+        options.apply(unsafe { &mut (*raw_data).rawparams });
+        image.open_in_place(len, open)?;
+        if options.shot_select != 0 {
+            if let Err(err) = image.select_shot(options.shot_select) {
+                return Err(OpenDiagnosis::new(
+                    &image,
+                    len,
+                    image.context(ErrorStage::Open, err),
+                ));
+            }
+        }
+        Ok(image)
+    }
+
+    fn open_in_place(
+        &mut self,
+        len: u64,
+        open: impl FnOnce(*mut sys::libraw_data_t) -> std::ffi::c_int,
+    ) -> std::result::Result<(), OpenDiagnosis> {
+        let start = Instant::now();
+        let result = self.staged(ErrorStage::Open, |image| Error::check(open(image.raw_data)));
+        if let Err(err) = result {
+            return Err(OpenDiagnosis::new(self, len, err));
+        }
+        self.metrics.open = start.elapsed();
+        self.metrics.decoder = self.decoder_info().name;
+        event!(
+            make = %self.make(),
+            model = %self.model(),
+            width = self.width(),
+            height = self.height(),
+            decoder = %self.metrics.decoder,
+            elapsed = ?self.metrics.open,
+            "opened"
+        );
+        Ok(())
+    }
+
+    // Frees everything the file left in LibRaw and lets go of it, keeping
+    // the context with its settings: threads, limits, event and progress
+    // callbacks, params. The image is empty until `reuse` opens another.
+    pub fn recycle(&mut self) {
+        unsafe { sys::libraw_recycle(self.raw_data) };
+        self.owned = None;
+        self.padded = None;
+        self.datastream = None;
+        self.dark_borders = None;
+        self.sidecar = None;
+        self.cropbox = None;
+        self.metrics = Metrics::default();
+        self.data_errors.reset();
+    }
+
+    // `open` into this image's LibRaw context rather than a new one, for
+    // decoding file after file without setting up and tearing down LibRaw
+    // and its allocations every time. On failure the image is left empty, as
+    // after `recycle`. Safety: `buf` has to outlive the image as for `open`,
+    // or until the next `reuse` or `recycle`.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn reuse(&mut self, buf: &[u8]) -> Result<()> {
+        span!("open", len = buf.len());
+        self.recycle();
+        self.open_in_place(buf.len() as _, |raw_data| unsafe {
             sys::libraw_open_buffer(raw_data, buf.as_ptr() as *const _, buf.len())
-        })?;
-        Ok(Self { raw_data })
+        })
+        .map_err(Error::from)
+    }
+
+    // `reuse` keeping `buf` with the image, see `open_owned`
+    pub fn reuse_owned(&mut self, buf: impl AsRef<[u8]> + Send + 'static) -> Result<()> {
+        let buf: Box<dyn AsRef<[u8]> + Send> = Box::new(buf);
+        unsafe { self.reuse((*buf).as_ref())? };
+        self.owned = Some(buf);
+        Ok(())
+    }
+
+    // Opens a file that may be cut short, e.g. by a crashed tether or a card
+    // pulled mid-write. Missing image data is padded with zeros so unpack
+    // decodes whatever is there instead of failing, `partial` then tells how
+    // much of the frame is real. The image keeps `buf` as with `open_owned`.
+    pub fn open_partial(
+        buf: impl AsRef<[u8]> + Send + 'static,
+    ) -> std::result::Result<Self, ContextError> {
+        let mut image = Self::open_owned(buf)?;
+        let len = image
+            .owned
+            .as_deref()
+            .map_or(0, |buf| buf.as_ref().len() as u64);
+        let (offset, size) = image.data_segment();
+        // both come from the file's headers, a corrupt one mustn't have us
+        // allocate and zero gigabytes
+        let needed = match offset.checked_add(size) {
+            Some(needed) if needed <= len => return Ok(image),
+            Some(needed) => image.check_output_size("open", needed).map(|()| needed),
+            None => Err(Error::TooBig),
+        }
+        .map_err(|err| image.context(ErrorStage::Open, err))?;
+        let mut padded = Vec::new();
+        if padded.try_reserve_exact(needed as usize).is_err() {
+            return Err(image.context(ErrorStage::Open, Error::UnsufficientMemory));
+        }
+        if let Some(buf) = &image.owned {
+            padded.extend_from_slice((**buf).as_ref());
+        }
+        padded.resize(needed as usize, 0);
+        // the Vec's heap buffer doesn't move with it, LibRaw keeps reading from it
+        Error::check(unsafe {
+            sys::rsraw_swap_buffer(image.raw_data, padded.as_ptr() as *const _, padded.len())
+        })
+        .map_err(|err| image.context(ErrorStage::Open, err))?;
+        image.padded = Some((padded, len));
+        // LibRaw reads the padded copy from now on
+        image.owned = None;
+        Ok(image)
+    }
+
+    // `None` unless the image was opened with `open_partial` and data was
+    // missing. The row count is estimated from how much of the image data
+    // was present, compressed formats don't spread it evenly.
+    pub fn partial(&self) -> Option<PartialDecode> {
+        let (_, available) = self.padded.as_ref()?;
+        let (offset, size) = self.data_segment();
+        let present = available.saturating_sub(offset).min(size);
+        let rows = self.as_ref().sizes.raw_height as u32;
+        Some(PartialDecode {
+            rows,
+            rows_recovered: (rows as u64 * present / size.max(1)) as u32,
+        })
+    }
+
+    // LibRaw's idea of where the raw image data sits in the file, falling
+    // back to the unpacked size when the format doesn't record its length
+    pub(crate) fn data_segment(&self) -> (u64, u64) {
+        let (mut offset, mut size) = (0, 0);
+        unsafe { sys::rsraw_data_segment(self.raw_data, &mut offset, &mut size) };
+        let sizes = &self.as_ref().sizes;
+        let pitch = match sizes.raw_pitch {
+            0 => sizes.raw_width as u64 * 2,
+            pitch => pitch as u64,
+        };
+        let size = match size {
+            0 => pitch * sizes.raw_height as u64,
+            size => size as u64,
+        };
+        (offset.max(0) as u64, size)
+    }
+
+    // DNG: offset of the raw IFD's OpcodeList2, 0 without one
+    pub(crate) fn meta_offset(&self) -> u64 {
+        unsafe { sys::rsraw_meta_offset(self.raw_data) }.max(0) as u64
+    }
+
+    // `len` bytes of the file LibRaw has open, `None` past its end
+    pub(crate) fn read_at(&self, offset: u64, len: u32) -> Option<Vec<u8>> {
+        let mut buf = vec![0u8; len as usize];
+        let read = unsafe {
+            sys::rsraw_read_at(self.raw_data, offset as _, buf.as_mut_ptr() as *mut _, len)
+        };
+        (read == len as i32).then_some(buf)
+    }
+
+    // CR3 tracks as (media type, offset, size), empty for other formats
+    pub(crate) fn crx_tracks(&self) -> Vec<(u32, u64, u32)> {
+        let mut tracks = Vec::new();
+        for i in 0.. {
+            let (mut offset, mut size) = (0, 0);
+            match unsafe { sys::rsraw_crx_track(self.raw_data, i, &mut offset, &mut size) } {
+                0 => break,
+                kind => tracks.push((kind, offset.max(0) as u64, size)),
+            }
+        }
+        tracks
+    }
+
+    pub fn unpack(&mut self) -> std::result::Result<(), ContextError> {
+        self.unpack_with(DecoderPreference::Auto)
+    }
+
+    // Like `unpack` with the decoder to use, `Metrics::decoded_by` tells
+    // which one ran. When a forced decoder rejects the file LibRaw's own
+    // still unpacks it, but the call is `Error::FileUnsupported`. To compare
+    // two decoders open the file once for each.
+    pub fn unpack_with(
+        &mut self,
+        decoder: DecoderPreference,
+    ) -> std::result::Result<(), ContextError> {
+        decoder
+            .check()
+            .map_err(|err| self.context(ErrorStage::Unpack, err))?;
+        self.staged(ErrorStage::Unpack, |image| image.unpack_inner(decoder))
     }
 
-    pub fn unpack(&mut self) -> Result<()> {
+    fn unpack_inner(&mut self, decoder: DecoderPreference) -> Result<()> {
+        span!(
+            "unpack",
+            model = %self.model(),
+            width = self.width(),
+            height = self.height()
+        );
         unsafe {
             let raw_param = &mut (*self.raw_data).rawparams;
-            raw_param.use_rawspeed = 1;
-            raw_param.max_raw_memory_mb = 1024;
-            Error::check(sys::libraw_unpack(self.raw_data))
+            decoder.apply(raw_param);
+            raw_param.max_raw_memory_mb = self.memory_limit_mb;
         }
+        // warnings accumulate, so those of an earlier unpack are put aside
+        // to see which decoder this one ran
+        let processed = Warnings::RAWSPEED_PROCESSED.bits()
+            | Warnings::RAWSPEED3_PROCESSED.bits()
+            | Warnings::DNGSDK_PROCESSED.bits();
+        let before = self.warnings().bits();
+        unsafe { (*self.raw_data).process_warnings &= !processed };
+        let start = Instant::now();
+        let result =
+            self.with_threads(|raw_data| Error::check(unsafe { sys::libraw_unpack(raw_data) }));
+        let decoded_by = Decoder::from_warnings(self.warnings());
+        unsafe { (*self.raw_data).process_warnings |= before };
+        self.report("unpack", result)?;
+        self.metrics.decoded_by = Some(decoded_by);
+        if !decoder.allows(decoded_by) {
+            return Err(Error::FileUnsupported);
+        }
+        let errors = self.data_errors();
+        if self.max_data_errors.is_some_and(|max| errors.count > max) {
+            return Err(Error::Data);
+        }
+        self.metrics.unpack = Some(start.elapsed());
+        self.detect_borders_after_unpack()?;
+        self.record_memory(0);
+        event!(elapsed = ?self.metrics.unpack, "unpacked");
+        Ok(())
     }
 
-    pub fn extract_thumbs(&mut self) -> Result<Vec<ThumbnailImage>> {
+    // upper bound for any single buffer LibRaw allocates while unpacking and
+    // processing, and for the output bitmap. Exceeding it fails with
+    // `Error::TooBig` rather than letting a hostile file exhaust memory.
+    pub fn set_memory_limit_mb(&mut self, limit: u32) {
+        self.memory_limit_mb = limit;
+    }
+
+    pub fn memory_limit_mb(&self) -> u32 {
+        self.memory_limit_mb
+    }
+
+    // called with the error and the stage name whenever an allocation fails
+    // or the memory limit is hit, before the error is returned to the caller
+    pub fn on_memory_error(&mut self, hook: impl FnMut(&Error, &'static str) + Send + 'static) {
+        self.memory_error = Some(Box::new(hook));
+    }
+
+    fn report<T>(&mut self, stage: &'static str, result: Result<T>) -> Result<T> {
+        // LibRaw only sees a short read from a stream and may carry on, the
+        // source's error says what went wrong
+        let result = match self.datastream.as_mut().and_then(Datastream::take_error) {
+            Some(err) => Err(err.into()),
+            None => result,
+        };
+        if let (Err(err), Some(hook)) = (&result, &mut self.memory_error) {
+            if err.is_out_of_memory() {
+                hook(err, stage);
+            }
+        }
+        result
+    }
+
+    fn check_output_size(&mut self, stage: &'static str, bytes: u64) -> Result<()> {
+        if bytes > self.memory_limit_mb as u64 * 1024 * 1024 {
+            return self.report(stage, Err(Error::TooBig));
+        }
+        Ok(())
+    }
+
+    // corrupt data found so far, LibRaw keeps decoding past damaged sectors
+    pub fn data_errors(&self) -> DataErrors {
+        self.data_errors.get(self.raw_data)
+    }
+
+    // makes unpack fail with `Error::Data` once more than `max` damaged
+    // reads were counted, `None` accepts any amount of corruption
+    pub fn set_max_data_errors(&mut self, max: Option<u32>) {
+        self.max_data_errors = max;
+    }
+
+    // checked during unpack and process, replaces any previously set token
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.progress.cancel = token;
+    }
+
+    // called with the stage, the step within it and the expected number of
+    // steps whenever LibRaw reports progress during unpack and process
+    pub fn on_progress(&mut self, callback: impl FnMut(ProgressStage, i32, i32) + Send + 'static) {
+        self.progress.callback = Some(std::sync::Mutex::new(Box::new(callback)));
+    }
+
+    // replaces the receiver `open_with_events` set, `None` stops sending
+    pub fn set_decode_events(&mut self, events: Option<DecodeEvents>) {
+        self.progress.events = events;
+    }
+
+    // runs `stage`, telling the `DecodeEvents` receiver if there is one when
+    // it starts, which warnings it raised and how it ended, and puts the
+    // stage and camera on its error
+    fn staged<T>(
+        &mut self,
+        stage: ErrorStage,
+        run: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> std::result::Result<T, ContextError> {
+        let camera = self.camera();
+        let Some(events) = self.progress.events.clone() else {
+            return run(self).map_err(|err| self.context_with(stage, camera, err));
+        };
+        let warnings = self.warnings();
+        events.send(DecodeEvent::Started(stage));
+        let start = Instant::now();
+        let result = run(self);
+        let raised = self.warnings().bits() & !warnings.bits();
+        if raised != 0 {
+            events.send(DecodeEvent::Warning(Warnings::from_bits(raised)));
+        }
+        events.send(DecodeEvent::Finished {
+            stage,
+            elapsed: start.elapsed(),
+            error: result.as_ref().err().cloned(),
+        });
+        result.map_err(|err| self.context_with(stage, camera, err))
+    }
+
+    // what the error of a call on this image happened in, with the camera
+    // and, for io and data errors, the first damaged offset
+    pub(crate) fn context(&self, stage: ErrorStage, error: Error) -> ContextError {
+        self.context_with(stage, self.camera(), error)
+    }
+
+    // `camera` is taken before the call, LibRaw recycles the image on fatal
+    // errors and forgets make and model
+    fn context_with(
+        &self,
+        stage: ErrorStage,
+        (make, model): (Option<String>, Option<String>),
+        error: Error,
+    ) -> ContextError {
+        let offset = match error {
+            Error::Io | Error::Data => self.data_errors().first_offset,
+            _ => None,
+        };
+        ContextError {
+            stage,
+            make,
+            model,
+            offset,
+            error,
+        }
+    }
+
+    fn camera(&self) -> (Option<String>, Option<String>) {
+        let known = |s: Cow<'_, str>| (!s.is_empty()).then(|| s.into_owned());
+        (known(self.make()), known(self.model()))
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn warnings(&self) -> Warnings {
+        Warnings::from_bits(unsafe { (*self.raw_data).process_warnings })
+    }
+
+    pub fn decoder_info(&self) -> DecoderInfo {
+        let mut info = sys::libraw_decoder_info_t {
+            decoder_name: std::ptr::null(),
+            decoder_flags: 0,
+        };
+        let ret = unsafe { sys::libraw_get_decoder_info(self.raw_data, &mut info) };
+        if ret != 0 || info.decoder_name.is_null() {
+            return DecoderInfo {
+                name: String::new(),
+                flags: 0,
+            };
+        }
+        DecoderInfo {
+            name: unsafe { std::ffi::CStr::from_ptr(info.decoder_name) }
+                .to_string_lossy()
+                .into_owned(),
+            flags: info.decoder_flags as _,
+        }
+    }
+
+    // only known after unpack, LibRaw uses its own decoder whenever
+    // rawspeed isn't built in or rejects the file
+    pub fn decoded_by_rawspeed(&self) -> bool {
+        let warnings = self.warnings();
+        warnings.contains(Warnings::RAWSPEED_PROCESSED)
+            || warnings.contains(Warnings::RAWSPEED3_PROCESSED)
+    }
+
+    fn record_memory(&mut self, output: u64) {
+        let sizes = &self.as_ref().sizes;
+        let raw = match sizes.raw_pitch {
+            0 => sizes.raw_width as u64 * 2,
+            pitch => pitch as u64,
+        } * sizes.raw_height as u64;
+        let image = if self.as_ref().image.is_null() {
+            0
+        } else {
+            sizes.iwidth as u64 * sizes.iheight as u64 * 4 * 2
+        };
+        self.metrics.peak_memory = self.metrics.peak_memory.max(raw + image + output);
+    }
+
+    // Direct access to LibRaw's state for settings the safe API doesn't cover,
+    // e.g. fields of `params` or `rawparams` before unpack or process.
+    //
+    // Safety: `f` must leave every pointer, size and count as LibRaw set it:
+    // the raw mosaic, image and thumbnail buffers back the slices this type
+    // hands out, and `sizes` describes their layout. It must not install
+    // callbacks either, the progress and data error handlers point at state
+    // owned by `self`.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn with_raw_mut<R>(&mut self, f: impl FnOnce(&mut sys::libraw_data_t) -> R) -> R {
+        f(&mut *self.raw_data)
+    }
+
+    pub fn is_unpacked(&self) -> bool {
+        !self.as_ref().rawdata.raw_alloc.is_null()
+    }
+
+    // limits the threads LibRaw's OpenMP regions may use while this image
+    // unpacks and processes, 0 keeps the OpenMP default and 1 disables it.
+    // LibRaw only runs in parallel when built with the `openmp` feature.
+    pub fn set_max_threads(&mut self, threads: usize) {
+        self.threads = threads;
+    }
+
+    pub fn max_threads(&self) -> usize {
+        self.threads
+    }
+
+    #[cfg(feature = "openmp")]
+    fn with_threads<T>(&mut self, f: impl FnOnce(*mut sys::libraw_data_t) -> T) -> T {
+        let threads = match self.deterministic {
+            true => 1,
+            false => self.threads,
+        };
+        if threads == 0 {
+            return f(self.raw_data);
+        }
+        // the setting is per calling thread, restore it for whoever runs next
+        let previous = unsafe { sys::omp_get_max_threads() };
+        unsafe { sys::omp_set_num_threads(threads as _) };
+        let result = f(self.raw_data);
+        unsafe { sys::omp_set_num_threads(previous) };
+        result
+    }
+
+    #[cfg(not(feature = "openmp"))]
+    fn with_threads<T>(&mut self, f: impl FnOnce(*mut sys::libraw_data_t) -> T) -> T {
+        f(self.raw_data)
+    }
+
+    pub fn extract_thumbs(&mut self) -> std::result::Result<Vec<ThumbnailImage>, ContextError> {
+        span!(
+            "extract_thumbs",
+            model = %self.model(),
+            count = self.as_ref().thumbs_list.thumbcount
+        );
         let mut thumbs = Thumbnails::new();
         for i in 0..self.as_ref().thumbs_list.thumbcount {
-            Error::check(unsafe { sys::libraw_unpack_thumb_ex(self.raw_data, i) })?;
-            let thumb = &self.as_ref().thumbnail;
-            let thumb_image = ThumbnailImage {
-                format: thumb.tformat.into(),
-                width: thumb.twidth as _,
-                height: thumb.theight as _,
-                colors: thumb.tcolors as _,
-                data: unsafe {
-                    std::slice::from_raw_parts(thumb.thumb as *const u8, thumb.tlength as _)
-                        .to_vec()
-                },
-            };
-            thumbs.append(thumb_image);
+            thumbs.append(self.extract_thumb(i)?);
         }
         Ok(thumbs.into_inner())
     }
 
+    pub fn extract_thumb(
+        &mut self,
+        index: i32,
+    ) -> std::result::Result<ThumbnailImage, ContextError> {
+        let camera = self.camera();
+        self.extract_thumb_inner(index)
+            .map_err(|err| self.context_with(ErrorStage::Thumb(index), camera, err))
+    }
+
+    fn extract_thumb_inner(&mut self, index: i32) -> Result<ThumbnailImage> {
+        span!("extract_thumb", index);
+        let bytes = self.unpack_thumb(index)?;
+        let mut data = Vec::new();
+        if data.try_reserve_exact(bytes.len()).is_err() {
+            return self.report("extract_thumb", Err(Error::UnsufficientMemory));
+        }
+        data.extend_from_slice(bytes);
+        let thumb = &self.as_ref().thumbnail;
+        Ok(ThumbnailImage {
+            format: thumb.tformat.into(),
+            width: thumb.twidth as _,
+            height: thumb.theight as _,
+            colors: thumb.tcolors as _,
+            data,
+        })
+    }
+
+    // Like `extract_thumb`, writing the thumbnail's bytes to `out` as LibRaw
+    // holds them rather than copying them into a `ThumbnailImage`. Format
+    // and size are those of the unpacked thumbnail. Write failures are
+    // `Error::Fs`.
+    pub fn extract_thumb_to(
+        &mut self,
+        index: i32,
+        out: impl Write,
+    ) -> std::result::Result<ThumbInfo, ContextError> {
+        let camera = self.camera();
+        self.extract_thumb_to_inner(index, out)
+            .map_err(|err| self.context_with(ErrorStage::Thumb(index), camera, err))
+    }
+
+    fn extract_thumb_to_inner(&mut self, index: i32, mut out: impl Write) -> Result<ThumbInfo> {
+        span!("extract_thumb_to", index);
+        let bytes = self.unpack_thumb(index)?;
+        if let Err(err) = out.write_all(bytes) {
+            return self.report("extract_thumb_to", Err(err.into()));
+        }
+        let thumb = &self.as_ref().thumbnail;
+        let listed = self
+            .thumb_infos()
+            .into_iter()
+            .find(|info| info.index == index);
+        Ok(ThumbInfo {
+            index,
+            format: thumb.tformat.into(),
+            width: thumb.twidth as _,
+            height: thumb.theight as _,
+            flip: listed.as_ref().map_or(0, |info| info.flip),
+            length: thumb.tlength as _,
+            offset: listed.map_or(0, |info| info.offset),
+        })
+    }
+
+    // the thumbnail at `index` in LibRaw's buffer, valid until the next one
+    // is unpacked
+    fn unpack_thumb(&mut self, index: i32) -> Result<&[u8]> {
+        let result = Error::check(unsafe { sys::libraw_unpack_thumb_ex(self.raw_data, index) });
+        self.report("extract_thumb", result)?;
+        let thumb = &self.as_ref().thumbnail;
+        event!(
+            format = ?ThumbFormat::from(thumb.tformat),
+            width = thumb.twidth,
+            height = thumb.theight,
+            length = thumb.tlength,
+            "extracted thumbnail"
+        );
+        Ok(unsafe { std::slice::from_raw_parts(thumb.thumb as *const u8, thumb.tlength as _) })
+    }
+
+    pub fn thumb_infos(&self) -> Vec<ThumbInfo> {
+        let list = &self.as_ref().thumbs_list;
+        list.thumblist
+            .iter()
+            .take(list.thumbcount.max(0) as usize)
+            .enumerate()
+            .map(|(i, item)| ThumbInfo {
+                index: i as _,
+                format: ThumbFormat::from_internal(item.tformat),
+                width: item.twidth as _,
+                height: item.theight as _,
+                flip: item.tflip,
+                length: item.tlength,
+                offset: item.toffset,
+            })
+            .collect()
+    }
+
+    pub fn best_thumb_info(&self) -> Option<ThumbInfo> {
+        self.thumb_infos()
+            .into_iter()
+            .max_by_key(|info| (info.pixels(), info.length))
+    }
+
+    // the biggest embedded JPEG, usually a full resolution one on current
+    // bodies, read straight from the file without unpacking the raw data
+    pub fn largest_jpeg_preview(&mut self) -> std::result::Result<ThumbnailImage, ContextError> {
+        let info = self
+            .thumb_infos()
+            .into_iter()
+            .filter(|info| info.format == ThumbFormat::Jpeg)
+            .max_by_key(|info| (info.pixels(), info.length));
+        match info {
+            Some(info) => self.extract_thumb(info.index),
+            None => Err(self.context(ErrorStage::Thumb(0), Error::NoThumbnail)),
+        }
+    }
+
+    // The largest embedded preview as something displayable. With the `heif`
+    // feature H.265 previews are decoded to RGB, without it they are skipped
+    // for the next best one, so the result is never `ThumbFormat::H265`.
+    pub fn extract_best_thumb(&mut self) -> std::result::Result<ThumbnailImage, ContextError> {
+        self.best_thumb().map(|(_, thumb)| thumb)
+    }
+
+    // `extract_best_thumb` along with the info of the thumbnail it picked
+    pub(crate) fn best_thumb(
+        &mut self,
+    ) -> std::result::Result<(ThumbInfo, ThumbnailImage), ContextError> {
+        let mut infos = self.thumb_infos();
+        infos.sort_by_key(|info| std::cmp::Reverse((info.pixels(), info.length)));
+        let mut result = Err(self.context(ErrorStage::Thumb(0), Error::NoThumbnail));
+        for info in infos {
+            let stage = ErrorStage::Thumb(info.index);
+            // thumbs_list calls them JPEGs, only unpacking tells them apart
+            result = match self.extract_thumb(info.index) {
+                #[cfg(feature = "heif")]
+                Ok(thumb) if thumb.format == ThumbFormat::H265 => {
+                    crate::heif::decode_h265(&thumb).map_err(|err| self.context(stage, err))
+                }
+                Ok(thumb) if thumb.format == ThumbFormat::H265 => {
+                    Err(self.context(stage, Error::UnsupportedThumbnail))
+                }
+                result => result,
+            }
+            .map(|thumb| (info, thumb));
+            if result.is_ok() {
+                break;
+            }
+        }
+        result
+    }
+
+    // Everything from here to `raw_image` is filled in by `open` and only
+    // reads what LibRaw parsed from the headers, none of it needs `unpack`.
+
     pub fn width(&self) -> u32 {
         self.as_ref().sizes.width as _
     }
@@ -92,11 +921,23 @@ impl RawImage {
         self.as_ref().other.focal_len as _
     }
 
+    #[cfg(feature = "chrono")]
     pub fn datetime(&self) -> Option<DateTime<Local>> {
         let ts = self.as_ref().other.timestamp;
         Local.timestamp_opt(ts, 0).single()
     }
 
+    // capture time in seconds since the unix epoch, 0 when the file has none.
+    // LibRaw converts the camera's wall clock with the local time zone.
+    pub fn timestamp(&self) -> i64 {
+        self.as_ref().other.timestamp as _
+    }
+
+    pub fn capture_time(&self) -> Option<SystemTime> {
+        let ts = u64::try_from(self.timestamp()).ok().filter(|ts| *ts > 0)?;
+        SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(ts))
+    }
+
     pub fn gps(&self) -> GpsInfo {
         self.as_ref().other.parsed_gps.into()
     }
@@ -157,9 +998,43 @@ impl RawImage {
         (&self.as_ref().lens).into()
     }
 
-    pub fn raw_image<'img_buffer>(&self) -> &[u16] {
+    // width and height of the image `process` will produce with the current
+    // parameters, i.e. after half size, pixel aspect, Fuji rotation and
+    // orientation corrections, computed without touching any pixel data
+    pub fn sizes_only(&mut self) -> Result<(u32, u32)> {
+        let data = self.as_ref();
+        let saved = (data.sizes, data.color, data.idata, data.progress_flags);
+        let output = unsafe { sys::rsraw_output_params(self.raw_data) };
+        let saved_output = unsafe { *output };
+        let result = Error::check(unsafe { sys::libraw_adjust_sizes_info_only(self.raw_data) });
+        let adjusted = &self.as_ref().sizes;
+        let size = (adjusted.iwidth as u32, adjusted.iheight as u32);
+        // the call leaves LibRaw as if processing had started, with the Fuji
+        // width shrunk and the flip applied, undo that so a later unpack
+        // and process see the geometry open found
+        unsafe {
+            let data = &mut *self.raw_data;
+            (data.sizes, data.color, data.idata, data.progress_flags) = saved;
+            *output = saved_output;
+        }
+        result.map(|_| size)
+    }
+
+    // The metadata getters only read what open found and never unpack, the
+    // header makes that a type: it has them but no way to unpack or
+    // process, e.g. for an indexer that must stay cheap.
+    pub fn header(&mut self) -> RawHeader<'_> {
+        RawHeader { image: self }
+    }
+
+    // the bayer mosaic, empty before unpack and for sensors LibRaw delivers
+    // as full color pixels
+    pub fn raw_image(&self) -> &[u16] {
+        let ptr = self.as_ref().rawdata.raw_image;
+        if ptr.is_null() {
+            return &[];
+        }
         unsafe {
-            let ptr = self.as_ref().rawdata.raw_image;
             let w = self.as_ref().sizes.raw_width as usize;
             let h = self.as_ref().sizes.raw_height as usize;
 
@@ -168,6 +1043,59 @@ impl RawImage {
         }
     }
 
+    // The visible area of the unpacked mosaic with its black and white
+    // levels, what every reader of sensor values starts from.
+    // `Error::OutOfOrderCall` before `unpack`, `Error::NotImplemented` for
+    // files without a mosaic.
+    pub(crate) fn visible_mosaic(&self) -> Result<VisibleMosaic<'_>> {
+        if !self.is_unpacked() {
+            return Err(Error::OutOfOrderCall);
+        }
+        let data = self.as_ref();
+        if data.rawdata.raw_image.is_null() {
+            // linear DNGs and other full color layouts have no mosaic
+            return Err(Error::NotImplemented);
+        }
+        let sizes = &data.sizes;
+        Ok(VisibleMosaic {
+            mosaic: self.raw_image(),
+            pitch: sizes.raw_width as usize,
+            top: sizes.top_margin as usize,
+            left: sizes.left_margin as usize,
+            width: sizes.width as usize,
+            height: sizes.height as usize,
+            black: Black::new(&data.rawdata.color, data.rawdata.iparams.filters),
+            maximum: data.rawdata.color.maximum,
+        })
+    }
+
+    // the interleaved samples and samples per pixel, 3 or 4 of which the
+    // first `colors` are used, of an unpacked `is_linear_raw` image, rows of
+    // `raw_width` margins included. Empty for mosaics and before unpack.
+    pub fn linear_image(&self) -> (&[u16], usize) {
+        let raw = &self.as_ref().rawdata;
+        let (ptr, channels) = match (raw.color3_image.is_null(), raw.color4_image.is_null()) {
+            (false, _) => (raw.color3_image as *const u16, 3),
+            (_, false) => (raw.color4_image as *const u16, 4),
+            _ => return (&[], 0),
+        };
+        let sizes = &self.as_ref().sizes;
+        let pitch = match sizes.raw_pitch {
+            0 => sizes.raw_width as usize * channels,
+            pitch => pitch as usize / 2,
+        };
+        let len = pitch * sizes.raw_height as usize;
+        (unsafe { std::slice::from_raw_parts(ptr, len) }, channels)
+    }
+
+    // Full color pixels without a CFA: linear DNGs from converters and
+    // scanners, scan backs, multi-shot backs. Known after open. Processing
+    // skips the demosaic and only applies white balance, the color matrix
+    // and the curve; `raw_image` stays empty, see `linear_image`.
+    pub fn is_linear_raw(&self) -> bool {
+        self.filters() == 0 && self.colors() >= 3
+    }
+
     pub fn filters(&self) -> u32 {
         self.as_ref().rawdata.iparams.filters
     }
@@ -178,6 +1106,21 @@ impl RawImage {
         }
     }
 
+    // the camera body's serial number, empty if the makernotes don't have one
+    pub fn body_serial(&self) -> Cow<'_, str> {
+        unsafe {
+            std::ffi::CStr::from_ptr(&self.as_ref().shootinginfo.BodySerial as *const _)
+                .to_string_lossy()
+        }
+    }
+
+    // the vendor's drive mode code (single, continuous, self timer...) as
+    // LibRaw parses it from the makernotes, -1 when unknown. The numbers mean
+    // different things for different makes.
+    pub fn drive_mode(&self) -> i16 {
+        self.as_ref().shootinginfo.DriveMode
+    }
+
     pub fn full_info(&self) -> FullRawInfo {
         FullRawInfo {
             width: self.width(),
@@ -187,7 +1130,10 @@ impl RawImage {
             shutter: self.shutter(),
             aperture: self.aperture(),
             focal_len: self.focal_len(),
+            #[cfg(feature = "chrono")]
             datetime: self.datetime(),
+            #[cfg(not(feature = "chrono"))]
+            timestamp: self.timestamp(),
             gps: self.gps(),
             artist: self.artist().to_string(),
             desc: self.desc().trim().into(),
@@ -202,21 +1148,262 @@ impl RawImage {
         }
     }
 
-    pub fn process<const D: BitDepth>(&mut self) -> Result<ProcessedImage<D>> {
-        debug_assert!(D == BIT_DEPTH_8 || D == BIT_DEPTH_16);
-        unsafe { (*self.raw_data).params.output_bps = D as i32 };
-        Error::check(unsafe { sys::libraw_dcraw_process(self.raw_data) })?;
+    pub fn process<const D: BitDepth>(
+        &mut self,
+    ) -> std::result::Result<ProcessedImage<D>, ContextError> {
+        self.staged(ErrorStage::Process, Self::process_inner::<D>)
+    }
+
+    fn process_inner<const D: BitDepth>(&mut self) -> Result<ProcessedImage<D>> {
+        let () = CheckBitDepth::<D>::VALID;
+        span!(
+            "process",
+            model = %self.model(),
+            width = self.width(),
+            height = self.height(),
+            bits = D
+        );
+        let start = Instant::now();
+        self.dcraw_process(D)?;
+        let layout = self.mem_image_layout();
+        self.check_output_size("process", layout.len() as _)?;
 
         let mut result = 0i32;
         let processed = unsafe { sys::libraw_dcraw_make_mem_image(self.raw_data, &mut result) };
-        Error::check(result)?;
-        Ok(unsafe { ProcessedImage::from_raw(processed) })
+        self.report("process", Error::check(result))?;
+        let output_color =
+            OutputColor::from_libraw(unsafe { (*self.raw_data).params.output_color });
+        self.metrics.process = Some(start.elapsed());
+        self.record_memory(unsafe { (*processed).data_size } as _);
+        let log = self.processing_log();
+        let image = unsafe { ProcessedImage::<D>::from_raw(processed, output_color, log) };
+        event!(elapsed = ?self.metrics.process, "processed");
+        Ok(image)
+    }
+
+    // gives up with `Error::CancelledByCallback` once `timeout` has passed,
+    // checked whenever LibRaw reports progress between processing steps
+    pub fn process_with_deadline<const D: BitDepth>(
+        &mut self,
+        timeout: Duration,
+    ) -> std::result::Result<ProcessedImage<D>, ContextError> {
+        self.progress.deadline = Some(Instant::now() + timeout);
+        let result = self.process::<D>();
+        self.progress.deadline = None;
+        result
+    }
+
+    pub fn process_with<const D: BitDepth>(
+        &mut self,
+        params: &ProcessParams,
+    ) -> std::result::Result<ProcessedImage<D>, ContextError> {
+        self.apply_params(params)
+            .map_err(|err| self.context(ErrorStage::Process, err))?;
+        self.process::<D>()
+    }
+
+    // `process_with` on the part of the visible area `cropbox` (left, top,
+    // width, height) covers, see `process_region`
+    pub(crate) fn process_cropped<const D: BitDepth>(
+        &mut self,
+        params: &ProcessParams,
+        cropbox: [u32; 4],
+    ) -> std::result::Result<ProcessedImage<D>, ContextError> {
+        self.apply_params(params)
+            .map_err(|err| self.context(ErrorStage::Process, err))?;
+        unsafe { (*self.raw_data).params.cropbox = cropbox };
+        let result = self.process::<D>();
+        // LibRaw's default, the whole frame
+        unsafe { (*self.raw_data).params.cropbox = [0, 0, u32::MAX, u32::MAX] };
+        result
+    }
+
+    // like `process`, but writes the bitmap into `buf` instead of a fresh
+    // LibRaw allocation. A buffer that already has the right length is
+    // overwritten in place, see `BufferPool` for recycling them.
+    pub fn process_into<const D: BitDepth>(
+        &mut self,
+        buf: &mut Vec<u8>,
+    ) -> std::result::Result<ImageLayout, ContextError> {
+        self.staged(ErrorStage::Process, |image| {
+            image.process_into_inner::<D>(buf)
+        })
+    }
+
+    fn process_into_inner<const D: BitDepth>(&mut self, buf: &mut Vec<u8>) -> Result<ImageLayout> {
+        let () = CheckBitDepth::<D>::VALID;
+        span!(
+            "process_into",
+            model = %self.model(),
+            width = self.width(),
+            height = self.height(),
+            bits = D
+        );
+        let start = Instant::now();
+        self.dcraw_process(D)?;
+        let layout = self.mem_image_layout();
+        self.check_output_size("process", layout.len() as _)?;
+
+        if buf
+            .try_reserve_exact(layout.len().saturating_sub(buf.len()))
+            .is_err()
+        {
+            return self.report("process", Err(Error::UnsufficientMemory));
+        }
+        buf.resize(layout.len(), 0);
+        let result = Error::check(unsafe {
+            sys::rsraw_copy_mem_image(
+                self.raw_data,
+                buf.as_mut_ptr() as *mut _,
+                layout.stride() as _,
+                0,
+            )
+        });
+        self.report("process", result)?;
+        self.metrics.process = Some(start.elapsed());
+        self.record_memory(layout.len() as _);
+        event!(elapsed = ?self.metrics.process, "processed");
+        Ok(layout)
+    }
+
+    pub fn process_into_with<const D: BitDepth>(
+        &mut self,
+        params: &ProcessParams,
+        buf: &mut Vec<u8>,
+    ) -> std::result::Result<ImageLayout, ContextError> {
+        self.apply_params(params)
+            .map_err(|err| self.context(ErrorStage::Process, err))?;
+        self.process_into::<D>(buf)
+    }
+
+    fn apply_params(&mut self, params: &ProcessParams) -> Result<()> {
+        if let Some(index) = params.shot_select {
+            self.select_shot(index)?;
+        }
+        params.apply(unsafe { &mut (*self.raw_data).params });
+        self.deterministic = params.deterministic;
+        Ok(())
+    }
+
+    // unpacks first if the caller hasn't, processing needs the raw data
+    fn dcraw_process(&mut self, bit_depth: BitDepth) -> Result<()> {
+        if !self.is_unpacked() {
+            self.unpack()?;
+        }
+        unsafe {
+            (*self.raw_data).params.output_bps = bit_depth as i32;
+            (*self.raw_data).rawparams.max_raw_memory_mb = self.memory_limit_mb;
+        }
+        // auto crop only stands in for a cropbox the caller didn't set
+        let full = [0, 0, u32::MAX, u32::MAX];
+        let crop = match self.dark_borders {
+            Some(borders) if !borders.is_empty() && self.as_ref().params.cropbox == full => {
+                Some(borders.inner(self.width(), self.height()))
+            }
+            _ => None,
+        };
+        if let Some(cropbox) = crop {
+            unsafe { (*self.raw_data).params.cropbox = cropbox };
+        }
+        let cropbox = self.as_ref().params.cropbox;
+        self.cropbox = (cropbox != full).then_some(cropbox);
+        let result = self
+            .with_threads(|raw_data| Error::check(unsafe { sys::libraw_dcraw_process(raw_data) }));
+        if crop.is_some() {
+            unsafe { (*self.raw_data).params.cropbox = full };
+        }
+        self.report("process", result)
+    }
+
+    fn mem_image_layout(&self) -> ImageLayout {
+        let (mut width, mut height, mut colors, mut bits) = (0, 0, 0, 0);
+        unsafe {
+            sys::rsraw_get_mem_image_format(
+                self.raw_data,
+                &mut width,
+                &mut height,
+                &mut colors,
+                &mut bits,
+            )
+        };
+        ImageLayout {
+            width: width as _,
+            height: height as _,
+            colors: colors as _,
+            bits: bits as _,
+        }
+    }
+}
+
+// see `RawImage::header`
+pub struct RawHeader<'a> {
+    image: &'a mut RawImage,
+}
+
+impl RawHeader<'_> {
+    pub fn full_info(&self) -> FullRawInfo {
+        self.image.full_info()
+    }
+
+    pub fn lens_info(&self) -> LensInfo {
+        self.image.lens_info()
+    }
+
+    pub fn metadata(&self) -> RawMetadata {
+        self.image.metadata()
+    }
+
+    pub fn sizes(&self) -> SizesInfo {
+        self.image.sizes()
+    }
+
+    pub fn thumb_infos(&self) -> Vec<ThumbInfo> {
+        self.image.thumb_infos()
+    }
+
+    pub fn sizes_only(&mut self) -> Result<(u32, u32)> {
+        self.image.sizes_only()
+    }
+}
+
+// see `RawImage::visible_mosaic`, rows and columns count from the visible
+// top left like `Black::at`
+pub(crate) struct VisibleMosaic<'a> {
+    pub mosaic: &'a [u16],
+    pub pitch: usize,
+    pub top: usize,
+    pub left: usize,
+    pub width: usize,
+    pub height: usize,
+    pub black: Black,
+    pub maximum: u32,
+}
+
+impl VisibleMosaic<'_> {
+    pub fn row(&self, row: usize) -> &[u16] {
+        &self.mosaic[(self.top + row) * self.pitch + self.left..][..self.width]
+    }
+
+    // row by row, black level off and scaled by the range above it, both of
+    // the position's own CFA color
+    pub fn normalized(&self) -> impl Iterator<Item = f32> + '_ {
+        let white = self.maximum as f32;
+        (0..self.height).flat_map(move |row| {
+            self.row(row).iter().enumerate().map(move |(col, &v)| {
+                let black = self.black.at(row, col) as f32;
+                (v as f32 - black) / (white - black).max(1.0)
+            })
+        })
     }
 }
 
 impl Drop for RawImage {
     fn drop(&mut self) {
-        unsafe { sys::libraw_close(self.raw_data) }
+        unsafe {
+            #[cfg(feature = "gpr")]
+            sys::rsraw_gpr_detach(self.raw_data);
+            sys::libraw_close(self.raw_data)
+        }
     }
 }
 
@@ -226,13 +1413,8 @@ impl AsRef<sys::libraw_data_t> for RawImage {
     }
 }
 
-impl AsMut<sys::libraw_data_t> for RawImage {
-    fn as_mut(&mut self) -> &mut sys::libraw_data_t {
-        unsafe { &mut *self.raw_data }
-    }
-}
-
-#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FullRawInfo {
     pub width: u32,
     pub height: u32,
@@ -241,7 +1423,11 @@ pub struct FullRawInfo {
     pub shutter: f32,
     pub aperture: f32,
     pub focal_len: f32,
+    #[cfg(feature = "chrono")]
     pub datetime: Option<DateTime<Local>>,
+    // seconds since the unix epoch, 0 when unknown, like `RawImage::timestamp`
+    #[cfg(not(feature = "chrono"))]
+    pub timestamp: i64,
     pub gps: GpsInfo,
     pub artist: String,
     pub desc: String,
@@ -255,10 +1441,21 @@ pub struct FullRawInfo {
     pub lens_info: LensInfo,
 }
 
+impl FullRawInfo {
+    // like `RawImage::timestamp`, with or without the chrono feature
+    pub fn timestamp(&self) -> i64 {
+        #[cfg(feature = "chrono")]
+        return self.datetime.map_or(0, |dt| dt.timestamp());
+        #[cfg(not(feature = "chrono"))]
+        self.timestamp
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::path::PathBuf;
 
+    use chrono::{Local, TimeZone};
     use rsraw_sys::{
         LibRaw_camera_mounts_LIBRAW_MOUNT_Nikon_Z, LibRaw_camera_mounts_LIBRAW_MOUNT_Sony_E,
     };
@@ -266,13 +1463,49 @@ mod tests {
     use super::*;
     use crate::{lens::FocusType, processed::ImageFormat, Mounts};
 
-    fn get_test_assets_path() -> PathBuf {
+    pub(crate) fn get_test_assets_path() -> PathBuf {
         let root: PathBuf = std::env::var_os("CARGO_MANIFEST_DIR")
             .expect("must get manifest dir")
             .into();
         root.join("tests/assets")
     }
 
+    // a directory of the test's own under the system's, removed again when
+    // dropped, also when the test fails
+    #[cfg(feature = "fs")]
+    pub(crate) struct TempDir(PathBuf);
+
+    #[cfg(feature = "fs")]
+    impl TempDir {
+        pub(crate) fn new(name: &str) -> Self {
+            use std::sync::atomic::{AtomicUsize, Ordering};
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "rsraw-{name}-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).expect("temp dir");
+            Self(path)
+        }
+
+        pub(crate) fn path(&self) -> &Path {
+            &self.0
+        }
+
+        pub(crate) fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
     #[test]
     fn test_raw_metadata() {
         let assets = get_test_assets_path();
@@ -288,7 +1521,13 @@ mod tests {
                     shutter: 1.0 / 100.0,
                     aperture: 3.5,
                     focal_len: 105.,
+                    #[cfg(feature = "chrono")]
                     datetime: Local.with_ymd_and_hms(2024, 11, 4, 20, 11, 38).single(),
+                    #[cfg(not(feature = "chrono"))]
+                    timestamp: Local
+                        .with_ymd_and_hms(2024, 11, 4, 20, 11, 38)
+                        .unwrap()
+                        .timestamp(),
                     gps: Default::default(),
                     artist: "HEXILEE".into(),
                     desc: "".into(),
@@ -326,7 +1565,13 @@ mod tests {
                     shutter: 1.0 / 500.0,
                     aperture: 4.0,
                     focal_len: 40.,
+                    #[cfg(feature = "chrono")]
                     datetime: Local.with_ymd_and_hms(2023, 11, 17, 13, 0, 13).single(),
+                    #[cfg(not(feature = "chrono"))]
+                    timestamp: Local
+                        .with_ymd_and_hms(2023, 11, 17, 13, 0, 13)
+                        .unwrap()
+                        .timestamp(),
                     gps: Default::default(),
                     artist: "hexilee".into(),
                     desc: "".into(),
@@ -358,13 +1603,123 @@ mod tests {
         for (file, expected) in test_cases {
             let path = assets.join(file);
             let data = std::fs::read(path).unwrap();
-            let raw_image = RawImage::open(&data).expect("opened");
-            assert_eq!(raw_image.raw_data.is_null(), false);
+            let raw_image = RawImage::open_owned(data).expect("opened");
+            assert!(!raw_image.raw_data.is_null());
             let full_info = raw_image.full_info();
             assert_eq!(full_info, expected);
+            let capture_time = raw_image.capture_time().expect("has a capture time");
+            assert_eq!(
+                capture_time
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+                expected.timestamp()
+            );
         }
     }
 
+    #[test]
+    fn test_metadata_without_unpack() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        assert_eq!(raw_image.full_info().model, "Z 8");
+        assert!(!raw_image.is_unpacked());
+
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        unsafe { raw_image.with_raw_mut(|raw_data| params.apply(&mut raw_data.params)) };
+        let size = raw_image.sizes_only().expect("sizes");
+        assert!(!raw_image.is_unpacked());
+        assert!(raw_image.raw_image().is_empty());
+
+        // process unpacks on its own
+        let image = raw_image.process::<BIT_DEPTH_8>().expect("processed");
+        assert_eq!(size, (image.width(), image.height()));
+        assert!(raw_image.is_unpacked());
+        assert_eq!(raw_image.raw_image().len(), 8280 * 5520);
+    }
+
+    #[test]
+    fn test_sizes_only_restores() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        unsafe { raw_image.with_raw_mut(|raw_data| params.apply(&mut raw_data.params)) };
+        // what open leaves for a Fuji rotated sensor, the Z 8 has none
+        let output = unsafe { sys::rsraw_output_params(raw_image.raw_data) };
+        unsafe {
+            (*output).fuji_width = 4000;
+            (*raw_image.raw_data).rawdata.ioparams.fuji_width = 4000;
+        }
+        let before = (unsafe { *output }, raw_image.sizes());
+        let mut header = raw_image.header();
+        let rotated = header.sizes_only().expect("sizes");
+        assert_eq!(header.sizes_only().expect("sizes"), rotated);
+        assert_eq!(header.full_info().model, "Z 8");
+        // shrunk to 2000 for half size, then turned by 45 degrees
+        assert!(rotated.0 == 2828 || rotated.1 == 2828, "{rotated:?}");
+        assert_eq!((unsafe { *output }, raw_image.sizes()), before);
+        assert!(!raw_image.is_unpacked());
+
+        unsafe {
+            (*output).fuji_width = 0;
+            (*raw_image.raw_data).rawdata.ioparams.fuji_width = 0;
+        }
+        let size = raw_image.sizes_only().expect("sizes");
+        let image = raw_image.process::<BIT_DEPTH_8>().expect("processed");
+        assert_eq!(size, (image.width(), image.height()));
+    }
+
+    #[test]
+    fn test_largest_jpeg_preview() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let jpeg = raw_image.largest_jpeg_preview().expect("jpeg preview");
+        assert_eq!(jpeg.format, ThumbFormat::Jpeg);
+        assert_eq!((jpeg.width, jpeg.height), (8256, 5504));
+        assert_eq!(jpeg.data[..2], [0xff, 0xd8]);
+        assert!(!raw_image.is_unpacked());
+    }
+
+    #[test]
+    fn test_extract_best_thumb() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let thumb = raw_image.extract_best_thumb().expect("best thumb");
+        assert_eq!(thumb.format, ThumbFormat::Jpeg);
+        assert_eq!((thumb.width, thumb.height), (8256, 5504));
+    }
+
+    #[test]
+    fn test_extract_thumb_to() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let best = raw_image.best_thumb_info().expect("listed");
+        let mut out = Vec::new();
+        let info = raw_image
+            .extract_thumb_to(best.index, &mut out)
+            .expect("written");
+        assert_eq!(info.format, ThumbFormat::Jpeg);
+        assert_eq!(info.length as usize, out.len());
+        assert_eq!(
+            out,
+            raw_image.extract_thumb(best.index).expect("thumb").data
+        );
+
+        let mut full = [0u8; 16];
+        assert!(matches!(
+            raw_image
+                .extract_thumb_to(best.index, &mut full[..])
+                .map_err(Error::from),
+            Err(Error::Fs(_))
+        ));
+    }
+
     #[test]
     fn test_thumbnails() {
         let assets = get_test_assets_path();
@@ -373,12 +1728,338 @@ mod tests {
             let path = assets.join(file);
             println!("{path:?}");
             let data = std::fs::read(path).unwrap();
-            let mut raw_image = RawImage::open(&data).expect("opened");
+            let mut raw_image = RawImage::open_owned(data).expect("opened");
             let thumbs = raw_image.extract_thumbs().expect("extracted");
             println!("{:?}", thumbs);
         }
     }
 
+    #[test]
+    fn test_memory_limit() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        let stages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = stages.clone();
+        raw_image.on_memory_error(move |err, stage| {
+            seen.lock().unwrap().push((err.repr(), stage));
+        });
+        raw_image.set_memory_limit_mb(16);
+        assert!(matches!(
+            raw_image.unpack().map_err(Error::from),
+            Err(Error::TooBig)
+        ));
+        assert_eq!(*stages.lock().unwrap(), [("TooBig", "unpack")]);
+
+        // the raw mosaic fits, the 16 bit working image and bitmap don't
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let seen = stages.clone();
+        raw_image.on_memory_error(move |err, stage| {
+            seen.lock().unwrap().push((err.repr(), stage));
+        });
+        raw_image.set_memory_limit_mb(128);
+        raw_image.unpack().expect("unpacked");
+        assert!(matches!(
+            raw_image.process::<BIT_DEPTH_16>().map_err(Error::from),
+            Err(Error::TooBig)
+        ));
+        assert_eq!(stages.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_data_errors() {
+        let mut data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        assert!(raw_image.data_errors().is_empty());
+
+        let mut raw_image =
+            RawImage::open_owned(data[..data.len() * 3 / 4].to_vec()).expect("opened");
+        let err = raw_image.unpack().unwrap_err();
+        assert!(raw_image.data_errors().eof);
+        assert_eq!(err.stage, ErrorStage::Unpack);
+        assert_eq!(err.make.as_deref(), Some("Nikon"));
+        assert_eq!(err.model.as_deref(), Some("Z 8"));
+        assert_eq!(err.to_string(), "unpack failed for Nikon Z 8");
+        assert_eq!(err.code(), Some(sys::LibRaw_errors_LIBRAW_IO_ERROR as _));
+        assert!(matches!(
+            std::error::Error::source(&err).and_then(|e| e.downcast_ref()),
+            Some(Error::Io)
+        ));
+
+        // scribble over part of the compressed image data
+        let middle = data.len() / 2;
+        for (i, b) in data[middle..middle + 4096].iter_mut().enumerate() {
+            *b = (i * 7919 % 251) as u8;
+        }
+        let mut raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        raw_image.unpack().expect("decodes past the damage");
+        let errors = raw_image.data_errors();
+        assert!(errors.count > 0 && errors.first_offset.is_some());
+
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        raw_image.set_max_data_errors(Some(0));
+        let err = raw_image.unpack().unwrap_err();
+        assert!(matches!(err.error, Error::Data));
+        assert_eq!(err.offset, errors.first_offset);
+        assert!(err
+            .to_string()
+            .starts_with("unpack failed for Nikon Z 8 at byte "));
+    }
+
+    #[test]
+    fn test_open_partial() {
+        let data = std::fs::read(get_test_assets_path().join("test-a7rm4.ARW")).unwrap();
+        let raw_image = RawImage::open_partial(data.clone()).expect("opened");
+        assert!(raw_image.partial().is_none());
+
+        let mut raw_image =
+            RawImage::open_partial(data[..data.len() * 3 / 4].to_vec()).expect("opened");
+        raw_image.unpack().expect("unpacked what's there");
+        let partial = raw_image.partial().expect("data was missing");
+        assert_eq!(partial.rows, 6376);
+        assert!((4600..4800).contains(&partial.rows_recovered));
+
+        // the first missing rows decode as zeros
+        let width = raw_image.as_ref().sizes.raw_width as usize;
+        let row = partial.rows_recovered as usize + 20;
+        assert!(raw_image.raw_image()[row * width..][..width]
+            .iter()
+            .all(|v| *v == 0));
+    }
+
+    // a little endian linear DNG, 64x64 RGB, x ramps red and y blue
+    pub(crate) fn linear_dng() -> Vec<u8> {
+        linear_dng_with(Vec::new())
+    }
+
+    // with `more` entries after the others, tags above 50778 only
+    pub(crate) fn linear_dng_with(more: Vec<(u16, u16, u32, Vec<u8>)>) -> Vec<u8> {
+        const SIZE: u32 = 64;
+        let mut pixels = Vec::new();
+        for y in 0..SIZE as u16 {
+            for x in 0..SIZE as u16 {
+                for v in [1000 + x * 100, 2000, 3000 + y * 50] {
+                    pixels.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+        let mut ratio = |n: i32| [n.to_le_bytes(), 1i32.to_le_bytes()].concat();
+        let identity: Vec<u8> = [1, 0, 0, 0, 1, 0, 0, 0, 1]
+            .into_iter()
+            .flat_map(&mut ratio)
+            .collect();
+        let neutral: Vec<u8> = [1, 1, 1].into_iter().flat_map(ratio).collect();
+        // (tag, type, count, value bytes)
+        let mut entries: Vec<(u16, u16, u32, Vec<u8>)> = vec![
+            (254, 4, 1, 0u32.to_le_bytes().to_vec()),
+            (256, 4, 1, SIZE.to_le_bytes().to_vec()),
+            (257, 4, 1, SIZE.to_le_bytes().to_vec()),
+            (258, 3, 3, [16u16, 16, 16].map(u16::to_le_bytes).concat()),
+            (259, 3, 1, 1u16.to_le_bytes().to_vec()),
+            (262, 3, 1, 34892u16.to_le_bytes().to_vec()),
+            (271, 2, 5, b"Test\0".to_vec()),
+            (272, 2, 7, b"Linear\0".to_vec()),
+            (273, 4, 1, Vec::new()),
+            (277, 3, 1, 3u16.to_le_bytes().to_vec()),
+            (278, 4, 1, SIZE.to_le_bytes().to_vec()),
+            (279, 4, 1, (pixels.len() as u32).to_le_bytes().to_vec()),
+            (284, 3, 1, 1u16.to_le_bytes().to_vec()),
+            (50706, 1, 4, vec![1, 4, 0, 0]),
+            (50708, 2, 12, b"Test Linear\0".to_vec()),
+            (50717, 4, 1, 65535u32.to_le_bytes().to_vec()),
+            (50721, 10, 9, identity),
+            (50728, 5, 3, neutral),
+            (50778, 3, 1, 21u16.to_le_bytes().to_vec()),
+        ];
+        entries.extend(more);
+        let ifd_len = 2 + entries.len() * 12 + 4;
+        let mut extra = Vec::new();
+        let extra_at = 8 + ifd_len;
+        let mut ifd = (entries.len() as u16).to_le_bytes().to_vec();
+        let extra_len: usize = entries
+            .iter()
+            .filter(|e| e.3.len() > 4)
+            .map(|e| e.3.len())
+            .sum();
+        let pixels_at = (extra_at + extra_len) as u32;
+        for (tag, kind, count, mut value) in entries {
+            if tag == 273 {
+                value = pixels_at.to_le_bytes().to_vec();
+            }
+            ifd.extend_from_slice(&tag.to_le_bytes());
+            ifd.extend_from_slice(&kind.to_le_bytes());
+            ifd.extend_from_slice(&count.to_le_bytes());
+            if value.len() > 4 {
+                ifd.extend_from_slice(&((extra_at + extra.len()) as u32).to_le_bytes());
+                extra.extend(value);
+            } else {
+                value.resize(4, 0);
+                ifd.extend(value);
+            }
+        }
+        ifd.extend_from_slice(&0u32.to_le_bytes());
+        let mut buf = b"II*\0".to_vec();
+        buf.extend_from_slice(&8u32.to_le_bytes());
+        buf.extend(ifd);
+        buf.extend(extra);
+        buf.extend(pixels);
+        buf
+    }
+
+    #[test]
+    fn test_linear_raw() {
+        let data = linear_dng();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(raw_image.is_linear_raw());
+        assert_eq!((raw_image.width(), raw_image.height()), (64, 64));
+        raw_image.unpack().expect("unpacked");
+        assert!(raw_image.raw_image().is_empty());
+        let (pixels, channels) = raw_image.linear_image();
+        // LibRaw pads three colors to four
+        assert_eq!((pixels.len(), channels), (64 * 64 * 4, 4));
+        assert_eq!(pixels[4..7], [1100, 2000, 3000]);
+
+        let stats = raw_image.channel_stats().expect("stats");
+        assert_eq!(stats.len(), 3);
+        assert_eq!((stats[1].min, stats[1].max), (2000, 2000));
+        assert_eq!((stats[0].min, stats[0].max), (1000, 1000 + 63 * 100));
+        assert!(stats.iter().all(|s| s.count == 64 * 64));
+
+        let image = raw_image.process::<BIT_DEPTH_8>().expect("processed");
+        assert_eq!((image.width(), image.height(), image.colors()), (64, 64, 3));
+        assert!(raw_image.processing_log().linear_raw);
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(!raw_image.is_linear_raw());
+        assert_eq!(raw_image.linear_image().1, 0);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_open_file() {
+        let path = get_test_assets_path().join("test-z8.NEF");
+        let data = std::fs::read(&path).unwrap();
+        let buffered = RawImage::open_owned(data).expect("opened");
+        let mut raw_image = RawImage::open_file(&path).expect("opened");
+        assert_eq!(raw_image.model(), buffered.model());
+        assert_eq!(raw_image.thumb_infos(), buffered.thumb_infos());
+        raw_image.unpack().expect("unpacked");
+        assert!(raw_image.extract_best_thumb().is_ok());
+
+        assert!(matches!(
+            RawImage::open_file(path.with_extension("missing")).map_err(Error::from),
+            Err(Error::Fs(_))
+        ));
+    }
+
+    #[test]
+    fn test_open_owned() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let shared: std::sync::Arc<[u8]> = data.clone().into();
+        // the image moves to another thread, the buffer goes with it
+        let thumb = std::thread::spawn(move || {
+            let mut raw_image = RawImage::open_owned(data).expect("opened");
+            raw_image.unpack().expect("unpacked");
+            raw_image.extract_best_thumb().expect("thumb").data
+        })
+        .join()
+        .unwrap();
+        let mut raw_image = RawImage::open_owned(shared.clone()).expect("opened");
+        assert_eq!(raw_image.extract_best_thumb().expect("thumb").data, thumb);
+        assert!(RawImage::open_owned(vec![0u8; 1024]).is_err());
+    }
+
+    #[test]
+    fn test_reuse() {
+        let z8 = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let a7rm4 = std::fs::read(get_test_assets_path().join("test-a7rm4.ARW")).unwrap();
+        let mut raw_image = RawImage::open_owned(z8.clone()).expect("opened");
+        raw_image.set_memory_limit_mb(1024);
+        raw_image.unpack().expect("unpacked");
+        raw_image.reuse_owned(a7rm4.clone()).expect("reused");
+        assert_eq!(raw_image.make(), "Sony");
+        assert!(!raw_image.is_unpacked());
+        assert_eq!(raw_image.memory_limit_mb(), 1024);
+        raw_image.unpack().expect("unpacked");
+        assert_eq!(
+            raw_image.full_info(),
+            RawImage::open_owned(a7rm4).unwrap().full_info()
+        );
+
+        assert!(raw_image.reuse_owned(vec![0; 1024]).is_err());
+        assert_eq!(raw_image.make(), "");
+        raw_image.reuse_owned(z8).expect("reused");
+        assert_eq!(raw_image.model(), "Z 8");
+        raw_image.unpack().expect("unpacked");
+        raw_image.recycle();
+        assert!(!raw_image.is_unpacked());
+        assert!(raw_image.unpack().is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mmap() {
+        let path = get_test_assets_path().join("test-z8.NEF");
+        let mut raw_image = RawImage::open_mmap(&path).expect("opened");
+        assert_eq!(raw_image.model(), "Z 8");
+        raw_image.unpack().expect("unpacked");
+        assert!(raw_image.extract_best_thumb().is_ok());
+        assert!(matches!(
+            RawImage::open_mmap(path.with_extension("missing")).map_err(Error::from),
+            Err(Error::Fs(_))
+        ));
+    }
+
+    #[test]
+    fn test_process_deadline() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        raw_image.unpack().expect("unpacked");
+        assert!(matches!(
+            raw_image
+                .process_with_deadline::<BIT_DEPTH_8>(Duration::ZERO)
+                .map_err(Error::from),
+            Err(Error::CancelledByCallback)
+        ));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let token = CancellationToken::new();
+        raw_image.set_cancellation_token(Some(token.clone()));
+        token.cancel();
+        assert!(matches!(
+            raw_image.unpack().map_err(Error::from),
+            Err(Error::CancelledByCallback)
+        ));
+    }
+
+    #[test]
+    fn test_progress() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let stages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = stages.clone();
+        raw_image.on_progress(move |stage, iteration, expected| {
+            seen.lock().unwrap().push((stage, iteration, expected));
+        });
+        raw_image.unpack().expect("unpacked");
+        let image = raw_image
+            .process_with::<BIT_DEPTH_8>(&ProcessParams {
+                half_size: true,
+                output_color: OutputColor::Rec2020,
+                ..Default::default()
+            })
+            .expect("processed");
+        assert_eq!(image.output_color(), OutputColor::Rec2020);
+        let stages = stages.lock().unwrap();
+        assert_eq!(stages[0], (ProgressStage::LoadRaw, 0, 2));
+        assert!(stages.contains(&(ProgressStage::ConvertRgb, 0, 2)));
+    }
+
     #[test]
     fn test_processed() {
         let assets = get_test_assets_path();
@@ -406,7 +2087,7 @@ mod tests {
             let path = assets.join(file);
             println!("{path:?}");
             let data = std::fs::read(path).unwrap();
-            let mut raw_image = RawImage::open(&data).expect("opened");
+            let mut raw_image = RawImage::open_owned(data).expect("opened");
             raw_image.unpack().expect("unpacked");
             let image = raw_image.process::<BIT_DEPTH_16>().expect("decoded");
             assert_eq!(image.width(), width);
@@ -415,6 +2096,12 @@ mod tests {
             assert_eq!(image.colors(), colors);
             assert_eq!(image.bits(), bits);
             assert_eq!(image.data_size(), data_size);
+            assert_eq!(image.output_color(), OutputColor::Srgb);
+
+            let metrics = raw_image.metrics();
+            assert!(metrics.unpack.is_some() && metrics.process.is_some());
+            assert!(metrics.peak_memory > data_size as u64);
+            assert!(!metrics.decoder.is_empty());
         }
     }
 }
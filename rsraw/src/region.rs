@@ -0,0 +1,213 @@
+// Developing a window of the frame for pan/zoom viewers. LibRaw's cropbox
+// cuts the mosaic before demosaicing, so a tile costs about what its pixels
+// do. The crop is widened by a margin for the demosaic and denoise
+// neighbourhoods and snapped to the CFA's repeat, so tile pixels come out the
+// way they do in a full develop, then the margin is cut off again.
+
+use crate::{
+    err::{ContextError, Error, ErrorStage},
+    raw::BitDepth,
+    ProcessParams, ProcessedImage, RawImage,
+};
+
+// sensor pixels on every side, more than AHD, DHT or Markesteijn reach
+const MARGIN: u32 = 32;
+const FILTERS_LEAF: u32 = 1;
+const FILTERS_XTRANS: u32 = 9;
+
+// A window in pixels, x to the right and y down
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    // From the oriented image to the sensor's orientation, `width` and
+    // `height` being the unrotated size. Flip bits as LibRaw's flip_index.
+    fn to_sensor(self, flip: i32, width: u32, height: u32) -> Self {
+        let (mut row, rows, mut col, cols) = match flip & 4 {
+            0 => (self.y, self.height, self.x, self.width),
+            _ => (self.x, self.width, self.y, self.height),
+        };
+        if flip & 2 != 0 {
+            row = height - row - rows;
+        }
+        if flip & 1 != 0 {
+            col = width - col - cols;
+        }
+        Self::new(col, row, cols, rows)
+    }
+
+    // the inverse of `to_sensor`
+    fn to_oriented(self, flip: i32, width: u32, height: u32) -> Self {
+        let (mut row, mut col) = (self.y, self.x);
+        if flip & 2 != 0 {
+            row = height - row - self.height;
+        }
+        if flip & 1 != 0 {
+            col = width - col - self.width;
+        }
+        match flip & 4 {
+            0 => Self::new(col, row, self.width, self.height),
+            _ => Self::new(row, col, self.height, self.width),
+        }
+    }
+}
+
+impl RawImage {
+    // `rect` of what `process_with(params)` would develop, in its (rotated,
+    // possibly half size) coordinates. `Error::BadCrop` for a window outside
+    // the image, `Error::NotImplemented` for layouts LibRaw reshapes while
+    // processing (Fuji SuperCCD, non-square pixels). Auto white balance and
+    // auto brightness only see the tile, so tiles meant to sit next to each
+    // other want the camera's white balance and `no_auto_bright`.
+    pub fn process_region<const D: BitDepth>(
+        &mut self,
+        rect: Rect,
+        params: &ProcessParams,
+    ) -> std::result::Result<ProcessedImage<D>, ContextError> {
+        if !self.is_unpacked() {
+            self.unpack()?;
+        }
+        let sizes = &self.as_ref().rawdata.sizes;
+        let (width, height) = (sizes.width as u32, sizes.height as u32);
+        let filters = self.filters();
+        let flip = match self.as_ref().params.user_flip {
+            flip if flip >= 0 => flip,
+            _ => sizes.flip,
+        };
+        let flip = match (flip + 3600) % 360 {
+            270 => 5,
+            180 => 3,
+            90 => 6,
+            _ => flip,
+        };
+        // half size develops one pixel per 2x2 block of a mosaic
+        let scale = match params.half_size && filters != 0 {
+            true => 2,
+            false => 1,
+        };
+        let (full_w, full_h) = (width.div_ceil(scale), height.div_ceil(scale));
+        let (oriented_w, oriented_h) = match flip & 4 {
+            0 => (full_w, full_h),
+            _ => (full_h, full_w),
+        };
+        if rect.width == 0
+            || rect.height == 0
+            || rect.x.saturating_add(rect.width) > oriented_w
+            || rect.y.saturating_add(rect.height) > oriented_h
+        {
+            return Err(self.context(ErrorStage::Process, Error::BadCrop));
+        }
+
+        let wanted = rect.to_sensor(flip, full_w, full_h);
+        let align = match filters {
+            FILTERS_LEAF => 16,
+            FILTERS_XTRANS => 6,
+            _ => 2,
+        };
+        let left = (wanted.x * scale).saturating_sub(MARGIN) / align * align;
+        let top = (wanted.y * scale).saturating_sub(MARGIN) / align * align;
+        let right = ((wanted.x + wanted.width) * scale + MARGIN).min(width);
+        let bottom = ((wanted.y + wanted.height) * scale + MARGIN).min(height);
+        let cropbox = [left, top, right - left, bottom - top];
+        let mut image = self.process_cropped::<D>(params, cropbox)?;
+
+        // the tile in developed pixels
+        let (tile_w, tile_h) = (
+            (right - left).div_ceil(scale),
+            (bottom - top).div_ceil(scale),
+        );
+        let expected = match flip & 4 {
+            0 => (tile_w, tile_h),
+            _ => (tile_h, tile_w),
+        };
+        if (image.width(), image.height()) != expected {
+            return Err(self.context(ErrorStage::Process, Error::NotImplemented));
+        }
+        let inside = Rect::new(
+            wanted.x - left / scale,
+            wanted.y - top / scale,
+            wanted.width,
+            wanted.height,
+        )
+        .to_oriented(flip, tile_w, tile_h);
+        image.crop(inside.x, inside.y, inside.width, inside.height);
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    #[test]
+    fn test_rect_flips() {
+        for flip in [0, 3, 5, 6] {
+            let rect = Rect::new(10, 20, 30, 5);
+            let sensor = rect.to_sensor(flip, 200, 100);
+            assert_eq!(sensor.to_oriented(flip, 200, 100), rect);
+        }
+        // 90° clockwise: the oriented top left is the sensor's bottom left
+        assert_eq!(
+            Rect::new(0, 0, 1, 1).to_sensor(6, 200, 100),
+            Rect::new(0, 99, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_process_region() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let params = ProcessParams {
+            use_camera_wb: true,
+            no_auto_bright: true,
+            ..Default::default()
+        };
+        let full = raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        let rect = Rect::new(3001, 2000, 200, 120);
+        let tile = raw_image
+            .process_region::<BIT_DEPTH_8>(rect, &params)
+            .expect("region");
+        assert_eq!((tile.width(), tile.height()), (200, 120));
+        // the margin leaves nothing for the demosaic to guess at the tile's edges
+        let stride = full.width() as usize * 3;
+        for (y, row) in tile.chunks(200 * 3).enumerate() {
+            let start = (2000 + y) * stride + 3001 * 3;
+            assert_eq!(row, &full[start..start + 200 * 3]);
+        }
+
+        // the full frame again, the crop doesn't stick
+        let again = raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        assert_eq!(
+            (again.width(), again.height()),
+            (full.width(), full.height())
+        );
+        assert!(matches!(
+            raw_image.process_region::<BIT_DEPTH_8>(Rect::new(full.width(), 0, 1, 1), &params),
+            Err(ContextError {
+                stage: ErrorStage::Process,
+                error: Error::BadCrop,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+// Sample access shared by the passes that resample processed images.
+
+// the sample types of 8 and 16 bit processed images
+pub trait Sample: Copy + Default {
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+    #[cfg(feature = "lensfun")]
+    fn devignette(modifier: &lensfun::Modifier, pixels: &mut [Self], width: usize, channels: usize);
+}
+
+impl Sample for u8 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value.round().clamp(0.0, u8::MAX as f32) as u8
+    }
+
+    #[cfg(feature = "lensfun")]
+    fn devignette(
+        modifier: &lensfun::Modifier,
+        pixels: &mut [Self],
+        width: usize,
+        channels: usize,
+    ) {
+        let rows = pixels.len() / (width * channels);
+        modifier.apply_color_modification_u8(pixels, 0.0, 0.0, width, rows, channels);
+    }
+}
+
+impl Sample for u16 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value.round().clamp(0.0, u16::MAX as f32) as u16
+    }
+
+    #[cfg(feature = "lensfun")]
+    fn devignette(
+        modifier: &lensfun::Modifier,
+        pixels: &mut [Self],
+        width: usize,
+        channels: usize,
+    ) {
+        let rows = pixels.len() / (width * channels);
+        modifier.apply_color_modification_u16(pixels, 0.0, 0.0, width, rows, channels);
+    }
+}
+
+// An interleaved image to sample from, samples outside the frame come out black.
+pub(crate) struct Source<'a, T> {
+    pub pixels: &'a [T],
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+}
+
+impl<T: Sample> Source<'_, T> {
+    pub fn bilinear(&self, c: usize, x: f32, y: f32) -> T {
+        let (width, height) = (self.width, self.height);
+        if !(x >= 0.0 && y >= 0.0 && x <= (width - 1) as f32 && y <= (height - 1) as f32) {
+            return T::default();
+        }
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let at = |x: usize, y: usize| self.pixels[(y * width + x) * self.channels + c].to_f32();
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * fx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * fx;
+        T::from_f32(top + (bottom - top) * fy)
+    }
+}
@@ -0,0 +1,635 @@
+#[cfg(feature = "fs")]
+use std::{io::Read, path::Path};
+use std::{path::PathBuf, time::Duration};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Local, TimeZone};
+
+#[cfg(feature = "fs")]
+use crate::{
+    batch::{has_extension, BatchItem},
+    err::{Error, Result},
+    raw::BitDepth,
+    BufferPool, ImageLayout, PooledBuffer, ProcessParams, RawImage,
+};
+use crate::{FullRawInfo, RawMetadata};
+
+// settings are adjusted in 1/3 stop increments at the finest,
+// anything below this is rounding noise in the recorded exif values
+const EV_EPSILON: f32 = 0.05;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub path: PathBuf,
+    // seconds since the unix epoch, 0 when unknown, like `RawImage::timestamp`
+    pub timestamp: i64,
+    pub iso_speed: u32,
+    pub shutter: f32,
+    pub aperture: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameExposure {
+    pub index: usize,
+    pub ev: f32,
+    pub relative_ev: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExposureRamp {
+    pub start: usize,
+    pub end: usize,
+    pub delta_ev: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sequence {
+    pub frames: Vec<Frame>,
+}
+
+// Frames shot in one go by one body, for culling UIs to show as a stack
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Burst {
+    // indices into what was passed to `group_bursts`, in capture order
+    pub frames: Vec<usize>,
+    pub body_serial: String,
+    pub drive_mode: i16,
+}
+
+impl Frame {
+    pub fn new(path: impl Into<PathBuf>, info: &FullRawInfo) -> Self {
+        Self {
+            path: path.into(),
+            timestamp: info.timestamp(),
+            iso_speed: info.iso_speed,
+            shutter: info.shutter,
+            aperture: info.aperture,
+        }
+    }
+
+    #[cfg(feature = "fs")]
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let raw_image = RawImage::open_file(path)?;
+        Ok(Self::new(path, &raw_image.full_info()))
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn datetime(&self) -> Option<DateTime<Local>> {
+        Local.timestamp_opt(self.capture_time()?, 0).single()
+    }
+
+    fn capture_time(&self) -> Option<i64> {
+        Some(self.timestamp).filter(|&ts| ts > 0)
+    }
+
+    // exposure value normalized to ISO 100, higher means less light reached the sensor
+    pub fn ev(&self) -> Option<f32> {
+        if self.shutter <= 0.0 || self.aperture <= 0.0 || self.iso_speed == 0 {
+            return None;
+        }
+        let ev = (self.aperture * self.aperture / self.shutter).log2();
+        Some(ev - (self.iso_speed as f32 / 100.0).log2())
+    }
+}
+
+impl Sequence {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn duration(&self) -> Duration {
+        match (
+            self.frames.first().and_then(Frame::capture_time),
+            self.frames.last().and_then(Frame::capture_time),
+        ) {
+            (Some(first), Some(last)) => seconds(last - first).unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        let mut gaps: Vec<_> = self.gaps().collect();
+        if gaps.is_empty() {
+            return None;
+        }
+        gaps.sort();
+        Some(gaps[gaps.len() / 2])
+    }
+
+    pub fn exposures(&self) -> Vec<FrameExposure> {
+        let mut base = None;
+        self.frames
+            .iter()
+            .enumerate()
+            .filter_map(|(index, frame)| {
+                let ev = frame.ev()?;
+                let base = *base.get_or_insert(ev);
+                Some(FrameExposure {
+                    index,
+                    ev,
+                    relative_ev: ev - base,
+                })
+            })
+            .collect()
+    }
+
+    pub fn exposure_ramps(&self) -> Vec<ExposureRamp> {
+        let mut ramps = Vec::new();
+        let mut current: Option<ExposureRamp> = None;
+        let evs: Vec<_> = self.frames.iter().map(Frame::ev).collect();
+        for i in 1..evs.len() {
+            let (Some(prev), Some(ev)) = (evs[i - 1], evs[i]) else {
+                ramps.extend(current.take());
+                continue;
+            };
+            let delta = ev - prev;
+            if delta.abs() < EV_EPSILON {
+                continue;
+            }
+            match current.as_mut() {
+                Some(ramp) if ramp.delta_ev.signum() == delta.signum() => {
+                    ramp.end = i;
+                    ramp.delta_ev += delta;
+                }
+                _ => {
+                    ramps.extend(current.take());
+                    current = Some(ExposureRamp {
+                        start: i - 1,
+                        end: i,
+                        delta_ev: delta,
+                    });
+                }
+            }
+        }
+        ramps.extend(current);
+        ramps
+    }
+
+    fn gaps(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.frames.windows(2).filter_map(|w| {
+            let (a, b) = (w[0].capture_time()?, w[1].capture_time()?);
+            seconds(b - a)
+        })
+    }
+}
+
+// frames without a capture time can't be placed on the timeline and are skipped
+pub fn group_by_interval(
+    frames: impl IntoIterator<Item = Frame>,
+    max_gap: Duration,
+) -> Vec<Sequence> {
+    let mut frames: Vec<_> = frames
+        .into_iter()
+        .filter(|f| f.capture_time().is_some())
+        .collect();
+    frames.sort_by_key(|f| f.timestamp);
+
+    let mut sequences: Vec<Sequence> = Vec::new();
+    for frame in frames {
+        let joins = sequences
+            .last()
+            .and_then(|seq| seq.frames.last())
+            .and_then(|last| seconds(frame.capture_time()? - last.capture_time()?))
+            .is_some_and(|gap| gap <= max_gap);
+        match sequences.last_mut() {
+            Some(seq) if joins => seq.frames.push(frame),
+            _ => sequences.push(Sequence {
+                frames: vec![frame],
+            }),
+        }
+    }
+    sequences
+}
+
+// negative spans are out of order frames
+fn seconds(span: i64) -> Option<Duration> {
+    Some(Duration::from_secs(span.try_into().ok()?))
+}
+
+impl Burst {
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+// Splits a shoot into bursts: frames of the same body and drive mode at most
+// `max_gap` apart. Bodies shooting at the same time get separate bursts, a
+// change of drive mode starts a new one. Sub-second times come from the EXIF
+// tags with the exif feature (after `merge_exif`), otherwise frames of the
+// same second keep their order. Frames without a capture time are skipped.
+// Bursts come out ordered by their first frame.
+pub fn group_bursts<'a>(
+    metadata: impl IntoIterator<Item = &'a RawMetadata>,
+    max_gap: Duration,
+) -> Vec<Burst> {
+    let mut frames: Vec<(usize, f64, &RawMetadata)> = metadata
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, m)| Some((index, capture_time(m)?, m)))
+        .collect();
+    frames.sort_by(|a, b| {
+        body_serial(a.2)
+            .cmp(body_serial(b.2))
+            .then(a.1.total_cmp(&b.1))
+            .then(a.0.cmp(&b.0))
+    });
+
+    let mut bursts: Vec<(f64, Burst)> = Vec::new();
+    let mut last_time = 0.0;
+    for (index, time, m) in frames {
+        let joins = bursts.last().is_some_and(|(_, burst)| {
+            burst.body_serial == body_serial(m)
+                && burst.drive_mode == m.drive_mode
+                && time - last_time <= max_gap.as_secs_f64()
+        });
+        match bursts.last_mut() {
+            Some((_, burst)) if joins => burst.frames.push(index),
+            _ => bursts.push((
+                time,
+                Burst {
+                    frames: vec![index],
+                    body_serial: body_serial(m).to_string(),
+                    drive_mode: m.drive_mode,
+                },
+            )),
+        }
+        last_time = time;
+    }
+    bursts.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.frames[0].cmp(&b.1.frames[0])));
+    bursts.into_iter().map(|(_, burst)| burst).collect()
+}
+
+// seconds since the epoch
+fn capture_time(metadata: &RawMetadata) -> Option<f64> {
+    if metadata.info.timestamp() <= 0 {
+        return None;
+    }
+    let fraction = metadata
+        .exif
+        .sub_sec_time_original()
+        .map(str::trim)
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| format!("0.{digits}").parse().ok())
+        .unwrap_or(0.0);
+    Some(metadata.info.timestamp() as f64 + fraction)
+}
+
+fn body_serial(metadata: &RawMetadata) -> &str {
+    if metadata.body_serial.is_empty() {
+        return metadata.exif.body_serial_number().unwrap_or_default();
+    }
+    &metadata.body_serial
+}
+
+// files that can't be opened don't end the scan, they come back next to the
+// sequences with their error
+#[cfg(feature = "fs")]
+pub fn scan<P: AsRef<Path>>(
+    paths: impl IntoIterator<Item = P>,
+    max_gap: Duration,
+) -> (Vec<Sequence>, Vec<BatchItem<Frame>>) {
+    let (frames, failed): (Vec<_>, Vec<_>) = paths
+        .into_iter()
+        .map(|path| BatchItem {
+            path: path.as_ref().to_path_buf(),
+            result: Frame::open(path),
+        })
+        .partition(|item| item.result.is_ok());
+    let frames = frames.into_iter().filter_map(|item| item.result.ok());
+    (group_by_interval(frames, max_gap), failed)
+}
+
+// A CinemaDNG clip: one DNG per frame, numbered at the end of the file name
+// (`A001_C002_0410_000123.dng`). Every frame is processed with the same
+// params into buffers recycled through a `BufferPool`, so once the pool is
+// warm a clip plays back without allocating per frame.
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone)]
+pub struct CinemaDng {
+    // frame number and path, in frame order
+    frames: Vec<(u64, PathBuf)>,
+    params: ProcessParams,
+    pool: BufferPool,
+}
+
+#[cfg(feature = "fs")]
+#[derive(Debug)]
+pub struct CinemaDngFrame {
+    pub index: usize,
+    // from the file name, clips rarely start at 0 and may skip frames
+    pub number: u64,
+    pub layout: ImageLayout,
+    pub data: PooledBuffer,
+}
+
+// decodes frames in order, reusing the buffer files are read into
+#[cfg(feature = "fs")]
+pub struct CinemaDngFrames<'a, const D: BitDepth> {
+    clip: &'a CinemaDng,
+    next: usize,
+    file: Vec<u8>,
+}
+
+#[cfg(feature = "fs")]
+impl CinemaDng {
+    // the numbered DNGs directly in `dir`
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if has_extension(&path, &["dng".into()]) {
+                paths.push(path);
+            }
+        }
+        Ok(Self::from_paths(paths))
+    }
+
+    // paths without a frame number are left out
+    pub fn from_paths<P: Into<PathBuf>>(paths: impl IntoIterator<Item = P>) -> Self {
+        let mut frames: Vec<_> = paths
+            .into_iter()
+            .map(Into::into)
+            .filter_map(|path| Some((frame_number(&path)?, path)))
+            .collect();
+        frames.sort();
+        Self {
+            frames,
+            params: ProcessParams::default(),
+            pool: BufferPool::default(),
+        }
+    }
+
+    pub fn params(mut self, params: ProcessParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    // share a pool with whatever consumes the frames, e.g. an encoder
+    pub fn pool(mut self, pool: BufferPool) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frame_number(&self, index: usize) -> Option<u64> {
+        self.frames.get(index).map(|(number, _)| *number)
+    }
+
+    pub fn path(&self, index: usize) -> Option<&Path> {
+        self.frames.get(index).map(|(_, path)| path.as_path())
+    }
+
+    // numbers missing between the first and last frame, a number that's
+    // there twice, e.g. from two card folders, doesn't make up for another
+    pub fn dropped_frames(&self) -> u64 {
+        let (Some((first, _)), Some((last, _))) = (self.frames.first(), self.frames.last()) else {
+            return 0;
+        };
+        let distinct = 1 + self.frames.windows(2).filter(|w| w[0].0 != w[1].0).count();
+        (last - first + 1).saturating_sub(distinct as u64)
+    }
+
+    pub fn decode<const D: BitDepth>(&self, index: usize) -> Result<CinemaDngFrame> {
+        self.decode_with::<D>(index, &mut Vec::new())
+    }
+
+    pub fn frames<const D: BitDepth>(&self) -> CinemaDngFrames<'_, D> {
+        CinemaDngFrames {
+            clip: self,
+            next: 0,
+            file: Vec::new(),
+        }
+    }
+
+    fn decode_with<const D: BitDepth>(
+        &self,
+        index: usize,
+        file: &mut Vec<u8>,
+    ) -> Result<CinemaDngFrame> {
+        let (number, path) = self
+            .frames
+            .get(index)
+            .ok_or(Error::RequestForNonexistentImage)?;
+        file.clear();
+        std::fs::File::open(path)?.read_to_end(file)?;
+        // Safety: the image is dropped before `file` is read into again
+        let mut raw_image = unsafe { RawImage::open(file) }?;
+        let mut data = self.pool.get();
+        let layout = raw_image.process_into_with::<D>(&self.params, &mut data)?;
+        Ok(CinemaDngFrame {
+            index,
+            number: *number,
+            layout,
+            data,
+        })
+    }
+}
+
+#[cfg(feature = "fs")]
+impl<const D: BitDepth> Iterator for CinemaDngFrames<'_, D> {
+    type Item = Result<CinemaDngFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.clip.len() {
+            return None;
+        }
+        self.next += 1;
+        Some(self.clip.decode_with::<D>(self.next - 1, &mut self.file))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.clip.len() - self.next;
+        (left, Some(left))
+    }
+}
+
+// the digits the file stem ends in
+#[cfg(feature = "fs")]
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[stem.len() - digits..].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(secs: i64, iso_speed: u32, shutter: f32) -> Frame {
+        Frame {
+            path: format!("{secs}.NEF").into(),
+            timestamp: 1_700_000_000 + secs,
+            iso_speed,
+            shutter,
+            aperture: 2.8,
+        }
+    }
+
+    #[test]
+    fn test_group_by_interval() {
+        let frames = vec![
+            frame(20, 100, 0.5),
+            frame(0, 100, 0.5),
+            frame(10, 100, 0.5),
+            frame(100, 100, 0.5),
+            frame(105, 100, 0.5),
+        ];
+        let sequences = group_by_interval(frames, Duration::from_secs(15));
+        assert_eq!(sequences.len(), 2);
+        assert_eq!(sequences[0].len(), 3);
+        assert_eq!(sequences[0].interval(), Some(Duration::from_secs(10)));
+        assert_eq!(sequences[0].duration(), Duration::from_secs(20));
+        assert_eq!(sequences[1].len(), 2);
+        #[cfg(feature = "chrono")]
+        assert_eq!(
+            sequences[1].frames[0].datetime().map(|dt| dt.timestamp()),
+            Some(1_700_000_100)
+        );
+    }
+
+    #[test]
+    fn test_exposure_ramps() {
+        // sunset: shutter gets longer, then iso goes up, a plateau in between
+        let seq = Sequence {
+            frames: vec![
+                frame(0, 100, 1.0 / 100.0),
+                frame(10, 100, 1.0 / 50.0),
+                frame(20, 100, 1.0 / 50.0),
+                frame(30, 100, 1.0 / 25.0),
+                frame(40, 200, 1.0 / 25.0),
+                frame(50, 200, 1.0 / 50.0),
+            ],
+        };
+        let ramps = seq.exposure_ramps();
+        assert_eq!(ramps.len(), 2);
+        assert_eq!((ramps[0].start, ramps[0].end), (0, 4));
+        assert!((ramps[0].delta_ev + 3.0).abs() < 0.01);
+        assert_eq!((ramps[1].start, ramps[1].end), (4, 5));
+
+        let exposures = seq.exposures();
+        assert_eq!(exposures.len(), 6);
+        assert!((exposures[4].relative_ev + 3.0).abs() < 0.01);
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_group_bursts() {
+        use crate::{raw::tests::get_test_assets_path, RawImage};
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let base = RawImage::open_owned(data).expect("opened").metadata();
+        let shot = |secs: i64, serial: &str, drive_mode: i16| {
+            let mut m = base.clone();
+            #[cfg(feature = "chrono")]
+            {
+                m.info.datetime = Local.timestamp_opt(1_700_000_000 + secs, 0).single();
+            }
+            #[cfg(not(feature = "chrono"))]
+            {
+                m.info.timestamp = 1_700_000_000 + secs;
+            }
+            m.body_serial = serial.to_string();
+            m.drive_mode = drive_mode;
+            m
+        };
+        let shoot = vec![
+            shot(0, "A", 1),
+            shot(1, "B", 1),
+            shot(1, "A", 1),
+            shot(2, "A", 1),
+            shot(2, "B", 1),
+            shot(3, "A", 0),
+            shot(30, "A", 1),
+            shot(31, "A", 1),
+        ];
+        let bursts = group_bursts(&shoot, Duration::from_secs(2));
+        let frames: Vec<_> = bursts.iter().map(|b| b.frames.as_slice()).collect();
+        assert_eq!(frames, [&[0, 2, 3][..], &[1, 4], &[5], &[6, 7]]);
+        assert_eq!(bursts[1].body_serial, "B");
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_scan() {
+        use crate::raw::tests::get_test_assets_path;
+
+        let dir = get_test_assets_path();
+        let paths = [dir.join("test-z8.NEF"), dir.join("missing.NEF")];
+        let (sequences, failed) = scan(&paths, Duration::from_secs(60));
+        assert_eq!(sequences.len(), 1);
+        assert_eq!(sequences[0].frames[0].path, paths[0]);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].path, paths[1]);
+        assert!(failed[0].result.is_err());
+    }
+
+    #[cfg(feature = "fs")]
+    #[test]
+    fn test_cinema_dng() {
+        use crate::{
+            raw::tests::{get_test_assets_path, TempDir},
+            BIT_DEPTH_8,
+        };
+
+        let clip = CinemaDng::from_paths(["C0001_000010.dng", "C0001_000009.dng", "clip.dng"]);
+        assert_eq!(clip.len(), 2);
+        assert_eq!(clip.frame_number(0), Some(9));
+        assert_eq!(clip.path(1), Some(Path::new("C0001_000010.dng")));
+        assert_eq!(clip.dropped_frames(), 0);
+
+        let clip = CinemaDng::from_paths([
+            "A/C0001_000010.dng",
+            "B/C0001_000010.dng",
+            "C0001_000012.dng",
+            "C0001_000012.dng",
+        ]);
+        assert_eq!((clip.len(), clip.dropped_frames()), (4, 1));
+
+        // any raw decodes, named like a clip's frames
+        let assets = get_test_assets_path();
+        let dir = TempDir::new("cinema-dng");
+        for (asset, name) in [
+            ("test-a7rm4.ARW", "A001_C002_0410_000123.dng"),
+            ("test-z8.NEF", "A001_C002_0410_000126.dng"),
+        ] {
+            let (from, to) = (assets.join(asset), dir.join(name));
+            std::fs::hard_link(&from, &to)
+                .or_else(|_| std::fs::copy(&from, &to).map(drop))
+                .unwrap();
+        }
+        let clip = CinemaDng::open(dir.path()).unwrap().params(ProcessParams {
+            half_size: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            (clip.frame_number(0), clip.dropped_frames()),
+            (Some(123), 2)
+        );
+        let mut frames = clip.frames::<BIT_DEPTH_8>();
+        assert_eq!(frames.size_hint(), (2, Some(2)));
+        let frame = frames.next().unwrap().expect("decoded");
+        assert_eq!((frame.index, frame.number), (0, 123));
+        assert_eq!((frame.layout.width, frame.layout.height), (4784, 3188));
+        assert_eq!(frame.data.len(), frame.layout.len());
+        assert!(matches!(
+            clip.decode::<BIT_DEPTH_8>(2),
+            Err(Error::RequestForNonexistentImage)
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+
+use crate::RawImage;
+
+// A `RawImage` behind a mutex for callers that really need one handle on
+// several threads. Every access, metadata getters included, takes the lock,
+// so a reader never sees the libraw_data_t halfway through unpack or process.
+// When only metadata is needed elsewhere, `RawImage::metadata` avoids the
+// contention entirely.
+#[derive(Clone)]
+pub struct SharedRawImage {
+    inner: Arc<Mutex<RawImage>>,
+}
+
+impl SharedRawImage {
+    pub fn new(raw_image: RawImage) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(raw_image)),
+        }
+    }
+
+    // a panic while the lock was held leaves LibRaw in whatever state the
+    // failed call produced, which its own error codes already cover
+    pub fn lock(&self) -> MutexGuard<'_, RawImage> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, RawImage>> {
+        match self.inner.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    // the image back if this is the last handle
+    pub fn into_inner(self) -> Option<RawImage> {
+        Arc::into_inner(self.inner).map(|m| m.into_inner().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl From<RawImage> for SharedRawImage {
+    fn from(raw_image: RawImage) -> Self {
+        Self::new(raw_image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    #[test]
+    fn test_shared_raw_image() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let shared = SharedRawImage::new(RawImage::open_owned(data).expect("opened"));
+        let worker = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                let mut raw_image = shared.lock();
+                raw_image.set_max_threads(1);
+                raw_image.unpack().expect("unpacked");
+            })
+        };
+        assert_eq!(shared.lock().model(), "Z 8");
+        worker.join().unwrap();
+        assert!(shared.lock().is_unpacked());
+
+        let mut raw_image = shared.into_inner().expect("last handle");
+        let params = crate::ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+    }
+}
@@ -0,0 +1,135 @@
+// LibRaw's `libraw_image_sizes_t` in plain types. Sizes are those of the
+// current state: after processing `iwidth` and `iheight` reflect half size
+// and `flip` what LibRaw rotated by.
+
+use rsraw_sys as sys;
+
+use crate::{region::Rect, RawImage};
+
+// LIBRAW_IMAGE_ASPECT_OTHER, set for aspects LibRaw has no name for
+const ASPECT_OTHER: u16 = 1;
+// the left or top of an inset crop the file didn't give
+const CROP_UNSET: u16 = 0xffff;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SizesInfo {
+    // the mosaic as stored, margins included
+    pub raw_width: u32,
+    pub raw_height: u32,
+    // bytes per mosaic row, 0 until unpacked
+    pub raw_pitch: u32,
+    // the visible area and where it starts in the mosaic
+    pub width: u32,
+    pub height: u32,
+    pub top_margin: u32,
+    pub left_margin: u32,
+    // the working image, half of the visible area with half size
+    pub iwidth: u32,
+    pub iheight: u32,
+    // of a pixel's width to its height, 1.0 for square ones
+    pub pixel_aspect: f64,
+    // LibRaw's flip bits: 2 reverses the rows (vertical), 1 the columns
+    // (horizontal), 4 transposes
+    pub flip: i32,
+    // masked areas of the sensor in mosaic coordinates, those the file gives
+    pub masks: Vec<Rect>,
+    // the aspect the camera was set to crop to, width over height. `None`
+    // when unknown or not one LibRaw names.
+    pub raw_aspect: Option<f32>,
+    // the crops the file suggests within the mosaic, the camera's default
+    // first, those it doesn't give are `None`
+    pub raw_inset_crops: [Option<Rect>; 2],
+}
+
+impl SizesInfo {
+    pub(crate) fn from_libraw(sizes: &sys::libraw_image_sizes_t) -> Self {
+        let masks = sizes
+            .mask
+            .iter()
+            .filter(|&&[top, left, bottom, right]| {
+                top >= 0 && left >= 0 && bottom > top && right > left
+            })
+            .map(|&[top, left, bottom, right]| {
+                Rect::new(
+                    left as _,
+                    top as _,
+                    (right - left) as _,
+                    (bottom - top) as _,
+                )
+            })
+            .collect();
+        let crop = |crop: &sys::libraw_raw_inset_crop_t| {
+            let unset = crop.cleft == CROP_UNSET || crop.ctop == CROP_UNSET;
+            (!unset && crop.cwidth > 0 && crop.cheight > 0).then(|| {
+                Rect::new(
+                    crop.cleft as _,
+                    crop.ctop as _,
+                    crop.cwidth as _,
+                    crop.cheight as _,
+                )
+            })
+        };
+        Self {
+            raw_width: sizes.raw_width as _,
+            raw_height: sizes.raw_height as _,
+            raw_pitch: sizes.raw_pitch,
+            width: sizes.width as _,
+            height: sizes.height as _,
+            top_margin: sizes.top_margin as _,
+            left_margin: sizes.left_margin as _,
+            iwidth: sizes.iwidth as _,
+            iheight: sizes.iheight as _,
+            pixel_aspect: sizes.pixel_aspect,
+            flip: sizes.flip,
+            masks,
+            raw_aspect: (sizes.raw_aspect > ASPECT_OTHER).then(|| sizes.raw_aspect as f32 / 1000.0),
+            raw_inset_crops: [
+                crop(&sizes.raw_inset_crops[0]),
+                crop(&sizes.raw_inset_crops[1]),
+            ],
+        }
+    }
+}
+
+impl RawImage {
+    pub fn sizes(&self) -> SizesInfo {
+        SizesInfo::from_libraw(&self.as_ref().sizes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_sizes() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let sizes = raw_image.sizes();
+        assert_eq!((sizes.width, sizes.height), (8280, 5520));
+        assert!(sizes.raw_width >= sizes.width + sizes.left_margin);
+        assert_eq!(sizes.pixel_aspect, 1.0);
+        raw_image.unpack().expect("unpacked");
+        assert!(raw_image.sizes().raw_pitch >= sizes.raw_width * 2);
+
+        let mut raw: sys::libraw_image_sizes_t = unsafe { std::mem::zeroed() };
+        raw.mask[0] = [0, 0, 10, 4];
+        raw.raw_aspect = 1500;
+        raw.raw_inset_crops[0] = sys::libraw_raw_inset_crop_t {
+            cleft: 8,
+            ctop: 2,
+            cwidth: 100,
+            cheight: 60,
+        };
+        raw.raw_inset_crops[1].cleft = CROP_UNSET;
+        let sizes = SizesInfo::from_libraw(&raw);
+        assert_eq!(sizes.masks, [Rect::new(0, 0, 4, 10)]);
+        assert_eq!(sizes.raw_aspect, Some(1.5));
+        assert_eq!(
+            sizes.raw_inset_crops,
+            [Some(Rect::new(8, 2, 100, 60)), None]
+        );
+    }
+}
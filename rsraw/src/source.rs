@@ -0,0 +1,140 @@
+use crate::{err::Result, FullRawInfo, RawImage};
+
+// Common access to decoded files, whichever decoder produced them.
+
+pub trait MetadataAccess {
+    fn full_info(&self) -> FullRawInfo;
+}
+
+pub trait MosaicAccess {
+    // sensor values row by row including any masked borders, `None` until the
+    // pixel data has been decoded (`RawImage::unpack`) or for non-integer data
+    fn mosaic(&self) -> Option<&[u16]>;
+    fn mosaic_width(&self) -> u32;
+    fn mosaic_height(&self) -> u32;
+    // LibRaw's packed CFA descriptor: 0 for linear data, 9 for X-Trans,
+    // otherwise 2 bits per pixel of an 8 row by 2 column pattern that starts
+    // at the top left of the visible area
+    fn filters(&self) -> u32;
+}
+
+pub enum Decoded {
+    LibRaw(Box<RawImage>),
+    #[cfg(feature = "fallback")]
+    Fallback(Box<crate::fallback::FallbackImage>),
+}
+
+// Opens `buf` with LibRaw. With the `fallback` feature, files LibRaw doesn't
+// recognize are handed to the pure Rust decoder; if that fails as well the
+// original LibRaw error is returned. A LibRaw image keeps `buf` as with
+// `RawImage::open_owned`.
+pub fn open_any(buf: impl AsRef<[u8]> + Send + 'static) -> Result<Decoded> {
+    // boxed, so the bytes stay put however the image moves
+    let buf: Box<dyn AsRef<[u8]> + Send> = Box::new(buf);
+    // Safety: the image takes `buf` along as soon as it's open
+    match unsafe { RawImage::open((*buf).as_ref()) } {
+        Ok(mut raw_image) => {
+            raw_image.owned = Some(buf);
+            Ok(Decoded::LibRaw(Box::new(raw_image)))
+        }
+        #[cfg(feature = "fallback")]
+        Err(err)
+            if matches!(
+                err.error,
+                crate::Error::FileUnsupported | crate::Error::NotImplemented
+            ) =>
+        {
+            crate::fallback::FallbackImage::open((*buf).as_ref())
+                .map(|image| Decoded::Fallback(Box::new(image)))
+                .map_err(|_| err.error)
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+impl Decoded {
+    fn metadata(&self) -> &dyn MetadataAccess {
+        match self {
+            Decoded::LibRaw(raw_image) => &**raw_image,
+            #[cfg(feature = "fallback")]
+            Decoded::Fallback(image) => image.as_ref(),
+        }
+    }
+
+    fn mosaic_source(&self) -> &dyn MosaicAccess {
+        match self {
+            Decoded::LibRaw(raw_image) => &**raw_image,
+            #[cfg(feature = "fallback")]
+            Decoded::Fallback(image) => image.as_ref(),
+        }
+    }
+}
+
+impl MetadataAccess for Decoded {
+    fn full_info(&self) -> FullRawInfo {
+        self.metadata().full_info()
+    }
+}
+
+impl MosaicAccess for Decoded {
+    fn mosaic(&self) -> Option<&[u16]> {
+        self.mosaic_source().mosaic()
+    }
+
+    fn mosaic_width(&self) -> u32 {
+        self.mosaic_source().mosaic_width()
+    }
+
+    fn mosaic_height(&self) -> u32 {
+        self.mosaic_source().mosaic_height()
+    }
+
+    fn filters(&self) -> u32 {
+        self.mosaic_source().filters()
+    }
+}
+
+impl MetadataAccess for RawImage {
+    fn full_info(&self) -> FullRawInfo {
+        RawImage::full_info(self)
+    }
+}
+
+impl MosaicAccess for RawImage {
+    fn mosaic(&self) -> Option<&[u16]> {
+        if self.as_ref().rawdata.raw_image.is_null() {
+            return None;
+        }
+        Some(self.raw_image())
+    }
+
+    fn mosaic_width(&self) -> u32 {
+        self.as_ref().sizes.raw_width as _
+    }
+
+    fn mosaic_height(&self) -> u32 {
+        self.as_ref().sizes.raw_height as _
+    }
+
+    fn filters(&self) -> u32 {
+        RawImage::filters(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_open_any() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let Ok(Decoded::LibRaw(mut raw_image)) = open_any(data) else {
+            panic!("not opened with LibRaw");
+        };
+        // the buffer is gone from here, the image reads its own
+        raw_image.unpack().expect("unpacked");
+        assert_eq!(raw_image.full_info().model, "Z 8");
+        assert!(open_any(vec![0u8; 1024]).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    err::{Error, Result},
+    RawImage,
+};
+
+const FILTERS_XTRANS: u32 = 9;
+
+// Sensor values of one CFA color over the visible area, black level included.
+// A channel stuck at one value has `min == max`, an unusual black offset shows
+// in the darkest frames' `mean`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelStats {
+    // LibRaw's color index, 3 being the second green of RGBG Bayer layouts
+    pub color: u8,
+    // its letter in `channel_description`
+    pub name: char,
+    pub count: u64,
+    pub min: u16,
+    pub max: u16,
+    pub mean: f64,
+    pub stddev: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Accumulator {
+    count: u64,
+    min: u16,
+    max: u16,
+    sum: u64,
+    sum_sq: u128,
+}
+
+impl RawImage {
+    // One entry per color of the CFA, ordered by color index, in a single pass
+    // over the unpacked mosaic, or per channel of a linear raw's pixels.
+    // `Error::OutOfOrderCall` before `unpack`, `Error::NotImplemented` for
+    // mosaics other than Bayer and X-Trans.
+    pub fn channel_stats(&self) -> Result<Vec<ChannelStats>> {
+        if !self.is_unpacked() {
+            return Err(Error::OutOfOrderCall);
+        }
+        let data = self.as_ref();
+        let sizes = &data.sizes;
+        // less what auto crop found, whole CFA periods so colors stay put
+        let [left, top, width, height] = self
+            .dark_borders()
+            .unwrap_or_default()
+            .inner(sizes.width as u32, sizes.height as u32)
+            .map(|v| v as usize);
+        let mut acc = [Accumulator {
+            count: 0,
+            min: u16::MAX,
+            max: 0,
+            sum: 0,
+            sum_sq: 0,
+        }; 4];
+
+        let (linear, channels) = self.linear_image();
+        if channels > 0 {
+            let pitch = linear.len() / (sizes.raw_height as usize).max(1);
+            let colors = (self.colors() as usize).min(channels);
+            let (top, left) = (
+                sizes.top_margin as usize + top,
+                sizes.left_margin as usize + left,
+            );
+            for row in top..top + height {
+                let src = &linear[row * pitch + left * channels..][..width * channels];
+                for pixel in src.chunks_exact(channels) {
+                    for (a, &v) in acc.iter_mut().zip(&pixel[..colors]) {
+                        a.add(v);
+                    }
+                }
+            }
+            return Ok(self.finish(&acc));
+        }
+        let visible = self.visible_mosaic()?;
+        // the color of every position of one repeat of the pattern
+        let filters = self.filters();
+        let (period_rows, period_cols, pattern) = match filters {
+            FILTERS_XTRANS => (6, 6, {
+                let xtrans = &data.idata.xtrans;
+                (0..36).map(|i| xtrans[i / 6][i % 6] as u8 & 3).collect()
+            }),
+            f if f >= 1000 => (8, 2, {
+                (0..16)
+                    .map(|i| ((f >> ((((i / 2) << 1) & 14 | (i % 2)) << 1)) & 3) as u8)
+                    .collect::<Vec<_>>()
+            }),
+            _ => return Err(Error::NotImplemented),
+        };
+
+        for row in 0..height {
+            let colors = &pattern[(row % period_rows) * period_cols..][..period_cols];
+            let src = &visible.row(top + row)[left..][..width];
+            for (col, &v) in src.iter().enumerate() {
+                acc[colors[col % period_cols] as usize].add(v);
+            }
+        }
+        Ok(self.finish(&acc))
+    }
+
+    fn finish(&self, acc: &[Accumulator; 4]) -> Vec<ChannelStats> {
+        let cdesc: Vec<char> = self.channel_description().chars().collect();
+        acc.iter()
+            .enumerate()
+            .filter(|(_, a)| a.count > 0)
+            .map(|(color, a)| {
+                let n = a.count as f64;
+                let mean = a.sum as f64 / n;
+                let variance = (a.sum_sq as f64 / n - mean * mean).max(0.0);
+                ChannelStats {
+                    color: color as u8,
+                    name: cdesc.get(color).copied().unwrap_or('?'),
+                    count: a.count,
+                    min: a.min,
+                    max: a.max,
+                    mean,
+                    stddev: variance.sqrt(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Accumulator {
+    fn add(&mut self, v: u16) {
+        self.count += 1;
+        self.min = self.min.min(v);
+        self.max = self.max.max(v);
+        self.sum += v as u64;
+        self.sum_sq += (v as u64 * v as u64) as u128;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_channel_stats() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(matches!(
+            raw_image.channel_stats(),
+            Err(Error::OutOfOrderCall)
+        ));
+        raw_image.unpack().expect("unpacked");
+        let stats = raw_image.channel_stats().expect("stats");
+        let names: String = stats.iter().map(|s| s.name).collect();
+        assert_eq!(names, "RGBG");
+        let pixels = raw_image.width() as u64 * raw_image.height() as u64;
+        assert_eq!(stats.iter().map(|s| s.count).sum::<u64>(), pixels);
+        for s in &stats {
+            assert_eq!(s.count, pixels / 4);
+            assert!(s.min as f64 <= s.mean && s.mean <= s.max as f64);
+            assert!(s.stddev > 0.0);
+        }
+    }
+}
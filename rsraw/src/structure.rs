@@ -0,0 +1,124 @@
+use crate::{ifd::FileTiff, RawImage};
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_MAKER_NOTE: u16 = 0x927c;
+
+// One region of the file LibRaw found, `offset` and `size` in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    pub kind: BlockKind,
+    pub offset: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlockKind {
+    // an IFD of IFD0's chain or a SubIFD, the header and entries only
+    Ifd { entries: u16, subfile_type: u32 },
+    ExifIfd { entries: u16 },
+    MakerNote,
+    // what unpack decodes
+    RawData,
+    // index as in `thumb_infos`
+    Thumbnail(i32),
+    // a CR3 track by its media type, the CTMD metadata being 3
+    Track(u32),
+}
+
+impl RawImage {
+    // The layout of the open file, sorted by offset. IFDs, the EXIF IFD and
+    // the maker note for TIFF based files, CR3 tracks, and for every format
+    // the raw data and previews where LibRaw will read them. Nothing is
+    // checked against the file's length, a block running past the end is
+    // what a truncated or tampered file looks like.
+    pub fn structure(&self) -> Vec<Block> {
+        let mut blocks = Vec::new();
+        if let Some(tiff) = FileTiff::new(self) {
+            let ifd_size = |entries: usize| 2 + entries as u64 * 12 + 4;
+            for ifd in tiff.ifds() {
+                let entries = ifd.entries.len() as u16;
+                blocks.push(Block {
+                    kind: BlockKind::Ifd {
+                        entries,
+                        subfile_type: ifd.subfile_type(),
+                    },
+                    offset: ifd.offset,
+                    size: ifd_size(ifd.entries.len()),
+                });
+                let Some(exif) = ifd.get(TAG_EXIF_IFD) else {
+                    continue;
+                };
+                let Some((exif, _)) = tiff.ifd(exif.value as u64) else {
+                    continue;
+                };
+                blocks.push(Block {
+                    kind: BlockKind::ExifIfd {
+                        entries: exif.entries.len() as u16,
+                    },
+                    offset: exif.offset,
+                    size: ifd_size(exif.entries.len()),
+                });
+                if let Some(note) = exif.get(TAG_MAKER_NOTE) {
+                    blocks.push(Block {
+                        kind: BlockKind::MakerNote,
+                        offset: note.value as u64,
+                        size: note.count as u64,
+                    });
+                }
+            }
+        }
+        blocks.extend(
+            self.crx_tracks()
+                .into_iter()
+                .map(|(kind, offset, size)| Block {
+                    kind: BlockKind::Track(kind),
+                    offset,
+                    size: size as u64,
+                }),
+        );
+        let (offset, size) = self.data_segment();
+        if size > 0 {
+            blocks.push(Block {
+                kind: BlockKind::RawData,
+                offset,
+                size,
+            });
+        }
+        blocks.extend(self.thumb_infos().into_iter().map(|info| Block {
+            kind: BlockKind::Thumbnail(info.index),
+            offset: info.offset.max(0) as u64,
+            size: info.length as u64,
+        }));
+        blocks.sort_by_key(|block| (block.offset, block.size));
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_structure() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let raw_image = RawImage::open_owned(data.clone()).expect("opened");
+        let blocks = raw_image.structure();
+        let has = |f: fn(&BlockKind) -> bool| blocks.iter().any(|b| f(&b.kind));
+        assert!(has(|k| matches!(k, BlockKind::Ifd { .. })));
+        assert!(has(|k| matches!(k, BlockKind::ExifIfd { .. })));
+        assert!(has(|k| matches!(k, BlockKind::MakerNote)));
+        assert!(has(|k| matches!(k, BlockKind::Thumbnail(_))));
+        let raw = blocks
+            .iter()
+            .find(|b| b.kind == BlockKind::RawData)
+            .expect("raw data");
+        assert!(raw.size > data.len() as u64 / 2);
+        assert!(blocks.windows(2).all(|w| w[0].offset <= w[1].offset));
+        assert!(blocks
+            .iter()
+            .all(|b| b.offset + b.size <= data.len() as u64));
+    }
+}
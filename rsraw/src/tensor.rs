@@ -0,0 +1,141 @@
+// Pixels as float tensors for training and inference, channels first (CHW)
+// the way candle and PyTorch models take them. Processed images are scaled to
+// 0..1 by their bit depth. The mosaic is the visible area as one plane, the
+// black level taken off per CFA position and divided by the range up to the
+// white level; noise below black stays negative, a denoiser wants to see it.
+// Both are written planar in one pass, the tensor takes the buffer as is.
+
+#[cfg(any(feature = "candle", feature = "tch"))]
+use crate::{err::Error, raw::BitDepth, ProcessedImage};
+use crate::{err::Result, RawImage};
+
+// the samples in CHW order and the tensor's shape
+#[cfg(any(feature = "candle", feature = "tch"))]
+fn planar<const D: BitDepth>(image: &ProcessedImage<D>) -> (Vec<f32>, [usize; 3]) {
+    let channels = image.colors() as usize;
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let plane = width * height;
+    let mut out = vec![0.0; plane * channels];
+    let mut put = |i: usize, v: f32| out[(i % channels) * plane + i / channels] = v;
+    let bytes = image.bytes();
+    match image.bits() {
+        16 => {
+            let scale = 1.0 / u16::MAX as f32;
+            for (i, b) in bytes.chunks_exact(2).enumerate() {
+                put(i, u16::from_ne_bytes([b[0], b[1]]) as f32 * scale);
+            }
+        }
+        _ => {
+            let scale = 1.0 / u8::MAX as f32;
+            for (i, &b) in bytes.iter().enumerate() {
+                put(i, b as f32 * scale);
+            }
+        }
+    }
+    (out, [channels, height, width])
+}
+
+impl RawImage {
+    // the visible area of the unpacked mosaic, normalized, and its 1xHxW shape
+    pub(crate) fn normalized_mosaic(&self) -> Result<(Vec<f32>, [usize; 3])> {
+        let visible = self.visible_mosaic()?;
+        let out = visible.normalized().collect();
+        Ok((out, [1, visible.height, visible.width]))
+    }
+}
+
+#[cfg(feature = "candle")]
+fn candle_tensor(
+    samples: Vec<f32>,
+    [c, h, w]: [usize; 3],
+    device: &candle_core::Device,
+) -> Result<candle_core::Tensor> {
+    candle_core::Tensor::from_vec(samples, (c, h, w), device).map_err(|_| Error::Unspecified)
+}
+
+#[cfg(feature = "candle")]
+impl<const D: BitDepth> ProcessedImage<D> {
+    pub fn to_candle(&self, device: &candle_core::Device) -> Result<candle_core::Tensor> {
+        let (samples, shape) = planar(self);
+        candle_tensor(samples, shape, device)
+    }
+}
+
+#[cfg(feature = "candle")]
+impl RawImage {
+    // `Error::OutOfOrderCall` before `unpack`
+    pub fn mosaic_to_candle(&self, device: &candle_core::Device) -> Result<candle_core::Tensor> {
+        let (samples, shape) = self.normalized_mosaic()?;
+        candle_tensor(samples, shape, device)
+    }
+}
+
+#[cfg(feature = "tch")]
+fn tch_tensor(samples: &[f32], shape: [usize; 3]) -> Result<tch::Tensor> {
+    tch::Tensor::f_from_slice(samples)
+        .and_then(|t| t.f_reshape(shape.map(|n| n as i64)))
+        .map_err(|_| Error::Unspecified)
+}
+
+#[cfg(feature = "tch")]
+impl<const D: BitDepth> ProcessedImage<D> {
+    // on the CPU, `to_device` moves it from there
+    pub fn to_tch(&self) -> Result<tch::Tensor> {
+        let (samples, shape) = planar(self);
+        tch_tensor(&samples, shape)
+    }
+}
+
+#[cfg(feature = "tch")]
+impl RawImage {
+    pub fn mosaic_to_tch(&self) -> Result<tch::Tensor> {
+        let (samples, shape) = self.normalized_mosaic()?;
+        tch_tensor(&samples, shape)
+    }
+}
+
+#[cfg(all(test, any(feature = "candle", feature = "tch")))]
+mod tests {
+    use super::*;
+    use crate::{err::Error, raw::tests::get_test_assets_path, BIT_DEPTH_16};
+
+    #[test]
+    fn test_planar() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(matches!(
+            raw_image.normalized_mosaic(),
+            Err(Error::OutOfOrderCall)
+        ));
+        let image = raw_image
+            .process_with::<BIT_DEPTH_16>(&Default::default())
+            .expect("processed");
+        let (samples, shape) = planar(&image);
+        let [channels, height, width] = shape;
+        assert_eq!(
+            (channels, height, width),
+            (3, image.height() as usize, image.width() as usize)
+        );
+        // the green of the second pixel
+        let plane = width * height;
+        assert_eq!(samples[plane + 1], image[4] as f32 / u16::MAX as f32);
+
+        let (mosaic, shape) = raw_image.normalized_mosaic().expect("unpacked");
+        assert_eq!(
+            shape,
+            [1, raw_image.height() as usize, raw_image.width() as usize]
+        );
+        assert_eq!(mosaic.len(), shape[1] * shape[2]);
+        let mean = mosaic.iter().map(|&v| v as f64).sum::<f64>() / mosaic.len() as f64;
+        assert!(mean > 0.0 && mean < 1.0, "{mean}");
+
+        #[cfg(feature = "candle")]
+        {
+            let device = candle_core::Device::Cpu;
+            let tensor = image.to_candle(&device).expect("tensor");
+            assert_eq!(tensor.dims(), &[channels, height, width]);
+            let tensor = raw_image.mosaic_to_candle(&device).expect("tensor");
+            assert_eq!(tensor.dims(), &shape);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::{path::Path, time::Duration};
+
+use crate::{
+    err::{Error, Result},
+    OpenFailure, RawImage,
+};
+
+// Opening raws a tethering app or card reader is still writing. A file that
+// ends before its image data, or keeps changing size while it fails to open,
+// is read again until the attempts run out and then reported as
+// `Error::Incomplete`, so callers can tell "poll again" from a damaged file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TetherOptions {
+    pub attempts: u32,
+    pub interval: Duration,
+    // the last attempt decodes whatever arrived like `RawImage::open_partial`
+    // instead of failing
+    pub accept_partial: bool,
+}
+
+impl Default for TetherOptions {
+    fn default() -> Self {
+        Self {
+            attempts: 10,
+            interval: Duration::from_millis(200),
+            accept_partial: false,
+        }
+    }
+}
+
+// the image keeps the file contents it was opened from, as with `open_owned`
+pub fn open(path: impl AsRef<Path>, options: &TetherOptions) -> Result<RawImage> {
+    let path = path.as_ref();
+    let attempts = options.attempts.max(1);
+    let mut last_len = None;
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            std::thread::sleep(options.interval);
+        }
+        let data = std::fs::read(path)?;
+        let len = data.len();
+        if options.accept_partial && attempt + 1 == attempts {
+            return Ok(RawImage::open_partial(data)?);
+        }
+        let growing = last_len.is_some_and(|last| last != len);
+        match RawImage::open_owned_diagnosed(data) {
+            Ok(image) if is_complete(&image, len) => return Ok(image),
+            Ok(_) => {}
+            Err(diagnosis)
+                if diagnosis.failure == OpenFailure::Truncated || len == 0 || growing => {}
+            Err(diagnosis) => return Err(diagnosis.into()),
+        }
+        last_len = Some(len);
+    }
+    Err(Error::Incomplete)
+}
+
+// whether all of the image data LibRaw expects is in the first `len` bytes
+fn is_complete(image: &RawImage, len: usize) -> bool {
+    let (offset, size) = image.data_segment();
+    offset + size <= len as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    #[test]
+    fn test_open_tethered() {
+        let source = get_test_assets_path().join("test-z8.NEF");
+        let options = TetherOptions {
+            attempts: 2,
+            interval: Duration::ZERO,
+            accept_partial: false,
+        };
+        let image = open(&source, &options).expect("complete");
+        assert_eq!(image.model(), "Z 8");
+        assert!(image.partial().is_none());
+
+        // a capture cut off in the middle of the image data
+        let data = std::fs::read(&source).unwrap();
+        let path = std::env::temp_dir().join(format!("rsraw-tether-{}.NEF", std::process::id()));
+        std::fs::write(&path, &data[..data.len() * 3 / 4]).unwrap();
+        assert!(matches!(open(&path, &options), Err(Error::Incomplete)));
+        let partial = open(
+            &path,
+            &TetherOptions {
+                accept_partial: true,
+                ..options.clone()
+            },
+        )
+        .expect("partial");
+        assert!(partial.partial().is_some());
+
+        // garbage that isn't changing is a plain failure
+        std::fs::write(&path, [0x55; 4096]).unwrap();
+        let err = open(&path, &options).err().expect("not raw");
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(err, Error::FileUnsupported));
+    }
+}
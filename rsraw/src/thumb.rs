@@ -2,7 +2,14 @@ use std::fmt;
 
 use rsraw_sys as sys;
 
+use crate::{format::Tiff, RawImage};
+
+const TAG_ORIENTATION: u16 = 0x112;
+// LibRaw's flip, 1 mirrors columns, 2 rows, 4 transposes, as EXIF orientations
+const FLIP_TO_ORIENTATION: [u16; 8] = [1, 2, 4, 3, 5, 8, 6, 7];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ThumbFormat {
     Unknown,
     Jpeg,
@@ -18,6 +25,18 @@ pub struct Thumbnails {
     thumbs: Vec<ThumbnailImage>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThumbInfo {
+    pub index: i32,
+    pub format: ThumbFormat,
+    pub width: u32,
+    pub height: u32,
+    pub flip: u16,
+    pub length: u32,
+    pub offset: i64,
+}
+
 #[derive(Hash)]
 pub struct ThumbnailImage {
     pub format: ThumbFormat,
@@ -34,7 +53,7 @@ impl Thumbnails {
 
     pub fn append(&mut self, image: ThumbnailImage) {
         self.thumbs.push(image);
-        self.thumbs.sort_by(|a, b| a.height.cmp(&b.height));
+        self.thumbs.sort_by_key(|t| t.height);
     }
 
     pub fn into_inner(self) -> Vec<ThumbnailImage> {
@@ -56,6 +75,212 @@ impl From<sys::LibRaw_thumbnail_formats> for ThumbFormat {
     }
 }
 
+impl ThumbFormat {
+    // thumbs_list entries carry the format LibRaw detected while parsing,
+    // before unpacking decides what it will be converted to
+    pub(crate) fn from_internal(ft: sys::LibRaw_internal_thumbnail_formats) -> Self {
+        match ft {
+            sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_JPEG => Self::Jpeg,
+            sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_LAYER => Self::Layer,
+            sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_ROLLEI => Self::Rollei,
+            sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_PPM16 => {
+                Self::Bitmap16
+            }
+            sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_KODAK_THUMB
+            | sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_KODAK_YCBCR
+            | sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_KODAK_RGB
+            | sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_PPM
+            | sys::LibRaw_internal_thumbnail_formats_LIBRAW_INTERNAL_THUMBNAIL_X3F => Self::Bitmap,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl ThumbInfo {
+    pub fn pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+impl ThumbnailImage {
+    // The Orientation tag of a JPEG preview's own EXIF, 1 to 8. Cameras may
+    // write 1 there for a portrait frame and leave rotating to the raw's flag,
+    // or the other way round. `None` for bitmaps and JPEGs without one.
+    pub fn exif_orientation(&self) -> Option<u16> {
+        let (pos, le) = self.orientation_pos()?;
+        let tiff = Tiff {
+            buf: &self.data,
+            le,
+        };
+        tiff.u16_at(pos).filter(|o| (1..=8).contains(o))
+    }
+
+    // Rewrites the tag in place, or adds it to IFD0 or as a new EXIF segment
+    // when the JPEG has none. False for bitmaps and JPEGs whose EXIF
+    // segment can't take another entry.
+    pub fn set_exif_orientation(&mut self, orientation: u16) -> bool {
+        if self.format != ThumbFormat::Jpeg {
+            return false;
+        }
+        let Some((pos, le)) = self.orientation_pos() else {
+            return self.insert_orientation(orientation).is_some();
+        };
+        let bytes = match le {
+            true => orientation.to_le_bytes(),
+            false => orientation.to_be_bytes(),
+        };
+        self.data[pos..pos + 2].copy_from_slice(&bytes);
+        true
+    }
+
+    fn insert_orientation(&mut self, orientation: u16) -> Option<()> {
+        let Some((start, tiff)) = jpeg_exif(&self.data) else {
+            // after SOI and a JFIF APP0, which has to come first
+            let mut pos = 2;
+            if self.data.get(pos..pos + 2)? == [0xff, 0xe0] {
+                let len = self.data.get(pos + 2..pos + 4)?;
+                pos += 2 + u16::from_be_bytes([len[0], len[1]]) as usize;
+            }
+            let mut segment = vec![0xff, 0xe1, 0, 34];
+            segment.extend(b"Exif\0\0II*\0\x08\0\0\0\x01\0");
+            segment.extend(orientation_entry(true, orientation));
+            segment.extend([0; 4]);
+            self.data.splice(pos..pos, segment);
+            return Some(());
+        };
+        // IFD0 again with the tag in order at the end of the TIFF, values
+        // elsewhere keep their offsets
+        let le = tiff.le;
+        let ifd = tiff.u32_at(4)? as usize;
+        let count = tiff.u16_at(ifd)?;
+        let entries = tiff.buf.get(ifd + 2..ifd + 2 + count as usize * 12)?;
+        let next = tiff.buf.get(ifd + 2 + count as usize * 12..)?.get(..4)?;
+        let end = tiff.buf.len();
+        let mut moved = vec![0; end % 2];
+        let new_ifd = end + moved.len();
+        moved.extend(match le {
+            true => (count + 1).to_le_bytes(),
+            false => (count + 1).to_be_bytes(),
+        });
+        let tag = |entry: &[u8]| match le {
+            true => u16::from_le_bytes([entry[0], entry[1]]),
+            false => u16::from_be_bytes([entry[0], entry[1]]),
+        };
+        let at = entries
+            .chunks_exact(12)
+            .position(|entry| tag(entry) > TAG_ORIENTATION)
+            .unwrap_or(count as usize);
+        moved.extend(&entries[..at * 12]);
+        moved.extend(orientation_entry(le, orientation));
+        moved.extend(&entries[at * 12..]);
+        moved.extend(next);
+
+        let len_pos = start - 8;
+        let len = u16::from_be_bytes([self.data[len_pos], self.data[len_pos + 1]]) as usize;
+        let len = u16::try_from(len + moved.len()).ok()?;
+        let new_ifd = u32::try_from(new_ifd).ok()?;
+        self.data[len_pos..len_pos + 2].copy_from_slice(&len.to_be_bytes());
+        self.data[start + 4..start + 8].copy_from_slice(&match le {
+            true => new_ifd.to_le_bytes(),
+            false => new_ifd.to_be_bytes(),
+        });
+        self.data.splice(start + end..start + end, moved);
+        Some(())
+    }
+
+    // where the Orientation value sits in IFD0 of the APP1 EXIF segment, and
+    // whether that TIFF is little endian
+    fn orientation_pos(&self) -> Option<(usize, bool)> {
+        if self.format != ThumbFormat::Jpeg {
+            return None;
+        }
+        let (start, tiff) = jpeg_exif(&self.data)?;
+        let ifd = tiff.u32_at(4)? as usize;
+        let count = tiff.u16_at(ifd)? as usize;
+        (0..count).map(|i| ifd + 2 + i * 12).find_map(|entry| {
+            // a single short
+            let found = tiff.u16_at(entry)? == TAG_ORIENTATION
+                && tiff.u16_at(entry + 2)? == 3
+                && tiff.u32_at(entry + 4)? == 1;
+            found.then_some((start + entry + 8, tiff.le))
+        })
+    }
+}
+
+// an IFD entry of the tag, a single short
+fn orientation_entry(le: bool, orientation: u16) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(12);
+    match le {
+        true => {
+            entry.extend(TAG_ORIENTATION.to_le_bytes());
+            entry.extend(3u16.to_le_bytes());
+            entry.extend(1u32.to_le_bytes());
+            entry.extend(orientation.to_le_bytes());
+        }
+        false => {
+            entry.extend(TAG_ORIENTATION.to_be_bytes());
+            entry.extend(3u16.to_be_bytes());
+            entry.extend(1u32.to_be_bytes());
+            entry.extend(orientation.to_be_bytes());
+        }
+    }
+    entry.extend([0, 0]);
+    entry
+}
+
+// The TIFF structure of a JPEG's APP1 EXIF segment and where it starts in
+// `data`, `None` for JPEGs without one
+pub(crate) fn jpeg_exif(data: &[u8]) -> Option<(usize, Tiff<'_>)> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut pos = 2;
+    // markers up to the start of the scan, EXIF is always among the first
+    while data.get(pos) == Some(&0xff) {
+        let marker = *data.get(pos + 1)?;
+        let len = u16::from_be_bytes([*data.get(pos + 2)?, *data.get(pos + 3)?]) as usize;
+        if marker == 0xda {
+            return None;
+        }
+        let segment = data.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xe1 && segment.starts_with(b"Exif\0\0") {
+            let le = match segment.get(6..8)? {
+                b"II" => true,
+                b"MM" => false,
+                _ => return None,
+            };
+            let start = pos + 10;
+            let buf = &data[start..pos + 2 + len];
+            return Some((start, Tiff { buf, le }));
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+impl RawImage {
+    // the orientation `process` turns the image to, as an EXIF Orientation
+    pub fn exif_orientation(&self) -> u16 {
+        FLIP_TO_ORIENTATION[(self.as_ref().sizes.flip & 7) as usize]
+    }
+
+    // the flip that turns a thumbnail upright. LibRaw's 0xffff is unknown,
+    // most previews don't tag their own and follow the raw
+    pub(crate) fn thumb_flip(&self, info: &ThumbInfo) -> i32 {
+        match info.flip {
+            0 | 0xffff => self.as_ref().sizes.flip,
+            flip => flip as i32,
+        }
+    }
+
+    // Makes a JPEG preview's EXIF say what the developed image does, so a
+    // viewer that honors the tag shows both the same way up, adding the tag
+    // when there's none. False for bitmaps, see `set_exif_orientation`.
+    pub fn normalize_thumb_orientation(&self, thumb: &mut ThumbnailImage) -> bool {
+        thumb.set_exif_orientation(self.exif_orientation())
+    }
+}
+
 impl fmt::Debug for ThumbnailImage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ThumbnailImage")
@@ -67,3 +292,109 @@ impl fmt::Debug for ThumbnailImage {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    // SOI, a JFIF APP0, then APP1 EXIF with IFD0 holding these shorts
+    fn jpeg(le: bool, tags: &[(u16, u16)]) -> ThumbnailImage {
+        let u16b = |v: u16| match le {
+            true => v.to_le_bytes(),
+            false => v.to_be_bytes(),
+        };
+        let u32b = |v: u32| match le {
+            true => v.to_le_bytes(),
+            false => v.to_be_bytes(),
+        };
+        let mut tiff = match le {
+            true => b"II".to_vec(),
+            false => b"MM".to_vec(),
+        };
+        tiff.extend(u16b(42));
+        tiff.extend(u32b(8));
+        tiff.extend(u16b(tags.len() as u16));
+        for &(tag, value) in tags {
+            tiff.extend(u16b(tag));
+            tiff.extend(u16b(3));
+            tiff.extend(u32b(1));
+            tiff.extend(u16b(value));
+            tiff.extend([0, 0]);
+        }
+        tiff.extend([0, 0, 0, 0]);
+        let mut data = vec![0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xe1];
+        data.extend((8 + tiff.len() as u16).to_be_bytes());
+        data.extend(b"Exif\0\0");
+        data.extend(tiff);
+        data.extend([0xff, 0xda, 0, 2, 0xff, 0xd9]);
+        ThumbnailImage {
+            format: ThumbFormat::Jpeg,
+            width: 1,
+            height: 1,
+            colors: 3,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_exif_orientation() {
+        for le in [true, false] {
+            let mut thumb = jpeg(le, &[(TAG_ORIENTATION, 6)]);
+            assert_eq!(thumb.exif_orientation(), Some(6));
+            assert!(thumb.set_exif_orientation(8));
+            assert_eq!(thumb.exif_orientation(), Some(8));
+
+            // added between the tags around it, those keep their values
+            let (width, resolution_unit) = (0x100, 0x128);
+            let mut thumb = jpeg(le, &[(width, 640), (resolution_unit, 2)]);
+            assert_eq!(thumb.exif_orientation(), None);
+            assert!(thumb.set_exif_orientation(3));
+            assert_eq!(thumb.exif_orientation(), Some(3));
+            let (_, tiff) = jpeg_exif(&thumb.data).expect("exif");
+            let ifd = tiff.u32_at(4).unwrap() as usize;
+            let tags: Vec<_> = (0..tiff.u16_at(ifd).unwrap() as usize)
+                .map(|i| ifd + 2 + i * 12)
+                .map(|e| (tiff.u16_at(e).unwrap(), tiff.u16_at(e + 8).unwrap()))
+                .collect();
+            assert_eq!(
+                tags,
+                [(width, 640), (TAG_ORIENTATION, 3), (resolution_unit, 2)]
+            );
+            assert!(thumb.data.ends_with(&[0xff, 0xda, 0, 2, 0xff, 0xd9]));
+        }
+        // no EXIF at all, a segment is added after the JFIF one
+        let mut thumb = jpeg(true, &[]);
+        let jfif = [0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0];
+        thumb.data = [&jfif[..], &[0xff, 0xda, 0, 2, 0xff, 0xd9]].concat();
+        assert!(thumb.set_exif_orientation(6));
+        assert_eq!(thumb.exif_orientation(), Some(6));
+        assert!(thumb.data.starts_with(&jfif));
+
+        let mut thumb = jpeg(true, &[(TAG_ORIENTATION, 1)]);
+        thumb.format = ThumbFormat::Bitmap;
+        assert_eq!(thumb.exif_orientation(), None);
+        assert!(!thumb.set_exif_orientation(1));
+
+        // the Z8's preview has no tag, it's added saying what the raw does
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let orientation = raw_image.exif_orientation();
+        assert!((1..=8).contains(&orientation));
+        let mut thumb = raw_image.extract_best_thumb().expect("best thumb");
+        assert_eq!(thumb.exif_orientation(), None);
+        assert!(raw_image.normalize_thumb_orientation(&mut thumb));
+        assert_eq!(thumb.exif_orientation(), Some(orientation));
+        assert!(crate::preview::decode_jpeg(&thumb.data, 256).is_ok());
+
+        // a thumb's own flip wins, untagged ones follow the raw
+        let (mut info, _) = raw_image.best_thumb().expect("best thumb");
+        let raw_flip = raw_image.as_ref().sizes.flip;
+        for flip in [0, 0xffff] {
+            info.flip = flip;
+            assert_eq!(raw_image.thumb_flip(&info), raw_flip);
+        }
+        info.flip = 5;
+        assert_eq!(raw_image.thumb_flip(&info), 5);
+    }
+}
@@ -0,0 +1,121 @@
+// Decoding from async code. LibRaw calls block, so everything here runs on
+// tokio's blocking pool and needs to be polled within a tokio runtime.
+
+use std::panic;
+
+use crate::{
+    err::ContextError, raw::BitDepth, ProcessParams, ProcessedImage, RawImage, ThumbnailImage,
+};
+
+pub mod batch;
+
+// A panic in the call is raised again in the awaiting task. Other join errors
+// only come from a runtime shutting down, which drops that task as well.
+async fn blocking<T: Send + 'static>(run: impl FnOnce() -> T + Send + 'static) -> T {
+    match ::tokio::task::spawn_blocking(run).await {
+        Ok(value) => value,
+        Err(err) if err.is_panic() => panic::resume_unwind(err.into_panic()),
+        Err(err) => panic!("{err}"),
+    }
+}
+
+// The image moves to the pool for every call and comes back with the result,
+// also when the call failed. Dropping a future doesn't stop the call, the
+// image is dropped once it's done.
+impl RawImage {
+    // `open` for an owned file, which the image keeps
+    pub async fn open_async(data: Vec<u8>) -> std::result::Result<Self, ContextError> {
+        blocking(move || Self::open_owned(data)).await
+    }
+
+    pub async fn process_async<const D: BitDepth>(
+        mut self,
+    ) -> (Self, std::result::Result<ProcessedImage<D>, ContextError>)
+    where
+        ProcessedImage<D>: Send,
+    {
+        blocking(move || {
+            let result = self.process::<D>();
+            (self, result)
+        })
+        .await
+    }
+
+    pub async fn process_with_async<const D: BitDepth>(
+        mut self,
+        params: &ProcessParams,
+    ) -> (Self, std::result::Result<ProcessedImage<D>, ContextError>)
+    where
+        ProcessedImage<D>: Send,
+    {
+        let params = params.clone();
+        blocking(move || {
+            let result = self.process_with::<D>(&params);
+            (self, result)
+        })
+        .await
+    }
+
+    pub async fn extract_thumbs_async(
+        mut self,
+    ) -> (Self, std::result::Result<Vec<ThumbnailImage>, ContextError>) {
+        blocking(move || {
+            let result = self.extract_thumbs();
+            (self, result)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{err::Error, raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    #[test]
+    fn test_async() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert!(RawImage::open_async(vec![0; 1024]).await.is_err());
+            let raw_image = RawImage::open_async(data).await.expect("opened");
+            let (raw_image, thumbs) = raw_image.extract_thumbs_async().await;
+            assert!(!thumbs.expect("thumbs").is_empty());
+            let params = ProcessParams {
+                half_size: true,
+                ..Default::default()
+            };
+
+            let (mut raw_image, image) = raw_image.process_with_async::<BIT_DEPTH_8>(&params).await;
+            let image = image.expect("processed");
+            assert_eq!((image.width(), image.height()), (4140, 2760));
+
+            // a failed call hands the image back as well
+            raw_image.set_memory_limit_mb(1);
+            let (raw_image, image) = raw_image.process_async::<BIT_DEPTH_8>().await;
+            assert!(
+                matches!(
+                    image,
+                    Err(ContextError {
+                        error: Error::TooBig,
+                        ..
+                    })
+                ),
+                "{image:?}"
+            );
+            assert_eq!(raw_image.model(), "Z 8");
+        });
+    }
+
+    #[test]
+    fn test_blocking_panic() {
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = panic::catch_unwind(|| runtime.block_on(blocking(|| panic!("decoder bug"))));
+        let payload = result.expect_err("panicked");
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"decoder bug"));
+    }
+}
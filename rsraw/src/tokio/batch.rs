@@ -0,0 +1,91 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures_util::{stream, Stream, StreamExt};
+
+use super::blocking;
+use crate::{
+    batch::{process_file, BatchItem, MemoryBudget},
+    raw::BitDepth,
+    ProcessParams, ProcessedImage,
+};
+
+// Like `batch::process`, but as a stream: at most `concurrency` files are
+// decoded at once and the next one only starts when the consumer pulls, so a
+// slow consumer holds back decoding instead of piling up bitmaps. Results
+// come in completion order, concurrency of 0 uses one task per logical core.
+// Tasks wait for `memory_budget` like `batch::process_budgeted`'s workers,
+// `u64::MAX` leaves it to `concurrency` alone. A decode that panics panics
+// again in the consumer.
+pub fn stream<const D: BitDepth, P>(
+    paths: impl IntoIterator<Item = P>,
+    params: &ProcessParams,
+    concurrency: usize,
+    memory_budget: u64,
+) -> impl Stream<Item = BatchItem<ProcessedImage<D>>>
+where
+    P: AsRef<Path>,
+    ProcessedImage<D>: Send,
+{
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|p| p.as_ref().to_path_buf())
+        .collect();
+    let params = Arc::new(params.clone());
+    let budget = Arc::new(MemoryBudget::new(memory_budget));
+    let concurrency = match concurrency {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    stream::iter(paths)
+        .map(move |path| {
+            let (params, budget) = (params.clone(), budget.clone());
+            blocking(move || {
+                let result = process_file(&path, &params, &budget);
+                BatchItem { path, result }
+            })
+        })
+        .buffer_unordered(concurrency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{err::Error, raw::tests::get_test_assets_path, BIT_DEPTH_8};
+
+    #[test]
+    fn test_stream() {
+        let assets = get_test_assets_path();
+        let paths = [
+            assets.join("test-z8.NEF"),
+            assets.join("test-a7rm4.ARW"),
+            assets.join("missing.NEF"),
+        ];
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        // both files are over budget, so they decode one at a time
+        let items: Vec<_> =
+            runtime.block_on(stream::<BIT_DEPTH_8, _>(&paths, &params, 2, 1).collect());
+        assert_eq!(items.len(), 3);
+        let failed: Vec<_> = items
+            .iter()
+            .filter(|item| matches!(item.result, Err(Error::Fs(_))))
+            .map(|item| &item.path)
+            .collect();
+        assert_eq!(failed, [&paths[2]]);
+        let mut sizes: Vec<_> = items
+            .iter()
+            .filter_map(|item| item.result.as_ref().ok())
+            .map(|image| (image.width(), image.height()))
+            .collect();
+        sizes.sort();
+        assert_eq!(sizes, [(4140, 2760), (4784, 3188)]);
+    }
+}
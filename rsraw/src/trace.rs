@@ -0,0 +1,19 @@
+// Thin wrappers so call sites don't need a `cfg` each, without the `tracing`
+// feature they expand to nothing and the field expressions aren't evaluated.
+
+macro_rules! span {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($args)*).entered();
+    };
+}
+
+macro_rules! event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($args)*);
+    };
+}
+
+pub(crate) use event;
+pub(crate) use span;
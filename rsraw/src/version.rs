@@ -0,0 +1,100 @@
+use std::ffi::CStr;
+
+use rsraw_sys as sys;
+
+// What the linked LibRaw was built with. The bundled build never enables
+// Jasper (RED R3D), zlib or libjpeg; OpenMP follows the `openmp` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities {
+    pub rawspeed: bool,
+    pub rawspeed3: bool,
+    pub dng_sdk: bool,
+    pub gpr_sdk: bool,
+    pub x3f_tools: bool,
+    pub zlib: bool,
+    pub jpeg: bool,
+    pub jasper: bool,
+    pub openmp: bool,
+    // LibRaw's raw `LibRaw_runtime_capabilities` mask
+    pub bits: u32,
+}
+
+// How rsraw-sys built LibRaw. The optional components carry their version
+// when compiled in and are `None` otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BuildInfo {
+    pub libraw_version: &'static str,
+    pub compiler: &'static str,
+    pub target: &'static str,
+    pub openmp: bool,
+    pub zlib: Option<&'static str>,
+    pub jpeg: Option<&'static str>,
+    pub rawspeed: Option<&'static str>,
+    pub dng_sdk: Option<&'static str>,
+}
+
+pub fn build_info() -> BuildInfo {
+    use sys::build_info::*;
+    BuildInfo {
+        libraw_version: LIBRAW_VERSION,
+        compiler: COMPILER,
+        target: TARGET,
+        openmp: OPENMP,
+        zlib: ZLIB,
+        jpeg: JPEG,
+        rawspeed: RAWSPEED,
+        dng_sdk: DNG_SDK,
+    }
+}
+
+// e.g. "0.21.3-Release"
+pub fn version() -> &'static str {
+    unsafe { CStr::from_ptr(sys::libraw_version()) }
+        .to_str()
+        .unwrap_or_default()
+}
+
+// (major << 16) | (minor << 8) | patch, comparable like LIBRAW_MAKE_VERSION
+pub fn version_number() -> u32 {
+    unsafe { sys::libraw_versionNumber() as _ }
+}
+
+pub fn capabilities() -> Capabilities {
+    let bits = unsafe { sys::libraw_capabilities() } as u32;
+    let has = |cap: sys::LibRaw_runtime_capabilities| bits & cap as u32 != 0;
+    Capabilities {
+        rawspeed: has(sys::LibRaw_runtime_capabilities_LIBRAW_CAPS_RAWSPEED),
+        rawspeed3: has(sys::LibRaw_runtime_capabilities_LIBRAW_CAPS_RAWSPEED3),
+        dng_sdk: has(sys::LibRaw_runtime_capabilities_LIBRAW_CAPS_DNGSDK),
+        gpr_sdk: has(sys::LibRaw_runtime_capabilities_LIBRAW_CAPS_GPRSDK),
+        x3f_tools: has(sys::LibRaw_runtime_capabilities_LIBRAW_CAPS_X3FTOOLS),
+        zlib: has(sys::LibRaw_runtime_capabilities_LIBRAW_CAPS_ZLIB),
+        jpeg: has(sys::LibRaw_runtime_capabilities_LIBRAW_CAPS_JPEG),
+        jasper: false,
+        openmp: cfg!(feature = "openmp"),
+        bits,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version() {
+        assert!(version().starts_with("0.21.3"));
+        assert_eq!(version_number(), (21 << 8) | 3);
+        let caps = capabilities();
+        assert_eq!(caps.dng_sdk, cfg!(feature = "gpr"));
+        assert!(!caps.jasper);
+        assert_eq!(caps.openmp, cfg!(feature = "openmp"));
+
+        let build = build_info();
+        assert!(version().starts_with(build.libraw_version));
+        assert_eq!(build.openmp, caps.openmp);
+        assert_eq!(build.rawspeed.is_some(), caps.rawspeed);
+        assert_eq!(build.dng_sdk.is_some(), caps.dng_sdk);
+        assert_eq!(build.zlib.is_some(), caps.zlib);
+        assert_eq!(build.jpeg.is_some(), caps.jpeg);
+    }
+}
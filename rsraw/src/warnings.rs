@@ -0,0 +1,172 @@
+use std::fmt::{self, Display, Formatter};
+
+use rsraw_sys as sys;
+
+// LibRaw's `process_warnings` bitmask. Warnings accumulate over open, unpack
+// and process on the same image and are only cleared by opening a new file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Warnings(u32);
+
+const NAMES: &[(u32, &str)] = &[
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_BAD_CAMERA_WB as _,
+        "bad camera white balance",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_NO_METADATA as _,
+        "no metadata",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_NO_JPEGLIB as _,
+        "no jpeg library",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_NO_EMBEDDED_PROFILE as _,
+        "no embedded color profile",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_NO_INPUT_PROFILE as _,
+        "no input color profile",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_BAD_OUTPUT_PROFILE as _,
+        "bad output color profile",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_NO_BADPIXELMAP as _,
+        "no bad pixel map",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_BAD_DARKFRAME_FILE as _,
+        "bad dark frame file",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_BAD_DARKFRAME_DIM as _,
+        "bad dark frame dimensions",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED_PROBLEM as _,
+        "rawspeed problem",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED_UNSUPPORTED as _,
+        "unsupported by rawspeed",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED_PROCESSED as _,
+        "decoded by rawspeed",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_FALLBACK_TO_AHD as _,
+        "fell back to AHD demosaic",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_PARSEFUJI_PROCESSED as _,
+        "fuji parser used",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_DNGSDK_PROCESSED as _,
+        "decoded by the DNG SDK",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_DNG_IMAGES_REORDERED as _,
+        "DNG images reordered",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_DNG_STAGE2_APPLIED as _,
+        "DNG stage 2 applied",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_DNG_STAGE3_APPLIED as _,
+        "DNG stage 3 applied",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED3_PROBLEM as _,
+        "rawspeed3 problem",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED3_UNSUPPORTED as _,
+        "unsupported by rawspeed3",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED3_PROCESSED as _,
+        "decoded by rawspeed3",
+    ),
+    (
+        sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED3_NOTLISTED as _,
+        "not listed by rawspeed3",
+    ),
+];
+
+impl Warnings {
+    pub const BAD_CAMERA_WB: Self = Self(sys::LibRaw_warnings_LIBRAW_WARN_BAD_CAMERA_WB as _);
+    pub const NO_METADATA: Self = Self(sys::LibRaw_warnings_LIBRAW_WARN_NO_METADATA as _);
+    pub const NO_EMBEDDED_PROFILE: Self =
+        Self(sys::LibRaw_warnings_LIBRAW_WARN_NO_EMBEDDED_PROFILE as _);
+    pub const NO_INPUT_PROFILE: Self = Self(sys::LibRaw_warnings_LIBRAW_WARN_NO_INPUT_PROFILE as _);
+    pub const NO_BADPIXELMAP: Self = Self(sys::LibRaw_warnings_LIBRAW_WARN_NO_BADPIXELMAP as _);
+    pub const FALLBACK_TO_AHD: Self = Self(sys::LibRaw_warnings_LIBRAW_WARN_FALLBACK_TO_AHD as _);
+    pub const RAWSPEED_PROBLEM: Self = Self(sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED_PROBLEM as _);
+    pub const RAWSPEED_PROCESSED: Self =
+        Self(sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED_PROCESSED as _);
+    pub const RAWSPEED3_PROCESSED: Self =
+        Self(sys::LibRaw_warnings_LIBRAW_WARN_RAWSPEED3_PROCESSED as _);
+    pub const DNGSDK_PROCESSED: Self = Self(sys::LibRaw_warnings_LIBRAW_WARN_DNGSDK_PROCESSED as _);
+
+    pub fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, other: Warnings) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // descriptions of the set bits, unknown bits are left out
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        NAMES
+            .iter()
+            .filter(|(bit, _)| self.0 & bit != 0)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl Display for Warnings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "none");
+        }
+        for (i, name) in self.names().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{name}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warnings() {
+        let warnings =
+            Warnings::from_bits(Warnings::BAD_CAMERA_WB.bits() | Warnings::FALLBACK_TO_AHD.bits());
+        assert!(warnings.contains(Warnings::FALLBACK_TO_AHD));
+        assert!(!warnings.contains(Warnings::NO_METADATA));
+        assert_eq!(
+            warnings.to_string(),
+            "bad camera white balance, fell back to AHD demosaic"
+        );
+        assert_eq!(Warnings::default().to_string(), "none");
+    }
+}
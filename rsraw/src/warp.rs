@@ -0,0 +1,232 @@
+use std::ops::DerefMut;
+
+use crate::{
+    opcodes::{Reader, OPCODE_WARP_RECTILINEAR},
+    raw::BitDepth,
+    resample::{Sample, Source},
+    ProcessedImage, RawImage,
+};
+
+// A DNG WarpRectilinear opcode from OpcodeList3: the radial and tangential
+// distortion model phones and drones record instead of correcting in camera.
+// One coefficient set `[kr0, kr1, kr2, kr3, kt0, kt1]` per plane, or a single
+// set for all of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WarpRectilinear {
+    pub coefficients: Vec<[f64; 6]>,
+    // optical center relative to the image size
+    pub center: (f64, f64),
+}
+
+impl WarpRectilinear {
+    // where the corrected pixel (x, y) of `plane` samples the distorted image
+    pub fn source_position(
+        &self,
+        plane: usize,
+        x: f64,
+        y: f64,
+        width: u32,
+        height: u32,
+    ) -> (f64, f64) {
+        let [kr0, kr1, kr2, kr3, kt0, kt1] =
+            self.coefficients[plane.min(self.coefficients.len() - 1)];
+        let (last_x, last_y) = ((width.max(1) - 1) as f64, (height.max(1) - 1) as f64);
+        let (cx, cy) = (self.center.0 * last_x, self.center.1 * last_y);
+        // distances are normalized to the farthest corner
+        let m = cx.max(last_x - cx).hypot(cy.max(last_y - cy)).max(1.0);
+        let (dx, dy) = ((x - cx) / m, (y - cy) / m);
+        let r2 = dx * dx + dy * dy;
+        let f = kr0 + r2 * (kr1 + r2 * (kr2 + r2 * kr3));
+        let tx = kt0 * 2.0 * dx * dy + kt1 * (r2 + 2.0 * dx * dx);
+        let ty = kt1 * 2.0 * dx * dy + kt0 * (r2 + 2.0 * dy * dy);
+        (cx + m * (f * dx + tx), cy + m * (f * dy + ty))
+    }
+
+    // Undistorts `image` in place, it has to be processed from the DNG the
+    // warp came from. The model is in the sensor's orientation, `flip` is
+    // what LibRaw turned the image by, `raw_image.sizes().flip` after
+    // processing. Corners pulled in from outside the frame come out black.
+    pub fn apply<const D: BitDepth, T: Sample>(&self, image: &mut ProcessedImage<D>, flip: i32)
+    where
+        ProcessedImage<D>: DerefMut<Target = [T]>,
+    {
+        let (width, height) = (image.width(), image.height());
+        let sensor = Flip::new(flip, width, height);
+        let channels = image.colors() as usize;
+        let src = image.to_vec();
+        let src = Source {
+            pixels: &src,
+            width: width as usize,
+            height: height as usize,
+            channels,
+        };
+        for (y, row) in image.chunks_mut(width as usize * channels).enumerate() {
+            for (x, px) in row.chunks_mut(channels).enumerate() {
+                let (sensor_x, sensor_y) = sensor.to_sensor(x as f64, y as f64);
+                for (c, out) in px.iter_mut().enumerate() {
+                    let (sx, sy) =
+                        self.source_position(c, sensor_x, sensor_y, sensor.width, sensor.height);
+                    let (sx, sy) = sensor.to_image(sx, sy);
+                    *out = src.bilinear(c, sx as f32, sy as f32);
+                }
+            }
+        }
+    }
+
+    fn parse(params: &[u8]) -> Option<Self> {
+        let mut r = Reader(params);
+        let planes = r.u32()?;
+        if planes == 0 || planes > 4 {
+            return None;
+        }
+        let coefficients = (0..planes)
+            .map(|_| {
+                let mut k = [0.0; 6];
+                for k in &mut k {
+                    *k = r.f64()?;
+                }
+                Some(k)
+            })
+            .collect::<Option<_>>()?;
+        Some(Self {
+            coefficients,
+            center: (r.f64()?, r.f64()?),
+        })
+    }
+}
+
+// Between a processed image's pixels and the sensor's, flip bits as LibRaw's
+// flip_index: 4 swaps rows and columns, 2 reverses rows, 1 columns
+struct Flip {
+    flip: i32,
+    // the sensor's size, the image's turned back
+    width: u32,
+    height: u32,
+}
+
+impl Flip {
+    fn new(flip: i32, width: u32, height: u32) -> Self {
+        let (width, height) = match flip & 4 {
+            0 => (width, height),
+            _ => (height, width),
+        };
+        Self {
+            flip,
+            width,
+            height,
+        }
+    }
+
+    fn to_sensor(&self, x: f64, y: f64) -> (f64, f64) {
+        let (mut x, mut y) = match self.flip & 4 {
+            0 => (x, y),
+            _ => (y, x),
+        };
+        if self.flip & 2 != 0 {
+            y = (self.height.max(1) - 1) as f64 - y;
+        }
+        if self.flip & 1 != 0 {
+            x = (self.width.max(1) - 1) as f64 - x;
+        }
+        (x, y)
+    }
+
+    fn to_image(&self, mut x: f64, mut y: f64) -> (f64, f64) {
+        if self.flip & 2 != 0 {
+            y = (self.height.max(1) - 1) as f64 - y;
+        }
+        if self.flip & 1 != 0 {
+            x = (self.width.max(1) - 1) as f64 - x;
+        }
+        match self.flip & 4 {
+            0 => (x, y),
+            _ => (y, x),
+        }
+    }
+}
+
+impl RawImage {
+    // the warp in a DNG's OpcodeList3, `None` for other formats
+    pub fn warp_rectilinear(&self) -> Option<WarpRectilinear> {
+        self.opcode_list3()
+            .iter()
+            .filter(|op| op.id == OPCODE_WARP_RECTILINEAR)
+            .find_map(|op| WarpRectilinear::parse(&op.params))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{raw::tests::get_test_assets_path, ProcessParams, BIT_DEPTH_8};
+
+    fn warp_params(k: [f64; 6]) -> Vec<u8> {
+        let mut params = 1u32.to_be_bytes().to_vec();
+        for v in k.into_iter().chain([0.5, 0.5]) {
+            params.extend_from_slice(&v.to_be_bytes());
+        }
+        params
+    }
+
+    #[test]
+    fn test_warp_rectilinear() {
+        let identity =
+            WarpRectilinear::parse(&warp_params([1.0, 0.0, 0.0, 0.0, 0.0, 0.0])).unwrap();
+        assert_eq!(identity.center, (0.5, 0.5));
+        assert_eq!(identity.source_position(2, 3.0, 7.0, 11, 11), (3.0, 7.0));
+
+        // barrel distortion: corrected corners sample from further out
+        let barrel = WarpRectilinear::parse(&warp_params([1.0, 0.1, 0.0, 0.0, 0.0, 0.0])).unwrap();
+        let (x, y) = barrel.source_position(0, 0.0, 0.0, 11, 11);
+        assert!((x + 0.5).abs() < 1e-9 && (y + 0.5).abs() < 1e-9);
+        assert_eq!(barrel.source_position(0, 5.0, 5.0, 11, 11), (5.0, 5.0));
+        assert!(WarpRectilinear::parse(&warp_params([1.0; 6])[..20]).is_none());
+    }
+
+    #[test]
+    fn test_warp_flipped() {
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        let params = ProcessParams {
+            half_size: true,
+            ..Default::default()
+        };
+        // off center and tangential, so any mix-up of the axes shows
+        let warp = WarpRectilinear {
+            coefficients: vec![[1.0, 0.05, 0.0, 0.0, 0.01, -0.02]],
+            center: (0.3, 0.6),
+        };
+        let mut upright = raw_image
+            .process_with::<BIT_DEPTH_8>(&params)
+            .expect("processed");
+        assert_eq!(raw_image.sizes().flip, 0);
+        warp.apply(&mut upright, 0);
+
+        // portrait, as LibRaw turns it for a camera held on its side
+        let mut turned = raw_image
+            .process_with::<BIT_DEPTH_8>(&params.clone().flip(6))
+            .expect("processed");
+        let flip = raw_image.sizes().flip;
+        assert_eq!(flip, 6);
+        assert_eq!(
+            (turned.width(), turned.height()),
+            (upright.height(), upright.width())
+        );
+        warp.apply(&mut turned, flip);
+
+        let sensor = Flip::new(flip, turned.width(), turned.height());
+        let width = upright.width() as usize;
+        for (i, px) in turned.chunks(3).enumerate() {
+            let (x, y) = (i % turned.width() as usize, i / turned.width() as usize);
+            let (sx, sy) = sensor.to_sensor(x as f64, y as f64);
+            let j = (sy as usize * width + sx as usize) * 3;
+            for (a, b) in px.iter().zip(&upright[j..j + 3]) {
+                assert!(
+                    a.abs_diff(*b) <= 1,
+                    "{x} {y}: {px:?} {:?}",
+                    &upright[j..j + 3]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{
+    convert,
+    err::{Error, Result},
+    preview::fit_size,
+    Preview, ProcessedImage, BIT_DEPTH_16, BIT_DEPTH_8,
+};
+
+// WebP can't store anything larger
+const MAX_WEBP_EDGE: u32 = 16383;
+
+// Lossy WebP for proofing galleries and proxies, scaled down to fit
+// `max_edge` first. 0 keeps the size, up to WebP's limit of 16383 pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WebpOptions {
+    // 0 to 100
+    pub quality: f32,
+    pub max_edge: u32,
+}
+
+impl Default for WebpOptions {
+    fn default() -> Self {
+        Self {
+            quality: 75.0,
+            max_edge: 2048,
+        }
+    }
+}
+
+impl ProcessedImage<BIT_DEPTH_8> {
+    // RGB only, `Error::NotImplemented` for other outputs
+    pub fn to_webp(&self, options: &WebpOptions) -> Result<Vec<u8>> {
+        if self.colors() != 3 {
+            return Err(Error::NotImplemented);
+        }
+        encode(self.width(), self.height(), self, options)
+    }
+}
+
+impl ProcessedImage<BIT_DEPTH_16> {
+    // dithered down to 8 bits, RGB only
+    pub fn to_webp(&self, options: &WebpOptions) -> Result<Vec<u8>> {
+        if self.colors() != 3 {
+            return Err(Error::NotImplemented);
+        }
+        encode(self.width(), self.height(), &self.to_u8_dithered(), options)
+    }
+}
+
+impl Preview {
+    pub fn to_webp(&self, options: &WebpOptions) -> Result<Vec<u8>> {
+        encode(self.width, self.height, &self.data, options)
+    }
+}
+
+pub(crate) fn encode(
+    width: u32,
+    height: u32,
+    rgb: &[u8],
+    options: &WebpOptions,
+) -> Result<Vec<u8>> {
+    let max_edge = match options.max_edge {
+        0 => MAX_WEBP_EDGE,
+        edge => edge.min(MAX_WEBP_EDGE),
+    };
+    let (w, h) = fit_size(width, height, max_edge);
+    let scaled;
+    let rgb = if (w, h) == (width, height) {
+        rgb
+    } else {
+        scaled = convert::downscale_rgb8(rgb, width as _, height as _, w as _, h as _);
+        &scaled
+    };
+    let encoded = ::webp::Encoder::from_rgb(rgb, w, h)
+        .encode_simple(false, options.quality.clamp(0.0, 100.0))
+        .map_err(|_| Error::Unspecified)?;
+    Ok(encoded.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webp() {
+        let (width, height) = (300, 200);
+        let rgb: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+        let options = WebpOptions {
+            quality: 60.0,
+            max_edge: 150,
+        };
+        let webp = encode(width, height, &rgb, &options).expect("webp");
+        assert_eq!(&webp[..4], b"RIFF");
+        assert_eq!(&webp[8..12], b"WEBP");
+        let decoded = ::webp::Decoder::new(&webp).decode().expect("decoded");
+        assert_eq!((decoded.width(), decoded.height()), (150, 100));
+
+        let full = WebpOptions {
+            max_edge: 0,
+            ..options
+        };
+        let decoded = ::webp::Decoder::new(&encode(width, height, &rgb, &full).unwrap())
+            .decode()
+            .unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (300, 200));
+    }
+}
@@ -0,0 +1,210 @@
+// Ratings, color labels and develop settings from XMP: the packet embedded
+// in the raw and the `.xmp` sidecar Lightroom, Camera Raw, Capture One or
+// darktable write next to it. What the sidecar says wins, editors keep it
+// up to date while they leave the raw alone.
+
+use std::collections::BTreeMap;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use crate::{
+    err::{Error, Result},
+    RawImage,
+};
+
+// the namespace prefixes Adobe's tools write, which the others follow
+const XMP: &str = "xmp";
+const CAMERA_RAW: &str = "crs";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Xmp {
+    // 1 to 5 stars, 0 unrated and -1 rejected
+    pub rating: Option<i8>,
+    // the color label by name, e.g. "Red", as the editor shows it
+    pub label: Option<String>,
+    // the application that last wrote the metadata
+    pub creator_tool: Option<String>,
+    // Camera Raw settings (crs:) by name, e.g. "Exposure2012" => "+0.50",
+    // values as written. Lists such as tone curves are left out.
+    pub develop: BTreeMap<String, String>,
+}
+
+impl Xmp {
+    pub fn parse(xmp: &str) -> Self {
+        let first = |name| {
+            properties(xmp, XMP)
+                .find(|(property, _)| *property == name)
+                .map(|(_, value)| value)
+        };
+        Self {
+            rating: first("Rating")
+                .and_then(|value| value.parse::<i8>().ok())
+                .map(|rating| rating.clamp(-1, 5)),
+            label: first("Label").filter(|label| !label.is_empty()),
+            creator_tool: first("CreatorTool").filter(|tool| !tool.is_empty()),
+            develop: properties(xmp, CAMERA_RAW)
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+        }
+    }
+
+    // `other` over this, field by field and develop setting by setting
+    pub fn merge(&mut self, other: Xmp) {
+        self.rating = other.rating.or(self.rating);
+        self.label = other.label.or(self.label.take());
+        self.creator_tool = other.creator_tool.or(self.creator_tool.take());
+        self.develop.extend(other.develop);
+    }
+}
+
+impl RawImage {
+    // Adds a sidecar's metadata to what the raw embeds, replacing the one
+    // loaded before. `Error::FileUnsupported` if `xmp` isn't an XMP packet.
+    pub fn load_xmp_sidecar(&mut self, xmp: &[u8]) -> Result<()> {
+        let xmp = String::from_utf8_lossy(xmp);
+        if !xmp.contains("<x:xmpmeta") && !xmp.contains("<rdf:RDF") {
+            return Err(Error::FileUnsupported);
+        }
+        self.sidecar = Some(Xmp::parse(&xmp));
+        Ok(())
+    }
+
+    // like `load_xmp_sidecar`, for a sidecar on disk, usually named after
+    // the raw with an `.xmp` extension
+    #[cfg(feature = "fs")]
+    pub fn load_xmp_sidecar_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let xmp = std::fs::read(path)?;
+        self.load_xmp_sidecar(&xmp)
+    }
+
+    // the embedded XMP with the sidecar's merged over it
+    pub fn merged_xmp(&self) -> Xmp {
+        let mut xmp = Xmp::parse(&self.xmp());
+        if let Some(sidecar) = &self.sidecar {
+            xmp.merge(sidecar.clone());
+        }
+        xmp
+    }
+
+    pub fn rating(&self) -> Option<i8> {
+        self.merged_xmp().rating
+    }
+
+    pub fn label(&self) -> Option<String> {
+        self.merged_xmp().label
+    }
+
+    pub fn develop_settings(&self) -> BTreeMap<String, String> {
+        self.merged_xmp().develop
+    }
+}
+
+// Simple properties of the namespace `prefix`, written either as attribute
+// `prefix:name="value"` or as element `<prefix:name>value</prefix:name>`,
+// with XML's escapes undone
+fn properties<'a>(xmp: &'a str, prefix: &'a str) -> impl Iterator<Item = (&'a str, String)> + 'a {
+    let mut rest = xmp;
+    std::iter::from_fn(move || loop {
+        let pos = rest.find(prefix)?;
+        let (before, after) = rest.split_at(pos);
+        rest = &after[prefix.len()..];
+        let Some(after_prefix) = rest.strip_prefix(':') else {
+            continue;
+        };
+        if !before.is_empty() && !before.ends_with(|c: char| c == '<' || c.is_whitespace()) {
+            continue;
+        }
+        let end = after_prefix
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+            .unwrap_or(after_prefix.len());
+        let (name, tail) = after_prefix.split_at(end);
+        if name.is_empty() {
+            continue;
+        }
+        let value = if let Some(quoted) = tail.strip_prefix("=\"") {
+            quoted.split('"').next()
+        } else if let Some(quoted) = tail.strip_prefix("='") {
+            quoted.split('\'').next()
+        } else if let Some(text) = tail.strip_prefix('>') {
+            // anything but text inside, e.g. an rdf:Seq, isn't simple
+            let close = text.find('<').unwrap_or(text.len());
+            text[close..]
+                .strip_prefix("</")
+                .and_then(|closing| closing.strip_prefix(prefix))
+                .and_then(|closing| closing.strip_prefix(':'))
+                .and_then(|closing| closing.strip_prefix(name))
+                .filter(|closing| closing.starts_with('>'))
+                .map(|_| &text[..close])
+        } else {
+            None
+        };
+        if let Some(value) = value {
+            return Some((name, unescape(value.trim())));
+        }
+    })
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::tests::get_test_assets_path;
+
+    const SIDECAR: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmp:Rating="4"
+    xmp:Label="Red"
+    xmp:CreatorTool="Adobe Photoshop Lightroom Classic 13.0"
+    crs:Version="16.0"
+    crs:Exposure2012="+0.50"
+    crs:WhiteBalance="As Shot">
+   <crs:Look>Adobe Color &amp; Tone</crs:Look>
+   <crs:ToneCurvePV2012>
+    <rdf:Seq><rdf:li>0, 0</rdf:li></rdf:Seq>
+   </crs:ToneCurvePV2012>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+
+    #[test]
+    fn test_xmp_sidecar() {
+        let xmp = Xmp::parse(SIDECAR);
+        assert_eq!(xmp.rating, Some(4));
+        assert_eq!(xmp.label.as_deref(), Some("Red"));
+        assert_eq!(
+            xmp.develop.keys().collect::<Vec<_>>(),
+            ["Exposure2012", "Look", "Version", "WhiteBalance"]
+        );
+        assert_eq!(xmp.develop["Look"], "Adobe Color & Tone");
+        assert_eq!(Xmp::parse(r#"<x xmp:Rating="-1"/>"#).rating, Some(-1));
+
+        let mut embedded = Xmp::parse(r#"xmp:Rating="2" crs:Exposure2012="-1.00" crs:Tint="+3""#);
+        embedded.merge(xmp);
+        assert_eq!(embedded.rating, Some(4));
+        assert_eq!(embedded.develop["Exposure2012"], "+0.50");
+        assert_eq!(embedded.develop["Tint"], "+3");
+
+        let data = std::fs::read(get_test_assets_path().join("test-z8.NEF")).unwrap();
+        let mut raw_image = RawImage::open_owned(data).expect("opened");
+        assert!(matches!(
+            raw_image.load_xmp_sidecar(b"rating=5"),
+            Err(Error::FileUnsupported)
+        ));
+        raw_image
+            .load_xmp_sidecar(SIDECAR.as_bytes())
+            .expect("loaded");
+        assert_eq!(raw_image.rating(), Some(4));
+        assert_eq!(raw_image.label().as_deref(), Some("Red"));
+        assert_eq!(raw_image.develop_settings()["WhiteBalance"], "As Shot");
+        assert_eq!(raw_image.metadata().xmp, raw_image.merged_xmp());
+    }
+}